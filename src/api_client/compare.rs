@@ -6,48 +6,113 @@
 ///
 /// It includes structures and functions to fetch artist data, compare album lists,
 /// and identify discrepancies between local and remote music libraries.
+use crate::api_client::xml::xml_to_json;
 use crate::api_client::CompareError;
-use crate::configuration::ApiSettings;
-use crate::foundation::database::get_artist_data;
-use crate::foundation::utils::{clean_album_name, normalize_unicode};
-use reqwest::Client;
+use crate::api_client::{compare_with_listing, sort_albums};
+use crate::configuration::{
+    ApiSettings, ArtistEndpoint, ComparisonBackend, ComparisonMode, ReportSort, ResponseFormat,
+    Server,
+};
+use crate::foundation::database::{
+    album_counts_key, artist_entries, get_artist_data_by_key, get_metadata, key_articles,
+    key_strategy, store_metadata, AlbumData, ArtistData, ArtistStore,
+};
+use crate::foundation::utils::{
+    canonical_album_name, strip_leading_article, transliteration_key, AlbumNameCleaner, ArtistKeys,
+};
+use futures::stream::{self, StreamExt};
+use reqwest::{Client, ClientBuilder, StatusCode};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use sled::Db;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Represents a response from the Subsonic API.
+///
+/// Only OpenSubsonic servers report `openSubsonic`, `type` and `serverVersion`.
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
 struct SubsonicResponse {
     error: Option<ErrorDetails>,
-    #[serde(rename = "openSubsonic")]
+    #[serde(rename = "openSubsonic", default)]
     open_subsonic: bool,
-    #[serde(rename = "serverVersion")]
+    #[serde(rename = "serverVersion", default)]
     server_version: String,
     status: String,
-    #[serde(rename = "type")]
+    #[serde(rename = "type", default)]
     response_type: String,
     version: String,
 }
 
+/// The newest Subsonic API version musync speaks.
+const CLIENT_API_VERSION: &str = "1.16.1";
+
 /// Contains details about an error returned by the Subsonic API.
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize)]
 struct ErrorDetails {
     code: i32,
     message: String,
 }
 
-/// Compares local music data with the remote API and returns a list of missing album paths.
+/// Options controlling how the local library is compared with the remote API.
+#[derive(Debug, Default, Clone)]
+pub struct CompareOptions {
+    /// Fetch the artist list from the server even if a fresh cached copy exists.
+    pub refresh: bool,
+    /// Rules applied to the album names returned by the API, matching the ones used
+    /// when scanning the local library.
+    pub album_names: AlbumNameCleaner,
+    /// Match album names ignoring case, accents, punctuation and extra whitespace.
+    pub fuzzy_album_match: bool,
+    /// Match albums on their MusicBrainz ID when both the local album and the server's
+    /// have one, and on their name otherwise.
+    pub match_musicbrainz_ids: bool,
+    /// Compare the albums of every artist whose counts differ, even when neither count
+    /// changed since the last comparison.
+    pub full: bool,
+    /// What the local library is compared with.
+    pub backend: ComparisonBackend,
+    /// Which artists have their albums compared.
+    pub comparison_mode: ComparisonMode,
+    /// The order the albums of an artist are printed in.
+    pub report_sort: ReportSort,
+    /// Match server artists missing locally on their [`transliteration_key`], so a
+    /// server's `Mumiy Troll` matches a local `Мумий Тролль`.
+    pub transliterate_artists: bool,
+}
+
+/// Compares local music data with the remote API and reports the differences.
+///
+/// The album paths missing from the API are sorted by artist and album name, so two
+/// identical libraries always produce the same output regardless of the order the API
+/// returns artists in. A path reported for several artists is only returned once.
+///
+/// When `artist_cache_ttl_secs` is set, the artist list is cached in the database and
+/// reused until it expires, unless `options.refresh` is set.
+///
+/// Up to `compare_concurrency` artists are compared at once. An artist that can't be
+/// compared, e.g. because the server fails to list its albums, is reported in
+/// `failed_artists` and the others are still compared. Only failing to list the artists
+/// fails the whole comparison.
+///
+/// Artists are matched ignoring the leading articles of `ignored_articles`, or of the
+/// server's `ignoredArticles` when unset, so the server's `Beatles` matches a local
+/// `The Beatles`. With `options.transliterate_artists`, an artist still unmatched is
+/// matched on its name transliterated to Latin letters.
 ///
 /// # Arguments
 ///
 /// * `db` - A reference to the local database.
 /// * `settings` - API settings for authentication and connection.
+/// * `options` - Options controlling the comparison.
 ///
 /// # Example
 ///
 /// ```
-/// use musync::compare_with_api;
+/// use musync::{compare_with_api, CompareOptions};
 /// use sled::Db;
 /// use musync::ApiSettings;
 ///
@@ -55,152 +120,979 @@ struct ErrorDetails {
 ///     let db = Db::open("path/to/db").unwrap();
 ///     let settings = ApiSettings::new("http://api.example.com", "username", "password");
 ///
-///     match compare_with_api(&db, &settings).await {
-///         Ok(missing_albums) => println!("Missing albums: {:?}", missing_albums),
+///     match compare_with_api(&db, &settings, &CompareOptions::default()).await {
+///         Ok(report) => println!("Missing albums: {:?}", report.missing_in_api),
 ///         Err(e) => eprintln!("Error: {:?}", e),
 ///     }
 /// }
 /// ```
 pub async fn compare_with_api(
-    db: &Db,
+    db: &dyn ArtistStore,
     settings: &ApiSettings,
-) -> Result<Vec<String>, CompareError> {
-    let client = Client::new();
+    options: &CompareOptions,
+) -> Result<ComparisonReport, CompareError> {
+    let mut client = ApiClient::new(
+        PoolOptions::from_settings(settings)
+            .apply(Client::builder())
+            .build()?,
+    );
+    client.negotiate_api_version(settings).await;
 
-    println!("\x1b[1m\x1b[34mFetching artist data from the remote API...\x1b[0m");
-    let artists = fetch_artists(&client, settings).await?;
+    let list = load_artists(db, &client, settings, options).await?;
+    let articles = settings
+        .ignored_articles
+        .as_ref()
+        .unwrap_or(&list.ignored_articles);
+    let local_artists = LocalArtists::new(db, articles, options.transliterate_artists)?;
+
+    let mut all_missing_albums = Vec::new();
+    let mut missing_locally = Vec::new();
+    let mut failed_artists = Vec::new();
+
+    let (client, local_artists, source) = (&client, &local_artists, list.source);
+    let mut comparisons = stream::iter(list.artists)
+        .map(|artist| async move {
+            let name = artist["name"].as_str().unwrap_or("").to_string();
+            let comparison =
+                process_artist(db, client, settings, options, local_artists, source, artist).await;
+            (name, comparison)
+        })
+        .buffer_unordered(settings.compare_concurrency.max(1));
+    while let Some((artist, comparison)) = comparisons.next().await {
+        match comparison {
+            Ok(comparison) => {
+                all_missing_albums.extend(comparison.missing_in_api);
+                missing_locally.extend(comparison.missing_locally);
+            }
+            Err(e) => failed_artists.push(FailedArtist {
+                artist,
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    all_missing_albums.sort();
+    missing_locally.sort();
+    failed_artists.sort();
+
+    let mut seen_paths = HashSet::new();
+    all_missing_albums.retain(|album| seen_paths.insert(album.path.clone()));
+
+    Ok(ComparisonReport {
+        server: settings.api_base_url.clone(),
+        missing_in_api: all_missing_albums
+            .into_iter()
+            .map(|album| album.path)
+            .collect(),
+        missing_locally,
+        api_requests: client.requests.load(Ordering::Relaxed),
+        failed_artists,
+    })
+}
+
+/// How long [`ping_server`] waits for the server to answer.
+const PING_TIMEOUT_SECS: u64 = 10;
+
+/// Checks that the server answers a `ping` request with the configured credentials.
+///
+/// # Arguments
+///
+/// * `settings` - API settings holding the base URL and credentials of the server.
+///
+pub async fn ping_server(settings: &ApiSettings) -> Result<(), CompareError> {
+    let client = ApiClient::new(
+        PoolOptions::from_settings(settings)
+            .apply(Client::builder())
+            .timeout(Duration::from_secs(PING_TIMEOUT_SECS))
+            .build()?,
+    );
+    let ping_url = format!(
+        "{}/ping?{}",
+        settings.api_base_url,
+        client.auth_query(settings)
+    );
+
+    let response = get_response(&client, &ping_url, settings).await?;
+    check_api_error(&response)
+}
+
+/// The HTTP client of a server, counting the requests sent through it.
+struct ApiClient {
+    http: Client,
+    requests: AtomicUsize,
+    /// The API version sent with each request.
+    api_version: String,
+}
+
+impl ApiClient {
+    fn new(http: Client) -> Self {
+        Self {
+            http,
+            requests: AtomicUsize::new(0),
+            api_version: CLIENT_API_VERSION.to_string(),
+        }
+    }
+
+    /// The query parameters every request carries: the credentials, the API version,
+    /// the client name and the response format.
+    fn auth_query(&self, settings: &ApiSettings) -> String {
+        format!(
+            "u={}&p={}&v={}&c=navidrome&f={}",
+            settings.api_username,
+            settings.api_password,
+            self.api_version,
+            settings.response_format.query_value()
+        )
+    }
+
+    /// Asks the server which API version it implements with a `ping`, and sends the
+    /// older of it and [`CLIENT_API_VERSION`] with the following requests, so servers
+    /// implementing an older version don't reject them.
+    ///
+    /// The client's version is kept when the ping fails or reports no version: the
+    /// requests that follow report the problem.
+    async fn negotiate_api_version(&mut self, settings: &ApiSettings) {
+        let ping_url = format!(
+            "{}/ping?{}",
+            settings.api_base_url,
+            self.auth_query(settings)
+        );
+        let Ok(response) = get_response(self, &ping_url, settings).await else {
+            return;
+        };
+        let Ok(envelope) =
+            serde_json::from_value::<SubsonicResponse>(response["subsonic-response"].clone())
+        else {
+            return;
+        };
+        self.api_version = negotiated_api_version(&envelope.version);
+    }
+}
+
+/// Returns the older of the server's API version and [`CLIENT_API_VERSION`]. A version
+/// that can't be parsed is ignored.
+fn negotiated_api_version(server_version: &str) -> String {
+    let parse = |version: &str| -> Option<Vec<u32>> {
+        version
+            .trim()
+            .split('.')
+            .map(|part| part.parse().ok())
+            .collect()
+    };
+    match (parse(server_version), parse(CLIENT_API_VERSION)) {
+        (Some(server), Some(client)) if server < client => server_version.trim().to_string(),
+        _ => CLIENT_API_VERSION.to_string(),
+    }
+}
+
+/// Connection reuse settings of the HTTP client of a server.
+///
+/// Every request of a comparison goes through the same client, so its pool decides how
+/// many connections are kept open between requests instead of being opened again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PoolOptions {
+    max_idle_per_host: usize,
+    idle_timeout: Duration,
+    http2_prior_knowledge: bool,
+}
+
+impl PoolOptions {
+    /// The options configured in `settings`, with `reqwest`'s defaults for unset ones.
+    fn from_settings(settings: &ApiSettings) -> Self {
+        Self {
+            max_idle_per_host: settings.pool_max_idle_per_host.unwrap_or(usize::MAX),
+            idle_timeout: Duration::from_secs(settings.pool_idle_timeout_secs.unwrap_or(90)),
+            http2_prior_knowledge: settings.http2_prior_knowledge,
+        }
+    }
+
+    fn apply(self, builder: ClientBuilder) -> ClientBuilder {
+        let builder = builder
+            .pool_max_idle_per_host(self.max_idle_per_host)
+            .pool_idle_timeout(self.idle_timeout);
+        if self.http2_prior_knowledge {
+            builder.http2_prior_knowledge()
+        } else {
+            builder
+        }
+    }
+}
+
+/// Compares local music data with each of the given servers, in order.
+///
+/// Returns one report per server, named after it. See [`compare_with_api`] and
+/// [`compare_with_listing`] for how each comparison is done, depending on
+/// `options.backend`.
+///
+/// # Arguments
+///
+/// * `db` - A reference to the local database.
+/// * `servers` - The servers to compare against.
+/// * `options` - Options controlling the comparison.
+///
+pub async fn compare_with_servers(
+    db: &dyn ArtistStore,
+    servers: &[Server<'_>],
+    options: &CompareOptions,
+) -> Result<Vec<ComparisonReport>, CompareError> {
+    let mut reports = Vec::with_capacity(servers.len());
+
+    for server in servers {
+        if servers.len() > 1 {
+            println!(
+                "\x1b[1m\x1b[34mComparing with server '{}'...\x1b[0m",
+                server.name
+            );
+        }
+        let report = match options.backend {
+            ComparisonBackend::SubsonicApi => compare_with_api(db, server.api, options).await?,
+            ComparisonBackend::SshListing => compare_with_listing(db, server.remote, options)?,
+        };
+        reports.push(ComparisonReport {
+            server: server.name.to_string(),
+            ..report
+        });
+    }
+
+    Ok(reports)
+}
+
+/// The outcome of comparing the local library with the remote API.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ComparisonReport {
+    /// Name of the server, or its base URL when compared with [`compare_with_api`].
+    pub server: String,
+    /// Paths of local albums the server doesn't have, as stored: relative to the library
+    /// root.
+    pub missing_in_api: Vec<String>,
+    /// Albums the server has that the local library doesn't.
+    pub missing_locally: Vec<RemoteAlbum>,
+    /// Number of requests sent to the server, retries included.
+    #[serde(default)]
+    pub api_requests: usize,
+    /// Artists that couldn't be compared, sorted by name.
+    #[serde(default)]
+    pub failed_artists: Vec<FailedArtist>,
+}
+
+/// An artist of the server whose comparison failed.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct FailedArtist {
+    pub artist: String,
+    pub error: String,
+}
+
+/// An album known to the server but missing from the local library.
+///
+/// Field order matters: the derived `Ord` sorts by artist, then album.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct RemoteAlbum {
+    pub artist: String,
+    pub album: String,
+    /// The server's artist ID, usable to fetch the album later.
+    pub artist_id: String,
+    /// Name of the local artist the album was compared with.
+    #[serde(default)]
+    pub local_artist: String,
+}
+
+/// An album present locally but missing from the remote API.
+///
+/// Field order matters: the derived `Ord` sorts by artist, then album, then path.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct MissingAlbum {
+    artist: String,
+    album: String,
+    path: String,
+}
+
+/// Differences found for a single artist.
+#[derive(Default)]
+struct ArtistComparison {
+    missing_in_api: Vec<MissingAlbum>,
+    missing_locally: Vec<RemoteAlbum>,
+}
+
+/// The endpoint an artist list was fetched from, which decides how the albums of its
+/// artists are listed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum ArtistSource {
+    /// `getArtists`, with albums listed by `getArtist`.
+    Artists,
+    /// `getIndexes`, with albums listed by `getMusicDirectory`.
+    Indexes,
+}
+
+impl ArtistSource {
+    /// Name of the endpoint listing the artists.
+    fn endpoint(self) -> &'static str {
+        match self {
+            ArtistSource::Artists => "getArtists",
+            ArtistSource::Indexes => "getIndexes",
+        }
+    }
+
+    /// Element of the response holding the artist index.
+    fn response_key(self) -> &'static str {
+        match self {
+            ArtistSource::Artists => "artists",
+            ArtistSource::Indexes => "indexes",
+        }
+    }
+}
+
+/// The artists of a server along with the endpoint they were fetched from.
+struct ArtistList {
+    source: ArtistSource,
+    artists: Vec<Value>,
+    /// The server's `ignoredArticles`, which it leaves out when indexing artists.
+    ignored_articles: Vec<String>,
+}
+
+/// Finds the local artist matching a server artist, by key, by key without its leading
+/// article or, when enabled, by transliterated name.
+struct LocalArtists {
+    /// The keys local artists are stored under.
+    keys: ArtistKeys,
+    articles: Vec<String>,
+    /// Local artist keys by key without their leading article. Empty without articles.
+    keys_without_article: HashMap<String, String>,
+    /// Local artist keys by [`transliteration_key`] of their display name. Empty unless
+    /// transliteration is enabled.
+    transliterated_keys: HashMap<String, String>,
+}
+
+impl LocalArtists {
+    fn new(db: &dyn ArtistStore, articles: &[String], transliterate: bool) -> io::Result<Self> {
+        let key_strategy = key_strategy(db)?;
+        let keys = ArtistKeys::new(key_strategy, &key_articles(db)?);
+        let articles: Vec<String> = articles
+            .iter()
+            .map(|article| key_strategy.key(article))
+            .collect();
+        let mut keys_without_article = HashMap::new();
+        let mut transliterated_keys = HashMap::new();
+        if !articles.is_empty() || transliterate {
+            for entry in artist_entries(db) {
+                let (key, value) = entry?;
+                let key = String::from_utf8_lossy(&key).into_owned();
+                if transliterate {
+                    // Corrupt entries can't be matched anyway; `verify` reports them.
+                    if let Ok(data) = bincode::deserialize::<ArtistData>(&value) {
+                        transliterated_keys
+                            .insert(transliteration_key(&data.display_name), key.clone());
+                    }
+                }
+                if !articles.is_empty() {
+                    keys_without_article
+                        .insert(strip_leading_article(&key, &articles).to_string(), key);
+                }
+            }
+        }
+        Ok(Self {
+            keys,
+            articles,
+            keys_without_article,
+            transliterated_keys,
+        })
+    }
+
+    /// Returns the stored data of the local artist stored under `key`, or matching the
+    /// server artist `name`.
+    fn find(&self, db: &dyn ArtistStore, key: &str, name: &str) -> io::Result<Option<ArtistData>> {
+        if let Some(data) = get_artist_data_by_key(db, key)? {
+            return Ok(Some(data));
+        }
+        let local_key = self
+            .keys_without_article
+            .get(strip_leading_article(key, &self.articles))
+            .or_else(|| self.transliterated_keys.get(&transliteration_key(name)));
+        match local_key {
+            Some(key) => get_artist_data_by_key(db, key),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The artist list of a server, as cached in the database.
+#[derive(Serialize, Deserialize)]
+struct CachedArtistList {
+    /// Seconds since the Unix epoch at which the list was fetched.
+    fetched_at: u64,
+    /// The endpoint the list was fetched from.
+    source: ArtistSource,
+    /// The `artist` entries of the response, as JSON.
+    artists_json: String,
+    /// The server's `ignoredArticles`.
+    ignored_articles: Vec<String>,
+}
+
+/// The album counts seen the last time an artist's albums were compared, stored in the
+/// database per server.
+///
+/// Only stored when no local album was missing from the server, so skipping the
+/// comparison never hides an album to upload. The albums missing locally are kept so
+/// they are still reported.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct ComparedCounts {
+    api_album_count: Option<usize>,
+    local_album_count: usize,
+    local_last_modified: u64,
+    missing_locally: Vec<String>,
+}
+
+/// Subsonic error codes meaning the server doesn't support a request: a generic
+/// error, an incompatible protocol version, or a missing endpoint.
+const UNSUPPORTED_ERROR_CODES: [i32; 3] = [0, 30, 70];
+
+/// Returns the server's artist list, from the database cache when it is enabled and
+/// still fresh, or from the API otherwise.
+async fn load_artists(
+    db: &dyn ArtistStore,
+    client: &ApiClient,
+    settings: &ApiSettings,
+    options: &CompareOptions,
+) -> Result<ArtistList, CompareError> {
+    let cache_key = artist_cache_key(settings);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    if let (Some(ttl), false) = (settings.artist_cache_ttl_secs, options.refresh) {
+        // An unreadable cache entry is treated as expired.
+        let cached = get_metadata::<CachedArtistList>(db, &cache_key).unwrap_or(None);
+        if let Some(cached) = cached.filter(|c| now.saturating_sub(c.fetched_at) < ttl) {
+            if let Ok(artists) = serde_json::from_str(&cached.artists_json) {
+                println!("\x1b[1m\x1b[34mUsing cached artist data from the remote API...\x1b[0m");
+                return Ok(ArtistList {
+                    source: cached.source,
+                    artists,
+                    ignored_articles: cached.ignored_articles,
+                });
+            }
+        }
+    }
 
-    let mut all_missing_album_paths = Vec::new();
+    println!("\x1b[1m\x1b[34mFetching artist data from the remote API...\x1b[0m");
+    let list = fetch_artists(client, settings).await?;
 
-    for artist in artists {
-        let missing_albums = process_artist(db, &client, settings, artist).await?;
-        all_missing_album_paths.extend(missing_albums);
+    if settings.artist_cache_ttl_secs.is_some() {
+        let cached = CachedArtistList {
+            fetched_at: now,
+            source: list.source,
+            artists_json: serde_json::to_string(&list.artists)?,
+            ignored_articles: list.ignored_articles.clone(),
+        };
+        store_metadata(db, &cache_key, &cached)?;
     }
 
-    Ok(all_missing_album_paths)
+    Ok(list)
+}
+
+/// Key of the cached artist list of a server. Lists fetched with another endpoint,
+/// response format or music folder are cached separately.
+fn artist_cache_key(settings: &ApiSettings) -> String {
+    let key = format!(
+        "artists:{}:{:?}:{}",
+        settings.api_base_url,
+        settings.artist_endpoint,
+        settings.response_format.query_value()
+    );
+    match settings.music_folder_id {
+        Some(folder_id) => format!("{}:{}", key, folder_id),
+        None => key,
+    }
 }
 
 /// Fetches artist data from the remote API.
 ///
+/// With the `Auto` endpoint, `getArtists` is used first and `getIndexes` is tried when
+/// it returns no artists or an error meaning it isn't supported, as some older servers
+/// only implement the folder-based `getIndexes`.
+///
+/// When `music_folder_id` is set, the folder is first checked against the server's
+/// `getMusicFolders` list, since servers silently ignore an unknown `musicFolderId` and
+/// return every artist instead.
+///
 /// # Arguments
 ///
-/// * `client` - An HTTP client for making requests.
+/// * `client` - The HTTP client of the server.
 /// * `settings` - API settings for authentication and connection.
 ///
 async fn fetch_artists(
-    client: &Client,
+    client: &ApiClient,
+    settings: &ApiSettings,
+) -> Result<ArtistList, CompareError> {
+    if let Some(folder_id) = settings.music_folder_id {
+        ensure_music_folder_exists(client, settings, folder_id).await?;
+    }
+
+    match settings.artist_endpoint {
+        ArtistEndpoint::GetArtists => {
+            fetch_artist_index(client, settings, ArtistSource::Artists).await
+        }
+        ArtistEndpoint::GetIndexes => {
+            fetch_artist_index(client, settings, ArtistSource::Indexes).await
+        }
+        ArtistEndpoint::Auto => {
+            match fetch_artist_index(client, settings, ArtistSource::Artists).await {
+                Ok(list) if !list.artists.is_empty() => return Ok(list),
+                Ok(_) => {
+                    println!("\x1b[33mgetArtists returned no artists, trying getIndexes...\x1b[0m")
+                }
+                Err(CompareError::ApiError { code, message })
+                    if UNSUPPORTED_ERROR_CODES.contains(&code) =>
+                {
+                    println!(
+                        "\x1b[33mgetArtists failed ({}: {}), trying getIndexes...\x1b[0m",
+                        code, message
+                    )
+                }
+                Err(e) => return Err(e),
+            }
+            fetch_artist_index(client, settings, ArtistSource::Indexes).await
+        }
+    }
+}
+
+/// Fetches the artists listed by one endpoint. `getArtists` and `getIndexes` both
+/// group artists in `index` entries, under `artists` and `indexes` respectively.
+async fn fetch_artist_index(
+    client: &ApiClient,
+    settings: &ApiSettings,
+    source: ArtistSource,
+) -> Result<ArtistList, CompareError> {
+    let mut artists_url = format!(
+        "{}/{}?{}",
+        settings.api_base_url,
+        source.endpoint(),
+        client.auth_query(settings)
+    );
+
+    if let Some(folder_id) = settings.music_folder_id {
+        artists_url.push_str(&format!("&musicFolderId={}", folder_id));
+    }
+
+    let response = get_response(client, &artists_url, settings).await?;
+    check_api_error(&response)?;
+
+    Ok(ArtistList {
+        source,
+        artists: artists_in_response(&response, source),
+        ignored_articles: ignored_articles_in_response(&response, source),
+    })
+}
+
+/// Reads the space-separated `ignoredArticles` of a `getArtists` or `getIndexes`
+/// response.
+fn ignored_articles_in_response(response: &Value, source: ArtistSource) -> Vec<String> {
+    response["subsonic-response"][source.response_key()]["ignoredArticles"]
+        .as_str()
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect()
+}
+
+/// Collects the artists of all the `index` entries of a `getArtists` or `getIndexes`
+/// response, parsed from either JSON or XML.
+fn artists_in_response(response: &Value, source: ArtistSource) -> Vec<Value> {
+    response["subsonic-response"][source.response_key()]["index"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|index| index["artist"].as_array())
+        .flatten()
+        .cloned()
+        .collect()
+}
+
+/// Checks that the server has a music folder with the given ID.
+async fn ensure_music_folder_exists(
+    client: &ApiClient,
     settings: &ApiSettings,
-) -> Result<Vec<Value>, CompareError> {
-    let artists_url = format!(
-        "{}/getArtists?u={}&p={}&v=1.16.1&c=navidrome&f=json",
-        settings.api_base_url, settings.api_username, settings.api_password
+    folder_id: u32,
+) -> Result<(), CompareError> {
+    let folders_url = format!(
+        "{}/getMusicFolders?{}",
+        settings.api_base_url,
+        client.auth_query(settings)
     );
 
-    let response: Value = client.get(&artists_url).send().await?.json().await?;
+    let response = get_response(client, &folders_url, settings).await?;
+    check_api_error(&response)?;
+
+    let exists = response["subsonic-response"]["musicFolders"]["musicFolder"]
+        .as_array()
+        .is_some_and(|folders| {
+            folders
+                .iter()
+                .any(|folder| folder_id_of(folder) == Some(u64::from(folder_id)))
+        });
+
+    if exists {
+        Ok(())
+    } else {
+        Err(CompareError::MusicFolderNotFound { id: folder_id })
+    }
+}
+
+/// ID of a music folder, a number in JSON responses and a string in XML ones.
+fn folder_id_of(folder: &Value) -> Option<u64> {
+    folder["id"]
+        .as_u64()
+        .or_else(|| folder["id"].as_str()?.parse().ok())
+}
 
-    if let Some(error) = response["subsonic-response"]["error"].as_object() {
-        return Err(CompareError::ApiError {
+/// Turns the `error` element of a Subsonic response into a `CompareError::ApiError`.
+fn check_api_error(response: &Value) -> Result<(), CompareError> {
+    match response["subsonic-response"]["error"].as_object() {
+        Some(error) => Err(CompareError::ApiError {
             code: error["code"].as_i64().unwrap_or(0) as i32,
             message: error["message"]
                 .as_str()
                 .unwrap_or("Unknown error")
                 .to_string(),
-        });
+        }),
+        None => Ok(()),
     }
-
-    let mut artists = Vec::new();
-
-    if let Some(indexes) = response["subsonic-response"]["artists"]["index"].as_array() {
-        for index in indexes {
-            if let Some(index_artists) = index["artist"].as_array() {
-                artists.extend(index_artists.iter().cloned());
-            }
-        }
-    }
-
-    Ok(artists)
 }
 
 /// Processes an individual artist, comparing local and remote data.
 ///
+/// When the album counts differ but are the same as at the last comparison, and the
+/// local artist hasn't changed since, the previous result is reused instead of fetching
+/// the artist's albums again, unless `options.full` is set.
+///
+/// With the `AlbumSetAlways` comparison mode, the albums of every artist are fetched and
+/// compared, whatever their counts.
+///
 /// # Arguments
 ///
 /// * `db` - A reference to the local database.
-/// * `client` - An HTTP client for making requests.
+/// * `client` - The HTTP client of the server.
 /// * `settings` - API settings for authentication and connection.
+/// * `options` - Options controlling the comparison.
+/// * `local_artists` - Finds the local artist matching the server's.
+/// * `source` - The endpoint the artist was listed by.
 /// * `artist` - Artist data from the API.
 ///
 async fn process_artist(
-    db: &Db,
-    client: &Client,
+    db: &dyn ArtistStore,
+    client: &ApiClient,
     settings: &ApiSettings,
+    options: &CompareOptions,
+    local_artists: &LocalArtists,
+    source: ArtistSource,
     artist: Value,
-) -> Result<Vec<String>, CompareError> {
+) -> Result<ArtistComparison, CompareError> {
     let name = artist["name"].as_str().unwrap_or("");
-    let api_album_count = artist["albumCount"].as_u64().unwrap_or(0) as usize;
+    // `getIndexes` doesn't report album counts, so its artists are always compared.
+    let api_album_count = artist["albumCount"].as_u64().map(|count| count as usize);
     let id = artist["id"].as_str().unwrap_or("");
 
-    let normalized_name = normalize_unicode(name);
-    if let Some(local_data) = get_artist_data(db, &normalized_name)? {
-        if local_data.album_count != api_album_count {
-            println!(
-                "\x1b[33mMismatch for artist '{}': Local count: {}, API count: {} - Artist id: {}\x1b[0m",
-                normalized_name, local_data.album_count, api_album_count, id
-            );
-            let missing_albums =
-                compare_album_lists(client, &settings.api_base_url, id, &local_data.albums).await?;
-            Ok(missing_albums)
+    let key = local_artists.keys.key(name);
+    if let Some(local_data) = local_artists.find(db, &key, name)? {
+        let compare_always = options.comparison_mode == ComparisonMode::AlbumSetAlways;
+        let counts_differ = api_album_count != Some(local_data.album_count);
+        if counts_differ || compare_always {
+            if counts_differ {
+                println!(
+                    "\x1b[33mMismatch for artist '{}': Local count: {}, API count: {} - Artist id: {}\x1b[0m",
+                    local_data.display_name,
+                    local_data.album_count,
+                    api_album_count.map_or_else(|| "unknown".to_string(), |count| count.to_string()),
+                    id
+                );
+            }
+            let counts_key = album_counts_key(&settings.api_base_url, &key);
+            let counts = ComparedCounts {
+                api_album_count,
+                local_album_count: local_data.album_count,
+                local_last_modified: local_data.last_modified,
+                missing_locally: Vec::new(),
+            };
+            // An unreadable entry is treated as a missing one.
+            let previous = if options.full || compare_always {
+                None
+            } else {
+                get_metadata::<ComparedCounts>(db, &counts_key).unwrap_or(None)
+            };
+
+            let (missing_in_api, missing_locally) = match previous {
+                Some(previous)
+                    if ComparedCounts {
+                        missing_locally: Vec::new(),
+                        ..previous
+                    } == counts =>
+                {
+                    println!(
+                        "\x1b[33mAlbum counts unchanged since the last comparison, skipping\x1b[0m"
+                    );
+                    (Vec::new(), previous.missing_locally)
+                }
+                _ => {
+                    let (missing_in_api, missing_locally) = compare_album_lists(
+                        client,
+                        settings,
+                        options,
+                        source,
+                        id,
+                        &local_data.albums,
+                    )
+                    .await?;
+                    if missing_in_api.is_empty() {
+                        let counts = ComparedCounts {
+                            missing_locally: missing_locally.clone(),
+                            ..counts
+                        };
+                        store_metadata(db, &counts_key, &counts)?;
+                    }
+                    (missing_in_api, missing_locally)
+                }
+            };
+            Ok(ArtistComparison {
+                missing_in_api: missing_in_api
+                    .into_iter()
+                    .map(|(album, path)| MissingAlbum {
+                        artist: key.clone(),
+                        album,
+                        path,
+                    })
+                    .collect(),
+                missing_locally: missing_locally
+                    .into_iter()
+                    .map(|album| RemoteAlbum {
+                        artist: name.to_string(),
+                        album,
+                        artist_id: id.to_string(),
+                        local_artist: local_data.display_name.clone(),
+                    })
+                    .collect(),
+            })
         } else {
-            Ok(Vec::new())
+            Ok(ArtistComparison::default())
         }
     } else {
-        println!(
-            "\x1b[31mNo local data found for artist '{}'\x1b[0m",
-            normalized_name
-        );
-        Ok(Vec::new())
+        println!("\x1b[31mNo local data found for artist '{}'\x1b[0m", key);
+        Ok(ArtistComparison::default())
     }
 }
 
+/// Compares the albums of one artist, returning the local `(name, path)` pairs missing
+/// from the API and the names of the API albums missing locally.
+///
+/// Artists listed by `getArtists` have their albums fetched with `getArtist`; artist
+/// folders listed by `getIndexes` have theirs fetched with `getMusicDirectory`, where
+/// the albums are the sub-folders.
+///
+/// With `match_musicbrainz_ids`, albums sharing a MusicBrainz ID match whatever their
+/// names; the others are matched on their name. Local albums sharing a name, such as
+/// `Album [CD1]` and `Album [CD2]` once cleaned, are all reported when the name is
+/// missing from the API, so none of their paths is dropped.
 async fn compare_album_lists(
-    client: &Client,
-    base_url: &str,
+    client: &ApiClient,
+    settings: &ApiSettings,
+    options: &CompareOptions,
+    source: ArtistSource,
     artist_id: &str,
-    local_albums: &[(String, String)],
-) -> Result<Vec<String>, CompareError> {
+    local_albums: &[AlbumData],
+) -> Result<(Vec<(String, String)>, Vec<String>), CompareError> {
+    let endpoint = match source {
+        ArtistSource::Artists => "getArtist",
+        ArtistSource::Indexes => "getMusicDirectory",
+    };
     let artist_url = format!(
-        "{}/getArtist?id={}&u=thiago&p=Lopp1010&v=1.16.1&c=navidrome&f=json",
-        base_url, artist_id
+        "{}/{}?id={}&{}",
+        settings.api_base_url,
+        endpoint,
+        artist_id,
+        client.auth_query(settings)
     );
 
-    let response: Value = client.get(&artist_url).send().await?.json().await?;
+    let response = get_response(client, &artist_url, settings).await?;
+    // Without this check, an error would read as an artist without albums, and every
+    // local album of the artist would be reported as missing.
+    check_api_error(&response)?;
 
-    let api_albums: HashSet<String> = response["subsonic-response"]["artist"]["album"]
-        .as_array()
-        .unwrap_or(&Vec::new())
+    // (name, MusicBrainz ID) of each album.
+    let api_album_list: Vec<(&str, Option<&str>)> = match source {
+        ArtistSource::Artists => response["subsonic-response"]["artist"]["album"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|album| Some((album["name"].as_str()?, musicbrainz_id_of(album))))
+            .collect(),
+        ArtistSource::Indexes => response["subsonic-response"]["directory"]["child"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|child| child["isDir"].as_bool() == Some(true))
+            .filter_map(|child| Some((child["title"].as_str()?, musicbrainz_id_of(child))))
+            .collect(),
+    };
+    // Albums are matched on their key, but reported with their original name.
+    let album_key = |name: &str| {
+        if options.fuzzy_album_match {
+            canonical_album_name(name)
+        } else {
+            name.to_string()
+        }
+    };
+
+    let mut local_albums: Vec<&AlbumData> = local_albums.iter().collect();
+    let mut local_keys: HashSet<String> = local_albums
         .iter()
-        .filter_map(|album| album["name"].as_str().map(clean_album_name))
+        .map(|album| album_key(&album.name))
         .collect();
 
-    let local_set: HashSet<String> = local_albums.iter().map(|(name, _)| name.clone()).collect();
-
-    println!("\x1b[34mAPI albums: {:?}\x1b[0m", api_albums);
-    println!("\x1b[34mLocal albums: {:?}\x1b[0m", local_set);
+    let mut api_album_list = api_album_list;
+    if options.match_musicbrainz_ids {
+        // Albums matched on their ID are left out of the name matching.
+        let local_ids: HashSet<&str> = local_albums
+            .iter()
+            .filter_map(|album| album.musicbrainz_id.as_deref())
+            .collect();
+        let api_ids: HashSet<&str> = api_album_list.iter().filter_map(|(_, id)| *id).collect();
+        api_album_list.retain(|(_, id)| !id.is_some_and(|id| local_ids.contains(id)));
+        local_albums.retain(|album| {
+            !album
+                .musicbrainz_id
+                .as_deref()
+                .is_some_and(|id| api_ids.contains(id))
+        });
+        local_keys = local_albums
+            .iter()
+            .map(|album| album_key(&album.name))
+            .collect();
+    }
 
-    let missing_locally: Vec<_> = api_albums.difference(&local_set).collect();
-    let missing_in_api: Vec<_> = local_set.difference(&api_albums).collect();
+    let mut api_albums: HashMap<String, String> = HashMap::new();
+    for (name, _) in api_album_list {
+        let name = options.album_names.clean(name);
+        api_albums.entry(album_key(&name)).or_insert(name);
+    }
 
-    print_missing_albums(&missing_locally, &missing_in_api);
+    let mut api_album_names: Vec<&String> = api_albums.values().collect();
+    api_album_names.sort();
+    println!("\x1b[34mAPI albums: {:?}\x1b[0m", api_album_names);
+    println!(
+        "\x1b[34mLocal albums: {:?}\x1b[0m",
+        local_albums
+            .iter()
+            .map(|album| &album.name)
+            .collect::<Vec<_>>()
+    );
 
-    Ok(missing_in_api
+    let mut missing_locally: Vec<&String> = api_albums
+        .iter()
+        .filter(|(key, _)| !local_keys.contains(*key))
+        .map(|(_, name)| name)
+        .collect();
+    let mut missing_in_api: Vec<&AlbumData> = local_albums
         .into_iter()
-        .filter_map(|album_name| {
-            local_albums
-                .iter()
-                .find(|(name, _)| name == album_name)
-                .map(|(_, path)| path.clone())
-        })
-        .collect())
+        .filter(|album| !api_albums.contains_key(&album_key(&album.name)))
+        .collect();
+    sort_albums(&mut missing_locally, options.report_sort, |name| {
+        ("", name, None)
+    });
+    sort_albums(&mut missing_in_api, options.report_sort, |album| {
+        ("", &album.name, Some(album.total_bytes))
+    });
+
+    print_missing_albums(
+        &missing_locally,
+        &missing_in_api
+            .iter()
+            .map(|album| &album.name)
+            .collect::<Vec<_>>(),
+    );
+
+    Ok((
+        missing_in_api
+            .into_iter()
+            .map(|album| (album.name.clone(), album.path.clone()))
+            .collect(),
+        missing_locally.into_iter().cloned().collect(),
+    ))
+}
+
+/// Returns the MusicBrainz ID of an album of the API, if it has one.
+fn musicbrainz_id_of(album: &Value) -> Option<&str> {
+    album["musicBrainzId"]
+        .as_str()
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+}
+
+/// Longest delay between two attempts, including the `Retry-After` sent by the server.
+const MAX_BACKOFF_SECS: u64 = 60;
+
+/// Sends a GET request and parses the body in the configured `response_format`,
+/// retrying when rate-limited. XML responses are converted to the structure of the
+/// JSON ones.
+///
+/// On HTTP 429 the request is retried up to `max_retries` times, waiting as given by
+/// [`retry_delay`].
+///
+/// # Arguments
+///
+/// * `client` - The HTTP client of the server.
+/// * `url` - The URL to fetch.
+/// * `settings` - API settings holding the response format and retry count.
+///
+async fn get_response(
+    client: &ApiClient,
+    url: &str,
+    settings: &ApiSettings,
+) -> Result<Value, CompareError> {
+    let max_retries = settings.max_retries;
+    let mut attempt = 0;
+
+    loop {
+        client.requests.fetch_add(1, Ordering::Relaxed);
+        let response = client.http.get(url).send().await?;
+
+        if response.status() != StatusCode::TOO_MANY_REQUESTS {
+            return match settings.response_format {
+                ResponseFormat::Json => Ok(response.json().await?),
+                ResponseFormat::Xml => xml_to_json(&response.text().await?),
+            };
+        }
+
+        if attempt >= max_retries {
+            return Err(CompareError::RateLimited {
+                retries: max_retries,
+            });
+        }
+
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok());
+        let delay = retry_delay(retry_after, attempt);
+
+        println!(
+            "\x1b[33mRate limited by the API, retrying in {}s ({}/{})\x1b[0m",
+            delay,
+            attempt + 1,
+            max_retries
+        );
+        tokio::time::sleep(Duration::from_secs(delay)).await;
+        attempt += 1;
+    }
+}
+
+/// Seconds to wait before retrying a rate-limited request for the `attempt`-th time
+/// (from 0): the `Retry-After` delay when the server sends one in seconds, or an
+/// exponential backoff (1s, 2s, 4s, ...) otherwise. Both are capped at
+/// [`MAX_BACKOFF_SECS`], so a server can't stall the sync for hours.
+fn retry_delay(retry_after: Option<&str>, attempt: u32) -> u64 {
+    retry_after
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .unwrap_or_else(|| 2u64.saturating_pow(attempt))
+        .min(MAX_BACKOFF_SECS)
 }
 
 fn print_missing_albums(missing_locally: &[&String], missing_in_api: &[&String]) {
@@ -214,3 +1106,1095 @@ fn print_missing_albums(missing_locally: &[&String], missing_in_api: &[&String])
         println!("\x1b[33mAlbums missing in API: {:?}\x1b[0m", missing_in_api);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::RemoteSettings;
+    use crate::foundation::database::{get_artist_data, store_artist_data, MemoryStore};
+    use serde_json::json;
+    use wiremock::matchers::{path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn subsonic_ok(body: Value) -> ResponseTemplate {
+        let mut response = json!({
+            "status": "ok",
+            "version": "1.16.1",
+            "type": "navidrome",
+            "serverVersion": "0.53.0",
+            "openSubsonic": true,
+        });
+        response
+            .as_object_mut()
+            .unwrap()
+            .extend(body.as_object().unwrap().clone());
+        ResponseTemplate::new(200).set_body_json(json!({ "subsonic-response": response }))
+    }
+
+    fn subsonic_failed(code: i32, message: &str) -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_json(json!({
+            "subsonic-response": {
+                "status": "failed",
+                "version": "1.16.1",
+                "error": { "code": code, "message": message },
+            }
+        }))
+    }
+
+    async fn mock_artists(server: &MockServer, artists: Value) {
+        Mock::given(path("/getArtists"))
+            .respond_with(subsonic_ok(
+                json!({ "artists": { "index": [{ "name": "#", "artist": artists }] } }),
+            ))
+            .mount(server)
+            .await;
+    }
+
+    async fn mock_artist_albums(server: &MockServer, id: &str, albums: &[&str]) {
+        let albums: Vec<Value> = albums.iter().map(|name| json!({ "name": name })).collect();
+        Mock::given(path("/getArtist"))
+            .and(query_param("id", id))
+            .respond_with(subsonic_ok(
+                json!({ "artist": { "id": id, "album": albums } }),
+            ))
+            .mount(server)
+            .await;
+    }
+
+    async fn mock_indexes(server: &MockServer, artists: Value) {
+        Mock::given(path("/getIndexes"))
+            .respond_with(subsonic_ok(json!({
+                "indexes": { "lastModified": 0, "index": [{ "name": "B", "artist": artists }] }
+            })))
+            .mount(server)
+            .await;
+    }
+
+    async fn mock_music_directory(server: &MockServer, id: &str, albums: &[&str]) {
+        let mut children: Vec<Value> = albums
+            .iter()
+            .map(|title| json!({ "id": format!("{id}/{title}"), "title": title, "isDir": true }))
+            .collect();
+        children.push(json!({ "id": format!("{id}/cover"), "title": "cover", "isDir": false }));
+        Mock::given(path("/getMusicDirectory"))
+            .and(query_param("id", id))
+            .respond_with(subsonic_ok(
+                json!({ "directory": { "id": id, "child": children } }),
+            ))
+            .mount(server)
+            .await;
+    }
+
+    fn store_albums(db: &dyn ArtistStore, artist: &str, albums: &[&str]) {
+        let albums = albums
+            .iter()
+            .map(|album| AlbumData {
+                name: album.to_string(),
+                path: format!("/library/{artist}/{album}"),
+                ..AlbumData::default()
+            })
+            .collect::<Vec<_>>();
+        store_artist_data(db, artist, albums.len(), 0, albums).unwrap();
+    }
+
+    async fn missing_albums_for_artist_order(artists: Value) -> Vec<String> {
+        let server = MockServer::start().await;
+        mock_artists(&server, artists).await;
+        mock_artist_albums(&server, "1", &["Abbey Road"]).await;
+        mock_artist_albums(&server, "2", &[]).await;
+        mock_artist_albums(&server, "3", &["Kid A"]).await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        store_albums(&db, "beatles", &["Revolver", "Abbey Road", "Help"]);
+        store_albums(&db, "abba", &["Waterloo", "Arrival"]);
+        store_albums(&db, "radiohead", &["OK Computer", "Kid A"]);
+
+        let settings = ApiSettings::new(&server.uri(), "user", "password");
+        compare_with_api(&db, &settings, &CompareOptions::default())
+            .await
+            .unwrap()
+            .missing_in_api
+    }
+
+    #[tokio::test]
+    async fn test_compare_with_api_output_is_sorted() {
+        let beatles = json!({ "id": "1", "name": "Beatles", "albumCount": 1 });
+        let abba = json!({ "id": "2", "name": "ABBA", "albumCount": 0 });
+        let radiohead = json!({ "id": "3", "name": "Radiohead", "albumCount": 1 });
+
+        let first = missing_albums_for_artist_order(json!([beatles, abba, radiohead])).await;
+        let second = missing_albums_for_artist_order(json!([radiohead, beatles, abba])).await;
+
+        assert_eq!(
+            first,
+            vec![
+                "/library/abba/Arrival",
+                "/library/abba/Waterloo",
+                "/library/beatles/Help",
+                "/library/beatles/Revolver",
+                "/library/radiohead/OK Computer",
+            ]
+        );
+        assert_eq!(first, second);
+    }
+
+    #[tokio::test]
+    async fn test_compare_with_api_reports_albums_missing_locally() {
+        let server = MockServer::start().await;
+        mock_artists(
+            &server,
+            json!([{ "id": "ar-1", "name": "Beatles", "albumCount": 3 }]),
+        )
+        .await;
+        mock_artist_albums(&server, "ar-1", &["Revolver", "Let It Be", "Abbey Road"]).await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        store_albums(&db, "beatles", &["Revolver", "Help"]);
+
+        let settings = ApiSettings::new(&server.uri(), "user", "password");
+        let report = compare_with_api(&db, &settings, &CompareOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(report.missing_in_api, vec!["/library/beatles/Help"]);
+        assert_eq!(
+            report.missing_locally,
+            vec![
+                RemoteAlbum {
+                    artist: "Beatles".to_string(),
+                    album: "Abbey Road".to_string(),
+                    artist_id: "ar-1".to_string(),
+                    local_artist: "beatles".to_string(),
+                },
+                RemoteAlbum {
+                    artist: "Beatles".to_string(),
+                    album: "Let It Be".to_string(),
+                    artist_id: "ar-1".to_string(),
+                    local_artist: "beatles".to_string(),
+                },
+            ]
+        );
+    }
+
+    const ARTISTS_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <subsonic-response xmlns="http://subsonic.org/restapi" status="ok" version="1.16.1">
+            <artists ignoredArticles="The">
+                <index name="A">
+                    <artist id="2" name="ABBA" albumCount="2"/>
+                </index>
+                <index name="B">
+                    <artist id="1" name="Beatles" albumCount="1"/>
+                    <artist id="3" name="Björk &amp; Friends" albumCount="0"/>
+                </index>
+            </artists>
+        </subsonic-response>"#;
+
+    #[test]
+    fn test_json_and_xml_artists_are_identical() {
+        let json_response = json!({
+            "subsonic-response": {
+                "status": "ok",
+                "version": "1.16.1",
+                "artists": {
+                    "ignoredArticles": "The",
+                    "index": [
+                        { "name": "A", "artist": [
+                            { "id": "2", "name": "ABBA", "albumCount": 2 },
+                        ] },
+                        { "name": "B", "artist": [
+                            { "id": "1", "name": "Beatles", "albumCount": 1 },
+                            { "id": "3", "name": "Björk & Friends", "albumCount": 0 },
+                        ] },
+                    ]
+                }
+            }
+        });
+        let xml_response = xml_to_json(ARTISTS_XML).unwrap();
+
+        let json_artists = artists_in_response(&json_response, ArtistSource::Artists);
+        let xml_artists = artists_in_response(&xml_response, ArtistSource::Artists);
+
+        assert_eq!(json_artists.len(), 3);
+        assert_eq!(json_artists, xml_artists);
+    }
+
+    #[tokio::test]
+    async fn test_compare_with_xml_responses() {
+        let server = MockServer::start().await;
+        Mock::given(path("/getArtists"))
+            .and(query_param("f", "xml"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(ARTISTS_XML))
+            .mount(&server)
+            .await;
+        Mock::given(path("/getArtist"))
+            .and(query_param("id", "1"))
+            .and(query_param("f", "xml"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                r#"<subsonic-response status="ok" version="1.16.1">
+                    <artist id="1" name="Beatles" albumCount="1">
+                        <album id="10" name="Revolver" artist="Beatles"/>
+                    </artist>
+                </subsonic-response>"#,
+            ))
+            .mount(&server)
+            .await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        store_albums(&db, "beatles", &["Revolver", "Help"]);
+
+        let settings = ApiSettings {
+            response_format: ResponseFormat::Xml,
+            ..ApiSettings::new(&server.uri(), "user", "password")
+        };
+        let report = compare_with_api(&db, &settings, &CompareOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(report.missing_in_api, vec!["/library/beatles/Help"]);
+    }
+
+    #[tokio::test]
+    async fn test_artists_match_ignoring_the_servers_articles() {
+        let server = MockServer::start().await;
+        Mock::given(path("/getArtists"))
+            .respond_with(subsonic_ok(json!({ "artists": {
+                "ignoredArticles": "The El La",
+                "index": [{ "name": "B", "artist": [
+                    { "id": "1", "name": "Beatles", "albumCount": 1 },
+                ] }],
+            } })))
+            .mount(&server)
+            .await;
+        mock_artist_albums(&server, "1", &["Revolver"]).await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        store_albums(&db, "The Beatles", &["Revolver", "Help"]);
+
+        let settings = ApiSettings::new(&server.uri(), "user", "password");
+        let report = compare_with_api(&db, &settings, &CompareOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(report.missing_in_api, vec!["/library/The Beatles/Help"]);
+
+        let settings = ApiSettings {
+            ignored_articles: Some(Vec::new()),
+            ..settings
+        };
+        let report = compare_with_api(&db, &settings, &CompareOptions::default())
+            .await
+            .unwrap();
+        assert!(report.missing_in_api.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_artists_match_on_their_transliterated_name() {
+        let server = MockServer::start().await;
+        mock_artists(
+            &server,
+            json!([{ "id": "1", "name": "Mumiy Troll", "albumCount": 1 }]),
+        )
+        .await;
+        mock_artist_albums(&server, "1", &["Morskaya"]).await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        store_albums(&db, "Мумий Тролль", &["Morskaya", "Ikra"]);
+        let settings = ApiSettings::new(&server.uri(), "user", "password");
+
+        let report = compare_with_api(&db, &settings, &CompareOptions::default())
+            .await
+            .unwrap();
+        assert!(report.missing_in_api.is_empty());
+
+        let options = CompareOptions {
+            transliterate_artists: true,
+            ..CompareOptions::default()
+        };
+        let report = compare_with_api(&db, &settings, &options).await.unwrap();
+        assert_eq!(report.missing_in_api, vec!["/library/Мумий Тролль/Ikra"]);
+        assert!(get_artist_data(&db, "Mumiy Troll").unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_matching_library_has_no_missing_albums() {
+        let server = MockServer::start().await;
+        mock_artists(
+            &server,
+            json!([
+                { "id": "1", "name": "Beatles", "albumCount": 2 },
+                { "id": "2", "name": "Radiohead", "albumCount": 1 },
+            ]),
+        )
+        .await;
+        mock_artist_albums(&server, "1", &["Revolver", "Help"]).await;
+        mock_artist_albums(&server, "2", &["Kid A"]).await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        store_albums(&db, "beatles", &["Help", "Revolver"]);
+        store_albums(&db, "radiohead", &["Kid A"]);
+
+        let settings = ApiSettings::new(&server.uri(), "user", "password");
+        let report = compare_with_api(&db, &settings, &CompareOptions::default())
+            .await
+            .unwrap();
+
+        assert!(report.missing_in_api.is_empty());
+        assert!(report.missing_locally.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_api_error_envelope_is_returned() {
+        let server = MockServer::start().await;
+        Mock::given(path("/getArtists"))
+            .respond_with(subsonic_failed(40, "Wrong username or password"))
+            .mount(&server)
+            .await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        store_albums(&db, "beatles", &["Help"]);
+
+        let settings = ApiSettings::new(&server.uri(), "user", "wrong");
+        let error = compare_with_api(&db, &settings, &CompareOptions::default())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, CompareError::ApiError { code: 40, .. }));
+    }
+
+    #[tokio::test]
+    async fn test_artist_error_envelope_does_not_report_albums_missing() {
+        let server = MockServer::start().await;
+        mock_artists(
+            &server,
+            json!([{ "id": "1", "name": "Beatles", "albumCount": 1 }]),
+        )
+        .await;
+        Mock::given(path("/getArtist"))
+            .respond_with(subsonic_failed(70, "Artist not found"))
+            .mount(&server)
+            .await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        store_albums(&db, "beatles", &["Help", "Revolver"]);
+
+        let settings = ApiSettings::new(&server.uri(), "user", "password");
+        let report = compare_with_api(&db, &settings, &CompareOptions::default())
+            .await
+            .unwrap();
+
+        assert!(report.missing_in_api.is_empty());
+        assert_eq!(
+            report.failed_artists,
+            [FailedArtist {
+                artist: "Beatles".to_string(),
+                error: "API error (70): Artist not found".to_string(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_artists_are_compared_concurrently_with_a_stable_result() {
+        const ARTISTS: usize = 12;
+        const DELAY: Duration = Duration::from_millis(200);
+        let server = MockServer::start().await;
+        let artists: Vec<Value> = (0..ARTISTS)
+            .map(|i| {
+                let name = format!("Artist {i:02}");
+                json!({ "id": i.to_string(), "name": name, "albumCount": 1 })
+            })
+            .collect();
+        mock_artists(&server, Value::Array(artists)).await;
+        let db = MemoryStore::new();
+        for i in 0..ARTISTS {
+            // Later artists answer first, so they finish in reverse order.
+            let albums =
+                json!({ "artist": { "id": i.to_string(), "album": [{ "name": "Debut" }] } });
+            let delay = DELAY + Duration::from_millis(10 * (ARTISTS - i) as u64);
+            Mock::given(path("/getArtist"))
+                .and(query_param("id", i.to_string()))
+                .respond_with(subsonic_ok(albums).set_delay(delay))
+                .mount(&server)
+                .await;
+            store_albums(&db, &format!("artist {i:02}"), &["Debut", "Second"]);
+        }
+        let settings = ApiSettings {
+            compare_concurrency: ARTISTS,
+            ..ApiSettings::new(&server.uri(), "user", "password")
+        };
+        let started = std::time::Instant::now();
+        let report = compare_with_api(&db, &settings, &CompareOptions::default())
+            .await
+            .unwrap();
+
+        assert!(started.elapsed() < DELAY * ARTISTS as u32 / 2);
+        let expected: Vec<String> = (0..ARTISTS)
+            .map(|i| format!("/library/artist {i:02}/Second"))
+            .collect();
+        assert_eq!(report.missing_in_api, expected);
+        assert!(report.failed_artists.is_empty());
+    }
+
+    async fn compare_album_variants(fuzzy_album_match: bool) -> ComparisonReport {
+        let server = MockServer::start().await;
+        mock_artists(
+            &server,
+            json!([{ "id": "1", "name": "Beatles", "albumCount": 2 }]),
+        )
+        .await;
+        mock_artist_albums(&server, "1", &["Album Live", "sgt peppers LONELY hearts"]).await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        store_albums(
+            &db,
+            "beatles",
+            &["Album: Live!", "Sgt. Pepper's Lonely Hearts", "Help"],
+        );
+
+        let options = CompareOptions {
+            fuzzy_album_match,
+            ..CompareOptions::default()
+        };
+        let settings = ApiSettings::new(&server.uri(), "user", "password");
+        compare_with_api(&db, &settings, &options).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_albums_cleaned_to_the_same_name_keep_their_paths() {
+        let server = MockServer::start().await;
+        mock_artists(
+            &server,
+            json!([{ "id": "1", "name": "Beatles", "albumCount": 1 }]),
+        )
+        .await;
+        mock_artist_albums(&server, "1", &["Revolver"]).await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let albums = ["Revolver", "Anthology [CD1]", "Anthology [CD2]"]
+            .iter()
+            .map(|folder| AlbumData {
+                name: AlbumNameCleaner::default().clean(folder),
+                path: format!("Beatles/{folder}"),
+                ..AlbumData::default()
+            })
+            .collect::<Vec<_>>();
+        store_artist_data(&db, "beatles", albums.len(), 0, albums).unwrap();
+
+        let settings = ApiSettings::new(&server.uri(), "user", "password");
+        let report = compare_with_api(&db, &settings, &CompareOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            report.missing_in_api,
+            vec!["Beatles/Anthology [CD1]", "Beatles/Anthology [CD2]"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_albums_with_the_same_musicbrainz_id_match() {
+        let server = MockServer::start().await;
+        mock_artists(
+            &server,
+            json!([{ "id": "1", "name": "Beatles", "albumCount": 2 }]),
+        )
+        .await;
+        Mock::given(path("/getArtist"))
+            .and(query_param("id", "1"))
+            .respond_with(subsonic_ok(json!({ "artist": { "id": "1", "album": [
+                { "name": "Abbey Road (2019 Mix)", "musicBrainzId": "mbid-abbey-road" },
+                { "name": "Let It Be", "musicBrainzId": "" },
+            ] } })))
+            .mount(&server)
+            .await;
+
+        let db = MemoryStore::new();
+        let albums = vec![AlbumData {
+            name: "Abbey Road".to_string(),
+            path: "beatles/Abbey Road".to_string(),
+            musicbrainz_id: Some("mbid-abbey-road".to_string()),
+            ..AlbumData::default()
+        }];
+        store_artist_data(&db, "beatles", 1, 0, albums).unwrap();
+        let settings = ApiSettings::new(&server.uri(), "user", "password");
+        let missing_locally = |report: &ComparisonReport| {
+            report
+                .missing_locally
+                .iter()
+                .map(|album| album.album.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let options = CompareOptions {
+            match_musicbrainz_ids: true,
+            full: true,
+            ..CompareOptions::default()
+        };
+        let report = compare_with_api(&db, &settings, &options).await.unwrap();
+        assert!(report.missing_in_api.is_empty());
+        assert_eq!(missing_locally(&report), ["Let It Be"]);
+
+        // Without the IDs, the names differ.
+        let options = CompareOptions {
+            full: true,
+            ..CompareOptions::default()
+        };
+        let report = compare_with_api(&db, &settings, &options).await.unwrap();
+        assert_eq!(report.missing_in_api, ["beatles/Abbey Road"]);
+        assert_eq!(
+            missing_locally(&report),
+            ["Abbey Road (2019 Mix)", "Let It Be"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fuzzy_album_match_ignores_case_and_punctuation() {
+        let report = compare_album_variants(true).await;
+
+        assert_eq!(report.missing_in_api, vec!["/library/beatles/Help"]);
+        assert!(report.missing_locally.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_exact_album_match_by_default() {
+        let report = compare_album_variants(false).await;
+
+        assert_eq!(
+            report.missing_in_api,
+            vec![
+                "/library/beatles/Album: Live!",
+                "/library/beatles/Help",
+                "/library/beatles/Sgt. Pepper's Lonely Hearts"
+            ]
+        );
+        let missing_locally: Vec<_> = report
+            .missing_locally
+            .iter()
+            .map(|album| album.album.as_str())
+            .collect();
+        assert_eq!(
+            missing_locally,
+            vec!["Album Live", "sgt peppers LONELY hearts"]
+        );
+    }
+
+    async fn artist_fetches_with_cache(ttl_secs: u64, refresh: bool) -> usize {
+        let server = MockServer::start().await;
+        mock_artists(
+            &server,
+            json!([{ "id": "1", "name": "Beatles", "albumCount": 1 }]),
+        )
+        .await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        store_albums(&db, "beatles", &["Revolver"]);
+
+        let settings = ApiSettings {
+            artist_cache_ttl_secs: Some(ttl_secs),
+            ..ApiSettings::new(&server.uri(), "user", "password")
+        };
+        let options = CompareOptions {
+            refresh,
+            ..CompareOptions::default()
+        };
+        compare_with_api(&db, &settings, &options).await.unwrap();
+        compare_with_api(&db, &settings, &options).await.unwrap();
+
+        server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|request| request.url.path() == "/getArtists")
+            .count()
+    }
+
+    #[tokio::test]
+    async fn test_artist_list_is_cached_within_ttl() {
+        assert_eq!(artist_fetches_with_cache(3600, false).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_bypasses_artist_cache() {
+        assert_eq!(artist_fetches_with_cache(3600, true).await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_expired_artist_cache_is_refetched() {
+        assert_eq!(artist_fetches_with_cache(0, false).await, 2);
+    }
+
+    #[test]
+    fn test_artist_cache_key_depends_on_the_request() {
+        let settings = ApiSettings::new("http://music.local", "user", "password");
+        assert_eq!(
+            artist_cache_key(&settings),
+            "artists:http://music.local:Auto:json"
+        );
+
+        let other = ApiSettings {
+            artist_endpoint: ArtistEndpoint::GetIndexes,
+            response_format: ResponseFormat::Xml,
+            music_folder_id: Some(3),
+            ..ApiSettings::new("http://music.local", "user", "password")
+        };
+        assert_eq!(
+            artist_cache_key(&other),
+            "artists:http://music.local:GetIndexes:xml:3"
+        );
+    }
+
+    async fn album_fetches_across_runs(full: bool) -> usize {
+        let server = MockServer::start().await;
+        mock_artists(
+            &server,
+            json!([{ "id": "1", "name": "Beatles", "albumCount": 2 }]),
+        )
+        .await;
+        mock_artist_albums(&server, "1", &["Revolver", "Help"]).await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        store_albums(&db, "beatles", &["Revolver"]);
+
+        let settings = ApiSettings::new(&server.uri(), "user", "password");
+        let options = CompareOptions {
+            full,
+            ..CompareOptions::default()
+        };
+        let first = compare_with_api(&db, &settings, &options).await.unwrap();
+        let second = compare_with_api(&db, &settings, &options).await.unwrap();
+        assert_eq!(first.missing_locally, second.missing_locally);
+
+        server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|request| request.url.path() == "/getArtist")
+            .count()
+    }
+
+    #[tokio::test]
+    async fn test_unchanged_counts_skip_artist_comparison() {
+        assert_eq!(album_fetches_across_runs(false).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_full_compares_unchanged_counts() {
+        assert_eq!(album_fetches_across_runs(true).await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_album_set_always_compares_artists_with_matching_counts() {
+        let server = MockServer::start().await;
+        mock_artists(
+            &server,
+            json!([{ "id": "1", "name": "Beatles", "albumCount": 2 }]),
+        )
+        .await;
+        mock_artist_albums(&server, "1", &["Revolver", "Abbey Road"]).await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        store_albums(&db, "beatles", &["Revolver", "Help"]);
+
+        let settings = ApiSettings::new(&server.uri(), "user", "password");
+        let by_count = compare_with_api(&db, &settings, &CompareOptions::default())
+            .await
+            .unwrap();
+        assert!(by_count.missing_in_api.is_empty());
+
+        let options = CompareOptions {
+            comparison_mode: ComparisonMode::AlbumSetAlways,
+            ..CompareOptions::default()
+        };
+        let report = compare_with_api(&db, &settings, &options).await.unwrap();
+        assert_eq!(report.missing_in_api, ["/library/beatles/Help"]);
+        assert_eq!(report.missing_locally[0].album, "Abbey Road");
+    }
+
+    #[tokio::test]
+    async fn test_compare_with_api_on_memory_store() {
+        let server = MockServer::start().await;
+        mock_artists(
+            &server,
+            json!([{ "id": "1", "name": "Beatles", "albumCount": 1 }]),
+        )
+        .await;
+        mock_artist_albums(&server, "1", &["Abbey Road"]).await;
+
+        let store = MemoryStore::new();
+        store_albums(&store, "beatles", &["Revolver", "Abbey Road"]);
+
+        let settings = ApiSettings::new(&server.uri(), "user", "password");
+        let report = compare_with_api(&store, &settings, &CompareOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(report.missing_in_api, vec!["/library/beatles/Revolver"]);
+    }
+
+    #[test]
+    fn test_client_pool_uses_configured_settings() {
+        let settings = ApiSettings {
+            pool_max_idle_per_host: Some(4),
+            pool_idle_timeout_secs: Some(30),
+            ..ApiSettings::new("http://localhost", "user", "password")
+        };
+
+        assert_eq!(
+            PoolOptions::from_settings(&settings),
+            PoolOptions {
+                max_idle_per_host: 4,
+                idle_timeout: Duration::from_secs(30),
+                http2_prior_knowledge: false,
+            }
+        );
+        assert_eq!(
+            PoolOptions::from_settings(&ApiSettings::new("http://localhost", "user", "password"))
+                .max_idle_per_host,
+            usize::MAX
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pooled_client_compares_many_artists() {
+        const ARTISTS: usize = 200;
+        let server = MockServer::start().await;
+        let artists: Vec<Value> = (0..ARTISTS)
+            .map(|i| json!({ "id": i.to_string(), "name": format!("Artist {i}"), "albumCount": 1 }))
+            .collect();
+        mock_artists(&server, json!(artists)).await;
+        Mock::given(path("/getArtist"))
+            .respond_with(subsonic_ok(
+                json!({ "artist": { "album": [{ "name": "Remote" }] } }),
+            ))
+            .mount(&server)
+            .await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        for i in 0..ARTISTS {
+            store_albums(&db, &format!("artist {i}"), &["Local", "Other"]);
+        }
+
+        let settings = ApiSettings {
+            pool_max_idle_per_host: Some(1),
+            ..ApiSettings::new(&server.uri(), "user", "password")
+        };
+        let report = compare_with_api(&db, &settings, &CompareOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(report.missing_in_api.len(), 2 * ARTISTS);
+        assert_eq!(report.missing_locally.len(), ARTISTS);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_request_is_retried() {
+        let server = MockServer::start().await;
+        Mock::given(path("/getArtists"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        mock_artists(
+            &server,
+            json!([{ "id": "1", "name": "Beatles", "albumCount": 1 }]),
+        )
+        .await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        store_albums(&db, "beatles", &["Revolver"]);
+
+        let settings = ApiSettings::new(&server.uri(), "user", "password");
+        let report = compare_with_api(&db, &settings, &CompareOptions::default()).await;
+
+        assert!(report.unwrap().missing_in_api.is_empty());
+        let artist_requests = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|request| request.url.path() == "/getArtists")
+            .count();
+        assert_eq!(artist_requests, 2);
+    }
+
+    #[tokio::test]
+    async fn test_negotiated_api_version_is_sent_with_later_requests() {
+        let server = MockServer::start().await;
+        Mock::given(path("/ping"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "subsonic-response": { "status": "ok", "version": "1.13.0" }
+            })))
+            .mount(&server)
+            .await;
+        mock_artists(
+            &server,
+            json!([{ "id": "1", "name": "Beatles", "albumCount": 1 }]),
+        )
+        .await;
+        mock_artist_albums(&server, "1", &["Revolver"]).await;
+
+        let db = MemoryStore::new();
+        store_albums(&db, "beatles", &["Revolver", "Help"]);
+        let settings = ApiSettings::new(&server.uri(), "user", "password");
+        compare_with_api(&db, &settings, &CompareOptions::default())
+            .await
+            .unwrap();
+
+        let versions: Vec<(String, String)> = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(|request| {
+                let version = request
+                    .url
+                    .query_pairs()
+                    .find(|(key, _)| key == "v")
+                    .map(|(_, value)| value.into_owned())
+                    .unwrap_or_default();
+                (request.url.path().to_string(), version)
+            })
+            .collect();
+        assert_eq!(
+            versions,
+            [
+                ("/ping".to_string(), CLIENT_API_VERSION.to_string()),
+                ("/getArtists".to_string(), "1.13.0".to_string()),
+                ("/getArtist".to_string(), "1.13.0".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_negotiated_api_version_is_the_older_one() {
+        assert_eq!(negotiated_api_version("1.13.0"), "1.13.0");
+        assert_eq!(negotiated_api_version("1.16.1"), "1.16.1");
+        assert_eq!(negotiated_api_version("1.17.0"), CLIENT_API_VERSION);
+        assert_eq!(negotiated_api_version("1.9.10"), "1.9.10");
+        assert_eq!(negotiated_api_version("unknown"), CLIENT_API_VERSION);
+    }
+
+    #[test]
+    fn test_retry_delay_is_capped() {
+        assert_eq!(retry_delay(Some("5"), 0), 5);
+        assert_eq!(retry_delay(Some("86400"), 0), MAX_BACKOFF_SECS);
+        assert_eq!(retry_delay(None, 2), 4);
+        assert_eq!(retry_delay(Some("soon"), 10), MAX_BACKOFF_SECS);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_gives_up_after_max_retries() {
+        let server = MockServer::start().await;
+        Mock::given(path("/getArtists"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let settings = ApiSettings {
+            max_retries: 2,
+            ..ApiSettings::new(&server.uri(), "user", "password")
+        };
+        let result = compare_with_api(&db, &settings, &CompareOptions::default()).await;
+
+        assert!(matches!(
+            result,
+            Err(CompareError::RateLimited { retries: 2 })
+        ));
+    }
+
+    async fn artists_request_folder_ids(music_folder_id: Option<u32>) -> Vec<Option<String>> {
+        let server = MockServer::start().await;
+        Mock::given(path("/getMusicFolders"))
+            .respond_with(subsonic_ok(json!({
+                "musicFolders": { "musicFolder": [
+                    { "id": 1, "name": "Music" },
+                    { "id": 2, "name": "Podcasts" }
+                ] }
+            })))
+            .mount(&server)
+            .await;
+        mock_artists(
+            &server,
+            json!([{ "id": "1", "name": "Beatles", "albumCount": 1 }]),
+        )
+        .await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let settings = ApiSettings {
+            music_folder_id,
+            ..ApiSettings::new(&server.uri(), "user", "password")
+        };
+        compare_with_api(&db, &settings, &CompareOptions::default())
+            .await
+            .unwrap();
+
+        server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|request| request.url.path() == "/getArtists")
+            .map(|request| {
+                request
+                    .url
+                    .query_pairs()
+                    .find(|(key, _)| key == "musicFolderId")
+                    .map(|(_, value)| value.into_owned())
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_music_folder_id_is_only_sent_when_configured() {
+        assert_eq!(artists_request_folder_ids(None).await, vec![None]);
+        assert_eq!(
+            artists_request_folder_ids(Some(1)).await,
+            vec![Some("1".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unknown_music_folder_is_an_error() {
+        let server = MockServer::start().await;
+        Mock::given(path("/getMusicFolders"))
+            .respond_with(subsonic_ok(json!({
+                "musicFolders": { "musicFolder": [{ "id": 1, "name": "Music" }] }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(path("/getArtists"))
+            .respond_with(subsonic_ok(json!({ "artists": { "index": [] } })))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let settings = ApiSettings {
+            music_folder_id: Some(7),
+            ..ApiSettings::new(&server.uri(), "user", "password")
+        };
+        let result = compare_with_api(&db, &settings, &CompareOptions::default()).await;
+
+        assert!(matches!(
+            result,
+            Err(CompareError::MusicFolderNotFound { id: 7 })
+        ));
+    }
+
+    async fn compare_with_indexes_fallback(get_artists: ResponseTemplate) -> ComparisonReport {
+        let server = MockServer::start().await;
+        Mock::given(path("/getArtists"))
+            .respond_with(get_artists)
+            .mount(&server)
+            .await;
+        mock_indexes(&server, json!([{ "id": "dir-1", "name": "Beatles" }])).await;
+        mock_music_directory(&server, "dir-1", &["Abbey Road [2009]"]).await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        store_albums(&db, "beatles", &["Abbey Road", "Help"]);
+
+        let settings = ApiSettings::new(&server.uri(), "user", "password");
+        compare_with_api(&db, &settings, &CompareOptions::default())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_get_indexes_fallback_when_get_artists_is_empty() {
+        let report =
+            compare_with_indexes_fallback(subsonic_ok(json!({ "artists": { "index": [] } }))).await;
+
+        assert_eq!(report.missing_in_api, vec!["/library/beatles/Help"]);
+        assert!(report.missing_locally.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_indexes_fallback_when_get_artists_is_unsupported() {
+        let report = compare_with_indexes_fallback(subsonic_ok(json!({
+            "status": "failed",
+            "error": { "code": 70, "message": "Requested resource not found" }
+        })))
+        .await;
+
+        assert_eq!(report.missing_in_api, vec!["/library/beatles/Help"]);
+    }
+
+    #[tokio::test]
+    async fn test_configured_artist_endpoint_is_used_alone() {
+        let server = MockServer::start().await;
+        Mock::given(path("/getArtists"))
+            .respond_with(subsonic_ok(json!({ "artists": { "index": [] } })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(path("/getIndexes"))
+            .respond_with(subsonic_ok(json!({ "indexes": { "index": [] } })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        for artist_endpoint in [ArtistEndpoint::GetArtists, ArtistEndpoint::GetIndexes] {
+            let settings = ApiSettings {
+                artist_endpoint,
+                ..ApiSettings::new(&server.uri(), "user", "password")
+            };
+            let report = compare_with_api(&db, &settings, &CompareOptions::default()).await;
+            assert!(report.unwrap().missing_in_api.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compare_with_servers_reports_each_server() {
+        let home = MockServer::start().await;
+        mock_artists(
+            &home,
+            json!([{ "id": "1", "name": "Beatles", "albumCount": 1 }]),
+        )
+        .await;
+        mock_artist_albums(&home, "1", &["Revolver"]).await;
+
+        let vps = MockServer::start().await;
+        mock_artists(
+            &vps,
+            json!([{ "id": "9", "name": "Beatles", "albumCount": 0 }]),
+        )
+        .await;
+        mock_artist_albums(&vps, "9", &[]).await;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        store_albums(&db, "beatles", &["Revolver", "Help"]);
+
+        let home_api = ApiSettings::new(&home.uri(), "user", "password");
+        let vps_api = ApiSettings::new(&vps.uri(), "user", "password");
+        let remote = RemoteSettings::default();
+        let servers = [
+            Server {
+                name: "home",
+                api: &home_api,
+                remote: &remote,
+            },
+            Server {
+                name: "vps",
+                api: &vps_api,
+                remote: &remote,
+            },
+        ];
+
+        let reports = compare_with_servers(&db, &servers, &CompareOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].server, "home");
+        assert_eq!(reports[0].missing_in_api, vec!["/library/beatles/Help"]);
+        assert_eq!(reports[1].server, "vps");
+        assert_eq!(
+            reports[1].missing_in_api,
+            vec!["/library/beatles/Help", "/library/beatles/Revolver"]
+        );
+    }
+}
@@ -5,7 +5,19 @@ use std::{fmt, io};
 pub enum CompareError {
     IoError(io::Error),
     JsonParseError(serde_json::Error),
-    ApiError { code: i32, message: String },
+    XmlParseError(quick_xml::Error),
+    ApiError {
+        code: i32,
+        message: String,
+    },
+    /// The server kept answering HTTP 429 after every retry.
+    RateLimited {
+        retries: u32,
+    },
+    /// The configured `music_folder_id` isn't one of the server's music folders.
+    MusicFolderNotFound {
+        id: u32,
+    },
     ReqwestError(ReqwestError),
     Other(String),
     DatabaseError(sled::Error),
@@ -16,9 +28,20 @@ impl fmt::Display for CompareError {
         match self {
             CompareError::IoError(e) => write!(f, "IO error: {}", e),
             CompareError::JsonParseError(e) => write!(f, "JSON parse error: {}", e),
+            CompareError::XmlParseError(e) => write!(f, "XML parse error: {}", e),
             CompareError::ApiError { code, message } => {
                 write!(f, "API error ({}): {}", code, message)
             }
+            CompareError::RateLimited { retries } => {
+                write!(
+                    f,
+                    "Rate limited by the API, gave up after {} retries",
+                    retries
+                )
+            }
+            CompareError::MusicFolderNotFound { id } => {
+                write!(f, "Music folder {} not found on the server", id)
+            }
             CompareError::ReqwestError(e) => write!(f, "Reqwest error: {}", e),
             CompareError::Other(s) => write!(f, "Other error: {}", s),
             CompareError::DatabaseError(s) => write!(f, "Database error: {}", s),
@@ -38,6 +61,12 @@ impl From<serde_json::Error> for CompareError {
     }
 }
 
+impl From<quick_xml::Error> for CompareError {
+    fn from(error: quick_xml::Error) -> Self {
+        CompareError::XmlParseError(error)
+    }
+}
+
 impl From<ReqwestError> for CompareError {
     fn from(error: ReqwestError) -> Self {
         CompareError::ReqwestError(error)
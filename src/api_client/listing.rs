@@ -0,0 +1,288 @@
+//! This module compares the local library with the directory structure of the remote
+//! host, listed over ssh, for servers whose API is unreachable or untrusted. Only
+//! `remote_path/<artist>/<album>` folders are looked at, the layout uploads create, so
+//! the comparison is read-only and never talks to the Subsonic API.
+
+use crate::api_client::upload::{build_ssh_command, normalize_remote_dir, shell_quote};
+use crate::api_client::{CompareError, CompareOptions, ComparisonReport, RemoteAlbum};
+use crate::configuration::RemoteSettings;
+use crate::foundation::database::{artist_keys, export_database, ArtistStore};
+use crate::foundation::utils::canonical_album_name;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::process::Command;
+
+/// The album folders of each artist folder of the remote library, by artist folder name.
+pub type RemoteListing = BTreeMap<String, BTreeSet<String>>;
+
+/// Builds the ssh command listing the artist and album folders of the remote library.
+///
+/// # Arguments
+///
+/// * `settings` - Remote settings holding the host and the root of the remote library.
+///
+pub fn build_listing_command(settings: &RemoteSettings) -> Command {
+    let remote_dir = match normalize_remote_dir(&settings.remote_path) {
+        dir if dir.is_empty() => "/".to_string(),
+        dir => dir,
+    };
+    build_ssh_command(
+        &format!(
+            "find {} -mindepth 1 -maxdepth 2 -type d",
+            shell_quote(&remote_dir)
+        ),
+        settings,
+    )
+}
+
+/// Parses the output of the listing command into the album folders of each artist.
+///
+/// Lines outside `remote_path` and hidden folders are skipped. An artist folder without
+/// albums is kept with an empty set.
+///
+/// # Arguments
+///
+/// * `output` - The output of `find`, one directory per line.
+/// * `remote_path` - The root of the remote library the directories were listed from.
+///
+pub fn parse_listing(output: &str, remote_path: &str) -> RemoteListing {
+    let prefix = format!("{}/", normalize_remote_dir(remote_path));
+    let mut listing = RemoteListing::new();
+
+    for line in output.lines() {
+        let Some(relative) = line.trim_end_matches('\r').strip_prefix(&prefix) else {
+            continue;
+        };
+        let parts: Vec<&str> = relative
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .collect();
+        if parts.iter().any(|part| part.starts_with('.')) {
+            continue;
+        }
+        match parts[..] {
+            [artist] => {
+                listing.entry(artist.to_string()).or_default();
+            }
+            [artist, album] => {
+                listing
+                    .entry(artist.to_string())
+                    .or_default()
+                    .insert(album.to_string());
+            }
+            _ => {}
+        }
+    }
+    listing
+}
+
+/// Compares the local library with the folders of the remote host, listed over ssh.
+///
+/// Remote artist folders are matched with local artists on their key, and album folders
+/// with local albums on their cleaned name, as with the API. Local artists without a
+/// remote folder have all their albums reported missing. Albums missing locally are
+/// only reported for artists the local library has, and have no artist ID.
+///
+/// # Arguments
+///
+/// * `db` - A reference to the local database.
+/// * `settings` - Remote settings holding the host and the root of the remote library.
+/// * `options` - Options controlling the comparison.
+///
+pub fn compare_with_listing(
+    db: &dyn ArtistStore,
+    settings: &RemoteSettings,
+    options: &CompareOptions,
+) -> Result<ComparisonReport, CompareError> {
+    let output = build_listing_command(settings).output()?;
+    if !output.status.success() {
+        return Err(CompareError::Other(format!(
+            "Failed to list {} on {}: ssh exited with status {}: {}",
+            settings.remote_path,
+            settings.remote_host,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let listing = parse_listing(
+        &String::from_utf8_lossy(&output.stdout),
+        &settings.remote_path,
+    );
+    compare_listing(db, &listing, options)
+}
+
+/// Compares the local library with a parsed remote listing.
+fn compare_listing(
+    db: &dyn ArtistStore,
+    listing: &RemoteListing,
+    options: &CompareOptions,
+) -> Result<ComparisonReport, CompareError> {
+    // Albums are matched on their key, but reported with their original name.
+    let album_key = |name: &str| {
+        if options.fuzzy_album_match {
+            canonical_album_name(name)
+        } else {
+            name.to_string()
+        }
+    };
+
+    let keys = artist_keys(db)?;
+    let mut remote_artists = HashMap::new();
+    for (artist, albums) in listing {
+        remote_artists.insert(keys.key(artist), (artist, albums));
+    }
+
+    let mut missing_in_api = Vec::new();
+    let mut missing_locally = Vec::new();
+    for (key, data) in export_database(db)? {
+        let Some((artist, albums)) = remote_artists.get(&key) else {
+            println!(
+                "\x1b[33mNo remote folder found for artist '{}'\x1b[0m",
+                data.display_name
+            );
+            missing_in_api.extend(data.albums.iter().map(|album| album.path.clone()));
+            continue;
+        };
+
+        let mut remote_albums: HashMap<String, String> = HashMap::new();
+        for name in albums.iter() {
+            let name = options.album_names.clean(name);
+            remote_albums.entry(album_key(&name)).or_insert(name);
+        }
+        let local_keys: BTreeSet<String> = data
+            .albums
+            .iter()
+            .map(|album| album_key(&album.name))
+            .collect();
+
+        missing_in_api.extend(
+            data.albums
+                .iter()
+                .filter(|album| !remote_albums.contains_key(&album_key(&album.name)))
+                .map(|album| album.path.clone()),
+        );
+        missing_locally.extend(
+            remote_albums
+                .into_iter()
+                .filter(|(key, _)| !local_keys.contains(key))
+                .map(|(_, album)| RemoteAlbum {
+                    artist: artist.to_string(),
+                    album,
+                    artist_id: String::new(),
+                    local_artist: data.display_name.clone(),
+                }),
+        );
+    }
+
+    missing_in_api.sort();
+    missing_in_api.dedup();
+    missing_locally.sort();
+
+    Ok(ComparisonReport {
+        server: String::new(),
+        missing_in_api,
+        missing_locally,
+        api_requests: 0,
+        failed_artists: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::foundation::database::{store_artist_data, AlbumData, MemoryStore};
+
+    #[test]
+    fn test_listing_is_parsed_into_artists_and_albums() {
+        let output = "/srv/music/Radiohead\n\
+                      /srv/music/Radiohead/OK Computer\n\
+                      /srv/music/Radiohead/Kid A\r\n\
+                      /srv/music/Empty Artist\n\
+                      /srv/music/.thumbnails\n\
+                      /srv/music/.thumbnails/cache\n\
+                      /elsewhere/Artist\n";
+
+        let listing = parse_listing(output, "/srv//music/");
+
+        let expected: RemoteListing = [
+            ("Empty Artist", vec![]),
+            ("Radiohead", vec!["Kid A", "OK Computer"]),
+        ]
+        .into_iter()
+        .map(|(artist, albums)| {
+            (
+                artist.to_string(),
+                albums.into_iter().map(str::to_string).collect(),
+            )
+        })
+        .collect();
+        assert_eq!(listing, expected);
+    }
+
+    #[test]
+    fn test_listing_is_compared_with_the_local_library() {
+        let db = MemoryStore::new();
+        for (artist, albums) in [
+            ("Radiohead", ["OK Computer", "Amnesiac"]),
+            ("Muse", ["Absolution", "Origin of Symmetry"]),
+        ] {
+            let albums: Vec<AlbumData> = albums
+                .iter()
+                .map(|album| AlbumData {
+                    name: album.to_string(),
+                    path: format!("{artist}/{album}"),
+                    ..AlbumData::default()
+                })
+                .collect();
+            store_artist_data(&db, artist, albums.len(), 0, albums).unwrap();
+        }
+        let listing = parse_listing(
+            "/music/radiohead\n/music/radiohead/OK Computer\n/music/radiohead/Kid A [2000]\n",
+            "/music",
+        );
+
+        let report = compare_listing(&db, &listing, &CompareOptions::default()).unwrap();
+
+        assert_eq!(
+            report.missing_in_api,
+            [
+                "Muse/Absolution",
+                "Muse/Origin of Symmetry",
+                "Radiohead/Amnesiac"
+            ]
+        );
+        assert_eq!(
+            report.missing_locally,
+            [RemoteAlbum {
+                artist: "radiohead".to_string(),
+                album: "Kid A".to_string(),
+                artist_id: String::new(),
+                local_artist: "Radiohead".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_listing_command() {
+        let settings = RemoteSettings {
+            remote_user: "user".to_string(),
+            remote_host: "example.com".to_string(),
+            remote_path: "/srv/my music/".to_string(),
+            ..RemoteSettings::default()
+        };
+
+        let command = build_listing_command(&settings);
+
+        let args: Vec<_> = command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(command.get_program(), "ssh");
+        assert_eq!(
+            args,
+            [
+                "user@example.com",
+                "find '/srv/my music' -mindepth 1 -maxdepth 2 -type d"
+            ]
+        );
+    }
+}
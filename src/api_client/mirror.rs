@@ -0,0 +1,368 @@
+//! This module deletes, over ssh, the remote albums that the local library doesn't
+//! have, for users who want the server to mirror their library exactly.
+//!
+//! Remote albums are expected at `remote_path/<artist folder>/<album>`, the layout
+//! uploads create: the artist folder is named after the parent folder of the local
+//! albums of the artist, and the album after the name reported by the server.
+
+use crate::api_client::upload::{
+    build_ssh_command, extract_artist_and_album, normalize_remote_dir, shell_quote,
+};
+use crate::api_client::RemoteAlbum;
+use crate::configuration::RemoteSettings;
+use crate::foundation::database::{artist_keys, get_artist_data_by_key, ArtistStore};
+use crate::foundation::utils::canonical_album_name;
+use crate::observer::SyncObserver;
+use std::path::Path;
+use std::process::Command;
+use std::{fs, io};
+
+/// Printed by the delete command when it removed the album directory.
+const DELETED_MARKER: &str = "musync-deleted";
+
+/// A remote album to delete, with the remote folder of its artist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MirroredAlbum {
+    pub album: RemoteAlbum,
+    /// Name of the artist folder the albums of the artist were uploaded to.
+    pub artist_folder: String,
+}
+
+/// What deleting the remote albums missing locally did.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct MirrorSummary {
+    pub deleted: usize,
+    /// Albums left on the server: unsafe names, or no remote folder at the expected path.
+    pub skipped: usize,
+    /// Albums whose deletion failed.
+    pub failed: usize,
+}
+
+/// Returns the remote albums missing locally to delete, with their remote artist folder.
+///
+/// Albums are matched with the scanned artist they were compared with, as stored in
+/// `db`. An album is kept on the server when that artist has an album of the same name,
+/// compared like [`canonical_album_name`], or a folder of that name next to its stored
+/// albums, such as one left out of the library by `validate_audio`. Albums of artists
+/// that aren't stored, or have no stored album to tell their remote folder, are kept
+/// too. Each kept album is reported to `observer`.
+///
+/// # Arguments
+///
+/// * `db` - The scanned library, or any other [`ArtistStore`].
+/// * `local_path` - The root of the local library, which album paths are relative to.
+/// * `missing_locally` - Albums the server has but the local library doesn't.
+/// * `observer` - Receives a warning for each kept album.
+///
+pub fn albums_to_mirror(
+    db: &dyn ArtistStore,
+    local_path: &Path,
+    missing_locally: Vec<RemoteAlbum>,
+    observer: &dyn SyncObserver,
+) -> io::Result<Vec<MirroredAlbum>> {
+    let keys = artist_keys(db)?;
+    let mut albums = Vec::new();
+    for album in missing_locally {
+        let keep = |reason: &str| {
+            observer.warn(&format!(
+                "Keeping {} - {} on the server: {}",
+                album.artist, album.album, reason
+            ));
+        };
+        let Some(data) = get_artist_data_by_key(db, &keys.key(&album.local_artist))? else {
+            keep("its artist isn't in the scanned library");
+            continue;
+        };
+
+        let album_name = canonical_album_name(&album.album);
+        let album_paths: Vec<_> = data
+            .albums
+            .iter()
+            .map(|stored| stored.full_path(local_path))
+            .collect();
+        let has_local_album = data
+            .albums
+            .iter()
+            .any(|stored| canonical_album_name(&stored.name) == album_name)
+            || album_paths
+                .iter()
+                .filter_map(|path| path.parent())
+                .any(|artist_path| has_folder_named(artist_path, &album_name));
+        if has_local_album {
+            keep("it has a local folder");
+            continue;
+        }
+
+        // Uploads name the remote artist folder after the parent folder of the album.
+        let artist_folder = album_paths.iter().find_map(|path| {
+            extract_artist_and_album(&path.to_string_lossy())
+                .ok()
+                .map(|(artist, _)| artist)
+        });
+        match artist_folder {
+            Some(artist_folder) => albums.push(MirroredAlbum {
+                album,
+                artist_folder,
+            }),
+            None => keep("the remote folder of its artist is unknown"),
+        }
+    }
+    Ok(albums)
+}
+
+/// Returns `true` if `dir` has a folder whose name is `name` once canonicalized.
+fn has_folder_named(dir: &Path, name: &str) -> bool {
+    fs::read_dir(dir).is_ok_and(|entries| {
+        entries.filter_map(Result::ok).any(|entry| {
+            entry.path().is_dir()
+                && canonical_album_name(&entry.file_name().to_string_lossy()) == name
+        })
+    })
+}
+
+/// Builds the ssh command removing the remote directory of `album`.
+///
+/// Artist folder and album names that are empty, `.`, `..` or contain a `/`, such as
+/// `Live/Dead`, are rejected with an `InvalidInput` error, so a command never removes
+/// anything but an album directory. The directory is only removed when it exists, and
+/// the command then prints `DELETED_MARKER`, so an album that isn't at the expected path
+/// isn't reported deleted.
+///
+/// # Arguments
+///
+/// * `album` - An album the server has but the local library doesn't.
+/// * `settings` - Remote settings holding the host and the root of the remote library.
+///
+pub fn build_remote_delete_command(
+    album: &MirroredAlbum,
+    settings: &RemoteSettings,
+) -> io::Result<Command> {
+    for name in [&album.artist_folder, &album.album.album] {
+        if name.trim().is_empty() || name == "." || name == ".." || name.contains('/') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Refusing to delete a remote album named '{}'", name),
+            ));
+        }
+    }
+
+    let remote_dir = format!(
+        "{}/{}/{}",
+        normalize_remote_dir(&settings.remote_path),
+        album.artist_folder,
+        album.album.album
+    );
+    Ok(build_ssh_command(
+        &format!(
+            "if test -d {0}; then rm -rf -- {0} && echo {1}; fi",
+            shell_quote(&remote_dir),
+            DELETED_MARKER
+        ),
+        settings,
+    ))
+}
+
+/// Deletes the remote directory of each album, in order.
+///
+/// An album that can't be deleted doesn't stop the others: albums with unsafe names,
+/// or whose directory doesn't exist on the remote host, are skipped, and failed
+/// deletions are counted. Each of them is reported to `observer`.
+///
+pub async fn delete_remote_albums(
+    albums: &[MirroredAlbum],
+    settings: &RemoteSettings,
+    observer: &dyn SyncObserver,
+) -> MirrorSummary {
+    let mut summary = MirrorSummary::default();
+    for album in albums {
+        let label = format!("{} - {}", album.album.artist, album.album.album);
+        let command = match build_remote_delete_command(album, settings) {
+            Ok(command) => command,
+            Err(e) => {
+                observer.warn(&format!("Not deleting {}: {}", label, e));
+                summary.skipped += 1;
+                continue;
+            }
+        };
+        let output = tokio::process::Command::from(command)
+            .kill_on_drop(true)
+            .output()
+            .await;
+        match output {
+            Ok(output) if !output.status.success() => {
+                observer.warn(&format!(
+                    "Failed to delete {}: ssh exited with status {}",
+                    label, output.status
+                ));
+                summary.failed += 1;
+            }
+            Ok(output) if String::from_utf8_lossy(&output.stdout).contains(DELETED_MARKER) => {
+                println!("\x1b[33mDeleted from the remote host: {}\x1b[0m", label);
+                summary.deleted += 1;
+            }
+            Ok(_) => {
+                observer.warn(&format!(
+                    "Not deleted, no remote folder at the expected path: {}",
+                    label
+                ));
+                summary.skipped += 1;
+            }
+            Err(e) => {
+                observer.warn(&format!("Failed to delete {}: {}", label, e));
+                summary.failed += 1;
+            }
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::foundation::database::{store_artist_data, AlbumData, MemoryStore};
+    use crate::observer::tests::RecordingObserver;
+    use tempfile::TempDir;
+
+    fn album(artist: &str, album: &str) -> RemoteAlbum {
+        RemoteAlbum {
+            artist: artist.to_string(),
+            album: album.to_string(),
+            artist_id: "1".to_string(),
+            local_artist: artist.to_string(),
+        }
+    }
+
+    fn mirrored(artist_folder: &str, name: &str) -> MirroredAlbum {
+        MirroredAlbum {
+            album: album(artist_folder, name),
+            artist_folder: artist_folder.to_string(),
+        }
+    }
+
+    fn stored_album(path: &str) -> AlbumData {
+        AlbumData {
+            name: Path::new(path)
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned(),
+            path: path.to_string(),
+            ..AlbumData::default()
+        }
+    }
+
+    #[test]
+    fn test_remote_delete_command() {
+        let settings = RemoteSettings {
+            remote_user: "user".to_string(),
+            remote_host: "example.com".to_string(),
+            remote_path: "/music/".to_string(),
+            ssh_port: Some(2222),
+            ..RemoteSettings::default()
+        };
+
+        let command =
+            build_remote_delete_command(&mirrored("Guns N' Roses", "Appetite"), &settings).unwrap();
+
+        let args: Vec<_> = command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(command.get_program(), "ssh");
+        assert_eq!(
+            args,
+            [
+                "-p",
+                "2222",
+                "user@example.com",
+                r"if test -d '/music/Guns N'\'' Roses/Appetite'; then rm -rf -- '/music/Guns N'\'' Roses/Appetite' && echo musync-deleted; fi"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remote_delete_rejects_unsafe_names() {
+        let settings = RemoteSettings::default();
+
+        for (artist, name) in [
+            ("Artist", ""),
+            ("..", "Album"),
+            ("Artist", "a/b"),
+            (".", "x"),
+        ] {
+            let error = build_remote_delete_command(&mirrored(artist, name), &settings)
+                .err()
+                .unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unsafe_names_are_skipped_without_stopping_the_others() {
+        let observer = RecordingObserver::default();
+
+        let summary = delete_remote_albums(
+            &[mirrored("Artist", "Live/Dead"), mirrored("..", "Album")],
+            &RemoteSettings::default(),
+            &observer,
+        )
+        .await;
+
+        assert_eq!(
+            summary,
+            MirrorSummary {
+                deleted: 0,
+                skipped: 2,
+                failed: 0
+            }
+        );
+        assert_eq!(observer.events().len(), 2);
+        assert!(observer.events()[0].starts_with("warn Not deleting Artist - Live/Dead"));
+    }
+
+    #[test]
+    fn test_albums_to_mirror_uses_the_scanned_library() {
+        let temp_dir = TempDir::new().unwrap();
+        let air = temp_dir.path().join("Electronic").join("Air");
+        fs::create_dir_all(air.join("Moon Safari")).unwrap();
+        // Left out of the scan, e.g. by `validate_audio`, but still there.
+        fs::create_dir_all(air.join("Talkie Walkie")).unwrap();
+        let db = MemoryStore::new();
+        store_artist_data(
+            &db,
+            "Air",
+            1,
+            0,
+            vec![stored_album("Electronic/Air/Moon Safari")],
+        )
+        .unwrap();
+        store_artist_data(&db, "Muse", 0, 0, Vec::new()).unwrap();
+        let observer = RecordingObserver::default();
+
+        let albums = albums_to_mirror(
+            &db,
+            temp_dir.path(),
+            vec![
+                album("Air", "MOON SAFARI!"),
+                album("Air", "Talkie Walkie"),
+                album("Air", "Pocket Symphony"),
+                album("Muse", "Absolution"),
+                album("Blur", "Parklife"),
+            ],
+            &observer,
+        )
+        .unwrap();
+
+        // The artist nested in a genre folder is found, and named after its folder.
+        assert_eq!(albums, [mirrored("Air", "Pocket Symphony")]);
+        assert_eq!(
+            observer.events(),
+            [
+                "warn Keeping Air - MOON SAFARI! on the server: it has a local folder",
+                "warn Keeping Air - Talkie Walkie on the server: it has a local folder",
+                "warn Keeping Muse - Absolution on the server: the remote folder of its artist is unknown",
+                "warn Keeping Blur - Parklife on the server: its artist isn't in the scanned library",
+            ]
+        );
+    }
+}
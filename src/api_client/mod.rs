@@ -1,7 +1,31 @@
 mod compare;
 mod compare_error;
+mod listing;
+mod mirror;
+mod pending;
+mod report;
+mod rsync;
+mod snapshot;
+mod tar;
 mod upload;
+mod xml;
 
 pub use compare::*;
 pub use compare_error::CompareError;
-pub use upload::upload_missing_albums;
+pub use listing::{build_listing_command, compare_with_listing, parse_listing, RemoteListing};
+pub use mirror::{
+    albums_to_mirror, build_remote_delete_command, delete_remote_albums, MirrorSummary,
+    MirroredAlbum,
+};
+pub use pending::{
+    upload_queued_albums, upload_queued_with, PendingQueue, PendingUploads, PENDING_FILE,
+};
+pub use report::{reported_albums, sort_albums, ReportedAlbum};
+pub use snapshot::{
+    diff_reports, latest_snapshots, save_snapshot, MissingOnServer, Snapshot, SnapshotDiff,
+    MAX_SNAPSHOTS, SNAPSHOTS_DIR,
+};
+pub use upload::{
+    ensure_remote_space, upload_albums_with, upload_missing_albums, validate_progress_style,
+    AlbumUploader, ScpUploader, UploadSummary,
+};
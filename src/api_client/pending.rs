@@ -0,0 +1,357 @@
+//! This module keeps the queue of albums still to upload on disk, so a sync interrupted
+//! in the middle of its uploads can be resumed without scanning and comparing again.
+//! The queue is rewritten after each uploaded album and removed once it is empty.
+
+use crate::api_client::upload::{
+    ensure_remote_space, upload_albums_with, AlbumUploader, ScpUploader, SshMaster, UploadSummary,
+};
+use crate::configuration::RemoteSettings;
+use crate::observer::{ConsoleObserver, SyncObserver};
+use crate::process::ArtistOutcome;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+/// File, inside the configuration folder, holding the albums still to upload.
+pub const PENDING_FILE: &str = "pending.json";
+
+/// Albums still to upload, by server name.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct PendingUploads {
+    pub servers: BTreeMap<String, Vec<String>>,
+}
+
+/// The upload queue of a sync, persisted to a JSON file.
+pub struct PendingQueue {
+    path: PathBuf,
+    uploads: Mutex<PendingUploads>,
+}
+
+impl PendingQueue {
+    /// Loads the queue stored at `path`. A missing file is an empty queue.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let uploads = match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid {}: {}", path.display(), e),
+                )
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => PendingUploads::default(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            uploads: Mutex::new(uploads),
+        })
+    }
+
+    /// Creates the queue of a new sync at `path`, replacing any previous one.
+    pub fn create(path: &Path, mut uploads: PendingUploads) -> io::Result<Self> {
+        uploads.servers.retain(|_, albums| !albums.is_empty());
+        save(path, &uploads)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            uploads: Mutex::new(uploads),
+        })
+    }
+
+    /// Number of albums left to upload, across all servers.
+    pub fn len(&self) -> usize {
+        self.uploads().servers.values().map(Vec::len).sum()
+    }
+
+    /// Returns `true` when there is nothing left to upload.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Names of the servers with albums left to upload.
+    pub fn servers(&self) -> Vec<String> {
+        self.uploads().servers.keys().cloned().collect()
+    }
+
+    /// Albums left to upload to `server`.
+    pub fn albums(&self, server: &str) -> Vec<String> {
+        self.uploads()
+            .servers
+            .get(server)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Removes an uploaded album from the queue and saves it. The file is deleted once
+    /// the queue is empty.
+    pub fn complete(&self, server: &str, album_path: &str) -> io::Result<()> {
+        let mut uploads = self.uploads();
+        if let Some(albums) = uploads.servers.get_mut(server) {
+            albums.retain(|album| album != album_path);
+            if albums.is_empty() {
+                uploads.servers.remove(server);
+            }
+        }
+        save(&self.path, &uploads)
+    }
+
+    fn uploads(&self) -> MutexGuard<'_, PendingUploads> {
+        self.uploads.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Writes `uploads` to `path`, or removes the file when there is nothing left to upload.
+///
+/// The queue is written to a temporary file first, so an interruption never leaves a
+/// truncated queue behind.
+fn save(path: &Path, uploads: &PendingUploads) -> io::Result<()> {
+    if uploads.servers.is_empty() {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+
+    let json = serde_json::to_string_pretty(uploads).map_err(io::Error::other)?;
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, json)?;
+    fs::rename(&temp_path, path)
+}
+
+/// Uploads the queued albums of `server` with scp, removing each one from the queue once
+/// it has been uploaded. With `ssh_multiplex`, the uploads share one ssh connection.
+///
+/// # Arguments
+///
+/// * `queue` - The upload queue of the sync.
+/// * `server` - Name of the server whose albums are uploaded.
+/// * `settings` - Remote settings of that server.
+///
+pub async fn upload_queued_albums(
+    queue: &PendingQueue,
+    server: &str,
+    settings: &RemoteSettings,
+) -> io::Result<UploadSummary> {
+    let observer = ConsoleObserver::with_progress_style(settings.progress_style.as_ref())?;
+    let _master = SshMaster::start(settings).await;
+    ensure_remote_space(&queue.albums(server), settings).await?;
+    Ok(upload_queued_with(
+        queue,
+        server,
+        settings,
+        &ScpUploader::new(settings),
+        &observer,
+    )
+    .await)
+}
+
+/// Runs [`upload_albums_with`] on the queued albums of `server`, keeping the queue up
+/// to date as albums are uploaded. Failed albums stay in the queue.
+pub async fn upload_queued_with(
+    queue: &PendingQueue,
+    server: &str,
+    settings: &RemoteSettings,
+    uploader: &dyn AlbumUploader,
+    observer: &dyn SyncObserver,
+) -> UploadSummary {
+    let observer = QueueObserver {
+        queue,
+        server,
+        inner: observer,
+    };
+    upload_albums_with(&queue.albums(server), settings, uploader, &observer).await
+}
+
+/// Forwards events to another observer, removing uploaded albums from the queue.
+struct QueueObserver<'a> {
+    queue: &'a PendingQueue,
+    server: &'a str,
+    inner: &'a dyn SyncObserver,
+}
+
+impl SyncObserver for QueueObserver<'_> {
+    fn on_scan_started(&self) {
+        self.inner.on_scan_started();
+    }
+
+    fn on_artist_scanned(&self, artist: &str, outcome: &ArtistOutcome) {
+        self.inner.on_artist_scanned(artist, outcome);
+    }
+
+    fn on_scan_finished(&self, scanned: usize) {
+        self.inner.on_scan_finished(scanned);
+    }
+
+    fn on_upload_started(&self, album_count: usize) {
+        self.inner.on_upload_started(album_count);
+    }
+
+    fn on_album_upload_started(&self, artist: &str, album: &str) {
+        self.inner.on_album_upload_started(artist, album);
+    }
+
+    fn on_album_upload_progress(&self, percent: u64) {
+        self.inner.on_album_upload_progress(percent);
+    }
+
+    fn on_album_upload_file(&self, file: &str) {
+        self.inner.on_album_upload_file(file);
+    }
+
+    fn on_upload_done(&self, album_path: &str, result: &io::Result<()>) {
+        if result.is_ok() {
+            if let Err(e) = self.queue.complete(self.server, album_path) {
+                self.inner
+                    .warn(&format!("Failed to update the pending upload queue: {e}"));
+            }
+        }
+        self.inner.on_upload_done(album_path, result);
+    }
+
+    fn on_uploads_finished(&self, summary: &UploadSummary) {
+        self.inner.on_uploads_finished(summary);
+    }
+
+    fn warn(&self, message: &str) {
+        self.inner.warn(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_client::upload::MockAlbumUploader;
+    use crate::observer::tests::RecordingObserver;
+    use mockall::predicate::*;
+    use tempfile::TempDir;
+
+    fn queue_of(albums: &[&str]) -> PendingUploads {
+        PendingUploads {
+            servers: BTreeMap::from([(
+                "default".to_string(),
+                albums.iter().map(|album| album.to_string()).collect(),
+            )]),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resume_only_uploads_remaining_albums() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(PENDING_FILE);
+        let settings = RemoteSettings {
+            remote_user: "user".to_string(),
+            remote_host: "example.com".to_string(),
+            remote_path: "/music".to_string(),
+            ..RemoteSettings::default()
+        };
+
+        // The first run is interrupted after uploading its first album.
+        let queue = PendingQueue::create(
+            &path,
+            queue_of(&["/library/Artist/Album1", "/library/Artist/Album2"]),
+        )
+        .unwrap();
+        queue.complete("default", "/library/Artist/Album1").unwrap();
+        drop(queue);
+
+        let resumed = PendingQueue::load(&path).unwrap();
+        assert_eq!(resumed.albums("default"), ["/library/Artist/Album2"]);
+
+        let mut uploader = MockAlbumUploader::new();
+        uploader
+            .expect_upload()
+            .with(eq("/library/Artist/Album2"), always(), always())
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        let summary = upload_queued_with(
+            &resumed,
+            "default",
+            &settings,
+            &uploader,
+            &RecordingObserver::default(),
+        )
+        .await;
+
+        assert_eq!(summary.succeeded, 1);
+        assert!(resumed.is_empty());
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_failed_uploads_stay_queued() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(PENDING_FILE);
+        let queue = PendingQueue::create(
+            &path,
+            queue_of(&["/library/Artist/Album1", "/library/Artist/Album2"]),
+        )
+        .unwrap();
+
+        let mut uploader = MockAlbumUploader::new();
+        uploader
+            .expect_upload()
+            .with(eq("/library/Artist/Album1"), always(), always())
+            .returning(|_, _, _| Err(io::Error::other("connection refused")));
+        uploader.expect_upload().returning(|_, _, _| Ok(()));
+        upload_queued_with(
+            &queue,
+            "default",
+            &RemoteSettings::default(),
+            &uploader,
+            &RecordingObserver::default(),
+        )
+        .await;
+
+        let stored = PendingQueue::load(&path).unwrap();
+        assert_eq!(stored.albums("default"), ["/library/Artist/Album1"]);
+    }
+
+    #[tokio::test]
+    async fn test_queue_that_cant_be_saved_is_reported() {
+        let temp_dir = TempDir::new().unwrap();
+        let queue_dir = temp_dir.path().join("config");
+        fs::create_dir(&queue_dir).unwrap();
+        let queue = PendingQueue::create(
+            &queue_dir.join(PENDING_FILE),
+            queue_of(&["/library/Artist/Album1", "/library/Artist/Album2"]),
+        )
+        .unwrap();
+        fs::remove_dir_all(&queue_dir).unwrap();
+
+        let mut uploader = MockAlbumUploader::new();
+        uploader
+            .expect_upload()
+            .with(eq("/library/Artist/Album1"), always(), always())
+            .returning(|_, _, _| Ok(()));
+        uploader
+            .expect_upload()
+            .returning(|_, _, _| Err(io::Error::other("connection refused")));
+        let observer = RecordingObserver::default();
+        let summary = upload_queued_with(
+            &queue,
+            "default",
+            &RemoteSettings::default(),
+            &uploader,
+            &observer,
+        )
+        .await;
+
+        assert_eq!(summary.succeeded, 1);
+        assert!(observer
+            .events()
+            .iter()
+            .any(|event| event.starts_with("warn Failed to update the pending upload queue:")));
+    }
+
+    #[test]
+    fn test_missing_queue_file_is_empty() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let queue = PendingQueue::load(&temp_dir.path().join(PENDING_FILE)).unwrap();
+
+        assert!(queue.is_empty());
+    }
+}
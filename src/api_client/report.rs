@@ -0,0 +1,143 @@
+//! This module orders the albums listed in comparison reports, as chosen with the
+//! `report_sort` setting, so two runs over the same library print the same lists.
+
+use crate::api_client::upload::format_bytes;
+use crate::configuration::ReportSort;
+use crate::foundation::database::{export_database, ArtistStore};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::{fmt, io};
+
+/// A local album missing on a server, as listed in a report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportedAlbum {
+    pub artist: String,
+    pub album: String,
+    /// Path of the album directory, as stored: relative to the library root.
+    pub path: String,
+    /// Size of every file in the album directory, in bytes.
+    pub size: u64,
+}
+
+impl fmt::Display for ReportedAlbum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} - {} ({})",
+            self.artist,
+            self.album,
+            format_bytes(self.size)
+        )
+    }
+}
+
+/// Sorts `albums` in the order chosen by `sort`.
+///
+/// Names are compared ignoring case. Albums of the same size are sorted by artist, and
+/// items without a size (`None`) are sorted last.
+///
+/// # Arguments
+///
+/// * `albums` - The albums to sort.
+/// * `sort` - The order to sort them in.
+/// * `key` - Returns the artist, album name and size of an album.
+///
+pub fn sort_albums<T>(
+    albums: &mut [T],
+    sort: ReportSort,
+    key: impl Fn(&T) -> (&str, &str, Option<u64>),
+) {
+    albums.sort_by_cached_key(|album| {
+        let (artist, name, size) = key(album);
+        let (artist, name) = (artist.to_lowercase(), name.to_lowercase());
+        match sort {
+            ReportSort::Artist => (None, artist, name),
+            ReportSort::Album => (None, name, artist),
+            ReportSort::Size => (Some(Reverse(size)), artist, name),
+        }
+    });
+}
+
+/// Returns the stored artist, name and size of the albums at `paths`, sorted by `sort`.
+///
+/// A path that isn't stored is listed with an empty artist, its path as name and no
+/// size.
+///
+/// # Arguments
+///
+/// * `db` - A reference to the local database.
+/// * `paths` - Album paths, as stored in the database.
+/// * `sort` - The order to sort the albums in.
+///
+pub fn reported_albums(
+    db: &dyn ArtistStore,
+    paths: &[String],
+    sort: ReportSort,
+) -> io::Result<Vec<ReportedAlbum>> {
+    let mut stored = HashMap::new();
+    for (_, artist) in export_database(db)? {
+        for album in artist.albums {
+            stored.insert(album.path.clone(), (artist.display_name.clone(), album));
+        }
+    }
+
+    let mut albums: Vec<ReportedAlbum> = paths
+        .iter()
+        .map(|path| match stored.get(path) {
+            Some((artist, album)) => ReportedAlbum {
+                artist: artist.clone(),
+                album: album.name.clone(),
+                path: path.clone(),
+                size: album.total_bytes,
+            },
+            None => ReportedAlbum {
+                artist: String::new(),
+                album: path.clone(),
+                path: path.clone(),
+                size: 0,
+            },
+        })
+        .collect();
+    sort_albums(&mut albums, sort, |album| {
+        (&album.artist, &album.album, Some(album.size))
+    });
+    Ok(albums)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_albums_are_sorted_by_each_option() {
+        let albums = [
+            ("Radiohead", "Kid A", Some(300)),
+            ("beatles", "Abbey Road", Some(100)),
+            ("Beatles", "Revolver", None),
+            ("Air", "Moon Safari", Some(300)),
+        ];
+        let sorted = |sort| {
+            let mut sorted = albums.to_vec();
+            sort_albums(&mut sorted, sort, |&(artist, album, size)| {
+                (artist, album, size)
+            });
+            sorted
+                .into_iter()
+                .map(|(_, album, _)| album)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            sorted(ReportSort::Artist),
+            ["Moon Safari", "Abbey Road", "Revolver", "Kid A"]
+        );
+        assert_eq!(
+            sorted(ReportSort::Album),
+            ["Abbey Road", "Kid A", "Moon Safari", "Revolver"]
+        );
+        assert_eq!(
+            sorted(ReportSort::Size),
+            ["Moon Safari", "Kid A", "Abbey Road", "Revolver"]
+        );
+    }
+}
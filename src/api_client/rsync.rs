@@ -0,0 +1,443 @@
+//! This module provides the rsync transfers used to resume uploads that scp couldn't
+//! finish, and to upload several albums of an artist at once.
+//!
+//! rsync keeps partially transferred files (`--partial`), so an album interrupted by a
+//! dropped connection doesn't restart from zero.
+
+use crate::api_client::upload::{scp_progress_regex, shell_quote, ssh_options, wait_with_timeout};
+use crate::configuration::RemoteSettings;
+use crate::observer::SyncObserver;
+use std::io;
+use std::process::{Command, Stdio};
+
+/// Returns `true` if an `rsync` binary can be run.
+pub(super) fn is_available() -> bool {
+    Command::new("rsync")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Builds the rsync command used to upload a single album.
+///
+/// The contents of the album directory are synced into `remote_path`, keeping partial
+/// files. ssh is invoked with the same identity, port and options as scp. The bandwidth
+/// limit is converted from Kbit/s to the KB/s expected by `--bwlimit`. Only the files
+/// passing the upload extension filters are transferred, see [`extension_filters`].
+///
+/// # Arguments
+///
+/// * `album_path` - The local path of the album to be uploaded.
+/// * `remote_path` - The `user@host:path` destination of the album.
+/// * `settings` - A reference to the RemoteSettings containing the SSH and transfer options.
+///
+pub(super) fn build_rsync_command(
+    album_path: &str,
+    remote_path: &str,
+    settings: &RemoteSettings,
+) -> Command {
+    rsync_command(
+        album_path,
+        remote_path,
+        &extension_filters(settings),
+        settings,
+    )
+}
+
+/// Builds the rsync command uploading several albums of an artist in one transfer.
+///
+/// The artist directory is synced into `remote_path`, the remote artist directory,
+/// with filters limiting the transfer to the given album directories.
+///
+/// # Arguments
+///
+/// * `artist_path` - The local path of the artist directory.
+/// * `albums` - Names of the album directories to upload.
+/// * `remote_path` - The `user@host:path` destination of the artist.
+/// * `settings` - A reference to the RemoteSettings containing the SSH and transfer options.
+///
+pub(super) fn build_rsync_artist_command(
+    artist_path: &str,
+    albums: &[String],
+    remote_path: &str,
+    settings: &RemoteSettings,
+) -> Command {
+    rsync_command(
+        artist_path,
+        remote_path,
+        &album_filters(albums, settings),
+        settings,
+    )
+}
+
+/// Builds the filters transferring only `albums` out of an artist directory: each
+/// album directory and its contents are included, and everything else is excluded.
+///
+/// Album names are anchored to the top of the transfer and their wildcard characters
+/// escaped, so an album named `Live [2001]` only matches itself. With upload extension
+/// filters, only the album files passing them are included.
+pub(super) fn album_filters(albums: &[String], settings: &RemoteSettings) -> Vec<String> {
+    let mut filters = exclude_extension_filters(settings);
+    let included = included_extension_patterns(settings);
+    for album in albums {
+        let pattern = escape_filter_pattern(album);
+        filters.push(format!("--include=/{}/", pattern));
+        if included.is_empty() {
+            filters.push(format!("--include=/{}/**", pattern));
+        } else {
+            filters.push(format!("--include=/{}/**/", pattern));
+            filters.extend(
+                included
+                    .iter()
+                    .map(|extension| format!("--include=/{}/**.{}", pattern, extension)),
+            );
+        }
+    }
+    filters.push("--exclude=*".to_string());
+    if !included.is_empty() {
+        filters.push("--prune-empty-dirs".to_string());
+    }
+    filters
+}
+
+/// Builds the filters leaving out the files with the extensions of
+/// `upload_exclude_extensions` and, when `upload_include_extensions` is set, the files
+/// with other extensions. Folders left empty aren't created. Empty without filters.
+pub(super) fn extension_filters(settings: &RemoteSettings) -> Vec<String> {
+    let mut filters = exclude_extension_filters(settings);
+    let included = included_extension_patterns(settings);
+    if !included.is_empty() {
+        filters.push("--include=*/".to_string());
+        filters.extend(
+            included
+                .iter()
+                .map(|extension| format!("--include=*.{}", extension)),
+        );
+        filters.push("--exclude=*".to_string());
+        filters.push("--prune-empty-dirs".to_string());
+    }
+    filters
+}
+
+fn exclude_extension_filters(settings: &RemoteSettings) -> Vec<String> {
+    settings
+        .upload_exclude_extensions
+        .iter()
+        .map(|extension| format!("--exclude=*.{}", extension_pattern(extension)))
+        .collect()
+}
+
+fn included_extension_patterns(settings: &RemoteSettings) -> Vec<String> {
+    settings
+        .upload_include_extensions
+        .iter()
+        .map(|extension| extension_pattern(extension))
+        .collect()
+}
+
+/// Returns a pattern matching `extension` ignoring case, as rsync patterns are case
+/// sensitive: `Flac` becomes `[fF][lL][aA][cC]`.
+fn extension_pattern(extension: &str) -> String {
+    let mut pattern = String::new();
+    for c in extension.trim_start_matches('.').chars() {
+        let (lower, upper) = (c.to_ascii_lowercase(), c.to_ascii_uppercase());
+        if lower != upper {
+            pattern.push_str(&format!("[{}{}]", lower, upper));
+        } else {
+            pattern.push_str(&escape_filter_pattern(&c.to_string()));
+        }
+    }
+    pattern
+}
+
+fn escape_filter_pattern(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if matches!(c, '\\' | '*' | '?' | '[') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn rsync_command(
+    source_dir: &str,
+    remote_path: &str,
+    filters: &[String],
+    settings: &RemoteSettings,
+) -> Command {
+    let mut command = Command::new("rsync");
+    command.args(["-a", "--partial", "--info=progress2,name1"]);
+
+    let ssh_command = std::iter::once("ssh".to_string())
+        .chain(
+            ssh_options(settings)
+                .iter()
+                .map(|option| shell_quote(option)),
+        )
+        .collect::<Vec<_>>()
+        .join(" ");
+    command.arg("-e").arg(ssh_command);
+
+    if let Some(limit) = settings.bandwidth_limit_kbps {
+        command.arg(format!("--bwlimit={}", (limit / 8).max(1)));
+    }
+
+    command.args(filters);
+    command.arg(format!("{}/", source_dir.trim_end_matches('/')));
+    command.arg(remote_path);
+    command
+}
+
+/// Uploads a single album with rsync, reporting the progress and the files parsed from
+/// its output.
+///
+/// # Arguments
+///
+/// * `album_path` - The local path of the album to be uploaded.
+/// * `remote_path` - The `user@host:path` destination of the album.
+/// * `settings` - A reference to the RemoteSettings containing the SSH and transfer options.
+/// * `observer` - Receives the upload percentage and the file being transferred.
+///
+pub(super) async fn upload_album(
+    album_path: &str,
+    remote_path: &str,
+    settings: &RemoteSettings,
+    observer: &dyn SyncObserver,
+) -> io::Result<()> {
+    run_rsync(
+        build_rsync_command(album_path, remote_path, settings),
+        settings,
+        observer,
+    )
+    .await
+}
+
+/// Uploads several albums of an artist with a single rsync, reporting the overall
+/// progress and the files parsed from its output.
+///
+/// # Arguments
+///
+/// * `artist_path` - The local path of the artist directory.
+/// * `albums` - Names of the album directories to upload.
+/// * `remote_path` - The `user@host:path` destination of the artist.
+/// * `settings` - A reference to the RemoteSettings containing the SSH and transfer options.
+/// * `observer` - Receives the upload percentage and the file being transferred.
+///
+pub(super) async fn upload_artist(
+    artist_path: &str,
+    albums: &[String],
+    remote_path: &str,
+    settings: &RemoteSettings,
+    observer: &dyn SyncObserver,
+) -> io::Result<()> {
+    run_rsync(
+        build_rsync_artist_command(artist_path, albums, remote_path, settings),
+        settings,
+        observer,
+    )
+    .await
+}
+
+/// Returns the file named by a line of rsync output, if any.
+///
+/// With `--info=name1`, rsync prints the path of each file it transfers on a line of its
+/// own, before the progress lines of that file. Directories, which end with a `/`, and the
+/// indented progress lines are ignored.
+fn rsync_file_name(line: &str) -> Option<&str> {
+    let line = line.trim_end();
+    if line.is_empty()
+        || line.starts_with(char::is_whitespace)
+        || line.ends_with('/')
+        || line == "sending incremental file list"
+    {
+        return None;
+    }
+    Some(line)
+}
+
+async fn run_rsync(
+    command: Command,
+    settings: &RemoteSettings,
+    observer: &dyn SyncObserver,
+) -> io::Result<()> {
+    let re = scp_progress_regex();
+    let mut child = tokio::process::Command::from(command)
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let stdout = child.stdout.take();
+
+    // rsync separates progress updates with carriage returns.
+    let status = wait_with_timeout(
+        &mut child,
+        stdout,
+        b'\r',
+        settings.upload_timeout(),
+        &mut |chunk| {
+            // File names end with a newline, so a chunk may hold several lines.
+            for line in chunk.lines() {
+                if let Some(file) = rsync_file_name(line) {
+                    observer.on_album_upload_file(file);
+                } else if let Some(percent) = re
+                    .captures(line)
+                    .and_then(|cap| cap.get(1))
+                    .and_then(|m| m.as_str().parse::<u64>().ok())
+                {
+                    observer.on_album_upload_progress(percent);
+                }
+            }
+        },
+    )
+    .await?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "rsync command failed with status: {}",
+            status
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command_args(command: &Command) -> Vec<String> {
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_rsync_command() {
+        let settings = RemoteSettings {
+            remote_user: String::from("user"),
+            remote_host: String::from("example.com"),
+            remote_path: String::from("/music"),
+            ssh_key_path: Some(String::from("/keys/my key")),
+            ssh_port: Some(2222),
+            bandwidth_limit_kbps: Some(8000),
+            ..RemoteSettings::default()
+        };
+
+        let command = build_rsync_command("/library/A/B", "user@example.com:/music/A/B", &settings);
+
+        assert_eq!(command.get_program(), "rsync");
+        assert_eq!(
+            command_args(&command),
+            [
+                "-a",
+                "--partial",
+                "--info=progress2,name1",
+                "-e",
+                "ssh '-i' '/keys/my key' '-p' '2222'",
+                "--bwlimit=1000",
+                "/library/A/B/",
+                "user@example.com:/music/A/B"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_album_filters_for_three_albums() {
+        let albums = [
+            String::from("Revolver"),
+            String::from("Let It Be"),
+            String::from("Live [1964]"),
+        ];
+
+        assert_eq!(
+            album_filters(&albums, &RemoteSettings::default()),
+            [
+                "--include=/Revolver/",
+                "--include=/Revolver/**",
+                "--include=/Let It Be/",
+                "--include=/Let It Be/**",
+                "--include=/Live \\[1964]/",
+                "--include=/Live \\[1964]/**",
+                "--exclude=*",
+            ]
+        );
+
+        let command = build_rsync_artist_command(
+            "/library/Beatles",
+            &albums,
+            "user@example.com:/music/Beatles",
+            &RemoteSettings::default(),
+        );
+        let args = command_args(&command);
+        assert_eq!(
+            args[args.len() - 3..],
+            [
+                "--exclude=*",
+                "/library/Beatles/",
+                "user@example.com:/music/Beatles"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_upload_extension_filters() {
+        let settings = RemoteSettings {
+            upload_include_extensions: vec!["flac".to_string(), ".mp3".to_string()],
+            upload_exclude_extensions: vec!["log".to_string()],
+            ..RemoteSettings::default()
+        };
+
+        let command = build_rsync_command("/library/A/B", "user@example.com:/music/A/B", &settings);
+        assert_eq!(
+            command_args(&command)[5..],
+            [
+                "--exclude=*.[lL][oO][gG]",
+                "--include=*/",
+                "--include=*.[fF][lL][aA][cC]",
+                "--include=*.[mM][pP]3",
+                "--exclude=*",
+                "--prune-empty-dirs",
+                "/library/A/B/",
+                "user@example.com:/music/A/B"
+            ]
+        );
+
+        assert_eq!(
+            album_filters(&[String::from("Live [1964]")], &settings),
+            [
+                "--exclude=*.[lL][oO][gG]",
+                "--include=/Live \\[1964]/",
+                "--include=/Live \\[1964]/**/",
+                "--include=/Live \\[1964]/**.[fF][lL][aA][cC]",
+                "--include=/Live \\[1964]/**.[mM][pP]3",
+                "--exclude=*",
+                "--prune-empty-dirs",
+            ]
+        );
+
+        let settings = RemoteSettings {
+            upload_exclude_extensions: vec!["cue".to_string()],
+            ..RemoteSettings::default()
+        };
+        assert_eq!(extension_filters(&settings), ["--exclude=*.[cC][uU][eE]"]);
+        assert!(extension_filters(&RemoteSettings::default()).is_empty());
+    }
+
+    #[test]
+    fn test_rsync_file_name() {
+        let output = "sending incremental file list\nAbbey Road/\nAbbey Road/01 - Come Together.flac\n     32,768   0%    0.00kB/s    0:00:00";
+        let files: Vec<_> = output.lines().filter_map(rsync_file_name).collect();
+        assert_eq!(files, ["Abbey Road/01 - Come Together.flac"]);
+
+        for line in [
+            "  1,234,567  42%   1.20MB/s    0:00:10 (xfr#1, to-chk=3/5)",
+            "",
+            "CD1/",
+        ] {
+            assert_eq!(rsync_file_name(line), None, "{line}");
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! This module keeps a snapshot of the comparison reports of each sync, so two runs can
+//! be compared to see which albums went missing on a server and which were resolved.
+//! Snapshots are JSON files named after the time they were taken; only the most recent
+//! ones are kept.
+
+use crate::api_client::ComparisonReport;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Folder, inside the configuration folder, holding the snapshots.
+pub const SNAPSHOTS_DIR: &str = "snapshots";
+
+/// Number of snapshots kept; older ones are removed when a new one is saved.
+pub const MAX_SNAPSHOTS: usize = 30;
+
+const SNAPSHOT_PREFIX: &str = "snapshot-";
+
+/// The comparison reports of a sync.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Snapshot {
+    /// When the snapshot was taken, as a Unix timestamp in seconds.
+    pub taken_at: u64,
+    pub reports: Vec<ComparisonReport>,
+}
+
+/// An album missing on a server.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MissingOnServer {
+    pub server: String,
+    /// Path of the local album.
+    pub path: String,
+}
+
+/// What changed between two snapshots.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SnapshotDiff {
+    /// Albums missing on a server in the newer snapshot but not in the older one.
+    pub newly_missing: Vec<MissingOnServer>,
+    /// Albums missing on a server in the older snapshot but not anymore.
+    pub resolved: Vec<MissingOnServer>,
+}
+
+impl SnapshotDiff {
+    /// Returns `true` when nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.newly_missing.is_empty() && self.resolved.is_empty()
+    }
+}
+
+/// Compares the albums missing on each server between two sets of reports.
+///
+/// Both lists are sorted by server, then path.
+///
+/// # Arguments
+///
+/// * `older` - The reports of the earlier sync.
+/// * `newer` - The reports of the later sync.
+///
+pub fn diff_reports(older: &[ComparisonReport], newer: &[ComparisonReport]) -> SnapshotDiff {
+    let older = missing_on_servers(older);
+    let newer = missing_on_servers(newer);
+
+    SnapshotDiff {
+        newly_missing: newer.difference(&older).cloned().collect(),
+        resolved: older.difference(&newer).cloned().collect(),
+    }
+}
+
+fn missing_on_servers(reports: &[ComparisonReport]) -> BTreeSet<MissingOnServer> {
+    reports
+        .iter()
+        .flat_map(|report| {
+            report.missing_in_api.iter().map(|path| MissingOnServer {
+                server: report.server.clone(),
+                path: path.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Saves `snapshot` in `dir`, then removes the oldest snapshots beyond `max_snapshots`.
+///
+/// Snapshots taken in the same second get increasing counters in their file name, so
+/// none overwrites another.
+///
+/// # Returns
+///
+/// The path of the saved snapshot.
+///
+pub fn save_snapshot(dir: &Path, snapshot: &Snapshot, max_snapshots: usize) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let json = serde_json::to_string_pretty(snapshot).map_err(io::Error::other)?;
+    let mut counter = 0;
+    let (path, mut file) = loop {
+        // Zero-padded so file names sort in the order the snapshots were taken.
+        let path = dir.join(format!(
+            "{}{:020}-{:04}.json",
+            SNAPSHOT_PREFIX, snapshot.taken_at, counter
+        ));
+        match fs::File::create_new(&path) {
+            Ok(file) => break (path, file),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => counter += 1,
+            Err(e) => return Err(e),
+        }
+    };
+    file.write_all(json.as_bytes())?;
+
+    let snapshots = snapshot_paths(dir)?;
+    for old in snapshots
+        .iter()
+        .take(snapshots.len().saturating_sub(max_snapshots))
+    {
+        fs::remove_file(old)?;
+    }
+    Ok(path)
+}
+
+/// Loads the last `count` snapshots of `dir`, oldest first. A missing folder has no
+/// snapshots.
+pub fn latest_snapshots(dir: &Path, count: usize) -> io::Result<Vec<Snapshot>> {
+    let paths = snapshot_paths(dir)?;
+    paths[paths.len().saturating_sub(count)..]
+        .iter()
+        .map(|path| {
+            let json = fs::read_to_string(path)?;
+            serde_json::from_str(&json).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid {}: {}", path.display(), e),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Paths of the snapshots of `dir`, oldest first.
+fn snapshot_paths(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let is_snapshot = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with(SNAPSHOT_PREFIX) && name.ends_with(".json"));
+        if is_snapshot {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn report(server: &str, missing_in_api: &[&str]) -> ComparisonReport {
+        ComparisonReport {
+            server: server.to_string(),
+            missing_in_api: missing_in_api.iter().map(|path| path.to_string()).collect(),
+            ..ComparisonReport::default()
+        }
+    }
+
+    fn missing(server: &str, path: &str) -> MissingOnServer {
+        MissingOnServer {
+            server: server.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_diff_reports_newly_missing_and_resolved() {
+        let older = [
+            report("home", &["/library/A/One", "/library/A/Two"]),
+            report("vps", &["/library/A/One"]),
+        ];
+        let newer = [
+            report("home", &["/library/A/Two", "/library/B/Three"]),
+            report("vps", &["/library/A/One"]),
+        ];
+
+        let diff = diff_reports(&older, &newer);
+
+        assert_eq!(diff.newly_missing, [missing("home", "/library/B/Three")]);
+        assert_eq!(diff.resolved, [missing("home", "/library/A/One")]);
+        assert!(diff_reports(&newer, &newer).is_empty());
+    }
+
+    #[test]
+    fn test_only_the_latest_snapshots_are_kept() {
+        let temp_dir = TempDir::new().unwrap();
+        for taken_at in [100, 300, 200] {
+            let snapshot = Snapshot {
+                taken_at,
+                reports: vec![report("home", &[])],
+            };
+            save_snapshot(temp_dir.path(), &snapshot, 2).unwrap();
+        }
+
+        let snapshots = latest_snapshots(temp_dir.path(), 5).unwrap();
+
+        let taken_at: Vec<_> = snapshots.iter().map(|s| s.taken_at).collect();
+        assert_eq!(taken_at, [200, 300]);
+    }
+
+    #[test]
+    fn test_snapshots_taken_in_the_same_second_are_all_kept() {
+        let temp_dir = TempDir::new().unwrap();
+        for server in ["home", "vps"] {
+            let snapshot = Snapshot {
+                taken_at: 100,
+                reports: vec![report(server, &[])],
+            };
+            save_snapshot(temp_dir.path(), &snapshot, 5).unwrap();
+        }
+
+        let snapshots = latest_snapshots(temp_dir.path(), 5).unwrap();
+
+        let servers: Vec<_> = snapshots
+            .iter()
+            .map(|s| s.reports[0].server.as_str())
+            .collect();
+        assert_eq!(servers, ["home", "vps"]);
+    }
+}
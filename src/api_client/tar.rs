@@ -0,0 +1,272 @@
+//! This module uploads an album as a tar stream piped into ssh, which is much faster
+//! than scp for albums made of many small files (cue sheets, rip logs, artwork), as scp
+//! pays a round trip per file.
+//!
+//! The loose tracks of an artist's singles album are always uploaded this way, as scp
+//! can't create the remote album directory of a list of files.
+
+use crate::api_client::upload::{
+    build_ssh_command, local_file_count, shell_quote, uploaded_files, wait_with_timeout,
+};
+use crate::configuration::RemoteSettings;
+use crate::observer::SyncObserver;
+use crate::process::loose_tracks;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Returns `true` if the album at `album_path` should be uploaded as a tar stream: with
+/// `stream_tar`, unless the album is a single file, which scp uploads just as fast.
+pub(super) fn should_stream(album_path: &str, settings: &RemoteSettings) -> bool {
+    settings.stream_tar && local_file_count(album_path, settings) > 1
+}
+
+/// Builds the tar command writing the contents of the album directory to its stdout.
+///
+/// The names of the archived files are printed on stderr, as they are archived.
+///
+/// # Arguments
+///
+/// * `album_path` - The local path of the album to be uploaded.
+///
+pub(super) fn build_tar_command(album_path: &str) -> Command {
+    let mut command = Command::new("tar");
+    command.args(["-cvf", "-", "-C", album_path, "."]);
+    command
+}
+
+/// Builds the tar command writing the loose tracks of a singles album to its stdout,
+/// without the folder holding them.
+///
+/// # Arguments
+///
+/// * `tracks` - The audio files directly in the artist folder, see [`loose_tracks`].
+///
+pub(super) fn build_loose_tracks_tar_command(tracks: &[PathBuf]) -> Command {
+    let artist_path = tracks
+        .first()
+        .and_then(|track| track.parent())
+        .unwrap_or(Path::new("."));
+    build_files_tar_command(artist_path, tracks)
+}
+
+/// Builds the tar command writing `files` to its stdout, with their paths relative to
+/// `dir`.
+///
+/// # Arguments
+///
+/// * `dir` - The directory holding the files, such as the album directory.
+/// * `files` - The files to archive, e.g. those passing the upload extension filters.
+///
+pub(super) fn build_files_tar_command(dir: &Path, files: &[PathBuf]) -> Command {
+    let mut command = Command::new("tar");
+    command.args(["-cvf", "-", "-C"]).arg(dir).arg("--");
+    command.args(
+        files
+            .iter()
+            .map(|file| file.strip_prefix(dir).unwrap_or(file)),
+    );
+    command
+}
+
+/// Builds the ssh command extracting the tar stream read from its stdin into the
+/// remote album directory, created when missing.
+///
+/// # Arguments
+///
+/// * `remote_path` - The `user@host:path` destination of the album.
+/// * `settings` - A reference to the RemoteSettings containing the SSH options.
+///
+pub(super) fn build_untar_command(remote_path: &str, settings: &RemoteSettings) -> Command {
+    let remote_dir = shell_quote(
+        remote_path
+            .split_once(':')
+            .map_or(remote_path, |(_, path)| path),
+    );
+    build_ssh_command(
+        &format!("mkdir -p -- {0} && tar -xf - -C {0}", remote_dir),
+        settings,
+    )
+}
+
+/// Uploads an album by piping `tar` into `ssh`, reporting each archived file and the
+/// share of the album's files sent so far.
+///
+/// An upload running longer than `upload_timeout_secs` is killed and returned with the
+/// `TimedOut` kind; once tar is done, ssh gets as long again to extract the archive.
+/// With upload extension filters, only the files passing them are archived, and
+/// nothing is uploaded when none does.
+///
+/// # Arguments
+///
+/// * `album_path` - The local path of the album to be uploaded.
+/// * `remote_path` - The `user@host:path` destination of the album.
+/// * `settings` - A reference to the RemoteSettings containing the SSH and transfer options.
+/// * `observer` - Receives the upload percentage and the file being transferred.
+///
+pub(super) async fn upload_album(
+    album_path: &str,
+    remote_path: &str,
+    settings: &RemoteSettings,
+    observer: &dyn SyncObserver,
+) -> io::Result<()> {
+    let is_singles_album = loose_tracks(Path::new(album_path)).is_some();
+    let files = uploaded_files(album_path, settings);
+    if files.is_empty() && (is_singles_album || settings.filters_uploads()) {
+        return Ok(());
+    }
+    let total_files = files.len().max(1);
+    let tar = if is_singles_album {
+        build_loose_tracks_tar_command(&files)
+    } else if settings.filters_uploads() {
+        build_files_tar_command(Path::new(album_path), &files)
+    } else {
+        build_tar_command(album_path)
+    };
+    let mut tar = tokio::process::Command::from(tar)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let archive: Stdio = tar
+        .stdout
+        .take()
+        .ok_or_else(|| io::Error::other("tar has no stdout"))?
+        .try_into()?;
+    let mut ssh = tokio::process::Command::from(build_untar_command(remote_path, settings))
+        .stdin(archive)
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let mut sent_files = 0;
+    let stderr = tar.stderr.take();
+    let tar_status = wait_with_timeout(
+        &mut tar,
+        stderr,
+        b'\n',
+        settings.upload_timeout(),
+        &mut |line| {
+            // Directories, such as the `./` of the album itself, end with a `/`.
+            let file = line.trim_end().trim_start_matches("./");
+            if file.is_empty() || file.ends_with('/') {
+                return;
+            }
+            sent_files += 1;
+            observer.on_album_upload_file(file);
+            observer.on_album_upload_progress((sent_files * 100 / total_files).min(100) as u64);
+        },
+    )
+    .await;
+    let tar_status = match tar_status {
+        Ok(status) => status,
+        Err(e) => {
+            let _ = ssh.kill().await;
+            return Err(e);
+        }
+    };
+    let ssh_status = wait_with_timeout(
+        &mut ssh,
+        None::<tokio::process::ChildStdout>,
+        b'\n',
+        settings.upload_timeout(),
+        &mut |_| {},
+    )
+    .await?;
+
+    // A failed ssh also fails tar, which can't write the archive anymore.
+    if !ssh_status.success() {
+        return Err(io::Error::other(format!(
+            "ssh command extracting the album failed with status: {}",
+            ssh_status
+        )));
+    }
+    if !tar_status.success() {
+        return Err(io::Error::other(format!(
+            "tar command failed with status: {}",
+            tar_status
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn command_args(command: &Command) -> Vec<String> {
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_tar_pipeline_commands() {
+        let settings = RemoteSettings {
+            remote_user: "user".to_string(),
+            remote_host: "example.com".to_string(),
+            ssh_port: Some(2222),
+            ..RemoteSettings::default()
+        };
+
+        let tar = build_tar_command("/library/Guns N' Roses/Appetite");
+        let untar =
+            build_untar_command("user@example.com:/music/Guns N' Roses/Appetite", &settings);
+
+        assert_eq!(tar.get_program(), "tar");
+        assert_eq!(
+            command_args(&tar),
+            ["-cvf", "-", "-C", "/library/Guns N' Roses/Appetite", "."]
+        );
+        assert_eq!(untar.get_program(), "ssh");
+        assert_eq!(
+            command_args(&untar),
+            [
+                "-p",
+                "2222",
+                "user@example.com",
+                r"mkdir -p -- '/music/Guns N'\'' Roses/Appetite' && tar -xf - -C '/music/Guns N'\'' Roses/Appetite'"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_loose_tracks_tar_command() {
+        let tracks = [
+            PathBuf::from("/library/Artist/-single.mp3"),
+            PathBuf::from("/library/Artist/b side.mp3"),
+        ];
+
+        let tar = build_loose_tracks_tar_command(&tracks);
+
+        assert_eq!(
+            command_args(&tar),
+            [
+                "-cvf",
+                "-",
+                "-C",
+                "/library/Artist",
+                "--",
+                "-single.mp3",
+                "b side.mp3"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_single_file_albums_are_not_streamed() {
+        let album = tempfile::tempdir().unwrap();
+        let album_path = album.path().to_string_lossy().into_owned();
+        fs::write(album.path().join("album.flac"), b"").unwrap();
+        let settings = RemoteSettings {
+            stream_tar: true,
+            ..RemoteSettings::default()
+        };
+        assert!(!should_stream(&album_path, &settings));
+
+        fs::write(album.path().join("album.cue"), b"").unwrap();
+        assert!(should_stream(&album_path, &settings));
+        assert!(!should_stream(&album_path, &RemoteSettings::default()));
+    }
+}
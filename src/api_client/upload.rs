@@ -1,20 +1,261 @@
 //! This module provides functionality for uploading missing albums to a remote location
-//! with progress tracking. It includes functions for creating progress bars, extracting
-//! album information from file paths, constructing remote paths, and performing the
-//! actual upload using SCP.
+//! with progress tracking. It includes functions for extracting album information from
+//! file paths, constructing remote paths, and performing the actual upload using SCP.
+//! Progress is reported to a [`SyncObserver`].
 
+use crate::api_client::{rsync, tar};
 use crate::configuration::RemoteSettings;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use crate::observer::{progress_style, ConsoleObserver, SyncObserver};
+use crate::process::loose_tracks;
+use async_trait::async_trait;
 use regex::Regex;
-use std::io::{self, BufRead, BufReader};
-use std::path::Path;
-use std::process::{Command, Stdio};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use std::{env, fmt, fs, process};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Child;
+use walkdir::WalkDir;
+
+/// Transfers a single album directory to its remote destination.
+///
+/// The SCP-based [`ScpUploader`] is used by the CLI; the trait exists so the upload loop
+/// can be exercised without a remote server. Uploads are awaited, so the transfer
+/// processes run on the tokio runtime and are killed when the upload is cancelled.
+// The observer lifetimes are named for the mock, which can't generate them when elided.
+#[allow(unused_parens)]
+#[cfg_attr(test, mockall::automock)]
+#[async_trait(?Send)]
+pub trait AlbumUploader {
+    /// Uploads `album_path` to `remote_path`, reporting progress (0-100) to
+    /// `observer.on_album_upload_progress` and the file being transferred to
+    /// `observer.on_album_upload_file`.
+    async fn upload<'a>(
+        &self,
+        album_path: &str,
+        remote_path: &str,
+        observer: &'a (dyn SyncObserver + 'a),
+    ) -> io::Result<()>;
+
+    /// Uploads several albums of one artist in a single transfer. `albums` are the names
+    /// of album directories inside `artist_path`, and `remote_path` is the remote artist
+    /// directory. Progress (0-100) covers all the albums. Returns one result per album,
+    /// in the order of `albums`.
+    async fn upload_artist<'a>(
+        &self,
+        artist_path: &str,
+        albums: &[String],
+        remote_path: &str,
+        observer: &'a (dyn SyncObserver + 'a),
+    ) -> Vec<io::Result<()>>;
+}
+
+/// Outcome of an upload run.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct UploadSummary {
+    /// Number of albums uploaded successfully.
+    pub succeeded: usize,
+    /// Number of albums that failed to upload.
+    pub failed: usize,
+    /// Size of the uploaded albums, in bytes. Failed albums aren't counted.
+    pub total_bytes: u64,
+    /// Time spent uploading.
+    pub elapsed: Duration,
+}
+
+impl UploadSummary {
+    /// Total number of albums that were attempted.
+    pub fn total(&self) -> usize {
+        self.succeeded + self.failed
+    }
+
+    /// Adds the results of another run, e.g. the upload to another server.
+    pub fn merge(&mut self, other: &UploadSummary) {
+        self.succeeded += other.succeeded;
+        self.failed += other.failed;
+        self.total_bytes += other.total_bytes;
+        self.elapsed += other.elapsed;
+    }
+}
+
+impl fmt::Display for UploadSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Uploaded {} album(s), {} in {}",
+            self.succeeded,
+            format_bytes(self.total_bytes),
+            format_elapsed(self.elapsed)
+        )?;
+        if self.failed > 0 {
+            write!(f, ", {} failed", self.failed)?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats a byte count with binary units, e.g. `18.3 GiB`.
+pub(super) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Formats a duration with its two most significant units, e.g. `1h 5m` or `12m 3s`.
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {}s", m, s),
+        (h, m, _) => format!("{}h {}m", h, m),
+    }
+}
+
+/// Uploads albums with `scp`, parsing its percentage output to report progress.
+///
+/// When `upload_fallback` is enabled and the connection drops mid-album, the album is
+/// uploaded again with rsync, which resumes partially transferred files. With
+/// `stream_tar`, albums of several files are piped through tar and ssh instead.
+pub struct ScpUploader<'a> {
+    settings: &'a RemoteSettings,
+}
+
+impl<'a> ScpUploader<'a> {
+    pub fn new(settings: &'a RemoteSettings) -> Self {
+        Self { settings }
+    }
+}
+
+#[async_trait(?Send)]
+impl AlbumUploader for ScpUploader<'_> {
+    async fn upload<'a>(
+        &self,
+        album_path: &str,
+        remote_path: &str,
+        observer: &'a (dyn SyncObserver + 'a),
+    ) -> io::Result<()> {
+        if tar::should_stream(album_path, self.settings)
+            || loose_tracks(Path::new(album_path)).is_some()
+        {
+            tar::upload_album(album_path, remote_path, self.settings, observer).await?;
+        } else {
+            upload_with_fallback(
+                self.settings,
+                rsync::is_available,
+                upload_album(album_path, remote_path, self.settings, observer),
+                rsync::upload_album(album_path, remote_path, self.settings, observer),
+                observer,
+            )
+            .await?;
+        }
+
+        if self.settings.verify_uploads {
+            verify_remote_album(album_path, remote_path, self.settings).await?;
+        }
+        Ok(())
+    }
+
+    /// Uploads the albums with a single rsync, or one by one with scp when rsync isn't
+    /// installed. A failed scp upload doesn't stop the albums after it.
+    async fn upload_artist<'a>(
+        &self,
+        artist_path: &str,
+        albums: &[String],
+        remote_path: &str,
+        observer: &'a (dyn SyncObserver + 'a),
+    ) -> Vec<io::Result<()>> {
+        let album_paths = |album: &String| {
+            (
+                join_local_path(artist_path, album),
+                format!("{}/{}", normalize_remote_dir(remote_path), album),
+            )
+        };
+
+        let mut results = Vec::with_capacity(albums.len());
+        if !rsync::is_available() {
+            for (album_path, remote_album_path) in albums.iter().map(album_paths) {
+                results.push(self.upload(&album_path, &remote_album_path, observer).await);
+            }
+            return results;
+        }
+
+        if let Err(e) =
+            rsync::upload_artist(artist_path, albums, remote_path, self.settings, observer).await
+        {
+            // io::Error can't be cloned, so each album gets a copy of the error.
+            return albums
+                .iter()
+                .map(|_| Err(io::Error::new(e.kind(), e.to_string())))
+                .collect();
+        }
+
+        for (album_path, remote_album_path) in albums.iter().map(album_paths) {
+            results.push(if self.settings.verify_uploads {
+                verify_remote_album(&album_path, &remote_album_path, self.settings).await
+            } else {
+                Ok(())
+            });
+        }
+        results
+    }
+}
+
+/// Runs the scp upload, retrying with rsync when the connection dropped mid-transfer.
+///
+/// The fallback is only taken when `upload_fallback` is enabled, the scp error is a
+/// connection drop (`ConnectionAborted`) and rsync is installed. The rsync upload isn't
+/// started otherwise. Taking the fallback is reported to `observer`.
+async fn upload_with_fallback(
+    settings: &RemoteSettings,
+    rsync_available: impl FnOnce() -> bool,
+    scp: impl Future<Output = io::Result<()>>,
+    rsync: impl Future<Output = io::Result<()>>,
+    observer: &dyn SyncObserver,
+) -> io::Result<()> {
+    match scp.await {
+        Err(e)
+            if e.kind() == io::ErrorKind::ConnectionAborted
+                && settings.upload_fallback
+                && rsync_available() =>
+        {
+            observer.warn(&format!(
+                "Connection dropped during scp upload ({e}), resuming with rsync"
+            ));
+            rsync.await
+        }
+        result => result,
+    }
+}
 
 /// Uploads missing albums to a remote location with progress tracking.
 ///
 /// This function takes a slice of album paths and remote settings, then uploads each album
 /// to the specified remote location. It provides visual feedback using progress bars for
-/// both overall progress and individual album uploads.
+/// both overall progress and individual album uploads, through a [`ConsoleObserver`].
+///
+/// A failed album doesn't stop the remaining uploads. The number of albums that failed
+/// to upload is returned so callers can report partial failures. An error is returned
+/// when the progress bars can't be created, e.g. because of an invalid `progress_style`.
+/// Use [`upload_albums_with`] to report progress elsewhere.
+///
+/// With `ssh_multiplex`, a master ssh connection is opened before the first upload and
+/// closed once the last one is done.
+///
+/// The scp, rsync and tar processes run on the tokio runtime. Dropping the returned
+/// future, e.g. when Ctrl-C is pressed, kills the process of the album being uploaded.
 ///
 /// # Arguments
 ///
@@ -23,10 +264,12 @@ use std::process::{Command, Stdio};
 ///
 /// # Example
 ///
-/// ```
+/// ```no_run
 /// use musync::RemoteSettings;
 /// use musync::upload_missing_albums;
 ///
+/// # #[tokio::main]
+/// # async fn main() {
 /// let missing_albums = vec![
 ///     String::from("/path/to/Artist1/Album1"),
 ///     String::from("/path/to/Artist2/Album2"),
@@ -36,73 +279,328 @@ use std::process::{Command, Stdio};
 ///     remote_user: String::from("user"),
 ///     remote_host: String::from("example.com"),
 ///     remote_path: String::from("/music"),
-///     ssh_key_path: String::from("/path/to/ssh_key"),
+///     ssh_key_path: Some(String::from("/path/to/ssh_key")),
+///     ..RemoteSettings::default()
 /// };
 ///
-/// upload_missing_albums(&missing_albums, &settings).expect("Failed to upload albums");
+/// let summary = upload_missing_albums(&missing_albums, &settings)
+///     .await
+///     .expect("Failed to upload albums");
+/// println!("{}", summary);
+/// # }
 /// ```
 ///
-pub fn upload_missing_albums(
+pub async fn upload_missing_albums(
     missing_albums: &[String],
     settings: &RemoteSettings,
-) -> io::Result<()> {
-    let multi_progress = MultiProgress::new();
-    let overall_progress =
-        create_progress_bar(&multi_progress, missing_albums.len() as u64, "albums");
-    let re = Regex::new(r"(\d+)%").unwrap();
-
-    for album_path in missing_albums {
-        let (artist, album_name) = extract_artist_and_album(album_path)?;
-        let remote_album_path = create_remote_path(settings, &artist, &album_name);
-
-        overall_progress.set_message(format!("Uploading: {artist} - {album_name}"));
-
-        let album_progress = create_progress_bar(&multi_progress, 100, "%");
-        album_progress.set_message(format!("{artist} - {album_name}"));
-
-        match upload_album(
-            album_path,
-            &remote_album_path,
-            settings,
-            &re,
-            &album_progress,
-        ) {
-            Ok(()) => {
-                album_progress.finish_with_message(format!("Uploaded: {artist} - {album_name}"));
-                overall_progress.inc(1);
+) -> io::Result<UploadSummary> {
+    let observer = ConsoleObserver::with_progress_style(settings.progress_style.as_ref())?;
+    let _master = SshMaster::start(settings).await;
+    ensure_remote_space(missing_albums, settings).await?;
+    Ok(upload_albums_with(
+        missing_albums,
+        settings,
+        &ScpUploader::new(settings),
+        &observer,
+    )
+    .await)
+}
+
+/// Runs the upload loop for `missing_albums` using the given uploader, reporting
+/// progress to `observer`.
+///
+/// Paths pointing to the same album directory (e.g. through a symlink) are uploaded
+/// only once. With `group_by_artist`, the albums sharing an artist directory are
+/// uploaded in a single transfer, and each of them is reported with its own result.
+/// When `delete_after_upload` is set, the local album directory is removed after a
+/// successful upload; failed uploads are always left in place. Returns how many albums
+/// were uploaded or failed, how many bytes were uploaded and how long it took.
+///
+/// # Arguments
+///
+/// * `missing_albums` - A slice of strings representing paths to albums that need to be uploaded.
+/// * `settings` - Remote settings used to build the remote destination of each album.
+/// * `uploader` - The transfer implementation used for each album.
+/// * `observer` - Receives the progress of the uploads.
+///
+pub async fn upload_albums_with(
+    missing_albums: &[String],
+    settings: &RemoteSettings,
+    uploader: &dyn AlbumUploader,
+    observer: &dyn SyncObserver,
+) -> UploadSummary {
+    let started = Instant::now();
+    let missing_albums = dedupe_album_paths(missing_albums);
+    observer.on_upload_started(missing_albums.len());
+    let mut summary = UploadSummary::default();
+
+    for group in group_albums(missing_albums, settings.group_by_artist) {
+        let mut albums = Vec::new();
+        for album_path in group {
+            match extract_artist_and_album(album_path) {
+                Ok((artist, name)) => albums.push(AlbumUpload {
+                    path: album_path,
+                    artist,
+                    name,
+                    // Measured before uploading, as the album may be deleted afterwards.
+                    bytes: album_size(album_path),
+                }),
+                Err(e) => {
+                    observer.on_upload_done(album_path, &Err(e));
+                    summary.failed += 1;
+                }
             }
-            Err(e) => {
-                album_progress.finish_with_message(format!("Failed: {artist} - {album_name}"));
-                eprintln!("Failed to upload {artist} - {album_name}: {e}");
+        }
+
+        match albums.as_slice() {
+            [] => {}
+            [album] => {
+                let remote_album_path = create_remote_path(settings, &album.artist, &album.name);
+                observer.on_album_upload_started(&album.artist, &album.name);
+                let result = uploader
+                    .upload(album.path, &remote_album_path, observer)
+                    .await;
+                finish_album_upload(album, &result, settings, observer, &mut summary);
+            }
+            [first, ..] => {
+                let artist_path = Path::new(first.path)
+                    .parent()
+                    .map_or_else(String::new, |path| path.to_string_lossy().into_owned());
+                let names: Vec<String> = albums.iter().map(|album| album.name.clone()).collect();
+                let remote_artist_path = create_remote_artist_path(settings, &first.artist);
+
+                observer
+                    .on_album_upload_started(&first.artist, &format!("{} albums", albums.len()));
+                let mut results = uploader
+                    .upload_artist(&artist_path, &names, &remote_artist_path, observer)
+                    .await
+                    .into_iter();
+                for album in &albums {
+                    let result = results.next().unwrap_or_else(|| {
+                        Err(io::Error::other("the uploader didn't report this album"))
+                    });
+                    finish_album_upload(album, &result, settings, observer, &mut summary);
+                }
             }
         }
     }
 
-    overall_progress.finish_with_message("All uploads completed");
-    Ok(())
+    summary.elapsed = started.elapsed();
+    observer.on_uploads_finished(&summary);
+    summary
 }
 
-/// Creates a stylized progress bar for tracking upload progress.
+/// An album about to be uploaded.
+struct AlbumUpload<'a> {
+    path: &'a str,
+    artist: String,
+    name: String,
+    bytes: u64,
+}
+
+/// Records the result of an album upload and deletes the uploaded album when
+/// `delete_after_upload` is set.
+fn finish_album_upload(
+    album: &AlbumUpload,
+    result: &io::Result<()>,
+    settings: &RemoteSettings,
+    observer: &dyn SyncObserver,
+    summary: &mut UploadSummary,
+) {
+    if result.is_ok() {
+        summary.succeeded += 1;
+        summary.total_bytes += album.bytes;
+    } else {
+        summary.failed += 1;
+    }
+    observer.on_upload_done(album.path, result);
+
+    if result.is_ok() && settings.delete_after_upload {
+        let deleted = match loose_tracks(Path::new(album.path)) {
+            Some(tracks) => tracks.iter().try_for_each(fs::remove_file),
+            None => fs::remove_dir_all(album.path),
+        };
+        if let Err(e) = deleted {
+            observer.warn(&format!(
+                "Uploaded {} - {} but failed to delete it: {e}",
+                album.artist, album.name
+            ));
+        }
+    }
+}
+
+/// Splits the albums into upload batches, keeping their order.
 ///
-/// This helper function sets up a progress bar with a custom style, making it easier
-/// to visualize the upload process for both individual albums and overall progress.
+/// With `by_artist`, albums sharing a parent (artist) directory form one batch, placed
+/// where the first of them appears. Otherwise each album is its own batch, as are the
+/// singles albums of loose tracks, which rsync can't upload as a folder.
+fn group_albums(album_paths: Vec<&String>, by_artist: bool) -> Vec<Vec<&String>> {
+    if !by_artist {
+        return album_paths.into_iter().map(|path| vec![path]).collect();
+    }
+
+    let mut groups: Vec<Vec<&String>> = Vec::new();
+    let mut group_of_parent: HashMap<&Path, usize> = HashMap::new();
+    for album_path in album_paths {
+        if loose_tracks(Path::new(album_path)).is_some() {
+            groups.push(vec![album_path]);
+            continue;
+        }
+        match Path::new(album_path).parent() {
+            Some(parent) => match group_of_parent.get(parent) {
+                Some(&index) => groups[index].push(album_path),
+                None => {
+                    group_of_parent.insert(parent, groups.len());
+                    groups.push(vec![album_path]);
+                }
+            },
+            None => groups.push(vec![album_path]),
+        }
+    }
+    groups
+}
+
+/// Checks that the remote host has room for `missing_albums`, when `check_remote_space`
+/// is enabled.
+///
+/// The free space of the filesystem holding `remote_path` is read with `df` over ssh
+/// and compared with the total size of the albums. Not having enough room is returned
+/// as a `StorageFull` error, so nothing is uploaded.
 ///
 /// # Arguments
 ///
-/// * `multi_progress` - A reference to the MultiProgress instance for managing multiple progress bars.
-/// * `total` - The total number of steps or items to track.
-/// * `unit` - A string representing the unit of measurement (e.g., "albums" or "%").
-fn create_progress_bar(multi_progress: &MultiProgress, total: u64, unit: &str) -> ProgressBar {
-    let progress = multi_progress.add(ProgressBar::new(total));
-    progress.set_style(
-        ProgressStyle::default_bar()
-            .template(&format!(
-                "{{elapsed_precise}} [{{bar:40.cyan/blue}}] {{pos}}/{{len}} {unit} {{msg}}"
-            ))
-            .unwrap()
-            .progress_chars("##-"),
+/// * `missing_albums` - Paths of the albums about to be uploaded.
+/// * `settings` - Remote settings holding the host and destination path.
+///
+pub async fn ensure_remote_space(
+    missing_albums: &[String],
+    settings: &RemoteSettings,
+) -> io::Result<()> {
+    if !settings.check_remote_space || missing_albums.is_empty() {
+        return Ok(());
+    }
+
+    let required: u64 = dedupe_album_paths(missing_albums)
+        .into_iter()
+        .map(|album_path| album_size(album_path))
+        .sum();
+    check_free_space(required, remote_free_bytes(settings).await?)
+}
+
+/// Reads the free space of the filesystem holding `remote_path` on the remote host.
+async fn remote_free_bytes(settings: &RemoteSettings) -> io::Result<u64> {
+    let remote_dir = normalize_remote_dir(&settings.remote_path);
+    let remote_command = format!(
+        "df -B1 {}",
+        shell_quote(if remote_dir.is_empty() {
+            "/"
+        } else {
+            &remote_dir
+        })
     );
-    progress
+
+    let output = tokio::process::Command::from(build_ssh_command(&remote_command, settings))
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "Failed to check remote free space: ssh exited with status {}",
+            output.status
+        )));
+    }
+
+    parse_df_free_bytes(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parses the available bytes from the output of `df -B1 <path>`.
+///
+/// The header is skipped, and the remaining fields are read as one record, since `df`
+/// moves the numbers to a second line when the filesystem name is long.
+fn parse_df_free_bytes(output: &str) -> io::Result<u64> {
+    output
+        .lines()
+        .skip(1)
+        .flat_map(str::split_whitespace)
+        .nth(3)
+        .and_then(|available| available.parse().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unexpected df output: '{}'", output.trim()),
+            )
+        })
+}
+
+/// Fails with `StorageFull` when `required` bytes don't fit in `free` bytes.
+fn check_free_space(required: u64, free: u64) -> io::Result<()> {
+    if required > free {
+        return Err(io::Error::new(
+            io::ErrorKind::StorageFull,
+            format!(
+                "Not enough space on the remote host: {} to upload but only {} free",
+                format_bytes(required),
+                format_bytes(free)
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Size of the album at `album_path`, in bytes: of its directory, or of its loose
+/// tracks for a singles album.
+fn album_size(album_path: &str) -> u64 {
+    match loose_tracks(Path::new(album_path)) {
+        Some(tracks) => tracks
+            .iter()
+            .filter_map(|track| fs::metadata(track).ok())
+            .map(|metadata| metadata.len())
+            .sum(),
+        None => directory_size(Path::new(album_path)),
+    }
+}
+
+/// Total size of the files under `path`, in bytes. Unreadable entries are skipped.
+fn directory_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Removes album paths that resolve to the same directory, keeping the first occurrence.
+///
+/// Paths are compared after canonicalization so that symlinked or differently spelled
+/// paths to the same album are detected. Paths that can't be canonicalized are compared
+/// as-is.
+fn dedupe_album_paths(album_paths: &[String]) -> Vec<&String> {
+    let mut seen = HashSet::new();
+
+    album_paths
+        .iter()
+        .filter(|album_path| {
+            let key = Path::new(album_path)
+                .canonicalize()
+                .unwrap_or_else(|_| Path::new(album_path).to_path_buf());
+            seen.insert(key)
+        })
+        .collect()
+}
+
+/// Checks that the configured `progress_style`, if any, is usable.
+///
+/// Meant to be called at startup so a bad template is reported before anything is
+/// scanned or uploaded.
+///
+/// # Arguments
+///
+/// * `settings` - Remote settings holding the optional progress style.
+///
+pub fn validate_progress_style(settings: &RemoteSettings) -> io::Result<()> {
+    progress_style(settings.progress_style.as_ref(), "").map(|_| ())
 }
 
 /// Extracts the artist and album name from a given album path.
@@ -111,35 +609,38 @@ fn create_progress_bar(multi_progress: &MultiProgress, total: u64, unit: &str) -
 /// directory) and the album name (from the directory name). It performs some basic validation
 /// to ensure the path structure is as expected.
 ///
+/// On Windows, both `/` and `\` separate folders, so a path such as
+/// `C:\Music\Artist\Album` gives the names of its last two folders, and the remote path
+/// built from them only ever uses `/`. Elsewhere, `\` is a character of the folder name,
+/// as in `AC\DC`.
+///
 /// # Arguments
 ///
 /// * `album_path` - A string slice representing the path to the album directory.
 ///
-/// ```
-///
-fn extract_artist_and_album(album_path: &str) -> io::Result<(String, String)> {
-    let path = Path::new(album_path);
-    let artist = path
-        .parent()
-        .and_then(|p| p.file_name())
-        .and_then(|n| n.to_str())
-        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid artist path"))?;
+pub(super) fn extract_artist_and_album(album_path: &str) -> io::Result<(String, String)> {
+    let mut names = album_path
+        .split(|c| c == '/' || (cfg!(windows) && c == '\\'))
+        .filter(|name| !name.is_empty() && *name != ".");
 
-    let album_name = path
-        .file_name()
-        .and_then(|n| n.to_str())
+    let album_name = names
+        .next_back()
+        .filter(|name| *name != "..")
         .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid album path"))?;
 
-    if album_name.is_empty() {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            "Empty album name",
-        ));
-    }
+    let artist = names
+        .next_back()
+        .filter(|name| *name != ".." && !(cfg!(windows) && is_windows_drive(name)))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid artist path"))?;
 
     Ok((artist.to_string(), album_name.to_string()))
 }
 
+/// Returns `true` if `name` is the drive of a Windows path, such as `C:`.
+fn is_windows_drive(name: &str) -> bool {
+    matches!(name.as_bytes(), [letter, b':'] if letter.is_ascii_alphabetic())
+}
+
 /// Constructs the remote path for an album based on the provided settings and album information.
 ///
 /// This function formats the remote path using the RemoteSettings and the extracted
@@ -153,53 +654,1632 @@ fn extract_artist_and_album(album_path: &str) -> io::Result<(String, String)> {
 fn create_remote_path(settings: &RemoteSettings, artist: &str, album_name: &str) -> String {
     format!(
         "{}@{}:{}/{}/{}",
-        settings.remote_user, settings.remote_host, settings.remote_path, artist, album_name
+        settings.remote_user,
+        settings.remote_host,
+        normalize_remote_dir(&settings.remote_path),
+        artist,
+        album_name
     )
 }
 
+/// Constructs the remote path of an artist directory, used when several of its albums
+/// are uploaded at once.
+///
+/// # Arguments
+///
+/// * `settings` - A reference to the RemoteSettings containing remote user, host, and path information.
+/// * `artist` - The name of the artist.
+fn create_remote_artist_path(settings: &RemoteSettings, artist: &str) -> String {
+    format!(
+        "{}@{}:{}/{}",
+        settings.remote_user,
+        settings.remote_host,
+        normalize_remote_dir(&settings.remote_path),
+        artist
+    )
+}
+
+/// Collapses repeated slashes and removes the trailing slash of a remote directory, so
+/// `/music/`, `/music//` and `/music` are joined the same way. The root is returned
+/// empty, as paths are joined with a `/`.
+pub(super) fn normalize_remote_dir(path: &str) -> String {
+    let mut normalized = String::with_capacity(path.len());
+    for c in path.chars() {
+        if c != '/' || !normalized.ends_with('/') {
+            normalized.push(c);
+        }
+    }
+    normalized.trim_end_matches('/').to_string()
+}
+
+/// Joins a local directory and an entry name, dropping duplicate separators.
+fn join_local_path(dir: &str, name: &str) -> String {
+    Path::new(dir)
+        .join(name)
+        .components()
+        .collect::<std::path::PathBuf>()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Builds the SCP command used to upload a single album.
+///
+/// The command copies the album directory recursively using the configured SSH key, or
+/// ssh's default identity (ssh-agent, `~/.ssh/config`) when no key is configured.
+/// A custom SSH port is passed with `-P` and each extra ssh option with `-o`. When a
+/// bandwidth limit is configured, it is passed to scp with `-l` (Kbit/s).
+///
+/// # Arguments
+///
+/// * `album_path` - The local path of the album to be uploaded.
+/// * `remote_path` - The constructed remote path where the album will be uploaded.
+/// * `settings` - A reference to the RemoteSettings containing the SSH and transfer options.
+///
+fn build_scp_command(album_path: &str, remote_path: &str, settings: &RemoteSettings) -> Command {
+    let mut command = scp_command(settings);
+    command.args([album_path, remote_path]);
+    command
+}
+
+/// Builds the scp command uploading `files` into the existing `remote_dir`.
+///
+/// # Arguments
+///
+/// * `files` - The local paths of the files to be uploaded.
+/// * `remote_dir` - The `user@host:path` directory the files are uploaded into.
+/// * `settings` - A reference to the RemoteSettings containing the SSH and transfer options.
+///
+fn build_scp_files_command(
+    files: &[PathBuf],
+    remote_dir: &str,
+    settings: &RemoteSettings,
+) -> Command {
+    let mut command = scp_command(settings);
+    command
+        .args(files)
+        .arg(format!("{}/", remote_dir.trim_end_matches('/')));
+    command
+}
+
+/// The scp command with the identity, port, cipher and transfer options of `settings`.
+fn scp_command(settings: &RemoteSettings) -> Command {
+    let mut command = Command::new("scp");
+    command.arg("-r");
+
+    if let Some(key_path) = settings.ssh_key_path.as_deref().filter(|p| !p.is_empty()) {
+        command.arg("-i").arg(key_path);
+    }
+
+    if let Some(port) = settings.ssh_port {
+        command.arg("-P").arg(port.to_string());
+    }
+
+    if let Some(cipher) = settings.ssh_cipher.as_deref().filter(|c| !c.is_empty()) {
+        command.arg("-c").arg(cipher);
+    }
+
+    if settings.ssh_compression {
+        command.arg("-C");
+    }
+
+    for option in &settings.ssh_extra_opts {
+        command.arg("-o").arg(option);
+    }
+
+    command.args(multiplex_options(settings));
+
+    if let Some(limit) = settings.bandwidth_limit_kbps {
+        command.arg("-l").arg(limit.to_string());
+    }
+
+    command
+}
+
+/// Builds an ssh command running `remote_command` on the remote host.
+///
+/// The same identity, port and extra options as the scp uploads are used. Note that
+/// ssh takes the port with `-p`, unlike scp.
+///
+pub(super) fn build_ssh_command(remote_command: &str, settings: &RemoteSettings) -> Command {
+    let mut command = Command::new("ssh");
+    command
+        .args(ssh_options(settings))
+        .arg(format!("{}@{}", settings.remote_user, settings.remote_host))
+        .arg(remote_command);
+    command
+}
+
+/// The identity, port and extra options passed to `ssh`.
+pub(super) fn ssh_options(settings: &RemoteSettings) -> Vec<String> {
+    let mut options = Vec::new();
+
+    if let Some(key_path) = settings.ssh_key_path.as_deref().filter(|p| !p.is_empty()) {
+        options.extend(["-i".to_string(), key_path.to_string()]);
+    }
+
+    if let Some(port) = settings.ssh_port {
+        options.extend(["-p".to_string(), port.to_string()]);
+    }
+
+    if let Some(cipher) = settings.ssh_cipher.as_deref().filter(|c| !c.is_empty()) {
+        options.extend(["-c".to_string(), cipher.to_string()]);
+    }
+
+    if settings.ssh_compression {
+        options.push("-C".to_string());
+    }
+
+    for option in &settings.ssh_extra_opts {
+        options.extend(["-o".to_string(), option.clone()]);
+    }
+
+    options.extend(multiplex_options(settings));
+    options
+}
+
+/// The options making scp and ssh go through the master connection of this process,
+/// with `ssh_multiplex`. A command that finds no master opens a connection of its own.
+fn multiplex_options(settings: &RemoteSettings) -> Vec<String> {
+    if !settings.ssh_multiplex {
+        return Vec::new();
+    }
+    vec![
+        "-o".to_string(),
+        "ControlMaster=auto".to_string(),
+        "-o".to_string(),
+        format!("ControlPath={}", ssh_control_path()),
+    ]
+}
+
+/// Path of the control socket of the master connection. ssh replaces `%C` with a hash
+/// of the host, port and user, so each remote host gets its own socket.
+fn ssh_control_path() -> String {
+    env::temp_dir()
+        .join(format!("musync-ssh-{}-%C", process::id()))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// A master ssh connection shared by the uploads of a server, closed when dropped.
+///
+/// The command closing it is started on the tokio runtime and not waited for, so
+/// dropping the master doesn't block the runtime.
+pub(super) struct SshMaster<'a> {
+    settings: &'a RemoteSettings,
+}
+
+impl<'a> SshMaster<'a> {
+    /// Opens the master connection when `ssh_multiplex` is set.
+    ///
+    /// A master that can't be opened is only warned about: each command then opens a
+    /// connection of its own.
+    pub(super) async fn start(settings: &'a RemoteSettings) -> Option<Self> {
+        if !settings.ssh_multiplex {
+            return None;
+        }
+        match tokio::process::Command::from(build_ssh_master_command(settings))
+            .status()
+            .await
+        {
+            Ok(status) if status.success() => Some(Self { settings }),
+            Ok(status) => {
+                eprintln!(
+                    "\x1b[33mCould not open a shared ssh connection, ssh exited with status {}\x1b[0m",
+                    status
+                );
+                None
+            }
+            Err(e) => {
+                eprintln!(
+                    "\x1b[33mCould not open a shared ssh connection: {}\x1b[0m",
+                    e
+                );
+                None
+            }
+        }
+    }
+}
+
+impl Drop for SshMaster<'_> {
+    fn drop(&mut self) {
+        let mut command = build_ssh_master_exit_command(self.settings);
+        command.stderr(Stdio::null());
+        // Outside of a runtime, e.g. while it shuts down, there is nothing to block.
+        if tokio::runtime::Handle::try_current().is_ok() {
+            let _ = tokio::process::Command::from(command).spawn();
+        } else {
+            let _ = command.status();
+        }
+    }
+}
+
+/// Builds the ssh command opening the master connection in the background, without
+/// running a remote command.
+fn build_ssh_master_command(settings: &RemoteSettings) -> Command {
+    let mut command = Command::new("ssh");
+    command
+        .args(ssh_options(settings))
+        .args(["-o", "ControlPersist=yes", "-f", "-N"])
+        .arg(format!("{}@{}", settings.remote_user, settings.remote_host));
+    command
+}
+
+/// Builds the ssh command closing the master connection.
+fn build_ssh_master_exit_command(settings: &RemoteSettings) -> Command {
+    let mut command = Command::new("ssh");
+    command
+        .args(ssh_options(settings))
+        .args(["-O", "exit"])
+        .arg(format!("{}@{}", settings.remote_user, settings.remote_host));
+    command
+}
+
+/// Quotes `value` for use as a single argument in a POSIX shell command line.
+pub(super) fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Checks that the remote copy of an album holds as many files as the local one.
+///
+/// The remote files are counted with `find | wc -l` over ssh. A mismatch, or a failure
+/// to count the remote files, is returned as an error.
+///
+/// # Arguments
+///
+/// * `album_path` - The local path of the uploaded album.
+/// * `remote_path` - The `user@host:path` destination the album was uploaded to.
+/// * `settings` - A reference to the RemoteSettings containing the SSH options.
+///
+async fn verify_remote_album(
+    album_path: &str,
+    remote_path: &str,
+    settings: &RemoteSettings,
+) -> io::Result<()> {
+    let remote_dir = remote_path
+        .split_once(':')
+        .map_or(remote_path, |(_, path)| path);
+    let remote_command = format!("find {} -type f | wc -l", shell_quote(remote_dir));
+
+    let output = tokio::process::Command::from(build_ssh_command(&remote_command, settings))
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "Failed to count remote files: ssh exited with status {}",
+            output.status
+        )));
+    }
+
+    let remote_count = parse_remote_file_count(&String::from_utf8_lossy(&output.stdout))?;
+    let local_count = local_file_count(album_path, settings);
+
+    if remote_count != local_count {
+        return Err(io::Error::other(format!(
+            "Upload verification failed: {} local files but {} on the remote host",
+            local_count, remote_count
+        )));
+    }
+
+    Ok(())
+}
+
+/// Number of files uploaded from the album directory at `album_path`, sub-folders
+/// included, or of loose tracks for a singles album.
+pub(super) fn local_file_count(album_path: &str, settings: &RemoteSettings) -> usize {
+    uploaded_files(album_path, settings).len()
+}
+
+/// The files uploaded from the album directory at `album_path`, sub-folders included,
+/// or the loose tracks of a singles album, that pass the upload extension filters. In
+/// path order.
+pub(super) fn uploaded_files(album_path: &str, settings: &RemoteSettings) -> Vec<PathBuf> {
+    let files = match loose_tracks(Path::new(album_path)) {
+        Some(tracks) => tracks,
+        None => WalkDir::new(album_path)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+            .map(walkdir::DirEntry::into_path)
+            .collect(),
+    };
+    files
+        .into_iter()
+        .filter(|file| settings.uploads_file(file))
+        .collect()
+}
+
+/// Parses the output of `wc -l`, which some platforms pad with spaces.
+fn parse_remote_file_count(output: &str) -> io::Result<usize> {
+    output.trim().parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unexpected remote file count output: '{}'", output.trim()),
+        )
+    })
+}
+
+/// Matches the percentage printed by scp and rsync, compiled once for all uploads.
+pub(super) fn scp_progress_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(\d+)%").expect("valid scp progress regex"))
+}
+
+/// Returns the name of the file an scp progress line reports, printed before the
+/// percentage, e.g. `Track01.flac` in `Track01.flac   42%   12MB   1.2MB/s   00:10 ETA`.
+fn scp_file_name(line: &str) -> Option<&str> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| Regex::new(r"^(.*\S)\s+\d+%").expect("valid scp file regex"));
+    // The progress meter redraws its line with carriage returns.
+    let line = line.rsplit('\r').next().unwrap_or(line);
+    re.captures(line.trim_start())
+        .and_then(|cap| cap.get(1))
+        .map(|m| m.as_str())
+}
+
 /// Uploads a single album to the remote location using SCP.
 ///
 /// This function spawns an SCP process to upload the album, capturing and parsing the
-/// progress output to update the progress bar. It handles potential errors and ensures
-/// the upload process completes successfully.
+/// progress output to report progress and the file being transferred. It handles potential errors and ensures
+/// the upload process completes successfully. A failure caused by a dropped connection
+/// is returned with the `ConnectionAborted` kind, and an upload running longer than
+/// `upload_timeout_secs` is killed and returned with the `TimedOut` kind.
 ///
 /// # Arguments
 ///
 /// * `album_path` - The local path of the album to be uploaded.
 /// * `remote_path` - The constructed remote path where the album will be uploaded.
 /// * `settings` - A reference to the RemoteSettings containing the SSH key path.
-/// * `re` - A reference to a Regex for parsing the SCP progress output.
-/// * `progress` - A reference to the ProgressBar for updating upload progress.
+/// * `observer` - Receives the upload percentage and the file being transferred.
 ///
-fn upload_album(
+async fn upload_album(
     album_path: &str,
     remote_path: &str,
     settings: &RemoteSettings,
-    re: &Regex,
-    progress: &ProgressBar,
+    observer: &dyn SyncObserver,
 ) -> io::Result<()> {
-    let mut child = Command::new("scp")
-        .args(&["-r", "-i", &settings.ssh_key_path, album_path, remote_path])
+    if settings.filters_uploads() {
+        return upload_album_files(album_path, remote_path, settings, observer).await;
+    }
+    run_scp(
+        build_scp_command(album_path, remote_path, settings),
+        settings.upload_timeout(),
+        observer,
+    )
+    .await
+}
+
+/// Uploads the files of an album passing the upload extension filters, as scp can't
+/// filter a recursive copy.
+///
+/// The remote album directory and its sub-folders are created over ssh, then the files
+/// of each folder are uploaded with one scp. Nothing is uploaded when no file passes
+/// the filters.
+///
+/// # Arguments
+///
+/// * `album_path` - The local path of the album to be uploaded.
+/// * `remote_path` - The `user@host:path` destination of the album.
+/// * `settings` - A reference to the RemoteSettings containing the SSH and transfer options.
+/// * `observer` - Receives the upload percentage and the file being transferred.
+///
+async fn upload_album_files(
+    album_path: &str,
+    remote_path: &str,
+    settings: &RemoteSettings,
+    observer: &dyn SyncObserver,
+) -> io::Result<()> {
+    let mut folders: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for file in uploaded_files(album_path, settings) {
+        let folder = file
+            .parent()
+            .and_then(|parent| parent.strip_prefix(album_path).ok())
+            .map(|relative| relative.to_string_lossy().replace('\\', "/"))
+            .unwrap_or_default();
+        folders.entry(folder).or_default().push(file);
+    }
+    if folders.is_empty() {
+        return Ok(());
+    }
+
+    let remote_folder = |folder: &str| match folder {
+        "" => remote_path.to_string(),
+        folder => format!("{}/{}", normalize_remote_dir(remote_path), folder),
+    };
+    let remote_dirs = folders
+        .keys()
+        .map(|folder| {
+            let remote = remote_folder(folder);
+            let dir = remote
+                .split_once(':')
+                .map_or(remote.as_str(), |(_, path)| path);
+            shell_quote(dir)
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mkdir = build_ssh_command(&format!("mkdir -p -- {}", remote_dirs), settings);
+    let status = tokio::process::Command::from(mkdir)
+        .kill_on_drop(true)
+        .status()
+        .await?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "Failed to create the remote album directories: ssh exited with status {}",
+            status
+        )));
+    }
+
+    for (folder, files) in &folders {
+        run_scp(
+            build_scp_files_command(files, &remote_folder(folder), settings),
+            settings.upload_timeout(),
+            observer,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Runs an scp `command` to completion on the tokio runtime, reporting the progress it
+/// prints on stderr to `observer`.
+///
+/// The process is killed if the returned future is dropped before it exits.
+async fn run_scp(
+    command: Command,
+    timeout: Option<Duration>,
+    observer: &dyn SyncObserver,
+) -> io::Result<()> {
+    let re = scp_progress_regex();
+    let mut current_file = String::new();
+    let mut child = tokio::process::Command::from(command)
         .stderr(Stdio::piped())
+        .kill_on_drop(true)
         .spawn()?;
+    let stderr = child.stderr.take();
+    let mut connection_dropped = false;
 
-    if let Some(stderr) = child.stderr.take() {
-        for line in BufReader::new(stderr).lines().filter_map(Result::ok) {
-            if let Some(cap) = re.captures(&line) {
-                if let Some(percent) = cap.get(1).and_then(|m| m.as_str().parse::<u64>().ok()) {
-                    progress.set_position(percent);
-                }
+    let status = wait_with_timeout(&mut child, stderr, b'\n', timeout, &mut |line| {
+        connection_dropped |= is_connection_drop(line);
+        if let Some(file) = scp_file_name(line) {
+            if file != current_file {
+                current_file = file.to_string();
+                observer.on_album_upload_file(file);
             }
         }
-    }
-
-    let status = child.wait()?;
+        if let Some(cap) = re.captures(line) {
+            if let Some(percent) = cap.get(1).and_then(|m| m.as_str().parse::<u64>().ok()) {
+                observer.on_album_upload_progress(percent);
+            }
+        }
+    })
+    .await?;
     if !status.success() {
+        // ssh exits with 255 when the connection fails.
+        let kind = if connection_dropped || status.code() == Some(255) {
+            io::ErrorKind::ConnectionAborted
+        } else {
+            io::ErrorKind::Other
+        };
         return Err(io::Error::new(
-            io::ErrorKind::Other,
+            kind,
             format!("SCP command failed with status: {}", status),
         ));
     }
 
     Ok(())
 }
+
+/// Waits for `child` to exit, passing each `delimiter`-separated chunk of `output` to
+/// `on_chunk` as it is printed.
+///
+/// When `timeout` elapses first, the child is killed and reaped, and a `TimedOut` error
+/// is returned. The output is read asynchronously, so a child that stalls without
+/// printing anything is still killed on time.
+///
+/// # Arguments
+///
+/// * `child` - The running process.
+/// * `output` - The piped stdout or stderr of the process, if any.
+/// * `delimiter` - The byte separating progress updates in `output`.
+/// * `timeout` - How long the process may run. It is never killed when unset, or when
+///   the deadline is too far in the future to be represented.
+/// * `on_chunk` - Called with each chunk of output, without its delimiter.
+///
+pub(super) async fn wait_with_timeout(
+    child: &mut Child,
+    output: Option<impl AsyncRead + Unpin>,
+    delimiter: u8,
+    timeout: Option<Duration>,
+    on_chunk: &mut dyn FnMut(&str),
+) -> io::Result<ExitStatus> {
+    let run = async {
+        if let Some(output) = output {
+            let mut chunks = BufReader::new(output).split(delimiter);
+            while let Ok(Some(chunk)) = chunks.next_segment().await {
+                on_chunk(&String::from_utf8_lossy(&chunk));
+            }
+        }
+        // The output is closed, but the process may still be running.
+        child.wait().await
+    };
+
+    let deadline = timeout.and_then(|duration| {
+        tokio::time::Instant::now()
+            .checked_add(duration)
+            .map(|deadline| (duration, deadline))
+    });
+    let Some((duration, deadline)) = deadline else {
+        return run.await;
+    };
+    match tokio::time::timeout_at(deadline, run).await {
+        Ok(status) => status,
+        Err(_) => kill_timed_out(child, duration).await,
+    }
+}
+
+/// Kills a process that ran past its timeout and waits for it so it doesn't linger as a
+/// zombie.
+async fn kill_timed_out(child: &mut Child, timeout: Duration) -> io::Result<ExitStatus> {
+    // The process may have exited on its own in the meantime.
+    let _ = child.start_kill();
+    child.wait().await?;
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!("Upload timed out after {}s", timeout.as_secs()),
+    ))
+}
+
+/// Returns `true` if an scp/ssh error line reports a connection lost mid-transfer.
+///
+/// The whole line must be one of the messages scp and ssh print when the connection
+/// drops, so a file name such as `Connection closed.flac`, in a progress line or in a
+/// `scp: <path>: <error>` line about a remote file, isn't taken for one.
+fn is_connection_drop(line: &str) -> bool {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(concat!(
+            r"^(lost connection",
+            r"|client_loop: send disconnect: .+",
+            r"|packet_write_wait: Connection to .+",
+            r"|Read from remote host .+: .+",
+            r"|Connection (to \S+ )?closed by remote host\.?",
+            r"|Connection reset by .+",
+            r"|Timeout, server .+ not responding\.?)$",
+        ))
+        .expect("valid connection drop regex")
+    })
+    .is_match(line.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::ProgressStyleSettings;
+    use crate::observer::tests::RecordingObserver;
+    use mockall::predicate::*;
+
+    fn test_settings() -> RemoteSettings {
+        RemoteSettings {
+            remote_user: String::from("user"),
+            remote_host: String::from("example.com"),
+            remote_path: String::from("/music"),
+            ssh_key_path: Some(String::from("/path/to/ssh_key")),
+            ..RemoteSettings::default()
+        }
+    }
+
+    fn command_args(command: &Command) -> Vec<String> {
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_upload_failure_count_propagates() {
+        let settings = test_settings();
+        let missing_albums = vec![
+            String::from("/library/Artist1/Album1"),
+            String::from("/library/Artist2/Album2"),
+        ];
+
+        let mut uploader = MockAlbumUploader::new();
+        uploader
+            .expect_upload()
+            .with(
+                eq("/library/Artist1/Album1"),
+                eq("user@example.com:/music/Artist1/Album1"),
+                always(),
+            )
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+        uploader
+            .expect_upload()
+            .with(
+                eq("/library/Artist2/Album2"),
+                eq("user@example.com:/music/Artist2/Album2"),
+                always(),
+            )
+            .times(1)
+            .returning(|_, _, _| Err(io::Error::other("connection refused")));
+
+        let summary = upload_albums_with(
+            &missing_albums,
+            &settings,
+            &uploader,
+            &ConsoleObserver::new(),
+        )
+        .await;
+
+        assert_eq!(summary.failed, 1);
+    }
+
+    #[tokio::test]
+    async fn test_upload_summary_counts_successes_failures_and_bytes() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut albums = Vec::new();
+        for (album, size) in [("Album1", 1000), ("Album2", 250), ("Album3", 4000)] {
+            let path = temp_dir.path().join("Artist").join(album);
+            std::fs::create_dir_all(&path).unwrap();
+            std::fs::write(path.join("01.flac"), vec![0u8; size]).unwrap();
+            std::fs::write(path.join("cover.jpg"), vec![0u8; 24]).unwrap();
+            albums.push(path.to_str().unwrap().to_string());
+        }
+
+        let mut uploader = MockAlbumUploader::new();
+        uploader
+            .expect_upload()
+            .with(eq(albums[1].clone()), always(), always())
+            .returning(|_, _, _| Err(io::Error::other("connection refused")));
+        uploader.expect_upload().returning(|_, _, _| Ok(()));
+
+        let summary = upload_albums_with(
+            &albums,
+            &test_settings(),
+            &uploader,
+            &ConsoleObserver::new(),
+        )
+        .await;
+
+        assert_eq!(summary.succeeded, 2);
+        assert_eq!(summary.failed, 1);
+        assert_eq!(summary.total(), 3);
+        assert_eq!(summary.total_bytes, 1024 + 4024);
+    }
+
+    #[tokio::test]
+    async fn test_upload_reports_progress_to_observer() {
+        let missing_albums = vec![
+            String::from("/library/Artist1/Album1"),
+            String::from("/"),
+            String::from("/library/Artist2/Album2"),
+        ];
+
+        let mut uploader = MockAlbumUploader::new();
+        uploader
+            .expect_upload()
+            .with(eq("/library/Artist1/Album1"), always(), always())
+            .returning(|_, _, observer| {
+                observer.on_album_upload_progress(50);
+                observer.on_album_upload_progress(100);
+                Ok(())
+            });
+        uploader
+            .expect_upload()
+            .with(eq("/library/Artist2/Album2"), always(), always())
+            .returning(|_, _, _| Err(io::Error::other("connection refused")));
+        let observer = RecordingObserver::default();
+
+        upload_albums_with(&missing_albums, &test_settings(), &uploader, &observer).await;
+
+        assert_eq!(
+            observer.events(),
+            [
+                "upload_started 3",
+                "album_started Artist1 - Album1",
+                "progress 50",
+                "progress 100",
+                "upload_done /library/Artist1/Album1 ok",
+                "upload_done / failed",
+                "album_started Artist2 - Album2",
+                "upload_done /library/Artist2/Album2 failed",
+                "uploads_finished 1/3",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_group_by_artist_uploads_artist_albums_together() {
+        let settings = RemoteSettings {
+            group_by_artist: true,
+            ..test_settings()
+        };
+        let missing_albums = vec![
+            String::from("/library/Beatles/Revolver"),
+            String::from("/library/Radiohead/Kid A"),
+            String::from("/library/Beatles/Help"),
+            String::from("/library/Beatles/Let It Be"),
+        ];
+
+        let mut uploader = MockAlbumUploader::new();
+        uploader
+            .expect_upload_artist()
+            .with(
+                eq("/library/Beatles"),
+                eq(vec![
+                    String::from("Revolver"),
+                    String::from("Help"),
+                    String::from("Let It Be"),
+                ]),
+                eq("user@example.com:/music/Beatles"),
+                always(),
+            )
+            .times(1)
+            .returning(|_, albums, _, _| albums.iter().map(|_| Ok(())).collect());
+        uploader
+            .expect_upload()
+            .with(
+                eq("/library/Radiohead/Kid A"),
+                eq("user@example.com:/music/Radiohead/Kid A"),
+                always(),
+            )
+            .times(1)
+            .returning(|_, _, _| Err(io::Error::other("connection refused")));
+
+        let summary = upload_albums_with(
+            &missing_albums,
+            &settings,
+            &uploader,
+            &ConsoleObserver::new(),
+        )
+        .await;
+
+        assert_eq!(summary.succeeded, 3);
+        assert_eq!(summary.failed, 1);
+    }
+
+    #[tokio::test]
+    async fn test_group_by_artist_reports_each_album_result() {
+        let settings = RemoteSettings {
+            group_by_artist: true,
+            ..test_settings()
+        };
+        let missing_albums = vec![
+            String::from("/library/Beatles/Revolver"),
+            String::from("/library/Beatles/Help"),
+            String::from("/library/Beatles/Let It Be"),
+        ];
+
+        let mut uploader = MockAlbumUploader::new();
+        uploader
+            .expect_upload_artist()
+            .times(1)
+            .returning(|_, _, _, _| {
+                vec![Ok(()), Err(io::Error::other("connection refused")), Ok(())]
+            });
+
+        let observer = RecordingObserver::default();
+        let summary = upload_albums_with(&missing_albums, &settings, &uploader, &observer).await;
+
+        assert_eq!(summary.succeeded, 2);
+        assert_eq!(summary.failed, 1);
+        let events = observer.events();
+        assert!(events.contains(&String::from("upload_done /library/Beatles/Revolver ok")));
+        assert!(events.contains(&String::from("upload_done /library/Beatles/Help failed")));
+        assert!(events.contains(&String::from("upload_done /library/Beatles/Let It Be ok")));
+    }
+
+    #[test]
+    fn test_upload_summary_display() {
+        let summary = UploadSummary {
+            succeeded: 42,
+            failed: 0,
+            total_bytes: 19_649_534_771,
+            elapsed: Duration::from_secs(12 * 60 + 5),
+        };
+        assert_eq!(
+            summary.to_string(),
+            "Uploaded 42 album(s), 18.3 GiB in 12m 5s"
+        );
+
+        let summary = UploadSummary {
+            succeeded: 1,
+            failed: 2,
+            total_bytes: 512,
+            elapsed: Duration::from_secs(3 * 3600 + 61),
+        };
+        assert_eq!(
+            summary.to_string(),
+            "Uploaded 1 album(s), 512 B in 3h 1m, 2 failed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_album_path_counts_as_failure() {
+        let settings = test_settings();
+        let missing_albums = vec![String::from("/"), String::from("/library/Artist/Album")];
+
+        let mut uploader = MockAlbumUploader::new();
+        uploader
+            .expect_upload()
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        let summary = upload_albums_with(
+            &missing_albums,
+            &settings,
+            &uploader,
+            &ConsoleObserver::new(),
+        )
+        .await;
+
+        assert_eq!(summary.failed, 1);
+    }
+
+    #[tokio::test]
+    async fn test_delete_after_upload_only_removes_uploaded_albums() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let uploaded = temp_dir.path().join("Artist").join("Uploaded");
+        let failed = temp_dir.path().join("Artist").join("Failed");
+        std::fs::create_dir_all(&uploaded).unwrap();
+        std::fs::create_dir_all(&failed).unwrap();
+        std::fs::write(uploaded.join("01.mp3"), b"audio").unwrap();
+        std::fs::write(failed.join("01.mp3"), b"audio").unwrap();
+        let uploaded = uploaded.to_str().unwrap().to_string();
+        let failed = failed.to_str().unwrap().to_string();
+
+        let settings = RemoteSettings {
+            delete_after_upload: true,
+            ..test_settings()
+        };
+        let missing_albums = vec![uploaded.clone(), failed.clone()];
+
+        let mut uploader = MockAlbumUploader::new();
+        uploader
+            .expect_upload()
+            .with(eq(uploaded.clone()), always(), always())
+            .returning(|_, _, _| Ok(()));
+        uploader
+            .expect_upload()
+            .with(eq(failed.clone()), always(), always())
+            .returning(|_, _, _| Err(io::Error::other("connection refused")));
+
+        let summary = upload_albums_with(
+            &missing_albums,
+            &settings,
+            &uploader,
+            &ConsoleObserver::new(),
+        )
+        .await;
+
+        assert_eq!(summary.failed, 1);
+        assert_eq!(summary.total_bytes, 5);
+        assert!(!Path::new(&uploaded).exists());
+        assert!(Path::new(&failed).exists());
+    }
+
+    #[tokio::test]
+    async fn test_failed_delete_after_upload_is_reported() {
+        let settings = RemoteSettings {
+            delete_after_upload: true,
+            ..test_settings()
+        };
+        let missing_albums = vec![String::from("/nonexistent/Artist/Album")];
+        let mut uploader = MockAlbumUploader::new();
+        uploader.expect_upload().returning(|_, _, _| Ok(()));
+        let observer = RecordingObserver::default();
+
+        let summary = upload_albums_with(&missing_albums, &settings, &uploader, &observer).await;
+
+        assert_eq!(summary.succeeded, 1);
+        assert!(observer.events().iter().any(
+            |event| event.starts_with("warn Uploaded Artist - Album but failed to delete it:")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_singles_album_uploads_and_deletes_only_loose_tracks() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let artist = temp_dir.path().join("Artist");
+        std::fs::create_dir_all(artist.join("Album")).unwrap();
+        std::fs::write(artist.join("Album").join("01.mp3"), b"audio").unwrap();
+        std::fs::write(artist.join("single.mp3"), b"single").unwrap();
+        let singles = artist.join("Singles").to_str().unwrap().to_string();
+        let album = artist.join("Album").to_str().unwrap().to_string();
+
+        let settings = RemoteSettings {
+            delete_after_upload: true,
+            group_by_artist: true,
+            ..test_settings()
+        };
+        assert_eq!(
+            group_albums(vec![&singles, &album], true),
+            [vec![&singles], vec![&album]]
+        );
+        let mut uploader = MockAlbumUploader::new();
+        uploader
+            .expect_upload()
+            .with(eq(singles.clone()), always(), always())
+            .withf(|_, remote_path, _| remote_path.ends_with("/Artist/Singles"))
+            .returning(|_, _, _| Ok(()));
+
+        let summary = upload_albums_with(
+            std::slice::from_ref(&singles),
+            &settings,
+            &uploader,
+            &ConsoleObserver::new(),
+        )
+        .await;
+
+        assert_eq!(summary.succeeded, 1);
+        assert_eq!(summary.total_bytes, 6);
+        assert!(!artist.join("single.mp3").exists());
+        assert!(Path::new(&album).exists());
+    }
+
+    #[test]
+    fn test_remote_path_slashes_are_normalized() {
+        for remote_path in ["/music", "/music/", "/music//"] {
+            let settings = RemoteSettings {
+                remote_path: String::from(remote_path),
+                ..test_settings()
+            };
+
+            assert_eq!(
+                create_remote_path(&settings, "Artist", "Album"),
+                "user@example.com:/music/Artist/Album"
+            );
+            assert_eq!(
+                create_remote_artist_path(&settings, "Artist"),
+                "user@example.com:/music/Artist"
+            );
+        }
+
+        assert_eq!(normalize_remote_dir("/srv//music/lib/"), "/srv/music/lib");
+        assert_eq!(normalize_remote_dir("/"), "");
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_windows_album_path_gets_a_forward_slash_remote_path() {
+        let settings = test_settings();
+
+        for album_path in [
+            r"C:\Music\Artist\Album",
+            r"C:\Music\Artist\Album\",
+            "/library/Artist/Album",
+        ] {
+            let (artist, album) = extract_artist_and_album(album_path).unwrap();
+            assert_eq!(
+                create_remote_path(&settings, &artist, &album),
+                "user@example.com:/music/Artist/Album",
+                "{album_path}"
+            );
+        }
+        assert!(extract_artist_and_album(r"C:\Album").is_err());
+        assert!(extract_artist_and_album("/").is_err());
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn test_backslash_is_part_of_a_folder_name() {
+        assert_eq!(
+            extract_artist_and_album(r"/library/AC\DC/Back in Black").unwrap(),
+            (r"AC\DC".to_string(), "Back in Black".to_string())
+        );
+        assert_eq!(
+            extract_artist_and_album("/library/Artist/Album/").unwrap(),
+            ("Artist".to_string(), "Album".to_string())
+        );
+        assert!(extract_artist_and_album("/").is_err());
+    }
+
+    #[test]
+    fn test_join_local_path() {
+        assert_eq!(
+            join_local_path("/library/Artist/", "Album"),
+            "/library/Artist/Album"
+        );
+        assert_eq!(
+            join_local_path("/library//Artist", "Album"),
+            "/library/Artist/Album"
+        );
+    }
+
+    #[test]
+    fn test_scp_command_without_bandwidth_limit() {
+        let settings = test_settings();
+
+        let command = build_scp_command("/library/A/B", "user@example.com:/music/A/B", &settings);
+
+        assert_eq!(command.get_program(), "scp");
+        assert_eq!(
+            command_args(&command),
+            [
+                "-r",
+                "-i",
+                "/path/to/ssh_key",
+                "/library/A/B",
+                "user@example.com:/music/A/B"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scp_command_without_ssh_key() {
+        let settings = RemoteSettings {
+            ssh_key_path: None,
+            ..test_settings()
+        };
+
+        let command = build_scp_command("/library/A/B", "user@example.com:/music/A/B", &settings);
+
+        assert_eq!(
+            command_args(&command),
+            ["-r", "/library/A/B", "user@example.com:/music/A/B"]
+        );
+    }
+
+    #[test]
+    fn test_scp_command_with_bandwidth_limit() {
+        let settings = RemoteSettings {
+            bandwidth_limit_kbps: Some(8000),
+            ..test_settings()
+        };
+
+        let command = build_scp_command("/library/A/B", "user@example.com:/music/A/B", &settings);
+
+        let args = command_args(&command);
+        let limit = args.iter().position(|arg| arg == "-l").unwrap();
+        assert_eq!(args[limit + 1], "8000");
+        assert_eq!(args.last().unwrap(), "user@example.com:/music/A/B");
+    }
+
+    #[test]
+    fn test_scp_command_with_port_and_extra_options() {
+        let settings = RemoteSettings {
+            ssh_port: Some(2222),
+            ssh_extra_opts: vec![
+                String::from("StrictHostKeyChecking=accept-new"),
+                String::from("ConnectTimeout=10"),
+            ],
+            ..test_settings()
+        };
+
+        let command = build_scp_command("/library/A/B", "user@example.com:/music/A/B", &settings);
+
+        assert_eq!(
+            command_args(&command),
+            [
+                "-r",
+                "-i",
+                "/path/to/ssh_key",
+                "-P",
+                "2222",
+                "-o",
+                "StrictHostKeyChecking=accept-new",
+                "-o",
+                "ConnectTimeout=10",
+                "/library/A/B",
+                "user@example.com:/music/A/B"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scp_and_ssh_commands_with_cipher_and_compression() {
+        let settings = RemoteSettings {
+            ssh_cipher: Some(String::from("aes128-gcm@openssh.com")),
+            ssh_compression: true,
+            ..test_settings()
+        };
+
+        let scp = build_scp_command("/library/A/B", "user@example.com:/music/A/B", &settings);
+        let ssh = build_ssh_command("true", &settings);
+
+        assert_eq!(
+            command_args(&scp),
+            [
+                "-r",
+                "-i",
+                "/path/to/ssh_key",
+                "-c",
+                "aes128-gcm@openssh.com",
+                "-C",
+                "/library/A/B",
+                "user@example.com:/music/A/B"
+            ]
+        );
+        assert_eq!(
+            command_args(&ssh),
+            [
+                "-i",
+                "/path/to/ssh_key",
+                "-c",
+                "aes128-gcm@openssh.com",
+                "-C",
+                "user@example.com",
+                "true"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ssh_multiplex_adds_control_options_to_every_command() {
+        let settings = RemoteSettings {
+            ssh_multiplex: true,
+            ..test_settings()
+        };
+        let control_options = [
+            "-o".to_string(),
+            "ControlMaster=auto".to_string(),
+            "-o".to_string(),
+            format!("ControlPath={}", ssh_control_path()),
+        ];
+
+        let scp = build_scp_command("/library/A/B", "user@example.com:/music/A/B", &settings);
+        let ssh = build_ssh_command("true", &settings);
+        let master = build_ssh_master_command(&settings);
+        let exit = build_ssh_master_exit_command(&settings);
+
+        for command in [&scp, &ssh, &master, &exit] {
+            let args = command_args(command);
+            assert!(
+                args.windows(4).any(|window| window == control_options),
+                "{args:?}"
+            );
+        }
+        assert!(command_args(&master).ends_with(&[
+            "-o".to_string(),
+            "ControlPersist=yes".to_string(),
+            "-f".to_string(),
+            "-N".to_string(),
+            "user@example.com".to_string()
+        ]));
+        assert!(command_args(&exit).ends_with(&[
+            "-O".to_string(),
+            "exit".to_string(),
+            "user@example.com".to_string()
+        ]));
+        assert!(ssh_control_path().ends_with(&format!("musync-ssh-{}-%C", process::id())));
+
+        let scp = build_scp_command(
+            "/library/A/B",
+            "user@example.com:/music/A/B",
+            &test_settings(),
+        );
+        assert!(!command_args(&scp).contains(&"ControlMaster=auto".to_string()));
+    }
+
+    #[test]
+    fn test_default_progress_style_is_valid() {
+        assert!(validate_progress_style(&test_settings()).is_ok());
+    }
+
+    #[test]
+    fn test_custom_progress_style_is_valid() {
+        let settings = RemoteSettings {
+            progress_style: Some(ProgressStyleSettings {
+                template: String::from("[{bar:30.green/white}] {pos}/{len} {unit}"),
+                progress_chars: String::from("=> "),
+            }),
+            ..test_settings()
+        };
+
+        assert!(validate_progress_style(&settings).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_progress_template_returns_error() {
+        let settings = RemoteSettings {
+            progress_style: Some(ProgressStyleSettings {
+                template: String::from("{elapsed_precise} [{bar:cyan/blue}]"),
+                progress_chars: String::from("##-"),
+            }),
+            ..test_settings()
+        };
+
+        let error = validate_progress_style(&settings).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_progress_template_fails_upload() {
+        let settings = RemoteSettings {
+            progress_style: Some(ProgressStyleSettings {
+                template: String::from("{elapsed_precise} [{bar:cyan/blue}]"),
+                progress_chars: String::from("##-"),
+            }),
+            ..test_settings()
+        };
+        let missing_albums = vec![String::from("/library/Artist/Album")];
+
+        let error = upload_missing_albums(&missing_albums, &settings)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_scp_progress_regex_parses_percentage() {
+        let line = "Track01.flac   42%   12MB   1.2MB/s   00:10 ETA";
+
+        let percent = &scp_progress_regex().captures(line).unwrap()[1];
+
+        assert_eq!(percent, "42");
+    }
+
+    #[test]
+    fn test_scp_file_name() {
+        for (line, file) in [
+            (
+                "Track01.flac   42%   12MB   1.2MB/s   00:10 ETA",
+                Some("Track01.flac"),
+            ),
+            (
+                "01 - 100% Pure Love.mp3                 100% 8192KB   4.0MB/s   00:02",
+                Some("01 - 100% Pure Love.mp3"),
+            ),
+            (
+                "01.flac  10%  1MB  1.0MB/s  00:09 ETA\r02.flac   0%    0     0.0KB/s   --:-- ETA",
+                Some("02.flac"),
+            ),
+            ("lost connection", None),
+            ("", None),
+        ] {
+            assert_eq!(scp_file_name(line), file, "{line}");
+        }
+    }
+
+    #[test]
+    fn test_too_few_progress_chars_returns_error() {
+        let settings = RemoteSettings {
+            progress_style: Some(ProgressStyleSettings {
+                template: String::from("[{bar}]"),
+                progress_chars: String::from("#"),
+            }),
+            ..test_settings()
+        };
+
+        assert!(validate_progress_style(&settings).is_err());
+    }
+
+    async fn connection_dropped() -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            "SCP command failed with status: exit status: 1",
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_connection_drop_falls_back_to_rsync() {
+        let settings = RemoteSettings {
+            upload_fallback: true,
+            ..test_settings()
+        };
+        let mut rsync_called = false;
+        let observer = RecordingObserver::default();
+
+        let result = upload_with_fallback(
+            &settings,
+            || true,
+            connection_dropped(),
+            async {
+                rsync_called = true;
+                Ok(())
+            },
+            &observer,
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert!(rsync_called);
+        assert_eq!(
+            observer.events(),
+            ["warn Connection dropped during scp upload \
+              (SCP command failed with status: exit status: 1), resuming with rsync"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_no_fallback_when_disabled_or_not_a_connection_drop() {
+        let enabled = RemoteSettings {
+            upload_fallback: true,
+            ..test_settings()
+        };
+        async fn rsync() -> io::Result<()> {
+            panic!("rsync must not be used")
+        }
+        let observer = RecordingObserver::default();
+
+        assert!(upload_with_fallback(
+            &test_settings(),
+            || true,
+            connection_dropped(),
+            rsync(),
+            &observer
+        )
+        .await
+        .is_err());
+        assert!(
+            upload_with_fallback(&enabled, || false, connection_dropped(), rsync(), &observer)
+                .await
+                .is_err()
+        );
+        assert!(upload_with_fallback(
+            &enabled,
+            || true,
+            async { Err(io::Error::other("permission denied")) },
+            rsync(),
+            &observer
+        )
+        .await
+        .is_err());
+        assert!(observer.events().is_empty());
+    }
+
+    #[test]
+    fn test_is_connection_drop() {
+        assert!(is_connection_drop("lost connection"));
+        assert!(is_connection_drop(
+            "client_loop: send disconnect: Broken pipe"
+        ));
+        assert!(is_connection_drop(
+            "Connection reset by 203.0.113.7 port 22"
+        ));
+        assert!(is_connection_drop(
+            "Connection to nas.local closed by remote host."
+        ));
+        assert!(!is_connection_drop("scp: /music/A/B: Permission denied"));
+        assert!(!is_connection_drop(
+            "scp: /music/Connection closed/01.flac: Permission denied"
+        ));
+        assert!(!is_connection_drop(
+            "Broken pipe.flac   42%   12MB   1.2MB/s   00:10 ETA"
+        ));
+        assert!(!is_connection_drop(
+            "01.flac   42%   12MB   1.2MB/s   00:10 ETA"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stuck_upload_is_killed_after_timeout() {
+        let mut child = tokio::process::Command::new("sleep")
+            .arg("30")
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdout = child.stdout.take();
+        let started = Instant::now();
+
+        let error = wait_with_timeout(
+            &mut child,
+            stdout,
+            b'\n',
+            Some(Duration::from_millis(200)),
+            &mut |_| {},
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(10));
+        // The killed child has been reaped.
+        assert!(child.try_wait().unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_upload_output_is_read_until_exit() {
+        let mut child = tokio::process::Command::new("sh")
+            .args(["-c", "echo 1%; echo 50%; echo 100%"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdout = child.stdout.take();
+        let mut chunks = Vec::new();
+
+        let status = wait_with_timeout(
+            &mut child,
+            stdout,
+            b'\n',
+            Some(Duration::from_secs(10)),
+            &mut |chunk| chunks.push(chunk.to_string()),
+        )
+        .await
+        .unwrap();
+
+        assert!(status.success());
+        assert_eq!(chunks, ["1%", "50%", "100%"]);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_too_long_for_a_deadline_never_expires() {
+        let mut child = tokio::process::Command::new("true").spawn().unwrap();
+
+        let status = wait_with_timeout(
+            &mut child,
+            None::<tokio::process::ChildStdout>,
+            b'\n',
+            Some(Duration::MAX),
+            &mut |_| {},
+        )
+        .await
+        .unwrap();
+
+        assert!(status.success());
+    }
+
+    #[tokio::test]
+    async fn test_scp_progress_is_reported_until_the_command_exits() {
+        // Stands in for scp, printing its progress lines on stderr while it runs.
+        let mut command = Command::new("sh");
+        command.args([
+            "-c",
+            "for p in 10 60; do echo \"01.flac  $p%  1MB  1.0MB/s  00:01 ETA\" >&2; sleep 0.1; done; \
+             echo '02.flac  100%  2MB  1.0MB/s  00:02' >&2",
+        ]);
+        let observer = RecordingObserver::default();
+
+        run_scp(command, Some(Duration::from_secs(10)), &observer)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            observer.events(),
+            [
+                "file 01.flac",
+                "progress 10",
+                "progress 60",
+                "file 02.flac",
+                "progress 100"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_df_free_bytes() {
+        let output = "Filesystem        1B-blocks         Used   Available Use% Mounted on\n\
+                      /dev/sda1      500107862016 445040496640 5367365376  99% /srv\n";
+        assert_eq!(parse_df_free_bytes(output).unwrap(), 5_367_365_376);
+
+        let wrapped = "Filesystem 1B-blocks Used Available Use% Mounted on\n\
+                       /dev/mapper/very--long--volume--group-music\n\
+                       1000 400 600  40% /srv/music\n";
+        assert_eq!(parse_df_free_bytes(wrapped).unwrap(), 600);
+
+        let error = parse_df_free_bytes("df: /music: No such file or directory\n").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_check_free_space() {
+        assert!(check_free_space(5 * 1024, 5 * 1024).is_ok());
+        assert!(check_free_space(0, 0).is_ok());
+
+        let error = check_free_space(30 * 1024 * 1024 * 1024, 5 * 1024 * 1024 * 1024).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::StorageFull);
+        assert_eq!(
+            error.to_string(),
+            "Not enough space on the remote host: 30.0 GiB to upload but only 5.0 GiB free"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remote_space_is_not_checked_by_default() {
+        let missing_albums = vec![String::from("/library/Artist/Album")];
+
+        assert!(ensure_remote_space(&missing_albums, &test_settings())
+            .await
+            .is_ok());
+    }
+
+    #[test]
+    fn test_parse_remote_file_count() {
+        assert_eq!(parse_remote_file_count("12\n").unwrap(), 12);
+        assert_eq!(parse_remote_file_count("      7\n").unwrap(), 7);
+        assert_eq!(parse_remote_file_count("0").unwrap(), 0);
+
+        let error = parse_remote_file_count("find: '/music/A': No such file\n").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_ssh_command_counts_remote_files() {
+        let settings = RemoteSettings {
+            ssh_port: Some(2222),
+            ..test_settings()
+        };
+
+        let command = build_ssh_command("find '/music/A/B' -type f | wc -l", &settings);
+
+        assert_eq!(command.get_program(), "ssh");
+        assert_eq!(
+            command_args(&command),
+            [
+                "-i",
+                "/path/to/ssh_key",
+                "-p",
+                "2222",
+                "user@example.com",
+                "find '/music/A/B' -type f | wc -l"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scp_uploads_the_files_passing_the_extension_filters() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let album = temp_dir.path().join("Album");
+        fs::create_dir_all(album.join("CD1")).unwrap();
+        fs::create_dir_all(album.join("Artwork")).unwrap();
+        for file in ["CD1/01.FLAC", "02.flac", "rip.log", "Artwork/cover.jpg"] {
+            fs::write(album.join(file), b"").unwrap();
+        }
+        let settings = RemoteSettings {
+            upload_include_extensions: vec!["flac".to_string(), "log".to_string()],
+            upload_exclude_extensions: vec![".log".to_string()],
+            ..test_settings()
+        };
+        let album_path = album.to_string_lossy();
+
+        assert_eq!(
+            uploaded_files(&album_path, &settings),
+            [album.join("02.flac"), album.join("CD1").join("01.FLAC")]
+        );
+        assert_eq!(local_file_count(&album_path, &test_settings()), 4);
+
+        let command = build_scp_files_command(
+            &[album.join("02.flac")],
+            "user@example.com:/music/A/Album",
+            &settings,
+        );
+        let args = command_args(&command);
+        assert_eq!(
+            args[args.len() - 2..],
+            [
+                album.join("02.flac").to_string_lossy().into_owned(),
+                "user@example.com:/music/A/Album/".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/music/A/B"), "'/music/A/B'");
+        assert_eq!(
+            shell_quote("/music/Guns N' Roses"),
+            r"'/music/Guns N'\'' Roses'"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_album_path_is_uploaded_once() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let album = temp_dir.path().join("Artist").join("Album");
+        std::fs::create_dir_all(&album).unwrap();
+        let album = album.to_str().unwrap().to_string();
+        let same_album = format!("{}/Artist/../Artist/Album", temp_dir.path().display());
+
+        let settings = test_settings();
+        let missing_albums = vec![album.clone(), same_album, album.clone()];
+
+        let mut uploader = MockAlbumUploader::new();
+        uploader
+            .expect_upload()
+            .with(eq(album), always(), always())
+            .times(1)
+            .returning(|_, _, _| Ok(()));
+
+        let summary = upload_albums_with(
+            &missing_albums,
+            &settings,
+            &uploader,
+            &ConsoleObserver::new(),
+        )
+        .await;
+
+        assert_eq!(summary.succeeded, 1);
+        assert_eq!(summary.failed, 0);
+    }
+}
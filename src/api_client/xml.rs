@@ -0,0 +1,159 @@
+//! This module converts Subsonic XML responses (`f=xml`) into the JSON structure the
+//! same request returns with `f=json`, so both formats go through the same parsing.
+//!
+//! Subsonic maps XML to JSON directly: attributes become fields and child elements
+//! become nested objects. Elements that can repeat are always turned into arrays, and
+//! the few numeric and boolean attributes used by musync get their JSON types back.
+
+use crate::api_client::CompareError;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde_json::{Map, Value};
+
+/// Child elements that can repeat, as `(parent, child)` pairs. They are collected into
+/// arrays even when there is a single one, as in the JSON responses.
+const LIST_ELEMENTS: [(&str, &str); 6] = [
+    ("artists", "index"),
+    ("indexes", "index"),
+    ("index", "artist"),
+    ("artist", "album"),
+    ("directory", "child"),
+    ("musicFolders", "musicFolder"),
+];
+
+/// Attributes holding numbers in the JSON responses.
+const NUMBER_ATTRIBUTES: [&str; 4] = ["albumCount", "code", "lastModified", "songCount"];
+
+/// Attributes holding booleans in the JSON responses.
+const BOOLEAN_ATTRIBUTES: [&str; 2] = ["isDir", "openSubsonic"];
+
+/// Converts an XML Subsonic response into the equivalent JSON value.
+///
+/// # Arguments
+///
+/// * `xml` - The body of the response.
+///
+pub(super) fn xml_to_json(xml: &str) -> Result<Value, CompareError> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    // Elements being read, from the document root down to the current element.
+    let mut stack: Vec<(String, Map<String, Value>)> = vec![(String::new(), Map::new())];
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                let name = element_name(&element);
+                stack.push((name, attributes(&element)?));
+            }
+            Event::Empty(element) => {
+                let name = element_name(&element);
+                let value = Value::Object(attributes(&element)?);
+                let (parent_name, parent) = stack.last_mut().expect("root is never popped");
+                insert_child(parent_name, parent, name, value);
+            }
+            Event::End(_) if stack.len() > 1 => {
+                let (name, object) = stack.pop().expect("checked above");
+                let (parent_name, parent) = stack.last_mut().expect("root is never popped");
+                insert_child(parent_name, parent, name, Value::Object(object));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    let (_, root) = stack.swap_remove(0);
+    Ok(Value::Object(root))
+}
+
+fn element_name(element: &BytesStart) -> String {
+    String::from_utf8_lossy(element.local_name().as_ref()).into_owned()
+}
+
+/// Reads the attributes of an element into JSON fields, skipping namespace declarations.
+fn attributes(element: &BytesStart) -> Result<Map<String, Value>, CompareError> {
+    let mut fields = Map::new();
+
+    for attribute in element.attributes() {
+        let attribute = attribute.map_err(quick_xml::Error::from)?;
+        if attribute.key.as_namespace_binding().is_some() {
+            continue;
+        }
+        let key = String::from_utf8_lossy(attribute.key.local_name().as_ref()).into_owned();
+        let value = attribute.unescape_value()?.into_owned();
+        let value = if NUMBER_ATTRIBUTES.contains(&key.as_str()) {
+            value
+                .parse::<i64>()
+                .map_or(Value::String(value), Value::from)
+        } else if BOOLEAN_ATTRIBUTES.contains(&key.as_str()) {
+            value
+                .parse::<bool>()
+                .map_or(Value::String(value), Value::Bool)
+        } else {
+            Value::String(value)
+        };
+        fields.insert(key, value);
+    }
+
+    Ok(fields)
+}
+
+fn insert_child(parent_name: &str, parent: &mut Map<String, Value>, name: String, value: Value) {
+    if LIST_ELEMENTS.contains(&(parent_name, name.as_str())) {
+        match parent
+            .entry(name)
+            .or_insert_with(|| Value::Array(Vec::new()))
+        {
+            Value::Array(items) => items.push(value),
+            other => *other = Value::Array(vec![other.take(), value]),
+        }
+    } else {
+        parent.insert(name, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_xml_error_response() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <subsonic-response xmlns="http://subsonic.org/restapi" status="failed" version="1.16.1">
+                <error code="40" message="Wrong username or password"/>
+            </subsonic-response>"#;
+
+        assert_eq!(
+            xml_to_json(xml).unwrap(),
+            json!({
+                "subsonic-response": {
+                    "status": "failed",
+                    "version": "1.16.1",
+                    "error": { "code": 40, "message": "Wrong username or password" }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_xml_directory_children_are_an_array() {
+        let xml = r#"<subsonic-response status="ok" version="1.16.1">
+                <directory id="10" name="ABBA">
+                    <child id="11" title="Arrival &amp; More" isDir="true"/>
+                </directory>
+            </subsonic-response>"#;
+
+        let response = xml_to_json(xml).unwrap();
+
+        assert_eq!(
+            response["subsonic-response"]["directory"]["child"],
+            json!([{ "id": "11", "title": "Arrival & More", "isDir": true }])
+        );
+    }
+
+    #[test]
+    fn test_invalid_xml_is_an_error() {
+        assert!(xml_to_json("<subsonic-response><artists></subsonic-response>").is_err());
+    }
+}
@@ -1,21 +1,401 @@
+use crate::foundation::utils::{
+    ask, confirm, AlbumNameCleaner, DiscFolderMatcher, KeyStrategy,
+    DEFAULT_ALBUM_NAME_STRIP_PATTERN, DEFAULT_DISC_FOLDER_PATTERN,
+};
 use config::ConfigError;
 use serde::Deserialize;
+use std::io::{BufRead, Write};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::{env, fs, io};
 
 #[derive(Deserialize)]
 pub struct Settings {
     pub local_path: String,
-    pub remote_settings: RemoteSettings,
-    pub api_settings: ApiSettings,
+    #[serde(default)]
+    pub layout: LibraryLayout,
+    /// How many levels below `local_path` the artist (or album) folders are.
+    #[serde(default = "default_library_depth")]
+    pub library_depth: usize,
+    #[serde(default)]
+    pub use_tags: bool,
+    #[serde(default)]
+    pub on_missing_locally: MissingLocallyAction,
+    /// Regular expressions removed from album names, in order, before comparing them.
+    #[serde(default = "default_album_name_strip_patterns")]
+    pub album_name_strip_patterns: Vec<String>,
+    /// Match album names ignoring case, accents, punctuation and extra whitespace.
+    #[serde(default)]
+    pub fuzzy_album_match: bool,
+    /// Match albums on the MusicBrainz ID of their tags, falling back to their name.
+    #[serde(default)]
+    pub match_musicbrainz_ids: bool,
+    /// Regular expression matching the disc folders of multi-disc albums.
+    #[serde(default = "default_disc_folder_pattern")]
+    pub disc_folder_pattern: String,
+    /// Skip albums containing an empty or unreadable audio file.
+    #[serde(default)]
+    pub validate_audio: bool,
+    /// What tells that an artist folder changed and must be scanned again.
+    #[serde(default)]
+    pub change_detection: ChangeDetection,
+    /// Warn about albums whose folder name has no year.
+    #[serde(default)]
+    pub require_year: bool,
+    /// Name of the album sidecar file holding the canonical title, year and ID.
+    pub sidecar_file: Option<String>,
+    /// Name of the album made of the audio files directly in an artist folder. Empty to
+    /// leave those files out.
+    #[serde(default = "default_singles_album")]
+    pub singles_album: String,
+    /// How artist names are reduced to the keys they are stored and matched under.
+    #[serde(default)]
+    pub key_strategy: KeyStrategy,
+    /// Leading articles left out of artist keys, so `The Beatles` and `Beatles` are one
+    /// artist.
+    #[serde(default = "default_ignore_articles")]
+    pub ignore_articles: Vec<String>,
+    /// What the local library is compared with.
+    #[serde(default)]
+    pub comparison_backend: ComparisonBackend,
+    /// Which artists have their albums compared with the server's.
+    #[serde(default)]
+    pub comparison_mode: ComparisonMode,
+    /// Match server artists missing locally on their name transliterated to Latin
+    /// letters.
+    #[serde(default)]
+    pub transliterate_artists: bool,
+    /// The order albums are listed in reports.
+    #[serde(default)]
+    pub report_sort: ReportSort,
+    /// Shell command run after each sync, with its outcome in `MUSYNC_*` variables.
+    pub post_sync_command: Option<String>,
+    /// Most albums uploaded to a server in one run without `--confirm-large`.
+    pub max_upload_batch: Option<usize>,
+    /// Most remote albums deleted from a server in one run by mirroring without
+    /// `--confirm-large`.
+    #[serde(default = "default_max_mirror_delete")]
+    pub max_mirror_delete: usize,
+    /// Upload destination of servers that don't define their own `remote_settings`.
+    pub remote_settings: Option<RemoteSettings>,
+    pub api_settings: ApiServers,
 }
 
+fn default_album_name_strip_patterns() -> Vec<String> {
+    vec![DEFAULT_ALBUM_NAME_STRIP_PATTERN.to_string()]
+}
+
+fn default_max_mirror_delete() -> usize {
+    10
+}
+
+fn default_library_depth() -> usize {
+    1
+}
+
+fn default_ignore_articles() -> Vec<String> {
+    ["The", "A", "An"].map(String::from).to_vec()
+}
+
+fn default_singles_album() -> String {
+    "Singles".to_string()
+}
+
+fn default_disc_folder_pattern() -> String {
+    DEFAULT_DISC_FOLDER_PATTERN.to_string()
+}
+
+impl Settings {
+    /// Compiles `album_name_strip_patterns`, reporting the first invalid pattern.
+    pub fn album_name_cleaner(&self) -> Result<AlbumNameCleaner, ConfigError> {
+        AlbumNameCleaner::from_patterns(&self.album_name_strip_patterns)
+            .map_err(|e| ConfigError::Message(format!("Invalid album_name_strip_patterns: {}", e)))
+    }
+
+    /// Compiles `disc_folder_pattern`.
+    pub fn disc_folder_matcher(&self) -> Result<DiscFolderMatcher, ConfigError> {
+        DiscFolderMatcher::from_pattern(&self.disc_folder_pattern)
+            .map_err(|e| ConfigError::Message(format!("Invalid disc_folder_pattern: {}", e)))
+    }
+
+    /// Returns the servers to compare against, each paired with its upload destination.
+    ///
+    /// A single `api_settings` block is returned as one server named `default`.
+    /// Servers without their own `remote_settings` use the top-level one; an error is
+    /// returned when neither is set.
+    pub fn servers(&self) -> Result<Vec<Server<'_>>, ConfigError> {
+        match &self.api_settings {
+            ApiServers::Single(api) => Ok(vec![Server {
+                name: DEFAULT_SERVER_NAME,
+                api,
+                remote: self.remote_for(DEFAULT_SERVER_NAME, None)?,
+            }]),
+            ApiServers::Multiple(servers) => servers
+                .iter()
+                .map(|server| {
+                    Ok(Server {
+                        name: &server.name,
+                        api: &server.api,
+                        remote: self.remote_for(&server.name, server.remote_settings.as_ref())?,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    fn remote_for<'a>(
+        &'a self,
+        name: &str,
+        own: Option<&'a RemoteSettings>,
+    ) -> Result<&'a RemoteSettings, ConfigError> {
+        own.or(self.remote_settings.as_ref()).ok_or_else(|| {
+            ConfigError::Message(format!(
+                "No remote_settings configured for server '{}'",
+                name
+            ))
+        })
+    }
+}
+
+/// Name given to the server of a configuration with a single `api_settings` block.
+pub const DEFAULT_SERVER_NAME: &str = "default";
+
+/// The `api_settings` of the configuration: either a single server or a list of named ones.
 #[derive(Deserialize)]
+#[serde(untagged)]
+pub enum ApiServers {
+    Single(ApiSettings),
+    Multiple(Vec<ServerSettings>),
+}
+
+/// A named Subsonic server, as listed in `api_settings`.
+#[derive(Deserialize)]
+pub struct ServerSettings {
+    pub name: String,
+    #[serde(flatten)]
+    pub api: ApiSettings,
+    /// Upload destination of this server. The top-level `remote_settings` is used when unset.
+    pub remote_settings: Option<RemoteSettings>,
+}
+
+/// A server to compare against, resolved from the configuration.
+pub struct Server<'a> {
+    pub name: &'a str,
+    pub api: &'a ApiSettings,
+    pub remote: &'a RemoteSettings,
+}
+
+/// Describes how the local music library is organized on disk.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LibraryLayout {
+    /// `Artist/Album/tracks`, one folder per artist containing album folders.
+    #[default]
+    ArtistAlbum,
+    /// `Album/tracks`, where each top-level folder is an album and the artist
+    /// is derived from the folder name (e.g. `"Artist - Album"`).
+    AlbumOnly,
+}
+
+/// What the local library is compared with to find the albums missing on a server.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ComparisonBackend {
+    /// The artists and albums reported by the server's Subsonic API.
+    #[default]
+    SubsonicApi,
+    /// The `remote_path/<artist>/<album>` folders of the remote host, listed over ssh.
+    SshListing,
+}
+
+/// Which artists have their albums compared with the server's. Only used with the
+/// `SubsonicApi` backend.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ComparisonMode {
+    /// Only artists whose local album count differs from the server's `albumCount`.
+    #[default]
+    AlbumCount,
+    /// Every artist, ignoring the album counts, for servers reporting inconsistent
+    /// ones. Slower, as the albums of every artist are fetched.
+    AlbumSetAlways,
+}
+
+/// What tells that an artist folder changed since it was stored, and must be scanned
+/// again. Only used with the `ArtistAlbum` layout.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeDetection {
+    /// The folder was modified after it was stored.
+    #[default]
+    Mtime,
+    /// The number of album folders differs from the stored album count.
+    AlbumCount,
+    /// The album folders, or the names, sizes or modification times of their files,
+    /// differ from the stored albums. Each album is digested, its files in parallel.
+    ContentHash,
+}
+
+/// What to do with albums the server has but the local library doesn't.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MissingLocallyAction {
+    /// Don't do anything beyond the comparison output.
+    #[default]
+    Ignore,
+    /// Print a summary with the artist, album and server artist ID of each album.
+    Report,
+    /// Write the albums to `wishlist.txt` in the configuration folder.
+    WriteList,
+}
+
+/// The order albums are listed in reports.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ReportSort {
+    /// By artist, then album name.
+    #[default]
+    Artist,
+    /// By album name, then artist.
+    Album,
+    /// Largest first, then by artist. Albums of unknown size come last.
+    Size,
+}
+
+/// Format requested from the Subsonic API with the `f` parameter.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFormat {
+    #[default]
+    Json,
+    /// For servers that only return valid responses in XML.
+    Xml,
+}
+
+impl ResponseFormat {
+    /// Value of the `f` query parameter.
+    pub fn query_value(self) -> &'static str {
+        match self {
+            ResponseFormat::Json => "json",
+            ResponseFormat::Xml => "xml",
+        }
+    }
+}
+
+/// Subsonic endpoint used to list the artists of a server.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ArtistEndpoint {
+    /// Use `getArtists`, falling back to `getIndexes` when it returns no artists or
+    /// reports that it isn't supported.
+    #[default]
+    Auto,
+    /// Only use `getArtists`, which lists artists from the tags of the files.
+    GetArtists,
+    /// Only use `getIndexes`, which lists artist folders. For older Subsonic servers.
+    GetIndexes,
+}
+
+#[derive(Deserialize, Default)]
 pub struct RemoteSettings {
     pub remote_user: String,
     pub remote_host: String,
     pub remote_path: String,
-    pub ssh_key_path: String,
+    /// SSH private key used for uploads. When unset, ssh's default identity resolution
+    /// (ssh-agent, `~/.ssh/config`) is used.
+    pub ssh_key_path: Option<String>,
+    /// Upload bandwidth limit in Kbit/s. No limit is applied when unset.
+    pub bandwidth_limit_kbps: Option<u64>,
+    /// SSH port of the remote host. The ssh default (22) is used when unset.
+    pub ssh_port: Option<u16>,
+    /// Cipher used by scp and ssh (`-c`), e.g. `aes128-gcm@openssh.com`. The ssh default
+    /// is used when unset.
+    pub ssh_cipher: Option<String>,
+    /// Compress transfers (`-C`). Off by default, as audio files rarely compress.
+    #[serde(default)]
+    pub ssh_compression: bool,
+    /// Extra ssh options passed as `-o` flags, e.g. `StrictHostKeyChecking=accept-new`.
+    #[serde(default)]
+    pub ssh_extra_opts: Vec<String>,
+    /// Share one ssh connection between all the scp, rsync and ssh commands of a sync,
+    /// through a ControlMaster socket, instead of a handshake per command.
+    #[serde(default)]
+    pub ssh_multiplex: bool,
+    /// Upload the missing albums of an artist with a single rsync of the artist directory
+    /// instead of one scp per album.
+    #[serde(default)]
+    pub group_by_artist: bool,
+    /// Resume an album with rsync when the scp connection drops mid-transfer.
+    #[serde(default)]
+    pub upload_fallback: bool,
+    /// Upload each album of several files as a tar stream piped into ssh instead of
+    /// with scp.
+    #[serde(default)]
+    pub stream_tar: bool,
+    /// After each upload, compare the number of files on the remote host with the local
+    /// album over ssh, reporting a mismatch as a failed upload.
+    #[serde(default)]
+    pub verify_uploads: bool,
+    /// Before uploading, check with `df` over ssh that the remote host has room for all
+    /// the albums, and upload nothing when it doesn't.
+    #[serde(default)]
+    pub check_remote_space: bool,
+    /// Remove the local album directory once it has been uploaded successfully.
+    #[serde(default)]
+    pub delete_after_upload: bool,
+    /// Delete the remote albums that the local library doesn't have, so the server
+    /// mirrors the library.
+    #[serde(default)]
+    pub mirror_delete: bool,
+    /// Overrides the look of the upload progress bars.
+    pub progress_style: Option<ProgressStyleSettings>,
+    /// Maximum time an album upload may take, in seconds, before it is killed and counted
+    /// as failed. Uploads never time out when unset.
+    pub upload_timeout_secs: Option<u64>,
+    /// Extensions of the album files uploaded, e.g. `flac`. Every file is uploaded when
+    /// empty.
+    #[serde(default)]
+    pub upload_include_extensions: Vec<String>,
+    /// Extensions of the album files never uploaded, e.g. `log` or `cue`.
+    #[serde(default)]
+    pub upload_exclude_extensions: Vec<String>,
+}
+
+impl RemoteSettings {
+    /// The configured `upload_timeout_secs`, as a duration.
+    pub fn upload_timeout(&self) -> Option<Duration> {
+        self.upload_timeout_secs.map(Duration::from_secs)
+    }
+
+    /// Returns `true` if upload extension filters are set, so albums can't be copied
+    /// whole.
+    pub fn filters_uploads(&self) -> bool {
+        !self.upload_include_extensions.is_empty() || !self.upload_exclude_extensions.is_empty()
+    }
+
+    /// Returns `true` if the file at `path` passes the upload extension filters.
+    /// Extensions are matched ignoring case, with or without their leading dot.
+    pub fn uploads_file(&self, path: &Path) -> bool {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy())
+            .unwrap_or_default();
+        let matches = |extensions: &[String]| {
+            extensions.iter().any(|listed| {
+                listed
+                    .trim_start_matches('.')
+                    .eq_ignore_ascii_case(&extension)
+            })
+        };
+        (self.upload_include_extensions.is_empty() || matches(&self.upload_include_extensions))
+            && !matches(&self.upload_exclude_extensions)
+    }
+}
+
+/// Custom template and characters for the upload progress bars.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProgressStyleSettings {
+    /// An `indicatif` template. `{unit}` is replaced by the unit of the bar ("albums" or "%").
+    pub template: String,
+    /// The characters used to draw the filled, current and empty parts of the bar.
+    #[serde(default = "default_progress_chars")]
+    pub progress_chars: String,
+}
+
+fn default_progress_chars() -> String {
+    "##-".to_string()
 }
 
 #[derive(Deserialize)]
@@ -23,6 +403,41 @@ pub struct ApiSettings {
     pub api_base_url: String,
     pub api_username: String,
     pub api_password: String,
+    /// How long, in seconds, the artist list fetched from the server is reused before
+    /// being fetched again. The list isn't cached when unset.
+    pub artist_cache_ttl_secs: Option<u64>,
+    /// How many times a request rate-limited by the server (HTTP 429) is retried.
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Only compare the artists of this music folder of the server.
+    pub music_folder_id: Option<u32>,
+    /// Endpoint used to list the artists of the server.
+    #[serde(default)]
+    pub artist_endpoint: ArtistEndpoint,
+    /// Format of the API responses.
+    #[serde(default)]
+    pub response_format: ResponseFormat,
+    /// Maximum number of idle connections kept open to the server. Unlimited when unset.
+    pub pool_max_idle_per_host: Option<usize>,
+    /// How long, in seconds, an idle connection is kept open. 90 seconds when unset.
+    pub pool_idle_timeout_secs: Option<u64>,
+    /// Talk HTTP/2 to the server without negotiating it first.
+    #[serde(default)]
+    pub http2_prior_knowledge: bool,
+    /// Leading articles ignored when matching artist names, like "The" in "The Beatles".
+    /// The server's `ignoredArticles` are used when unset; an empty list ignores none.
+    pub ignored_articles: Option<Vec<String>>,
+    /// How many artists are compared with the server at once.
+    #[serde(default = "default_compare_concurrency")]
+    pub compare_concurrency: usize,
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_compare_concurrency() -> usize {
+    1
 }
 
 impl ApiSettings {
@@ -31,16 +446,108 @@ impl ApiSettings {
             api_base_url: url.to_string(),
             api_username: username.to_string(),
             api_password: password.to_string(),
+            artist_cache_ttl_secs: None,
+            max_retries: default_max_retries(),
+            music_folder_id: None,
+            artist_endpoint: ArtistEndpoint::default(),
+            response_format: ResponseFormat::default(),
+            pool_max_idle_per_host: None,
+            pool_idle_timeout_secs: None,
+            http2_prior_knowledge: false,
+            ignored_articles: None,
+            compare_concurrency: default_compare_concurrency(),
+        }
+    }
+}
+
+pub fn get_configuration(cfg_file: &Path) -> Result<Settings, ConfigError> {
+    get_configuration_with_overrides(cfg_file, &[])
+}
+
+/// Loads the configuration file, with each `(key, value)` override replacing the value
+/// of the file.
+///
+/// Keys use dots for nested settings, e.g. `remote_settings.ssh_port`. Values are kept
+/// as strings and converted to the type of their setting when it is deserialized, so
+/// a password such as `0123` isn't read as a number.
+///
+/// # Arguments
+///
+/// * `cfg_file` - Path of the YAML configuration file.
+/// * `overrides` - Settings to override, as parsed by [`parse_setting_override`].
+///
+pub fn get_configuration_with_overrides(
+    cfg_file: &Path,
+    overrides: &[(String, String)],
+) -> Result<Settings, ConfigError> {
+    let mut builder = config::Config::builder()
+        .add_source(config::File::from(cfg_file).format(config::FileFormat::Yaml));
+    for (key, value) in overrides {
+        builder = builder.set_override(key.as_str(), value.as_str())?;
+    }
+
+    builder.build()?.try_deserialize::<Settings>()
+}
+
+/// Parses a `key=value` setting override, as passed to `musync run --set`.
+///
+/// # Examples
+///
+/// ```
+/// use musync::configuration::parse_setting_override;
+///
+/// assert_eq!(
+///     parse_setting_override("remote_settings.ssh_port=2222"),
+///     Ok(("remote_settings.ssh_port".to_string(), "2222".to_string()))
+/// );
+/// assert!(parse_setting_override("ssh_port").is_err());
+/// ```
+pub fn parse_setting_override(input: &str) -> Result<(String, String), String> {
+    match input.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.to_string()))
         }
+        _ => Err(format!("expected KEY=VALUE, got '{}'", input)),
     }
 }
 
-pub fn get_configuration(cfg_file: &str) -> Result<Settings, ConfigError> {
-    let settings = config::Config::builder()
-        .add_source(config::File::new(cfg_file, config::FileFormat::Yaml))
-        .build()?;
+/// Resolves the configured `local_path` into an absolute path to an existing directory.
+///
+/// A leading `~` is expanded to the home directory, then the path is canonicalized so relative
+/// paths, symlinks and trailing slashes all resolve to the same location. A clear
+/// error is returned when the path doesn't exist or isn't a directory.
+///
+/// # Arguments
+///
+/// * `raw` - The `local_path` value as written in the configuration file.
+///
+pub fn resolve_local_path(raw: &str) -> io::Result<PathBuf> {
+    let expanded = expand_tilde(raw, home_dir(|name| env::var(name).ok()).as_deref());
+
+    let resolved = expanded.canonicalize().map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Local library path '{}' is not accessible: {}", raw, e),
+        )
+    })?;
 
-    settings.try_deserialize::<Settings>()
+    if !resolved.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Local library path '{}' is not a directory", raw),
+        ));
+    }
+
+    Ok(resolved)
+}
+
+fn expand_tilde(raw: &str, home_dir: Option<&str>) -> PathBuf {
+    match (raw.strip_prefix('~'), home_dir) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            Path::new(home).join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(raw),
+    }
 }
 
 pub struct ConfigFolder {
@@ -49,66 +556,846 @@ pub struct ConfigFolder {
     pub musync_db: PathBuf,
 }
 
+/// Environment variable overriding the location of the local database.
+pub const MUSYNC_DB_ENV: &str = "MUSYNC_DB";
+
 impl ConfigFolder {
-    pub fn new() -> Self {
-        let home_dir = env::var("HOME").expect("Failed to get HOME environment variable");
+    /// Resolves the configuration folder from the environment.
+    ///
+    /// When `$XDG_CONFIG_HOME` or `$XDG_DATA_HOME` is set, the configuration lives in
+    /// `$XDG_CONFIG_HOME/musync` and the database in `$XDG_DATA_HOME/musync`, using the
+    /// XDG defaults for whichever of the two is missing. Otherwise, or when a legacy
+    /// `~/.musync` folder already exists, everything stays under `~/.musync`.
+    ///
+    /// The database location can be overridden with the `MUSYNC_DB` environment variable.
+    /// The home directory is `$HOME`, or `%USERPROFILE%` on systems without `HOME` such
+    /// as Windows. Returns an error instead of panicking when neither is set.
+    pub fn new() -> Result<Self, ConfigError> {
+        Self::from_env(|name| env::var(name).ok())
+    }
+
+    fn from_env(var: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let var = |name: &str| var(name).filter(|value| !value.is_empty());
+
+        let home_dir = home_dir(var).ok_or_else(|| {
+            ConfigError::Message(
+                "Neither the HOME nor the USERPROFILE environment variable is set; \
+                 unable to locate the configuration folder"
+                    .to_string(),
+            )
+        })?;
+
+        let legacy_dir = get_legacy_dir_name(&home_dir);
+        let xdg_config_home = var("XDG_CONFIG_HOME");
+        let xdg_data_home = var("XDG_DATA_HOME");
+
+        let config_folder =
+            if legacy_dir.exists() || (xdg_config_home.is_none() && xdg_data_home.is_none()) {
+                Self {
+                    config_file: legacy_dir.join("config.yaml"),
+                    musync_db: legacy_dir.join("musync_db"),
+                    config_dir: legacy_dir,
+                }
+            } else {
+                let config_dir = xdg_config_home
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| Path::new(&home_dir).join(".config"))
+                    .join("musync");
+                let data_dir = xdg_data_home
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| Path::new(&home_dir).join(".local").join("share"))
+                    .join("musync");
+
+                Self {
+                    config_file: config_dir.join("config.yaml"),
+                    musync_db: data_dir.join("musync_db"),
+                    config_dir,
+                }
+            };
+
+        Ok(config_folder.with_db_env(var))
+    }
+
+    /// Uses the configuration file at `config_file`, e.g. from the `--config` command
+    /// line option. The database defaults to a `musync_db` folder next to the file, and
+    /// can still be overridden with the `MUSYNC_DB` environment variable.
+    pub fn from_config_file(config_file: impl Into<PathBuf>) -> Self {
+        Self::for_config_file(config_file.into(), |name| env::var(name).ok())
+    }
 
+    fn for_config_file(config_file: PathBuf, var: impl Fn(&str) -> Option<String>) -> Self {
+        let musync_db = config_dir_of(&config_file).join("musync_db");
+        Self::with_paths(config_file, musync_db)
+            .with_db_env(|name| var(name).filter(|value| !value.is_empty()))
+    }
+
+    /// Uses the configuration file at `config_file` and the database at `musync_db`,
+    /// without reading the environment, e.g. to sync temporary folders in tests.
+    pub fn with_paths(config_file: impl Into<PathBuf>, musync_db: impl Into<PathBuf>) -> Self {
+        let config_file = config_file.into();
         Self {
-            config_dir: get_config_dir_name(&home_dir),
-            config_file: get_config_file_name(&home_dir),
-            musync_db: get_musync_db_name(&home_dir),
+            config_dir: config_dir_of(&config_file),
+            config_file,
+            musync_db: musync_db.into(),
         }
     }
+
+    fn with_db_env(self, var: impl Fn(&str) -> Option<String>) -> Self {
+        match var(MUSYNC_DB_ENV) {
+            Some(db_path) => self.with_db_path(db_path),
+            None => self,
+        }
+    }
+
+    /// Overrides the database location, e.g. from the `--db-path` command line option.
+    pub fn with_db_path(mut self, db_path: impl Into<PathBuf>) -> Self {
+        self.musync_db = db_path.into();
+        self
+    }
 }
 
-fn get_config_dir_name(home_dir: &String) -> PathBuf {
-    Path::new(&home_dir).join(".musync")
+/// The folder holding `config_file`: the current directory for a bare file name, or
+/// `.` when it can't be read.
+fn config_dir_of(config_file: &Path) -> PathBuf {
+    match config_file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+    }
 }
 
-fn get_config_file_name(home_dir: &String) -> PathBuf {
-    Path::new(&home_dir).join(".musync").join("config.yaml")
+/// The user's home directory: `$HOME`, falling back to `%USERPROFILE%` on Windows.
+fn home_dir(var: impl Fn(&str) -> Option<String>) -> Option<String> {
+    ["HOME", "USERPROFILE"]
+        .into_iter()
+        .find_map(|name| var(name).filter(|value| !value.is_empty()))
 }
 
-fn get_musync_db_name(home_dir: &String) -> PathBuf {
-    Path::new(&home_dir).join(".musync").join("musync_db")
+fn get_legacy_dir_name(home_dir: &str) -> PathBuf {
+    Path::new(home_dir).join(".musync")
 }
 
-pub fn create_config(cfg_folder: ConfigFolder) -> Result<(), Box<dyn std::error::Error>> {
-    println!("\x1b[1m\x1b[32mCreating configuration...\x1b[0m");
-    let config_dir = cfg_folder.config_dir;
+/// Creates the configuration folder, the database folder and a template `config.yaml`.
+///
+/// When the configuration file already exists the user is asked to confirm the
+/// overwrite, unless `force` is set. An existing folder without one, such as the
+/// current directory of a bare `--config` file name, is used as is. If confirmation is needed but stdin is not a
+/// terminal, an error is returned instead of blocking on input.
+///
+/// With `interactive`, the settings are asked on the terminal, see [`prompt_config`],
+/// and `config.yaml` is written filled in instead of as a template. The questions are
+/// printed on stderr when `format` is JSON.
+///
+/// The created paths are printed in `format`: as colored text, or as a JSON object with
+/// `config_dir`, `config_file` and `db` keys for scripts.
+pub fn create_config(
+    cfg_folder: ConfigFolder,
+    force: bool,
+    interactive: bool,
+    format: OutputFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if format == OutputFormat::Text {
+        println!("\x1b[1m\x1b[32mCreating configuration...\x1b[0m");
+    }
 
-    if config_dir.exists() && !confirm_overwrite()? {
-        println!("\x1b[33mOperation cancelled.\x1b[0m");
+    if cfg_folder.config_file.exists()
+        && !force
+        && !confirm(&format!(
+            "The configuration file {} already exists.\n\
+             Do you want to overwrite it?",
+            cfg_folder.config_file.display()
+        ))?
+    {
+        match format {
+            OutputFormat::Text => println!("\x1b[33mOperation cancelled.\x1b[0m"),
+            OutputFormat::Json => eprintln!("Operation cancelled."),
+        }
         return Ok(());
     }
 
-    fs::create_dir_all(&config_dir)?;
-    fs::create_dir_all(&cfg_folder.musync_db)?;
+    // Nothing is written until every answer has been given.
+    let config_content = if interactive {
+        let mut output: Box<dyn Write> = match format {
+            OutputFormat::Text => Box::new(io::stdout()),
+            OutputFormat::Json => Box::new(io::stderr()),
+        };
+        prompt_config(&mut io::stdin().lock(), &mut output)?
+    } else {
+        include_str!("config_template.yaml").to_string()
+    };
 
-    let config_content = include_str!("config_template.yaml");
+    fs::create_dir_all(&cfg_folder.config_dir)?;
+    fs::create_dir_all(&cfg_folder.musync_db)?;
     fs::write(&cfg_folder.config_file, config_content)?;
 
-    println!("\x1b[32mConfiguration folder created at:");
-    println!("  -> ~/.musync");
-    println!("Configuration file created at:");
-    println!("  -> ~/.musync/config.yaml");
-    println!("musync_db folder created at:");
-    println!("  -> ~/.musync/musync_db");
-    println!("\x1b[0mPlease edit the configuration file with your specific settings.");
-
+    write_config_paths(&mut io::stdout(), &cfg_folder, format)?;
     Ok(())
 }
 
-fn confirm_overwrite() -> Result<bool, io::Error> {
-    println!("\x1b[31mThe configuration folder already exists.");
-    println!("Do you want to overwrite it? Everything will be lost. (y/N)\x1b[0m");
+/// Asks for the settings every configuration needs and returns the contents of a
+/// `config.yaml` holding them.
+///
+/// Each answer is checked before moving on to the next question: the local library
+/// must be an existing directory, the SSH key an existing file and the API URL an
+/// `http` or `https` URL. An empty SSH key path leaves the key out, to use ssh-agent or
+/// `~/.ssh/config`.
+///
+/// # Arguments
+///
+/// * `input` - Where answers are read from, one per line.
+/// * `output` - Where the questions are printed.
+///
+pub fn prompt_config(input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<String> {
+    let required = |answer: &str| {
+        if answer.is_empty() {
+            Err("A value is required.".to_string())
+        } else {
+            Ok(())
+        }
+    };
+    let single_word = |answer: &str| {
+        required(answer)?;
+        if answer.contains(char::is_whitespace) {
+            return Err("The value can't contain spaces.".to_string());
+        }
+        Ok(())
+    };
 
-    let mut input = String::new();
-    io::stdin().read_line(&mut input)?;
+    let local_path = ask(input, output, "Local music library path", None, &|answer| {
+        required(answer)?;
+        resolve_local_path(answer)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    })?;
+    let remote_user = ask(input, output, "Remote SSH user", None, &single_word)?;
+    let remote_host = ask(input, output, "Remote SSH host", None, &single_word)?;
+    let remote_path = ask(input, output, "Remote library path", None, &required)?;
+    let ssh_key_path = ask(
+        input,
+        output,
+        "SSH key path (empty to use ssh-agent or ~/.ssh/config)",
+        Some(""),
+        &|answer| {
+            let path = expand_tilde(answer, home_dir(|name| env::var(name).ok()).as_deref());
+            if answer.is_empty() || path.is_file() {
+                Ok(())
+            } else {
+                Err(format!("No SSH key found at '{}'.", answer))
+            }
+        },
+    )?;
+    let api_base_url =
+        ask(
+            input,
+            output,
+            "Subsonic API URL",
+            None,
+            &|answer| match reqwest::Url::parse(answer) {
+                Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(()),
+                _ => Err(format!(
+                    "'{}' is not an http:// or https:// URL, e.g. https://music.example.com/rest.",
+                    answer
+                )),
+            },
+        )?;
+    let api_username = ask(input, output, "API username", None, &required)?;
+    let api_password = ask(input, output, "API password", None, &required)?;
 
-    if input.trim().to_lowercase() == "y" {
-        Ok(true)
-    } else {
-        Ok(false)
+    // JSON strings are valid YAML double-quoted scalars, escapes included.
+    let quote = |value: &str| serde_json::Value::from(value).to_string();
+    let mut config = format!("local_path: {}\n", quote(&local_path));
+    config.push_str("remote_settings:\n");
+    config.push_str(&format!("  remote_user: {}\n", quote(&remote_user)));
+    config.push_str(&format!("  remote_host: {}\n", quote(&remote_host)));
+    config.push_str(&format!("  remote_path: {}\n", quote(&remote_path)));
+    if !ssh_key_path.is_empty() {
+        config.push_str(&format!("  ssh_key_path: {}\n", quote(&ssh_key_path)));
+    }
+    config.push_str("api_settings:\n");
+    config.push_str(&format!("  api_base_url: {}\n", quote(&api_base_url)));
+    config.push_str(&format!("  api_username: {}\n", quote(&api_username)));
+    config.push_str(&format!("  api_password: {}\n", quote(&api_password)));
+    Ok(config)
+}
+
+/// Output format of the `config` command.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Colored text for people.
+    #[default]
+    Text,
+    /// The created paths as a JSON object, for scripts.
+    Json,
+}
+
+/// Writes the paths created by [`create_config`] in the given format.
+fn write_config_paths(
+    out: &mut dyn Write,
+    cfg_folder: &ConfigFolder,
+    format: OutputFormat,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Text => {
+            writeln!(out, "\x1b[32mConfiguration folder created at:")?;
+            writeln!(out, "  -> {}", cfg_folder.config_dir.display())?;
+            writeln!(out, "Configuration file created at:")?;
+            writeln!(out, "  -> {}", cfg_folder.config_file.display())?;
+            writeln!(out, "musync_db folder created at:")?;
+            writeln!(out, "  -> {}", cfg_folder.musync_db.display())?;
+            writeln!(
+                out,
+                "\x1b[0mPlease edit the configuration file with your specific settings."
+            )
+        }
+        OutputFormat::Json => {
+            let paths = serde_json::json!({
+                "config_dir": cfg_folder.config_dir,
+                "config_file": cfg_folder.config_file,
+                "db": cfg_folder.musync_db,
+            });
+            writeln!(out, "{}", paths)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn test_config_folder(temp_dir: &TempDir) -> ConfigFolder {
+        let config_dir = temp_dir.path().join(".musync");
+        ConfigFolder {
+            config_file: config_dir.join("config.yaml"),
+            musync_db: config_dir.join("musync_db"),
+            config_dir,
+        }
+    }
+
+    fn env_from(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_config_folder_defaults_to_home() {
+        let cfg_folder = ConfigFolder::from_env(env_from(&[("HOME", "/home/user")])).unwrap();
+
+        assert_eq!(cfg_folder.config_dir, Path::new("/home/user/.musync"));
+        assert_eq!(
+            cfg_folder.config_file,
+            Path::new("/home/user/.musync/config.yaml")
+        );
+        assert_eq!(
+            cfg_folder.musync_db,
+            Path::new("/home/user/.musync/musync_db")
+        );
+    }
+
+    #[test]
+    fn test_config_folder_db_override_precedence() {
+        let from_env = ConfigFolder::from_env(env_from(&[
+            ("HOME", "/home/user"),
+            (MUSYNC_DB_ENV, "/fast/db"),
+        ]))
+        .unwrap();
+        assert_eq!(from_env.musync_db, Path::new("/fast/db"));
+
+        let from_cli = from_env.with_db_path("/cli/db");
+        assert_eq!(from_cli.musync_db, Path::new("/cli/db"));
+        assert_eq!(
+            from_cli.config_file,
+            Path::new("/home/user/.musync/config.yaml")
+        );
+
+        let empty_env =
+            ConfigFolder::from_env(env_from(&[("HOME", "/home/user"), (MUSYNC_DB_ENV, "")]))
+                .unwrap();
+        assert_eq!(
+            empty_env.musync_db,
+            Path::new("/home/user/.musync/musync_db")
+        );
+    }
+
+    #[test]
+    fn test_config_folder_from_config_file() {
+        let cfg_folder =
+            ConfigFolder::for_config_file(PathBuf::from("/music/jazz/musync.yaml"), env_from(&[]));
+        assert_eq!(cfg_folder.config_file, Path::new("/music/jazz/musync.yaml"));
+        assert_eq!(cfg_folder.config_dir, Path::new("/music/jazz"));
+        assert_eq!(cfg_folder.musync_db, Path::new("/music/jazz/musync_db"));
+
+        let relative = ConfigFolder::for_config_file(
+            PathBuf::from("jazz.yaml"),
+            env_from(&[(MUSYNC_DB_ENV, "/fast/db")]),
+        );
+        assert_eq!(relative.config_dir, env::current_dir().unwrap());
+        assert_eq!(relative.musync_db, Path::new("/fast/db"));
+
+        let explicit = ConfigFolder::with_paths("/tmp/run/config.yaml", "/tmp/db");
+        assert_eq!(explicit.config_dir, Path::new("/tmp/run"));
+        assert_eq!(explicit.musync_db, Path::new("/tmp/db"));
+    }
+
+    #[test]
+    fn test_config_folder_missing_home_is_an_error() {
+        let result = ConfigFolder::from_env(env_from(&[]));
+        assert!(matches!(result, Err(ConfigError::Message(_))));
+
+        let result = ConfigFolder::from_env(env_from(&[("HOME", ""), ("USERPROFILE", "")]));
+        assert!(matches!(result, Err(ConfigError::Message(_))));
+    }
+
+    #[test]
+    fn test_config_folder_falls_back_to_userprofile() {
+        let cfg_folder =
+            ConfigFolder::from_env(env_from(&[("USERPROFILE", "/users/jane")])).unwrap();
+        assert_eq!(cfg_folder.config_dir, Path::new("/users/jane/.musync"));
+
+        let cfg_folder = ConfigFolder::from_env(env_from(&[
+            ("HOME", "/home/user"),
+            ("USERPROFILE", "/users/jane"),
+        ]))
+        .unwrap();
+        assert_eq!(cfg_folder.config_dir, Path::new("/home/user/.musync"));
+    }
+
+    #[test]
+    fn test_config_folder_uses_xdg_directories() {
+        let cfg_folder = ConfigFolder::from_env(env_from(&[
+            ("HOME", "/home/user"),
+            ("XDG_CONFIG_HOME", "/xdg/config"),
+            ("XDG_DATA_HOME", "/xdg/data"),
+        ]))
+        .unwrap();
+
+        assert_eq!(cfg_folder.config_dir, Path::new("/xdg/config/musync"));
+        assert_eq!(
+            cfg_folder.config_file,
+            Path::new("/xdg/config/musync/config.yaml")
+        );
+        assert_eq!(
+            cfg_folder.musync_db,
+            Path::new("/xdg/data/musync/musync_db")
+        );
+    }
+
+    #[test]
+    fn test_config_folder_xdg_defaults_for_unset_variable() {
+        let cfg_folder = ConfigFolder::from_env(env_from(&[
+            ("HOME", "/home/user"),
+            ("XDG_CONFIG_HOME", "/xdg/config"),
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            cfg_folder.musync_db,
+            Path::new("/home/user/.local/share/musync/musync_db")
+        );
+    }
+
+    #[test]
+    fn test_config_folder_keeps_existing_legacy_folder() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join(".musync")).unwrap();
+        let home = temp_dir.path().to_str().unwrap();
+
+        let cfg_folder = ConfigFolder::from_env(env_from(&[
+            ("HOME", home),
+            ("XDG_CONFIG_HOME", "/xdg/config"),
+            ("XDG_DATA_HOME", "/xdg/data"),
+        ]))
+        .unwrap();
+
+        assert_eq!(cfg_folder.config_dir, temp_dir.path().join(".musync"));
+    }
+
+    fn parse_configuration(temp_dir: &TempDir, yaml: &str) -> Result<Settings, ConfigError> {
+        let config_file = temp_dir.path().join("config.yaml");
+        fs::write(&config_file, yaml).unwrap();
+        get_configuration(&config_file)
+    }
+
+    #[test]
+    fn test_single_server_configuration() {
+        let temp_dir = TempDir::new().unwrap();
+        let settings = parse_configuration(
+            &temp_dir,
+            r#"
+local_path: "/music"
+remote_settings:
+  remote_user: "user"
+  remote_host: "home"
+  remote_path: "/music"
+api_settings:
+  api_base_url: "http://home:4533/rest"
+  api_username: "user"
+  api_password: "password"
+"#,
+        )
+        .unwrap();
+
+        let servers = settings.servers().unwrap();
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].name, DEFAULT_SERVER_NAME);
+        assert_eq!(servers[0].api.api_base_url, "http://home:4533/rest");
+        assert_eq!(servers[0].remote.remote_host, "home");
+    }
+
+    #[test]
+    fn test_album_name_strip_patterns_configuration() {
+        let temp_dir = TempDir::new().unwrap();
+        let base = r#"
+local_path: "/music"
+api_settings:
+  api_base_url: "http://home:4533/rest"
+  api_username: "user"
+  api_password: "password"
+"#;
+
+        let default = parse_configuration(&temp_dir, base).unwrap();
+        let cleaner = default.album_name_cleaner().unwrap();
+        assert_eq!(cleaner.clean("Help! {Mono} [Remastered]"), "Help! {Mono}");
+
+        let custom = parse_configuration(
+            &temp_dir,
+            &format!(
+                "{}album_name_strip_patterns:\n  - '\\[.*?\\]'\n  - '\\{{.*?\\}}'\n  - '\\s*- Single$'\n",
+                base
+            ),
+        )
+        .unwrap();
+        let cleaner = custom.album_name_cleaner().unwrap();
+        assert_eq!(cleaner.clean("Help! [Remastered] {Mono} - Single"), "Help!");
+
+        let invalid = parse_configuration(
+            &temp_dir,
+            &format!("{}album_name_strip_patterns: ['[unclosed']\n", base),
+        )
+        .unwrap();
+        assert!(invalid.album_name_cleaner().is_err());
+    }
+
+    #[test]
+    fn test_multiple_server_configuration() {
+        let temp_dir = TempDir::new().unwrap();
+        let settings = parse_configuration(
+            &temp_dir,
+            r#"
+local_path: "/music"
+remote_settings:
+  remote_user: "user"
+  remote_host: "home"
+  remote_path: "/music"
+api_settings:
+  - name: "home"
+    api_base_url: "http://home:4533/rest"
+    api_username: "user"
+    api_password: "password"
+  - name: "vps"
+    api_base_url: "https://vps.example.com/rest"
+    api_username: "user"
+    api_password: "password"
+    max_retries: 5
+    remote_settings:
+      remote_user: "user"
+      remote_host: "vps.example.com"
+      remote_path: "/srv/music"
+"#,
+        )
+        .unwrap();
+
+        let servers = settings.servers().unwrap();
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[0].name, "home");
+        assert_eq!(servers[0].remote.remote_host, "home");
+        assert_eq!(servers[1].name, "vps");
+        assert_eq!(servers[1].api.max_retries, 5);
+        assert_eq!(servers[1].remote.remote_host, "vps.example.com");
+    }
+
+    #[test]
+    fn test_server_without_remote_settings_is_an_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let settings = parse_configuration(
+            &temp_dir,
+            r#"
+local_path: "/music"
+api_settings:
+  - name: "home"
+    api_base_url: "http://home:4533/rest"
+    api_username: "user"
+    api_password: "password"
+"#,
+        )
+        .unwrap();
+
+        assert!(settings.servers().is_err());
+    }
+
+    #[test]
+    fn test_get_configuration_without_ssh_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.yaml");
+        fs::write(
+            &config_file,
+            r#"
+local_path: "/music"
+remote_settings:
+  remote_user: "user"
+  remote_host: "localhost"
+  remote_path: "/music"
+api_settings:
+  api_base_url: "http://localhost:4533/rest"
+  api_username: "user"
+  api_password: "password"
+"#,
+        )
+        .unwrap();
+
+        let settings = get_configuration(&config_file).unwrap();
+
+        assert!(settings.servers().unwrap()[0].remote.ssh_key_path.is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_get_configuration_from_a_non_utf8_path() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = temp_dir
+            .path()
+            .join(std::ffi::OsStr::from_bytes(b"musync-\xff"));
+        fs::create_dir(&config_dir).unwrap();
+        let config_file = config_dir.join("config.yaml");
+        fs::write(
+            &config_file,
+            r#"
+local_path: "/music"
+remote_settings:
+  remote_user: "user"
+  remote_host: "localhost"
+  remote_path: "/music"
+api_settings:
+  api_base_url: "http://localhost:4533/rest"
+  api_username: "user"
+  api_password: "password"
+"#,
+        )
+        .unwrap();
+
+        let settings = get_configuration(&config_file).unwrap();
+
+        assert_eq!(settings.local_path, "/music");
+    }
+
+    #[test]
+    fn test_setting_overrides_win_over_the_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.yaml");
+        fs::write(
+            &config_file,
+            r#"
+local_path: "/music"
+use_tags: false
+remote_settings:
+  remote_user: "user"
+  remote_host: "localhost"
+  remote_path: "/music"
+  upload_timeout_secs: 60
+api_settings:
+  api_base_url: "http://localhost:4533/rest"
+  api_username: "user"
+  api_password: "password"
+"#,
+        )
+        .unwrap();
+        let overrides = [
+            "remote_settings.upload_timeout_secs=300",
+            "remote_settings.remote_host=nas.local",
+            "use_tags=true",
+            "api_settings.api_password=0123",
+            "remote_settings.remote_path=1.10",
+        ]
+        .map(|input| parse_setting_override(input).unwrap());
+
+        let settings = get_configuration_with_overrides(&config_file, &overrides).unwrap();
+
+        assert!(settings.use_tags);
+        match &settings.api_settings {
+            ApiServers::Single(api) => assert_eq!(api.api_password, "0123"),
+            ApiServers::Multiple(_) => panic!("expected a single server"),
+        }
+        let remote = settings.remote_settings.unwrap();
+        assert_eq!(remote.upload_timeout_secs, Some(300));
+        assert_eq!(remote.remote_host, "nas.local");
+        assert_eq!(remote.remote_path, "1.10");
+    }
+
+    #[test]
+    fn test_expand_tilde() {
+        assert_eq!(
+            expand_tilde("~/Music", Some("/home/user")),
+            Path::new("/home/user/Music")
+        );
+        assert_eq!(
+            expand_tilde("~", Some("/home/user")),
+            Path::new("/home/user")
+        );
+        assert_eq!(
+            expand_tilde("~other/Music", Some("/home/user")),
+            Path::new("~other/Music")
+        );
+        assert_eq!(
+            expand_tilde("/srv/music", Some("/home/user")),
+            Path::new("/srv/music")
+        );
+        assert_eq!(expand_tilde("~/Music", None), Path::new("~/Music"));
+    }
+
+    #[test]
+    fn test_resolve_local_path_canonicalizes() {
+        let temp_dir = TempDir::new().unwrap();
+        let library = temp_dir.path().join("library");
+        fs::create_dir(&library).unwrap();
+
+        let raw = format!("{}/library/", temp_dir.path().display());
+        let resolved = resolve_local_path(&raw).unwrap();
+
+        assert_eq!(resolved, library.canonicalize().unwrap());
+    }
+
+    #[test]
+    fn test_resolve_local_path_nonexistent() {
+        let temp_dir = TempDir::new().unwrap();
+        let raw = temp_dir.path().join("missing");
+
+        let error = resolve_local_path(raw.to_str().unwrap()).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert!(error.to_string().contains("is not accessible"));
+    }
+
+    #[test]
+    fn test_resolve_local_path_not_a_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("file.txt");
+        fs::write(&file, "").unwrap();
+
+        let error = resolve_local_path(file.to_str().unwrap()).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_create_config_force_overwrites_existing_folder() {
+        let temp_dir = TempDir::new().unwrap();
+        let cfg_folder = test_config_folder(&temp_dir);
+        fs::create_dir_all(&cfg_folder.config_dir).unwrap();
+        fs::write(&cfg_folder.config_file, "local_path: \"/old\"").unwrap();
+        let config_file = cfg_folder.config_file.clone();
+        let musync_db = cfg_folder.musync_db.clone();
+
+        create_config(cfg_folder, true, false, OutputFormat::Text).unwrap();
+
+        let content = fs::read_to_string(config_file).unwrap();
+        assert_eq!(content, include_str!("config_template.yaml"));
+        assert!(musync_db.is_dir());
+    }
+
+    #[test]
+    fn test_create_config_in_existing_folder_without_a_config_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let cfg_folder = test_config_folder(&temp_dir);
+        fs::create_dir_all(&cfg_folder.config_dir).unwrap();
+        let config_file = cfg_folder.config_file.clone();
+
+        // Asking for confirmation would fail, as stdin isn't a terminal in tests.
+        create_config(cfg_folder, false, false, OutputFormat::Text).unwrap();
+
+        assert!(config_file.is_file());
+    }
+
+    #[test]
+    fn test_create_config_in_new_folder() {
+        let temp_dir = TempDir::new().unwrap();
+        let cfg_folder = test_config_folder(&temp_dir);
+        let config_file = cfg_folder.config_file.clone();
+
+        create_config(cfg_folder, false, false, OutputFormat::Text).unwrap();
+
+        assert!(config_file.is_file());
+    }
+
+    #[test]
+    fn test_prompt_config_writes_the_answers() {
+        let temp_dir = TempDir::new().unwrap();
+        let library = temp_dir.path().join("My Music");
+        fs::create_dir(&library).unwrap();
+        let answers = format!(
+            "{0}/missing\n{0}\nmusic user\nmusic\nnas.local\n/srv/music\n\nnas.local:4533\n\
+             http://nas.local:4533/rest\nadmin\np@ss \"word\"\n",
+            library.display()
+        );
+
+        let mut output = Vec::new();
+        let config = prompt_config(&mut answers.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(
+            config,
+            format!(
+                "local_path: \"{}\"\n\
+                 remote_settings:\n  remote_user: \"music\"\n  remote_host: \"nas.local\"\n  \
+                 remote_path: \"/srv/music\"\n\
+                 api_settings:\n  api_base_url: \"http://nas.local:4533/rest\"\n  \
+                 api_username: \"admin\"\n  api_password: \"p@ss \\\"word\\\"\"\n",
+                library.display()
+            )
+        );
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("is not accessible"));
+        assert!(output.contains("can't contain spaces"));
+        assert!(output.contains("'nas.local:4533' is not an http:// or https:// URL"));
+
+        let config_file = temp_dir.path().join("config.yaml");
+        fs::write(&config_file, &config).unwrap();
+        let settings = get_configuration(&config_file).unwrap();
+        let servers = settings.servers().unwrap();
+        assert_eq!(servers[0].api.api_password, "p@ss \"word\"");
+        assert!(servers[0].remote.ssh_key_path.is_none());
+    }
+
+    #[test]
+    fn test_prompt_config_fails_when_answers_run_out() {
+        let error = prompt_config(&mut "/music\n".as_bytes(), &mut Vec::new()).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_config_paths_as_json() {
+        let temp_dir = TempDir::new().unwrap();
+        let cfg_folder = test_config_folder(&temp_dir);
+
+        let mut output = Vec::new();
+        write_config_paths(&mut output, &cfg_folder, OutputFormat::Json).unwrap();
+
+        let paths: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(
+            paths["config_dir"].as_str().map(Path::new),
+            Some(cfg_folder.config_dir.as_path())
+        );
+        assert_eq!(
+            paths["config_file"].as_str().map(Path::new),
+            Some(cfg_folder.config_file.as_path())
+        );
+        assert_eq!(
+            paths["db"].as_str().map(Path::new),
+            Some(cfg_folder.musync_db.as_path())
+        );
     }
 }
@@ -0,0 +1,278 @@
+//! The checks of `musync doctor`, which looks for the usual causes of a failing sync:
+//! programs missing from the `PATH`, an invalid configuration, missing files and
+//! unreachable servers.
+
+use crate::api_client;
+use crate::configuration::{self, RemoteSettings, Server, Settings};
+use std::ffi::OsStr;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The outcome of one check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// What was checked, e.g. `scp is installed`.
+    pub name: String,
+    /// Why the check failed, `None` when it passed.
+    pub failure: Option<CheckFailure>,
+}
+
+/// What a failed check found, and how to fix it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckFailure {
+    pub problem: String,
+    pub hint: String,
+}
+
+impl Check {
+    fn pass(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            failure: None,
+        }
+    }
+
+    fn fail(name: impl Into<String>, problem: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            failure: Some(CheckFailure {
+                problem: problem.into(),
+                hint: hint.into(),
+            }),
+        }
+    }
+
+    /// Returns `true` if the check passed.
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.failure {
+            None => write!(f, "\x1b[32m✔ {}\x1b[0m", self.name),
+            Some(failure) => write!(
+                f,
+                "\x1b[31m✘ {}: {}\x1b[0m\n  \x1b[33m→ {}\x1b[0m",
+                self.name, failure.problem, failure.hint
+            ),
+        }
+    }
+}
+
+/// Returns the path of `program` in the directories of `path`, a `PATH`-like list.
+///
+/// # Arguments
+///
+/// * `program` - The name of the executable, e.g. `scp`.
+/// * `path` - The value of the `PATH` environment variable, if set.
+///
+pub fn find_program(program: &str, path: Option<&OsStr>) -> Option<PathBuf> {
+    std::env::split_paths(path?)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Checks that `program` is in one of the directories of `path`.
+///
+/// # Arguments
+///
+/// * `program` - The name of the executable, e.g. `scp`.
+/// * `path` - The value of the `PATH` environment variable, if set.
+///
+pub fn check_program(program: &str, path: Option<&OsStr>) -> Check {
+    let name = format!("{} is installed", program);
+    match find_program(program, path) {
+        Some(_) => Check::pass(name),
+        None => Check::fail(
+            name,
+            format!("{} was not found in PATH", program),
+            install_hint(program),
+        ),
+    }
+}
+
+fn install_hint(program: &str) -> String {
+    match program {
+        "scp" | "ssh" => "Install an OpenSSH client, e.g. the openssh-client package".to_string(),
+        "rsync" => "Install rsync, or turn off group_by_artist and upload_fallback".to_string(),
+        "tar" => "Install tar, or turn off stream_tar".to_string(),
+        _ => format!("Install {} and make sure it is in PATH", program),
+    }
+}
+
+/// The programs a sync runs for `remote`: scp and ssh, rsync when artists are uploaded
+/// together or dropped scp connections are resumed, and tar when albums are streamed.
+pub fn required_programs(remote: &RemoteSettings) -> Vec<&'static str> {
+    let mut programs = vec!["scp", "ssh"];
+    if remote.group_by_artist || remote.upload_fallback {
+        programs.push("rsync");
+    }
+    if remote.stream_tar {
+        programs.push("tar");
+    }
+    programs
+}
+
+/// Checks that the configuration file exists and parses, returning the settings it
+/// holds when it does.
+///
+/// # Arguments
+///
+/// * `config_file` - The path of `config.yaml`.
+///
+pub fn check_config(config_file: &Path) -> (Check, Option<Settings>) {
+    let name = format!("{} is valid", config_file.display());
+    if !config_file.is_file() {
+        return (
+            Check::fail(
+                name,
+                "the configuration file doesn't exist",
+                "Run 'musync config' to create it",
+            ),
+            None,
+        );
+    }
+
+    let parsed = configuration::get_configuration(config_file).and_then(|settings| {
+        settings.servers()?;
+        Ok(settings)
+    });
+    match parsed {
+        Ok(settings) => (Check::pass(name), Some(settings)),
+        Err(e) => (
+            Check::fail(
+                name,
+                e.to_string(),
+                "Fill in the missing settings, see the README for every setting",
+            ),
+            None,
+        ),
+    }
+}
+
+/// Checks that `local_path` is an existing directory.
+pub fn check_local_path(settings: &Settings) -> Check {
+    let name = format!("local_path '{}' exists", settings.local_path);
+    match configuration::resolve_local_path(&settings.local_path) {
+        Ok(_) => Check::pass(name),
+        Err(e) => Check::fail(
+            name,
+            e.to_string(),
+            "Point local_path at the root folder of your music library",
+        ),
+    }
+}
+
+/// Checks that the ssh key of a server exists, when one is configured.
+pub fn check_ssh_key(server: &Server) -> Option<Check> {
+    let key_path = server
+        .remote
+        .ssh_key_path
+        .as_deref()
+        .filter(|path| !path.is_empty())?;
+    let name = format!("ssh key '{}' of '{}' exists", key_path, server.name);
+    Some(if Path::new(key_path).is_file() {
+        Check::pass(name)
+    } else {
+        Check::fail(
+            name,
+            "the key file doesn't exist",
+            "Fix ssh_key_path, or remove it to use ssh-agent and ~/.ssh/config",
+        )
+    })
+}
+
+/// Checks that the API of a server answers with the configured credentials.
+pub async fn check_server(server: &Server<'_>) -> Check {
+    let name = format!("{} is reachable", server.api.api_base_url);
+    match api_client::ping_server(server.api).await {
+        Ok(()) => Check::pass(name),
+        Err(e) => Check::fail(
+            name,
+            e.to_string(),
+            "Check api_base_url (it usually ends with /rest), api_username and api_password",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_program_is_found_in_path() {
+        let bin = tempfile::tempdir().unwrap();
+        let empty = tempfile::tempdir().unwrap();
+        fs::write(bin.path().join("scp"), b"").unwrap();
+        fs::create_dir(bin.path().join("rsync")).unwrap();
+        let path = env::join_paths([empty.path(), bin.path()]).unwrap();
+
+        assert_eq!(
+            find_program("scp", Some(&path)),
+            Some(bin.path().join("scp"))
+        );
+        assert!(check_program("scp", Some(&path)).passed());
+
+        let missing = check_program("rsync", Some(&path));
+        assert_eq!(missing.name, "rsync is installed");
+        assert_eq!(
+            missing.failure.unwrap().problem,
+            "rsync was not found in PATH"
+        );
+        assert!(!check_program("ssh", None).passed());
+    }
+
+    #[test]
+    fn test_rsync_is_only_required_when_used() {
+        let remote = RemoteSettings::default();
+        assert_eq!(required_programs(&remote), ["scp", "ssh"]);
+
+        let remote = RemoteSettings {
+            upload_fallback: true,
+            ..RemoteSettings::default()
+        };
+        assert_eq!(required_programs(&remote), ["scp", "ssh", "rsync"]);
+    }
+
+    #[test]
+    fn test_config_check() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config_file = temp_dir.path().join("config.yaml");
+
+        let (check, settings) = check_config(&config_file);
+        assert_eq!(
+            check.failure.unwrap().hint,
+            "Run 'musync config' to create it"
+        );
+        assert!(settings.is_none());
+
+        fs::write(&config_file, "local_path: /music\n").unwrap();
+        let (check, settings) = check_config(&config_file);
+        let problem = check.failure.unwrap().problem;
+        assert!(problem.contains("missing field"), "{problem}");
+        assert!(settings.is_none());
+
+        fs::write(
+            &config_file,
+            r#"
+local_path: "/music"
+remote_settings:
+  remote_user: "user"
+  remote_host: "localhost"
+  remote_path: "/music"
+api_settings:
+  api_base_url: "http://localhost:4533/rest"
+  api_username: "user"
+  api_password: "password"
+"#,
+        )
+        .unwrap();
+        let (check, settings) = check_config(&config_file);
+        assert!(check.passed(), "{check}");
+        assert_eq!(settings.unwrap().local_path, "/music");
+    }
+}
@@ -1,8 +1,207 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct ArtistData {
+    pub album_count: usize,
+    pub last_modified: u64,
+    pub albums: Vec<AlbumData>,
+    /// The artist name as found in the library, before normalization, for display.
+    /// Entries are still keyed by the normalized name.
+    #[serde(default)]
+    pub display_name: String,
+}
+
+/// An album stored for an artist.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct AlbumData {
+    pub name: String,
+    /// Path of the album directory, relative to the library root. Albums stored outside
+    /// the root keep their full path.
+    pub path: String,
+    /// Number of audio files in the album directory.
+    pub track_count: usize,
+    /// Size of every file in the album directory, in bytes.
+    pub total_bytes: u64,
+    /// MusicBrainz release ID read from the `MUSICBRAINZ_ALBUMID` tag, when it was read.
+    pub musicbrainz_id: Option<String>,
+    /// Digest of the names, sizes and modification times of the files in the album
+    /// directory, computed by the `ContentHash` change detection. 0 when it wasn't.
+    pub content_digest: u64,
+}
+
+impl AlbumData {
+    /// Returns the full path of the album directory in the library at `root`.
+    pub fn full_path(&self, root: &Path) -> PathBuf {
+        root.join(&self.path)
+    }
+}
+
+/// Layout of `AlbumData` before MusicBrainz IDs were stored (schema versions 2 to 4).
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub(crate) struct AlbumDataV4 {
+    pub name: String,
+    pub path: String,
+    pub track_count: usize,
+    pub total_bytes: u64,
+}
+
+impl From<AlbumDataV4> for AlbumData {
+    fn from(legacy: AlbumDataV4) -> Self {
+        Self {
+            name: legacy.name,
+            path: legacy.path,
+            track_count: legacy.track_count,
+            total_bytes: legacy.total_bytes,
+            musicbrainz_id: None,
+            content_digest: 0,
+        }
+    }
+}
+
+/// Layout of `AlbumData` before content digests were stored (schema version 5).
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub(crate) struct AlbumDataV5 {
+    pub name: String,
+    pub path: String,
+    pub track_count: usize,
+    pub total_bytes: u64,
+    pub musicbrainz_id: Option<String>,
+}
+
+impl From<AlbumDataV5> for AlbumData {
+    fn from(legacy: AlbumDataV5) -> Self {
+        Self {
+            name: legacy.name,
+            path: legacy.path,
+            track_count: legacy.track_count,
+            total_bytes: legacy.total_bytes,
+            musicbrainz_id: legacy.musicbrainz_id,
+            content_digest: 0,
+        }
+    }
+}
+
+/// Layout of `ArtistData` before album statistics were stored (schema version 1).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct ArtistDataV1 {
     pub album_count: usize,
     pub last_modified: u64,
     pub albums: Vec<(String, String)>, // (album name, full path)
 }
+
+/// Layout of `ArtistData` before display names were stored (schema versions 2 and 3).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct ArtistDataV2 {
+    pub album_count: usize,
+    pub last_modified: u64,
+    pub albums: Vec<AlbumDataV4>,
+}
+
+impl ArtistDataV2 {
+    /// Converts the entry to the layout of version 4, with the given display name.
+    pub fn with_display_name(self, display_name: String) -> ArtistDataV4 {
+        ArtistDataV4 {
+            album_count: self.album_count,
+            last_modified: self.last_modified,
+            albums: self.albums,
+            display_name,
+        }
+    }
+}
+
+/// Layout of `ArtistData` before MusicBrainz IDs were stored (schema version 4).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct ArtistDataV4 {
+    pub album_count: usize,
+    pub last_modified: u64,
+    pub albums: Vec<AlbumDataV4>,
+    pub display_name: String,
+}
+
+impl From<ArtistDataV4> for ArtistData {
+    fn from(legacy: ArtistDataV4) -> Self {
+        Self {
+            album_count: legacy.album_count,
+            last_modified: legacy.last_modified,
+            albums: legacy.albums.into_iter().map(AlbumData::from).collect(),
+            display_name: legacy.display_name,
+        }
+    }
+}
+
+/// Layout of `ArtistData` before content digests were stored (schema version 5).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct ArtistDataV5 {
+    pub album_count: usize,
+    pub last_modified: u64,
+    pub albums: Vec<AlbumDataV5>,
+    pub display_name: String,
+}
+
+impl From<ArtistDataV5> for ArtistData {
+    fn from(legacy: ArtistDataV5) -> Self {
+        Self {
+            album_count: legacy.album_count,
+            last_modified: legacy.last_modified,
+            albums: legacy.albums.into_iter().map(AlbumData::from).collect(),
+            display_name: legacy.display_name,
+        }
+    }
+}
+
+impl From<ArtistDataV1> for ArtistDataV2 {
+    /// Album statistics are unknown for legacy entries, so they are zeroed and the
+    /// modification time is reset to force the next scan to refresh the artist.
+    fn from(legacy: ArtistDataV1) -> Self {
+        Self {
+            album_count: legacy.album_count,
+            last_modified: 0,
+            albums: legacy
+                .albums
+                .into_iter()
+                .map(|(name, path)| AlbumDataV4 {
+                    name,
+                    path,
+                    ..AlbumDataV4::default()
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A problem found while verifying the database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyIssue {
+    /// The database itself could not be read.
+    Unreadable { error: String },
+    /// The entry stored under `key` could not be deserialized.
+    Corrupt { key: String, error: String },
+    /// An album path stored for `artist` no longer exists on disk.
+    MissingPath {
+        artist: String,
+        album: String,
+        path: String,
+    },
+}
+
+impl fmt::Display for VerifyIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyIssue::Unreadable { error } => write!(f, "Unreadable database: {}", error),
+            VerifyIssue::Corrupt { key, error } => {
+                write!(f, "Corrupt entry '{}': {}", key, error)
+            }
+            VerifyIssue::MissingPath {
+                artist,
+                album,
+                path,
+            } => write!(
+                f,
+                "Missing album path for '{}' - '{}': {}",
+                artist, album, path
+            ),
+        }
+    }
+}
@@ -1,79 +1,448 @@
-use crate::foundation::database::ArtistData;
-use crate::foundation::utils::normalize_unicode;
+use crate::foundation::database::{
+    AlbumData, ArtistData, ArtistDataV1, ArtistDataV2, ArtistDataV4, ArtistDataV5, ArtistStore,
+    StoreEntry, VerifyIssue,
+};
+use crate::foundation::utils::{normalize_unicode, ArtistKeys, KeyStrategy};
+use fs2::FileExt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use sled::Db;
-use std::io;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::{fs, io};
+
+/// Prefix of the keys musync uses for its own bookkeeping, stored next to artist entries.
+///
+/// Artist names are normalized before being stored, so they never collide with it in
+/// practice. Code iterating over the database must skip these keys, as
+/// [`artist_entries`] does.
+pub const METADATA_KEY_PREFIX: &str = "__musync:";
+
+/// Returns `true` if `key` is a reserved metadata key rather than an artist entry.
+pub fn is_metadata_key(key: &[u8]) -> bool {
+    key.starts_with(METADATA_KEY_PREFIX.as_bytes())
+}
+
+/// Returns every artist entry of the database, sorted by key, skipping the metadata
+/// entries. Read errors are returned as they occur.
+pub fn artist_entries(db: &dyn ArtistStore) -> impl Iterator<Item = io::Result<StoreEntry>> + '_ {
+    db.iter()
+        .filter(|entry| !matches!(entry, Ok((key, _)) if is_metadata_key(key)))
+}
+
+/// Version of the layout used to store artist entries.
+///
+/// Bump it whenever `ArtistData` changes and add a conversion to [`migrate_database`].
+pub const SCHEMA_VERSION: u32 = 6;
+
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+const ALBUM_COUNTS_KEY: &str = "album_counts";
+
+/// Name of the metadata entry caching the album counts of the artist stored under `key`
+/// when it was last compared with the server at `api_base_url`.
+pub fn album_counts_key(api_base_url: &str, key: &str) -> String {
+    format!("{}:{}:{}", ALBUM_COUNTS_KEY, api_base_url, key)
+}
+
+/// Metadata entry holding the [`KeyStrategy`] artist entries are keyed with.
+const KEY_STRATEGY_KEY: &str = "key_strategy";
+
+/// Metadata entry holding the leading articles left out of artist keys.
+const KEY_ARTICLES_KEY: &str = "key_articles";
+
+/// Metadata entry holding the library root that album paths were last made relative to.
+const LIBRARY_ROOT_KEY: &str = "library_root";
 
 /// Opens a database at the specified path.
 ///
 /// This function creates a new database or opens an existing one at the given path.
 /// It's a friendly wrapper around `sled::open` that converts the error to a standard
-/// IO error for easier error handling.
+/// IO error for easier error handling. Entries stored by older versions of musync are
+/// migrated to the current layout.
 ///
 /// # Examples
 ///
-/// ```
+/// ```no_run
 /// use musync::open_database;
+/// # fn main() -> std::io::Result<()> {
 /// let db = open_database("/path/to/my/database")?;
+/// # Ok(())
+/// # }
 /// ```
 pub fn open_database(path: &str) -> io::Result<Db> {
-    sled::open(path).map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+    open_database_with(path, &DatabaseOptions::default())
+}
+
+/// Options for opening a database with [`open_database_with`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DatabaseOptions {
+    /// Size of the page cache, in bytes. sled's default of 1 GiB when unset.
+    pub cache_capacity: Option<u64>,
+    /// Open an existing database for commands that only read it.
+    pub read_only: bool,
+}
+
+/// Opens a database at the specified path with the given options.
+///
+/// sled has no read-only mode: an open database is locked, and a second process can't
+/// open it. With `read_only`, a missing database is returned as a `NotFound` error
+/// rather than created, and a database held by a running sync as a `WouldBlock` error,
+/// as reading files another process is writing wouldn't give consistent data. Only an
+/// older layout is written, when it is migrated.
+///
+/// # Examples
+///
+/// ```no_run
+/// use musync::{open_database_with, DatabaseOptions};
+/// # fn main() -> std::io::Result<()> {
+/// let options = DatabaseOptions {
+///     read_only: true,
+///     ..DatabaseOptions::default()
+/// };
+/// let db = open_database_with("/path/to/my/database", &options)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn open_database_with(path: &str, options: &DatabaseOptions) -> io::Result<Db> {
+    let mut config = sled::Config::new();
+    if let Some(cache_capacity) = options.cache_capacity {
+        config = config.cache_capacity(cache_capacity);
+    }
+
+    if options.read_only {
+        check_database_available(Path::new(path))?;
+    }
+
+    let db = config.path(path).open().map_err(io::Error::other)?;
+    migrate_database(&db)?;
+    Ok(db)
+}
+
+/// Returns an error if there is no database at `path`, or if another process holds it.
+///
+/// sled locks the `db` file of an open database exclusively; taking a shared lock on it
+/// tells whether it is in use without opening it.
+fn check_database_available(path: &Path) -> io::Result<()> {
+    if !path.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No database found at {}", path.display()),
+        ));
+    }
+
+    let file = fs::File::open(path.join("db"))?;
+    match FileExt::try_lock_shared(&file) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == fs2::lock_contended_error().kind() => Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            format!(
+                "The database at {} is in use, e.g. by a running sync",
+                path.display()
+            ),
+        )),
+        Err(e) => Err(e),
+    }
+}
+
+/// Converts the artist entries of an older database layout to the current one.
+///
+/// Databases without a stored schema version are considered version 1, where albums
+/// were stored as `(name, path)` tuples. Entries that can't be decoded with the legacy
+/// layout are left untouched so `verify` can report them. Up to version 2, artist keys
+/// kept combining marks (`"cafe\u{301}"`); they are re-keyed with the current
+/// [`normalize_unicode`]. Up to version 3, the original artist name wasn't stored, so
+/// the key is used as the display name until the artist is scanned again. Up to
+/// version 4, albums had no MusicBrainz ID; they get none. Up to version 5, albums had
+/// no content digest; they get 0, and the next `ContentHash` scan rescans the artist.
+///
+/// # Returns
+///
+/// The number of entries that were migrated.
+///
+pub fn migrate_database(db: &dyn ArtistStore) -> io::Result<usize> {
+    let version = get_metadata::<u32>(db, SCHEMA_VERSION_KEY)?.unwrap_or(1);
+    if version >= SCHEMA_VERSION {
+        return Ok(0);
+    }
+
+    let migrated = migrate_artist_entries(db, version)?;
+
+    store_metadata(db, SCHEMA_VERSION_KEY, &SCHEMA_VERSION)?;
+    db.flush()?;
+    Ok(migrated)
+}
+
+/// Rewrites every artist entry stored with the layout of `version` in the current one.
+///
+/// Albums stored as `(name, path)` tuples (version 1) become `AlbumData`, keys are
+/// normalized again without combining marks (up to version 2), the key becomes the
+/// display name (up to version 3), albums get an empty MusicBrainz ID (up to
+/// version 4) and an unknown content digest (up to version 5).
+fn migrate_artist_entries(db: &dyn ArtistStore, version: u32) -> io::Result<usize> {
+    // Collect the entries first, so re-keyed entries aren't visited a second time.
+    let entries = artist_entries(db).collect::<io::Result<Vec<_>>>()?;
+
+    let mut migrated = 0;
+    for (key, value) in entries {
+        let old_key = String::from_utf8_lossy(&key).into_owned();
+        let new_key = normalize_unicode(&old_key);
+        let legacy = if version < 2 {
+            bincode::deserialize::<ArtistDataV1>(&value)
+                .map(|v1| ArtistDataV2::from(v1).with_display_name(new_key.clone()))
+                .map(ArtistData::from)
+        } else if version < 4 {
+            bincode::deserialize::<ArtistDataV2>(&value)
+                .map(|v2| ArtistData::from(v2.with_display_name(new_key.clone())))
+        } else if version < 5 {
+            bincode::deserialize::<ArtistDataV4>(&value).map(ArtistData::from)
+        } else {
+            bincode::deserialize::<ArtistDataV5>(&value).map(ArtistData::from)
+        };
+        let Ok(data) = legacy else {
+            continue;
+        };
+
+        let serialized = bincode::serialize(&data).map_err(|e| io::Error::other(e.to_string()))?;
+        db.put(new_key.as_bytes(), &serialized)?;
+        if new_key != old_key {
+            db.remove(&key)?;
+        }
+        migrated += 1;
+    }
+    Ok(migrated)
+}
+
+/// Returns the [`KeyStrategy`] the artist entries of the database are keyed with,
+/// `NfdLower` unless [`set_key_strategy`] changed it.
+pub fn key_strategy(db: &dyn ArtistStore) -> io::Result<KeyStrategy> {
+    Ok(get_metadata(db, KEY_STRATEGY_KEY)?.unwrap_or_default())
+}
+
+/// Returns the leading articles left out of the keys of artist entries, none unless
+/// [`set_key_articles`] changed them.
+pub fn key_articles(db: &dyn ArtistStore) -> io::Result<Vec<String>> {
+    Ok(get_metadata(db, KEY_ARTICLES_KEY)?.unwrap_or_default())
+}
+
+/// Returns the [`ArtistKeys`] the artist entries of the database are keyed with, per
+/// its key strategy and articles.
+pub fn artist_keys(db: &dyn ArtistStore) -> io::Result<ArtistKeys> {
+    Ok(ArtistKeys::new(key_strategy(db)?, &key_articles(db)?))
+}
+
+/// Returns the key `artist_name` is stored under, per the key strategy and articles of
+/// the database.
+pub fn artist_key(db: &dyn ArtistStore, artist_name: &str) -> io::Result<String> {
+    Ok(artist_keys(db)?.key(artist_name))
+}
+
+/// Keys the artist entries of the database with `strategy`.
+///
+/// When the database was keyed with another strategy, every artist entry is stored again
+/// under the key of its `display_name`. Entries whose names get the same key are merged,
+/// the last one winning; the next scan stores the albums of both. Entries that can't be
+/// decoded are left untouched. The database is flushed afterwards.
+///
+/// # Returns
+///
+/// The number of entries that were re-keyed.
+///
+/// # Arguments
+///
+/// * `db` - A reference to the opened database, or any other [`ArtistStore`].
+/// * `strategy` - The configured `key_strategy`.
+///
+pub fn set_key_strategy(db: &dyn ArtistStore, strategy: KeyStrategy) -> io::Result<usize> {
+    if key_strategy(db)? == strategy {
+        return Ok(0);
+    }
+
+    let rekeyed = rekey_artists(db, &ArtistKeys::new(strategy, &key_articles(db)?))?;
+    store_metadata(db, KEY_STRATEGY_KEY, &strategy)?;
+    db.flush()?;
+    Ok(rekeyed)
+}
+
+/// Leaves `articles` out of the keys of the artist entries of the database, so that
+/// `The Beatles` and `Beatles` are stored as one artist.
+///
+/// Entries are re-keyed as by [`set_key_strategy`] when the articles changed.
+///
+/// # Returns
+///
+/// The number of entries that were re-keyed.
+///
+/// # Arguments
+///
+/// * `db` - A reference to the opened database, or any other [`ArtistStore`].
+/// * `articles` - The configured `ignore_articles`.
+///
+pub fn set_key_articles(db: &dyn ArtistStore, articles: &[String]) -> io::Result<usize> {
+    if key_articles(db)? == articles {
+        return Ok(0);
+    }
+
+    let rekeyed = rekey_artists(db, &ArtistKeys::new(key_strategy(db)?, articles))?;
+    store_metadata(db, KEY_ARTICLES_KEY, &articles)?;
+    db.flush()?;
+    Ok(rekeyed)
+}
+
+/// Stores every artist entry again under the key `keys` gives its `display_name`.
+///
+/// Entries getting the same key, such as `The Beatles` and `Beatles` once articles are
+/// ignored, are merged: their albums are combined, so none is lost until the next scan.
+/// The album counts cached by comparisons are keyed by artist key, so they are removed.
+///
+/// Returns the number of entries that were re-keyed.
+fn rekey_artists(db: &dyn ArtistStore, keys: &ArtistKeys) -> io::Result<usize> {
+    clear_album_counts(db)?;
+    // Collect the entries first, so re-keyed entries aren't visited a second time.
+    let entries = artist_entries(db).collect::<io::Result<Vec<_>>>()?;
+    let mut artists: BTreeMap<String, (ArtistData, bool)> = BTreeMap::new();
+    let mut rekeyed = 0;
+    for (key, value) in entries {
+        let Ok(data) = bincode::deserialize::<ArtistData>(&value) else {
+            continue;
+        };
+        let new_key = keys.key(&data.display_name);
+        let moved = new_key.as_bytes() != key.as_slice();
+        if moved {
+            db.remove(&key)?;
+            rekeyed += 1;
+        }
+        match artists.entry(new_key) {
+            Entry::Vacant(entry) => {
+                entry.insert((data, moved));
+            }
+            Entry::Occupied(mut entry) => {
+                let (merged, changed) = entry.get_mut();
+                merge_artist_data(merged, data);
+                *changed = true;
+            }
+        }
+    }
+
+    for (key, (data, changed)) in artists {
+        if changed {
+            let serialized =
+                bincode::serialize(&data).map_err(|e| io::Error::other(e.to_string()))?;
+            db.put(key.as_bytes(), &serialized)?;
+        }
+    }
+    Ok(rekeyed)
+}
+
+/// Removes the album counts cached by comparisons, see [`album_counts_key`].
+fn clear_album_counts(db: &dyn ArtistStore) -> io::Result<()> {
+    let prefix = metadata_key(&format!("{}:", ALBUM_COUNTS_KEY));
+    let keys = db
+        .iter()
+        .map(|entry| entry.map(|(key, _)| key))
+        .filter(|key| !matches!(key, Ok(key) if !key.starts_with(prefix.as_bytes())))
+        .collect::<io::Result<Vec<_>>>()?;
+    for key in keys {
+        db.remove(&key)?;
+    }
+    Ok(())
+}
+
+/// Adds the albums of `other` missing from `data`, by path, keeping the name of `data`.
+fn merge_artist_data(data: &mut ArtistData, other: ArtistData) {
+    for album in other.albums {
+        if !data.albums.iter().any(|stored| stored.path == album.path) {
+            data.albums.push(album);
+        }
+    }
+    data.album_count = data.albums.len();
+    data.last_modified = data.last_modified.max(other.last_modified);
 }
 
 /// Stores artist data in the database.
 ///
 /// This function takes various pieces of information about an artist and stores
-/// them in the database. It normalizes the artist name to ensure consistent storage
-/// and retrieval, even with different Unicode representations.
+/// them in the database. The artist name is reduced to a key with the database's
+/// [`KeyStrategy`] to ensure consistent storage and retrieval, even with different
+/// Unicode representations. The name itself is kept as the entry's `display_name`.
 ///
 /// # Arguments
 ///
-/// * `db` - A reference to the opened database.
+/// * `db` - A reference to the opened database, or any other [`ArtistStore`].
 /// * `artist_name` - The name of the artist to store.
 /// * `album_count` - The number of albums by the artist.
 /// * `last_modified` - A timestamp indicating when the data was last modified.
-/// * `albums` - A vector of tuples containing album names and years.
+/// * `albums` - The albums of the artist.
 ///
 /// # Examples
 ///
-/// ```
-/// use musync::store_artist_data;
-/// use musync::open_database;
+/// ```no_run
+/// use musync::{open_database, store_artist_data, AlbumData};
 ///
+/// # fn main() -> std::io::Result<()> {
 /// let db = open_database("/path/to/my/database")?;
 ///
-/// let albums = vec![("Album Name".to_string(), "2023".to_string())];
+/// let albums = vec![AlbumData {
+///     name: "Album Name".to_string(),
+///     path: "Artist Name/Album Name".to_string(),
+///     track_count: 10,
+///     total_bytes: 314_572_800,
+///     musicbrainz_id: None,
+///     content_digest: 0,
+/// }];
 /// store_artist_data(&db, "Artist Name", 1, 1234567890, albums)?;
+/// # Ok(())
+/// # }
 /// ```
 pub fn store_artist_data(
-    db: &Db,
+    db: &dyn ArtistStore,
     artist_name: &str,
     album_count: usize,
     last_modified: u64,
-    albums: Vec<(String, String)>,
+    albums: Vec<AlbumData>,
 ) -> io::Result<()> {
-    let normalized_name = normalize_unicode(artist_name);
+    let key = artist_key(db, artist_name)?;
+    store_artist_data_by_key(db, &key, artist_name, album_count, last_modified, albums)
+}
 
+/// Stores artist data under `key`, as [`store_artist_data`] does under the key of
+/// `artist_name`.
+///
+/// Scans use it with the [`ArtistKeys`] they read once, so the key strategy and
+/// articles of the database aren't read again for every artist.
+pub fn store_artist_data_by_key(
+    db: &dyn ArtistStore,
+    key: &str,
+    artist_name: &str,
+    album_count: usize,
+    last_modified: u64,
+    albums: Vec<AlbumData>,
+) -> io::Result<()> {
     let data = ArtistData {
         album_count,
         last_modified,
         albums,
+        display_name: artist_name.to_string(),
     };
 
-    let serialized = bincode::serialize(&data)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
-    db.insert(normalized_name.as_bytes(), serialized)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
-    Ok(())
+    put_artist_data(db, key, &data)
+}
+
+fn put_artist_data(db: &dyn ArtistStore, key: &str, data: &ArtistData) -> io::Result<()> {
+    let serialized = bincode::serialize(data).map_err(|e| io::Error::other(e.to_string()))?;
+    db.put(key.as_bytes(), &serialized)
 }
 
 /// Retrieves artist data from the database.
 ///
-/// This function fetches the stored data for a given artist. It normalizes the
-/// artist name to ensure consistent retrieval, regardless of Unicode representation.
+/// This function fetches the stored data for a given artist. The artist name is reduced
+/// to a key with the database's [`KeyStrategy`] to ensure consistent retrieval,
+/// regardless of Unicode representation.
 ///
 /// # Arguments
 ///
-/// * `db` - A reference to the opened database.
+/// * `db` - A reference to the opened database, or any other [`ArtistStore`].
 /// * `artist_name` - The name of the artist to retrieve data for.
 ///
 /// # Returns
@@ -84,34 +453,337 @@ pub fn store_artist_data(
 ///
 /// # Examples
 ///
-/// ```
+/// ```no_run
 /// use musync::get_artist_data;
 /// use musync::open_database;
 ///
+/// # fn main() -> std::io::Result<()> {
 /// let db = open_database("/path/to/my/database")?;
 ///
 /// match get_artist_data(&db, "Artist Name")? {
 ///     Some(data) => println!("Found artist data: {:?}", data),
 ///     None => println!("Artist not found in database"),
 /// }
+/// # Ok(())
+/// # }
 /// ```
-pub fn get_artist_data(db: &Db, artist_name: &str) -> io::Result<Option<ArtistData>> {
-    let normalized_name = normalize_unicode(artist_name);
-
-    db.get(normalized_name.as_bytes())
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?
-        .map(|ivec| {
-            bincode::deserialize(&ivec)
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
-        })
+pub fn get_artist_data(db: &dyn ArtistStore, artist_name: &str) -> io::Result<Option<ArtistData>> {
+    get_artist_data_by_key(db, &artist_key(db, artist_name)?)
+}
+
+/// Retrieves the artist data stored under `key`, as returned by [`ArtistKeys::key`].
+pub fn get_artist_data_by_key(db: &dyn ArtistStore, key: &str) -> io::Result<Option<ArtistData>> {
+    db.get(key.as_bytes())?
+        .map(|value| bincode::deserialize(&value).map_err(|e| io::Error::other(e.to_string())))
+        .transpose()
+}
+
+/// Stores a value under the reserved metadata key `name`.
+///
+/// # Arguments
+///
+/// * `db` - A reference to the opened database, or any other [`ArtistStore`].
+/// * `name` - The metadata name; it is prefixed with [`METADATA_KEY_PREFIX`].
+/// * `value` - The value to serialize and store.
+///
+pub fn store_metadata<T: Serialize>(db: &dyn ArtistStore, name: &str, value: &T) -> io::Result<()> {
+    let serialized = bincode::serialize(value).map_err(|e| io::Error::other(e.to_string()))?;
+    db.put(metadata_key(name).as_bytes(), &serialized)
+}
+
+/// Retrieves the value stored under the reserved metadata key `name`, if any.
+///
+/// # Arguments
+///
+/// * `db` - A reference to the opened database, or any other [`ArtistStore`].
+/// * `name` - The metadata name; it is prefixed with [`METADATA_KEY_PREFIX`].
+///
+pub fn get_metadata<T: DeserializeOwned>(
+    db: &dyn ArtistStore,
+    name: &str,
+) -> io::Result<Option<T>> {
+    db.get(metadata_key(name).as_bytes())?
+        .map(|value| bincode::deserialize(&value).map_err(|e| io::Error::other(e.to_string())))
         .transpose()
 }
 
+fn metadata_key(name: &str) -> String {
+    format!("{}{}", METADATA_KEY_PREFIX, name)
+}
+
+/// Makes the album paths stored under the library root relative to it, so the library
+/// can move to another mount point without invalidating them.
+///
+/// Full paths are stripped of `root` or of the root they were last made relative to,
+/// which is remembered in the database: after the library moved, paths stored by an
+/// older version of musync under the previous root are still converted. Paths outside
+/// both are kept as they are.
+///
+/// # Returns
+///
+/// The number of album paths that were made relative.
+///
+/// # Arguments
+///
+/// * `db` - A reference to the opened database, or any other [`ArtistStore`].
+/// * `root` - The current library root, `local_path`.
+///
+pub fn relativize_album_paths(db: &dyn ArtistStore, root: &Path) -> io::Result<usize> {
+    let root = root.to_string_lossy().into_owned();
+    let previous_root = get_metadata::<String>(db, LIBRARY_ROOT_KEY).unwrap_or(None);
+    let roots: Vec<&Path> = [Some(&root), previous_root.as_ref()]
+        .into_iter()
+        .flatten()
+        .map(Path::new)
+        .collect();
+
+    let mut relativized = 0;
+    for entry in artist_entries(db).collect::<Vec<_>>() {
+        let (key, value) = entry?;
+        let mut data: ArtistData =
+            bincode::deserialize(&value).map_err(|e| io::Error::other(e.to_string()))?;
+
+        let mut changed = false;
+        for album in &mut data.albums {
+            let path = Path::new(&album.path);
+            if !path.is_absolute() {
+                continue;
+            }
+            if let Some(relative) = roots.iter().find_map(|root| path.strip_prefix(root).ok()) {
+                album.path = relative.to_string_lossy().into_owned();
+                changed = true;
+                relativized += 1;
+            }
+        }
+        if changed {
+            put_artist_data(db, &String::from_utf8_lossy(&key), &data)?;
+        }
+    }
+
+    store_metadata(db, LIBRARY_ROOT_KEY, &root)?;
+    Ok(relativized)
+}
+
+/// Removes every entry from the database, including cached metadata such as the album
+/// counts of the last comparisons.
+///
+/// This is used to force a full rescan of the library on the next run. The database
+/// is flushed afterwards so the removal is persisted even if the process exits right away.
+///
+/// # Returns
+///
+/// The number of artist entries that were removed. The schema version, the key
+/// strategy and the key articles are kept.
+///
+/// # Examples
+///
+/// ```no_run
+/// use musync::{clear_database, open_database};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let db = open_database("/path/to/my/database")?;
+/// let removed = clear_database(&db)?;
+/// println!("Removed {} entries", removed);
+/// # Ok(())
+/// # }
+/// ```
+pub fn clear_database(db: &dyn ArtistStore) -> io::Result<usize> {
+    let strategy = key_strategy(db)?;
+    let articles = key_articles(db)?;
+    let removed = db
+        .iter()
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .map_or(true, |(key, _)| !is_metadata_key(key))
+        })
+        .count();
+    db.clear()?;
+    store_metadata(db, SCHEMA_VERSION_KEY, &SCHEMA_VERSION)?;
+    if strategy != KeyStrategy::default() {
+        store_metadata(db, KEY_STRATEGY_KEY, &strategy)?;
+    }
+    if !articles.is_empty() {
+        store_metadata(db, KEY_ARTICLES_KEY, &articles)?;
+    }
+    db.flush()?;
+    Ok(removed)
+}
+
+/// Returns the number of artist entries in the database. Metadata entries aren't counted.
+pub fn count_artists(db: &dyn ArtistStore) -> io::Result<usize> {
+    let mut count = 0;
+    for entry in artist_entries(db) {
+        entry?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Returns the display names of the artists of the database, sorted by key. Metadata
+/// entries aren't listed.
+pub fn list_all_artists(db: &dyn ArtistStore) -> io::Result<Vec<String>> {
+    Ok(export_database(db)?
+        .into_iter()
+        .map(|(_, artist)| artist.display_name)
+        .collect())
+}
+
+/// Returns the number of albums stored for all the artists of the database.
+pub fn count_albums(db: &dyn ArtistStore) -> io::Result<usize> {
+    Ok(export_database(db)?
+        .iter()
+        .map(|(_, artist)| artist.albums.len())
+        .sum())
+}
+
+/// Returns every artist entry of the database, keyed by normalized artist name and
+/// sorted by key. Metadata entries are skipped.
+///
+/// The result can be serialized (e.g. to JSON) and passed back to [`import_database`].
+/// An entry that can't be decoded is reported as an error rather than skipped, so an
+/// export is always complete.
+///
+/// # Examples
+///
+/// ```no_run
+/// use musync::{export_database, open_database};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let db = open_database("/path/to/my/database")?;
+/// let entries = export_database(&db)?;
+/// println!("Exported {} artists", entries.len());
+/// # Ok(())
+/// # }
+/// ```
+pub fn export_database(db: &dyn ArtistStore) -> io::Result<Vec<(String, ArtistData)>> {
+    let mut entries = Vec::new();
+
+    for entry in artist_entries(db) {
+        let (key, value) = entry?;
+        let key = String::from_utf8_lossy(&key).into_owned();
+        let data = bincode::deserialize(&value).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Corrupt entry '{}': {}", key, e),
+            )
+        })?;
+        entries.push((key, data));
+    }
+
+    Ok(entries)
+}
+
+/// Stores exported artist entries, replacing existing entries with the same key.
+///
+/// Entries are keyed again with the database's [`ArtistKeys`], so entries exported by an
+/// older version or keyed with another strategy are stored under the current key
+/// layout. Entries exported without a display name use their key as display name. The
+/// database is flushed afterwards.
+///
+/// # Returns
+///
+/// The number of entries that were imported.
+///
+/// # Arguments
+///
+/// * `db` - A reference to the opened database, or any other [`ArtistStore`].
+/// * `entries` - Artist entries, as returned by [`export_database`].
+///
+pub fn import_database(
+    db: &dyn ArtistStore,
+    entries: Vec<(String, ArtistData)>,
+) -> io::Result<usize> {
+    let count = entries.len();
+    let keys = artist_keys(db)?;
+    for (key, mut data) in entries {
+        if data.display_name.is_empty() {
+            data.display_name = key;
+        }
+        put_artist_data(db, &keys.key(&data.display_name), &data)?;
+    }
+    db.flush()?;
+    Ok(count)
+}
+
+/// Checks every entry of the database for problems.
+///
+/// Each stored value is deserialized as `ArtistData`, and every album path it references
+/// is checked for existence on disk, relative to the library `root`. Metadata entries are
+/// skipped. The database is only read, never modified.
+///
+/// # Returns
+///
+/// A list of issues found. An empty list means the database is healthy.
+///
+/// # Examples
+///
+/// ```no_run
+/// use musync::{open_database, verify_database};
+/// use std::path::Path;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let db = open_database("/path/to/my/database")?;
+/// for issue in verify_database(&db, Path::new("/path/to/music")) {
+///     println!("{}", issue);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn verify_database(db: &dyn ArtistStore, root: &Path) -> Vec<VerifyIssue> {
+    let mut issues = Vec::new();
+
+    for entry in artist_entries(db) {
+        let (key, value) = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                issues.push(VerifyIssue::Unreadable {
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let key = String::from_utf8_lossy(&key).into_owned();
+
+        let data: ArtistData = match bincode::deserialize(&value) {
+            Ok(data) => data,
+            Err(e) => {
+                issues.push(VerifyIssue::Corrupt {
+                    key,
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+
+        for album in data.albums {
+            if !album.full_path(root).exists() {
+                issues.push(VerifyIssue::MissingPath {
+                    artist: key.clone(),
+                    album: album.name,
+                    path: album.path,
+                });
+            }
+        }
+    }
+
+    issues
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::foundation::database::{AlbumDataV4, AlbumDataV5, MemoryStore};
     use tempfile::tempdir;
 
+    fn album(name: &str, path: &str) -> AlbumData {
+        AlbumData {
+            name: name.to_string(),
+            path: path.to_string(),
+            ..AlbumData::default()
+        }
+    }
+
     #[test]
     fn test_open_database() {
         let temp_dir = tempdir().unwrap();
@@ -122,6 +794,50 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_open_database_with_cache_capacity() {
+        let temp_dir = tempdir().unwrap();
+        let binding = temp_dir.path().join("test_db");
+        let db_path = binding.to_str().unwrap();
+        let options = DatabaseOptions {
+            cache_capacity: Some(16 * 1024 * 1024),
+            ..DatabaseOptions::default()
+        };
+
+        let db = open_database_with(db_path, &options).unwrap();
+        store_artist_data(&db, "Artist", 1, 0, vec![album("Album", "Artist/Album")]).unwrap();
+        db.flush().unwrap();
+        drop(db);
+
+        let db = open_database_with(db_path, &options).unwrap();
+        assert!(get_artist_data(&db, "Artist").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_read_only_open_refuses_a_database_in_use() {
+        let temp_dir = tempdir().unwrap();
+        let binding = temp_dir.path().join("test_db");
+        let db_path = binding.to_str().unwrap();
+        let read_only = DatabaseOptions {
+            read_only: true,
+            ..DatabaseOptions::default()
+        };
+        let missing = open_database_with(db_path, &read_only).unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+
+        // The writable database stays open, holding its lock.
+        let db = open_database(db_path).unwrap();
+        store_artist_data(&db, "Artist", 1, 0, vec![album("Album", "Artist/Album")]).unwrap();
+        db.flush().unwrap();
+
+        let in_use = open_database_with(db_path, &read_only).unwrap_err();
+        assert_eq!(in_use.kind(), io::ErrorKind::WouldBlock);
+
+        drop(db);
+        let db = open_database_with(db_path, &read_only).unwrap();
+        assert!(get_artist_data(&db, "Artist").unwrap().is_some());
+    }
+
     #[test]
     fn test_store_and_get_artist_data() {
         let temp_dir = tempdir().unwrap();
@@ -133,10 +849,7 @@ mod tests {
         let artist_name = "Test Artist";
         let album_count = 2;
         let last_modified = 1234567890;
-        let albums = vec![
-            ("Album 1".to_string(), "2020".to_string()),
-            ("Album 2".to_string(), "2022".to_string()),
-        ];
+        let albums = vec![album("Album 1", "2020"), album("Album 2", "2022")];
 
         // Store artist data
         let store_result =
@@ -153,6 +866,21 @@ mod tests {
         assert_eq!(artist_data.albums, albums);
     }
 
+    #[test]
+    fn test_store_and_get_artist_data_by_key() {
+        let db = MemoryStore::new();
+        set_key_articles(&db, &["The".to_string()]).unwrap();
+        let keys = artist_keys(&db).unwrap();
+
+        let key = keys.key("The Beatles");
+        store_artist_data_by_key(&db, &key, "The Beatles", 1, 0, vec![album("Help!", "1965")])
+            .unwrap();
+
+        let data = get_artist_data_by_key(&db, &key).unwrap().unwrap();
+        assert_eq!(data.display_name, "The Beatles");
+        assert_eq!(get_artist_data(&db, "Beatles").unwrap(), Some(data));
+    }
+
     #[test]
     fn test_get_nonexistent_artist() {
         let temp_dir = tempdir().unwrap();
@@ -178,7 +906,7 @@ mod tests {
         let normalized_name = normalize_unicode(artist_name);
         let album_count = 1;
         let last_modified = 1234567890;
-        let albums = vec![("Album".to_string(), "2020".to_string())];
+        let albums = vec![album("Album", "2020")];
 
         println!("Original artist name: {}", artist_name);
         println!("Normalized artist name: {}", normalized_name);
@@ -190,7 +918,7 @@ mod tests {
 
         // Print all keys in the database after storing
         println!("Keys in the database after storing:");
-        for key in db.iter().keys() {
+        for key in sled::Tree::iter(&db).keys() {
             println!("{:?}", String::from_utf8_lossy(&key.unwrap()));
         }
 
@@ -229,7 +957,7 @@ mod tests {
         let artist_name = "Test Artist";
         let album_count = 1;
         let last_modified = 1234567890;
-        let albums = vec![("Album 1".to_string(), "2020".to_string())];
+        let albums = vec![album("Album 1", "2020")];
 
         // Store initial data
         let store_result =
@@ -239,10 +967,7 @@ mod tests {
         // Overwrite with new data
         let new_album_count = 2;
         let new_last_modified = 1234567891;
-        let new_albums = vec![
-            ("Album 1".to_string(), "2020".to_string()),
-            ("Album 2".to_string(), "2022".to_string()),
-        ];
+        let new_albums = vec![album("Album 1", "2020"), album("Album 2", "2022")];
 
         let overwrite_result = store_artist_data(
             &db,
@@ -262,4 +987,404 @@ mod tests {
         assert_eq!(artist_data.last_modified, new_last_modified);
         assert_eq!(artist_data.albums, new_albums);
     }
+
+    #[test]
+    fn test_clear_database() {
+        let temp_dir = tempdir().unwrap();
+        let binding = temp_dir.path().join("test_db");
+        let db_path = binding.to_str().unwrap();
+
+        let db = open_database(db_path).unwrap();
+        let albums = vec![album("Album", "/music/Artist/Album")];
+        store_artist_data(&db, "Artist 1", 1, 1234567890, albums.clone()).unwrap();
+        store_artist_data(&db, "Artist 2", 1, 1234567890, albums).unwrap();
+        let counts_key = album_counts_key("http://localhost/rest", "artist 1");
+        store_metadata(&db, &counts_key, &1usize).unwrap();
+
+        let removed = clear_database(&db).unwrap();
+
+        assert_eq!(removed, 2);
+        assert_eq!(get_metadata::<usize>(&db, &counts_key).unwrap(), None);
+        assert!(sled::Tree::iter(&db)
+            .keys()
+            .all(|key| is_metadata_key(&key.unwrap())));
+        assert!(get_artist_data(&db, "Artist 1").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_rekeying_merges_the_albums_of_colliding_artists() {
+        let db = MemoryStore::new();
+        store_artist_data(
+            &db,
+            "The Beatles",
+            1,
+            20,
+            vec![album("Help", "The Beatles/Help")],
+        )
+        .unwrap();
+        store_artist_data(
+            &db,
+            "Beatles",
+            1,
+            10,
+            vec![album("Revolver", "Beatles/Revolver")],
+        )
+        .unwrap();
+
+        let counts_key = album_counts_key("http://localhost/rest", "the beatles");
+        store_metadata(&db, &counts_key, &2usize).unwrap();
+        assert_eq!(set_key_articles(&db, &["The".to_string()]).unwrap(), 1);
+
+        assert_eq!(get_metadata::<usize>(&db, &counts_key).unwrap(), None);
+        let artist_data = get_artist_data(&db, "Beatles").unwrap().unwrap();
+        assert_eq!(count_artists(&db).unwrap(), 1);
+        assert_eq!(artist_data.album_count, 2);
+        assert_eq!(artist_data.last_modified, 20);
+        assert_eq!(
+            artist_data.albums,
+            [
+                album("Revolver", "Beatles/Revolver"),
+                album("Help", "The Beatles/Help")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_changing_the_key_strategy_rekeys_entries() {
+        let db = MemoryStore::new();
+        for name in ["Björk", "AC/DC"] {
+            store_artist_data(&db, name, 1, 0, vec![album("Album", name)]).unwrap();
+        }
+        let keys = |db: &MemoryStore| {
+            let mut keys: Vec<String> = db
+                .iter()
+                .map(|entry| entry.unwrap().0)
+                .filter(|key| !is_metadata_key(key))
+                .map(|key| String::from_utf8(key).unwrap())
+                .collect();
+            keys.sort();
+            keys
+        };
+        assert_eq!(keys(&db), ["ac/dc", "bjork"]);
+        assert_eq!(set_key_strategy(&db, KeyStrategy::NfdLower).unwrap(), 0);
+
+        assert_eq!(set_key_strategy(&db, KeyStrategy::Exact).unwrap(), 2);
+
+        assert_eq!(keys(&db), ["AC/DC", "Björk"]);
+        assert_eq!(key_strategy(&db).unwrap(), KeyStrategy::Exact);
+        assert!(get_artist_data(&db, "Björk").unwrap().is_some());
+        assert!(get_artist_data(&db, "bjork").unwrap().is_none());
+        assert_eq!(set_key_strategy(&db, KeyStrategy::Exact).unwrap(), 0);
+
+        clear_database(&db).unwrap();
+        assert_eq!(key_strategy(&db).unwrap(), KeyStrategy::Exact);
+    }
+
+    #[test]
+    fn test_export_clear_import_round_trip() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        store_metadata(&db, SCHEMA_VERSION_KEY, &SCHEMA_VERSION).unwrap();
+        store_artist_data(
+            &db,
+            "Björk",
+            2,
+            1234567890,
+            vec![
+                album("Post", "/music/Björk/Post"),
+                AlbumData {
+                    track_count: 11,
+                    total_bytes: 314_572_800,
+                    ..album("Homogenic", "/music/Björk/Homogenic")
+                },
+            ],
+        )
+        .unwrap();
+        store_artist_data(
+            &db,
+            "Radiohead",
+            1,
+            42,
+            vec![album("Kid A", "/music/R/Kid A")],
+        )
+        .unwrap();
+
+        let exported = export_database(&db).unwrap();
+        assert_eq!(
+            exported
+                .iter()
+                .map(|(key, _)| key.as_str())
+                .collect::<Vec<_>>(),
+            ["bjork", "radiohead"]
+        );
+
+        let json = serde_json::to_string(&exported).unwrap();
+        clear_database(&db).unwrap();
+        let imported = import_database(&db, serde_json::from_str(&json).unwrap()).unwrap();
+
+        assert_eq!(imported, 2);
+        assert_eq!(export_database(&db).unwrap(), exported);
+        assert_eq!(
+            get_artist_data(&db, "Björk").unwrap().unwrap(),
+            exported[0].1
+        );
+    }
+
+    #[test]
+    fn test_export_reports_corrupt_entries() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        db.insert("broken", &[0xff_u8][..]).unwrap();
+
+        let error = export_database(&db).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("broken"));
+    }
+
+    #[test]
+    fn test_verify_healthy_database() {
+        let temp_dir = tempdir().unwrap();
+        let album_path = temp_dir.path().join("Artist").join("Album");
+        std::fs::create_dir_all(&album_path).unwrap();
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let albums = vec![album("Album", &album_path.to_string_lossy())];
+        store_artist_data(&db, "Artist", 1, 1234567890, albums).unwrap();
+
+        assert!(verify_database(&db, temp_dir.path()).is_empty());
+    }
+
+    #[test]
+    fn test_verify_database_reports_issues() {
+        let temp_dir = tempdir().unwrap();
+        let dangling_path = temp_dir.path().join("Artist").join("Gone");
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let albums = vec![album("Gone", &dangling_path.to_string_lossy())];
+        store_artist_data(&db, "Artist", 1, 1234567890, albums).unwrap();
+        db.insert("broken", &[0xFF, 0x01]).unwrap();
+
+        let issues = verify_database(&db, temp_dir.path());
+
+        assert_eq!(issues.len(), 2);
+        assert!(matches!(
+            &issues[0],
+            VerifyIssue::MissingPath { artist, album, .. } if artist == "artist" && album == "Gone"
+        ));
+        assert!(matches!(&issues[1], VerifyIssue::Corrupt { key, .. } if key == "broken"));
+    }
+
+    #[test]
+    fn test_metadata_is_kept_apart_from_artists() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        store_artist_data(&db, "Artist", 0, 1234567890, Vec::new()).unwrap();
+        store_metadata(&db, "answer", &42u64).unwrap();
+
+        assert_eq!(get_metadata::<u64>(&db, "answer").unwrap(), Some(42));
+        assert_eq!(get_metadata::<u64>(&db, "missing").unwrap(), None);
+        assert!(get_artist_data(&db, "answer").unwrap().is_none());
+        assert_eq!(count_artists(&db).unwrap(), 1);
+        assert!(verify_database(&db, Path::new("/music")).is_empty());
+        assert_eq!(clear_database(&db).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_metadata_keys_are_not_listed_as_artists() {
+        let db = MemoryStore::new();
+        store_artist_data(&db, "Radiohead", 0, 1234567890, Vec::new()).unwrap();
+        store_metadata(&db, "last_run", &1234567890u64).unwrap();
+        // Not an `ArtistData`, so listing it as an artist would fail to decode it.
+        db.put(
+            format!("{METADATA_KEY_PREFIX}fingerprint").as_bytes(),
+            b"\xff",
+        )
+        .unwrap();
+
+        assert_eq!(list_all_artists(&db).unwrap(), ["Radiohead"]);
+        assert_eq!(artist_entries(&db).count(), 1);
+    }
+
+    #[test]
+    fn test_relativize_album_paths_after_the_root_moves() {
+        let db = MemoryStore::new();
+        let albums = vec![
+            album("One", "/mnt/old/Artist/One"),
+            album("Two", "Artist/Two"),
+            album("Elsewhere", "/srv/Artist/Elsewhere"),
+        ];
+        store_artist_data(&db, "Artist", 3, 1234567890, albums).unwrap();
+        relativize_album_paths(&db, Path::new("/mnt/old")).unwrap();
+        // An entry stored by an older version, before the library moved.
+        store_artist_data(
+            &db,
+            "Other",
+            1,
+            1234567890,
+            vec![album("Three", "/mnt/old/Other/Three")],
+        )
+        .unwrap();
+
+        let relativized = relativize_album_paths(&db, Path::new("/media/new")).unwrap();
+
+        assert_eq!(relativized, 1);
+        let paths: Vec<_> = get_artist_data(&db, "Artist")
+            .unwrap()
+            .unwrap()
+            .albums
+            .into_iter()
+            .map(|album| album.path)
+            .collect();
+        assert_eq!(paths, ["Artist/One", "Artist/Two", "/srv/Artist/Elsewhere"]);
+        let other = get_artist_data(&db, "Other").unwrap().unwrap();
+        assert_eq!(other.albums[0].path, "Other/Three");
+        assert_eq!(
+            other.albums[0].full_path(Path::new("/media/new")),
+            Path::new("/media/new/Other/Three")
+        );
+    }
+
+    #[test]
+    fn test_migrate_legacy_artist_data() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let legacy = ArtistDataV1 {
+            album_count: 2,
+            last_modified: 1234567890,
+            albums: vec![
+                ("Album 1".to_string(), "/music/Artist/Album 1".to_string()),
+                ("Album 2".to_string(), "/music/Artist/Album 2".to_string()),
+            ],
+        };
+        db.insert("artist", bincode::serialize(&legacy).unwrap())
+            .unwrap();
+
+        assert_eq!(migrate_database(&db).unwrap(), 1);
+        assert_eq!(migrate_database(&db).unwrap(), 0);
+
+        let artist_data = get_artist_data(&db, "Artist").unwrap().unwrap();
+        assert_eq!(artist_data.album_count, 2);
+        assert_eq!(artist_data.last_modified, 0);
+        assert_eq!(
+            artist_data.albums,
+            vec![
+                album("Album 1", "/music/Artist/Album 1"),
+                album("Album 2", "/music/Artist/Album 2"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_open_database_marks_new_database_as_current() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("test_db");
+
+        let db = open_database(db_path.to_str().unwrap()).unwrap();
+
+        assert_eq!(
+            get_metadata::<u32>(&db, SCHEMA_VERSION_KEY).unwrap(),
+            Some(SCHEMA_VERSION)
+        );
+    }
+
+    #[test]
+    fn test_migrate_renormalizes_artist_keys() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        store_metadata(&db, SCHEMA_VERSION_KEY, &2u32).unwrap();
+        let data = ArtistDataV2 {
+            album_count: 1,
+            last_modified: 1234567890,
+            albums: vec![AlbumDataV4 {
+                name: "Homogenic".to_string(),
+                path: "/music/Björk/Homogenic".to_string(),
+                ..AlbumDataV4::default()
+            }],
+        };
+        db.insert("bjo\u{308}rk", bincode::serialize(&data).unwrap())
+            .unwrap();
+
+        assert_eq!(migrate_database(&db).unwrap(), 1);
+
+        assert!(sled::Tree::get(&db, "bjo\u{308}rk").unwrap().is_none());
+        let artist_data = get_artist_data(&db, "Björk").unwrap().unwrap();
+        assert_eq!(
+            artist_data.albums,
+            [album("Homogenic", "/music/Björk/Homogenic")]
+        );
+        assert_eq!(artist_data.display_name, "bjork");
+    }
+
+    #[test]
+    fn test_migrate_adds_empty_musicbrainz_ids() {
+        let db = MemoryStore::new();
+        store_metadata(&db, SCHEMA_VERSION_KEY, &4u32).unwrap();
+        let data = ArtistDataV4 {
+            album_count: 1,
+            last_modified: 1234567890,
+            albums: vec![AlbumDataV4 {
+                name: "Post".to_string(),
+                path: "Björk/Post".to_string(),
+                track_count: 11,
+                total_bytes: 1024,
+            }],
+            display_name: "Björk".to_string(),
+        };
+        db.put(b"bjork", &bincode::serialize(&data).unwrap())
+            .unwrap();
+
+        assert_eq!(migrate_database(&db).unwrap(), 1);
+
+        let artist_data = get_artist_data(&db, "Björk").unwrap().unwrap();
+        assert_eq!(artist_data.display_name, "Björk");
+        assert_eq!(
+            artist_data.albums,
+            [AlbumData {
+                track_count: 11,
+                total_bytes: 1024,
+                ..album("Post", "Björk/Post")
+            }]
+        );
+    }
+
+    #[test]
+    fn test_migrate_keeps_albums_without_content_digests() {
+        let db = MemoryStore::new();
+        store_metadata(&db, SCHEMA_VERSION_KEY, &5u32).unwrap();
+        let data = ArtistDataV5 {
+            album_count: 1,
+            last_modified: 1234567890,
+            albums: vec![AlbumDataV5 {
+                name: "Post".to_string(),
+                path: "Björk/Post".to_string(),
+                track_count: 11,
+                total_bytes: 1024,
+                musicbrainz_id: Some("mbid".to_string()),
+            }],
+            display_name: "Björk".to_string(),
+        };
+        db.put(b"bjork", &bincode::serialize(&data).unwrap())
+            .unwrap();
+
+        assert_eq!(migrate_database(&db).unwrap(), 1);
+
+        let artist_data = get_artist_data(&db, "Björk").unwrap().unwrap();
+        assert_eq!(artist_data.last_modified, 1234567890);
+        assert_eq!(
+            artist_data.albums,
+            [AlbumData {
+                track_count: 11,
+                total_bytes: 1024,
+                musicbrainz_id: Some("mbid".to_string()),
+                ..album("Post", "Björk/Post")
+            }]
+        );
+    }
+
+    #[test]
+    fn test_display_name_round_trip() {
+        let db = MemoryStore::new();
+        store_artist_data(&db, "Björk", 0, 1234567890, Vec::new()).unwrap();
+
+        let artist_data = get_artist_data(&db, "bjork").unwrap().unwrap();
+
+        assert_eq!(artist_data.display_name, "Björk");
+        assert_eq!(export_database(&db).unwrap()[0].0, "bjork");
+    }
 }
@@ -0,0 +1,142 @@
+//! Key-value storage behind the database operations.
+//!
+//! The operations in this module's siblings only need to read, write and iterate over
+//! raw entries, so they go through [`ArtistStore`] rather than `sled` directly. `sled`
+//! is the store used by the CLI; [`MemoryStore`] keeps everything in memory, which is
+//! handy for testing code built on top of musync.
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// A stored `(key, value)` pair.
+pub type StoreEntry = (Vec<u8>, Vec<u8>);
+
+/// Storage of artist entries and metadata, as raw bytes keyed by bytes.
+///
+/// Artists are scanned in parallel, which is why stores must be `Send` and `Sync`.
+pub trait ArtistStore: Send + Sync {
+    /// Returns the value stored under `key`, if any.
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>>;
+
+    /// Stores `value` under `key`, replacing any previous value.
+    fn put(&self, key: &[u8], value: &[u8]) -> io::Result<()>;
+
+    /// Removes the value stored under `key`, if any.
+    fn remove(&self, key: &[u8]) -> io::Result<()>;
+
+    /// Returns every entry, sorted by key.
+    ///
+    /// Entries written while iterating may or may not be returned.
+    fn iter(&self) -> Box<dyn Iterator<Item = io::Result<StoreEntry>> + '_>;
+
+    /// Removes every entry.
+    fn clear(&self) -> io::Result<()>;
+
+    /// Persists pending writes. Does nothing for stores that aren't backed by disk.
+    fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl ArtistStore for sled::Db {
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        sled::Tree::get(self, key)
+            .map(|value| value.map(|ivec| ivec.to_vec()))
+            .map_err(|e| io::Error::other(e.to_string()))
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        self.insert(key, value)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> io::Result<()> {
+        sled::Tree::remove(self, key).map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = io::Result<StoreEntry>> + '_> {
+        Box::new(sled::Tree::iter(self).map(|entry| {
+            entry
+                .map(|(key, value)| (key.to_vec(), value.to_vec()))
+                .map_err(|e| io::Error::other(e.to_string()))
+        }))
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        sled::Tree::clear(self).map_err(|e| io::Error::other(e.to_string()))
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        sled::Tree::flush(self).map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Store keeping its entries in a `HashMap`, lost when it is dropped.
+///
+/// # Examples
+///
+/// ```
+/// use musync::{get_artist_data, store_artist_data, MemoryStore};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let store = MemoryStore::new();
+/// store_artist_data(&store, "Artist Name", 0, 1234567890, Vec::new())?;
+/// assert!(get_artist_data(&store, "Artist Name")?.is_some());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    entries: RwLock<HashMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl MemoryStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn entries(&self) -> RwLockReadGuard<'_, HashMap<Vec<u8>, Vec<u8>>> {
+        self.entries.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn entries_mut(&self) -> RwLockWriteGuard<'_, HashMap<Vec<u8>, Vec<u8>>> {
+        self.entries.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl ArtistStore for MemoryStore {
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.entries().get(key).cloned())
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        self.entries_mut().insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn remove(&self, key: &[u8]) -> io::Result<()> {
+        self.entries_mut().remove(key);
+        Ok(())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = io::Result<StoreEntry>> + '_> {
+        // Iterate over a snapshot, so entries can be written while iterating.
+        let mut entries: Vec<StoreEntry> = self
+            .entries()
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        entries.sort();
+        Box::new(entries.into_iter().map(Ok))
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        self.entries_mut().clear();
+        Ok(())
+    }
+}
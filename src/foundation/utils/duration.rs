@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+/// Parses a human-friendly duration such as `"30m"`, `"24h"` or `"7d"`.
+///
+/// The value is a whole number followed by a unit: `s` (seconds), `m` (minutes),
+/// `h` (hours), `d` (days) or `w` (weeks).
+///
+/// # Arguments
+///
+/// * `value` - The duration to parse.
+///
+/// # Examples
+///
+/// ```
+/// use musync::foundation::utils::parse_duration;
+/// use std::time::Duration;
+///
+/// assert_eq!(parse_duration("24h"), Ok(Duration::from_secs(24 * 60 * 60)));
+/// assert!(parse_duration("soon").is_err());
+/// ```
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("Invalid duration '{}': expected e.g. 24h or 7d", value))?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "Invalid duration unit in '{}': use s, m, h, d or w",
+                value
+            ))
+        }
+    };
+
+    amount
+        .checked_mul(unit_secs)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("Duration '{}' is too large", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("45s"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_duration("24h"), Ok(Duration::from_secs(24 * 3600)));
+        assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(7 * 86400)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(14 * 86400)));
+    }
+
+    #[test]
+    fn test_parse_duration_rejects_invalid_values() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("7").is_err());
+        assert!(parse_duration("7y").is_err());
+        assert!(parse_duration("-1d").is_err());
+    }
+}
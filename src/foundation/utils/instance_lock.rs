@@ -0,0 +1,87 @@
+use fs2::FileExt;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the lock file created in the configuration folder.
+pub const LOCK_FILE: &str = "musync.lock";
+
+/// An advisory lock ensuring only one musync process syncs a configuration at a time.
+///
+/// The lock is held for as long as the value is alive and released when it is dropped.
+/// The operating system also releases it when the process exits for any reason,
+/// including Ctrl-C, so a leftover lock file never blocks the next run.
+#[derive(Debug)]
+pub struct InstanceLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl InstanceLock {
+    /// Acquires the lock file inside `dir`, without waiting.
+    ///
+    /// Returns a `WouldBlock` error when another process already holds the lock.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The folder in which the lock file is created.
+    ///
+    pub fn acquire(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(LOCK_FILE);
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+
+        if let Err(e) = file.try_lock_exclusive() {
+            if e.kind() == fs2::lock_contended_error().kind() {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    format!(
+                        "Another musync is already running (lock held on {})",
+                        path.display()
+                    ),
+                ));
+            }
+            return Err(e);
+        }
+
+        Ok(Self { file, path })
+    }
+
+    /// The path of the lock file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        // Closing the file releases the lock anyway; unlocking explicitly just makes
+        // the release independent of when the handle is closed.
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_second_acquisition_fails_while_lock_is_held() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let first = InstanceLock::acquire(temp_dir.path()).unwrap();
+        assert_eq!(first.path(), temp_dir.path().join(LOCK_FILE));
+
+        let error = InstanceLock::acquire(temp_dir.path()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+        assert!(error.to_string().contains("already running"));
+
+        drop(first);
+        assert!(InstanceLock::acquire(temp_dir.path()).is_ok());
+    }
+}
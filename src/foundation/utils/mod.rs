@@ -1,3 +1,9 @@
+mod duration;
+mod instance_lock;
+mod prompt;
 mod string_utils;
 
+pub use duration::*;
+pub use instance_lock::*;
+pub use prompt::*;
 pub use string_utils::*;
@@ -0,0 +1,74 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// Asks the user a yes/no question on stdin. Anything but `y` counts as "no".
+///
+/// When stdin is not a terminal nobody can answer, so an error is returned instead
+/// of blocking forever. Callers offer a `--force` flag to skip the question. The
+/// question is printed on stderr, keeping stdout for output read by scripts.
+///
+/// # Arguments
+///
+/// * `question` - The question to print before reading the answer.
+///
+pub fn confirm(question: &str) -> io::Result<bool> {
+    if !io::stdin().is_terminal() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Confirmation required but stdin is not a terminal. Re-run with --force.",
+        ));
+    }
+
+    eprintln!("\x1b[31m{} (y/N)\x1b[0m", question);
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+
+    Ok(input.trim().to_lowercase() == "y")
+}
+
+/// Asks `question` on `output` and reads the answer from `input`, asking again until
+/// `validate` accepts it. Each rejected answer is followed by the reason returned by
+/// `validate`.
+///
+/// An empty answer stands for `default`, when there is one. The end of `input` is
+/// returned as an `UnexpectedEof` error.
+///
+/// # Arguments
+///
+/// * `input` - Where answers are read from, one per line.
+/// * `output` - Where the question and the rejection reasons are printed.
+/// * `question` - The question to print before reading the answer.
+/// * `default` - The answer used when the line read is empty.
+/// * `validate` - Returns why an answer is invalid, if it is.
+///
+pub fn ask(
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+    question: &str,
+    default: Option<&str>,
+    validate: &dyn Fn(&str) -> Result<(), String>,
+) -> io::Result<String> {
+    loop {
+        match default {
+            Some(default) if !default.is_empty() => write!(output, "{} [{}]: ", question, default)?,
+            _ => write!(output, "{}: ", question)?,
+        }
+        output.flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("No answer given to '{}'", question),
+            ));
+        }
+        let answer = match (line.trim(), default) {
+            ("", Some(default)) => default.to_string(),
+            (answer, _) => answer.to_string(),
+        };
+        match validate(&answer) {
+            Ok(()) => return Ok(answer),
+            Err(reason) => writeln!(output, "\x1b[33m{}\x1b[0m", reason)?,
+        }
+    }
+}
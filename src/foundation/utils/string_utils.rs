@@ -1,11 +1,20 @@
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use unicode_normalization::char::is_combining_mark;
 use unicode_normalization::UnicodeNormalization;
 
+/// Pattern stripped from album names by default: anything within square brackets.
+pub const DEFAULT_ALBUM_NAME_STRIP_PATTERN: &str = r"\[.*?\]";
+
+/// Pattern matching the names of disc folders by default, such as `CD1` or `Disc 2`.
+pub const DEFAULT_DISC_FOLDER_PATTERN: &str = r"(?i)^(cd|disc|disk)\s*\d+$";
+
 /// Cleans up an album name by removing text within square brackets.
 ///
 /// This function takes a string slice representing an album name and removes
 /// any content enclosed in square brackets (e.g., "[Deluxe Edition]", "[2024]"). It then
-/// trims any leading or trailing whitespace.
+/// trims any leading or trailing whitespace. Use [`AlbumNameCleaner`] for other rules.
 ///
 /// # Arguments
 ///
@@ -21,18 +30,109 @@ use unicode_normalization::UnicodeNormalization;
 /// assert_eq!(cleaned, "Dark Side of the Moon");
 /// ```
 pub fn clean_album_name(name: &str) -> String {
-    let re = Regex::new(r"\[.*?\]").unwrap();
-    let cleaned = re.replace_all(name, "");
-    let result = cleaned.trim().to_string();
-    result
+    AlbumNameCleaner::default().clean(name)
+}
+
+/// Cleans up album names by removing every match of a list of patterns, in order.
+///
+/// The default cleaner strips text within square brackets, like [`clean_album_name`].
+///
+/// # Examples
+///
+/// ```
+/// use musync::foundation::utils::AlbumNameCleaner;
+///
+/// let cleaner = AlbumNameCleaner::from_patterns(&[r"\{.*?\}", r"\s*- Single$"]).unwrap();
+/// assert_eq!(cleaner.clean("Creep {Live} - Single"), "Creep");
+/// assert_eq!(cleaner.clean("OK Computer [1997]"), "OK Computer [1997]");
+/// ```
+#[derive(Debug, Clone)]
+pub struct AlbumNameCleaner {
+    patterns: Vec<Regex>,
+}
+
+impl AlbumNameCleaner {
+    /// Creates a cleaner removing the matches of `patterns`, applied in order.
+    pub fn new(patterns: Vec<Regex>) -> Self {
+        Self { patterns }
+    }
+
+    /// Compiles `patterns` into a cleaner, failing on the first invalid pattern.
+    pub fn from_patterns<S: AsRef<str>>(patterns: &[S]) -> Result<Self, regex::Error> {
+        patterns
+            .iter()
+            .map(|pattern| Regex::new(pattern.as_ref()))
+            .collect::<Result<_, _>>()
+            .map(Self::new)
+    }
+
+    /// Removes every match of the patterns from `name`, then trims whitespace.
+    pub fn clean(&self, name: &str) -> String {
+        self.patterns
+            .iter()
+            .fold(name.to_string(), |cleaned, re| {
+                re.replace_all(&cleaned, "").into_owned()
+            })
+            .trim()
+            .to_string()
+    }
+}
+
+impl Default for AlbumNameCleaner {
+    fn default() -> Self {
+        Self::from_patterns(&[DEFAULT_ALBUM_NAME_STRIP_PATTERN])
+            .expect("the default album name pattern is valid")
+    }
+}
+
+/// Recognizes the disc folders of multi-disc albums, such as `Album/CD1` and `Album/CD2`.
+///
+/// # Examples
+///
+/// ```
+/// use musync::foundation::utils::DiscFolderMatcher;
+///
+/// let discs = DiscFolderMatcher::default();
+/// assert!(discs.is_disc_folder("CD1"));
+/// assert!(discs.is_disc_folder("Disc 2"));
+/// assert!(!discs.is_disc_folder("Discovery"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct DiscFolderMatcher {
+    pattern: Regex,
+}
+
+impl DiscFolderMatcher {
+    /// Creates a matcher for folder names matching `pattern`.
+    pub fn new(pattern: Regex) -> Self {
+        Self { pattern }
+    }
+
+    /// Compiles `pattern` into a matcher.
+    pub fn from_pattern(pattern: &str) -> Result<Self, regex::Error> {
+        Regex::new(pattern).map(Self::new)
+    }
+
+    /// Returns `true` if a folder named `name` is a disc of an album.
+    pub fn is_disc_folder(&self, name: &str) -> bool {
+        self.pattern.is_match(name)
+    }
+}
+
+impl Default for DiscFolderMatcher {
+    fn default() -> Self {
+        Self::from_pattern(DEFAULT_DISC_FOLDER_PATTERN)
+            .expect("the default disc folder pattern is valid")
+    }
 }
 
 /// Normalizes Unicode characters and converts text to lowercase.
 ///
 /// This function takes a string slice, decomposes its Unicode characters
-/// (NFD normalization), and then converts the result to lowercase. This is
-/// useful for creating consistent, comparable versions of strings that may
-/// contain diacritics or other Unicode variations.
+/// (NFD normalization), drops the combining marks (accents, diaeresis, ...) and
+/// converts the result to lowercase. This is useful for creating consistent,
+/// comparable versions of strings that may contain diacritics or other Unicode
+/// variations: `"Café"`, `"Cafe\u{301}"` and `"CAFÉ"` all become `"cafe"`.
 ///
 /// # Arguments
 ///
@@ -48,5 +148,373 @@ pub fn clean_album_name(name: &str) -> String {
 /// assert_eq!(normalized, "cafe");
 /// ```
 pub fn normalize_unicode(input: &str) -> String {
-    input.nfd().collect::<String>().to_lowercase()
+    input
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Returns a secondary key of `name` written in Latin letters, for servers that store
+/// transliterated artist names.
+///
+/// Cyrillic and Greek letters are spelled out in Latin (`Щ` becomes `shch`, the soft
+/// and hard signs are dropped), then the name is normalized like [`normalize_unicode`].
+/// Other scripts, such as CJK, are kept as they are. Only meant to compare names: the
+/// artists are still stored under their primary key.
+///
+/// # Arguments
+///
+/// * `name` - A string slice that holds the artist name.
+///
+/// # Examples
+///
+/// ```
+/// use musync::foundation::utils::transliteration_key;
+///
+/// assert_eq!(transliteration_key("Мумий Тролль"), "mumiy troll");
+/// assert_eq!(transliteration_key("Björk"), "bjork");
+/// ```
+pub fn transliteration_key(name: &str) -> String {
+    let mut latin = String::new();
+    // Composed first, so `й` isn't read as `и` with a breve.
+    for c in name.nfc().flat_map(char::to_lowercase) {
+        // Accented letters without their own spelling, such as Greek `ά`, use their base.
+        let base = c.nfd().next().unwrap_or(c);
+        match transliterate_letter(c).or_else(|| transliterate_letter(base)) {
+            Some(spelling) => latin.push_str(spelling),
+            None => latin.push(c),
+        }
+    }
+    normalize_unicode(&latin)
+}
+
+/// Returns the Latin spelling of a lowercase Cyrillic or Greek letter.
+fn transliterate_letter(c: char) -> Option<&'static str> {
+    let latin = match c {
+        'а' | 'α' => "a",
+        'б' | 'β' => "b",
+        'в' => "v",
+        'г' | 'ґ' | 'γ' => "g",
+        'д' | 'δ' => "d",
+        'е' | 'э' | 'ε' => "e",
+        'ё' => "yo",
+        'є' => "ye",
+        'ж' => "zh",
+        'з' | 'ζ' => "z",
+        'и' | 'і' | 'η' | 'ι' => "i",
+        'ї' => "yi",
+        'й' | 'ы' | 'υ' => "y",
+        'к' | 'κ' => "k",
+        'л' | 'λ' => "l",
+        'м' | 'μ' => "m",
+        'н' | 'ν' => "n",
+        'о' | 'ο' | 'ω' => "o",
+        'п' | 'π' => "p",
+        'р' | 'ρ' => "r",
+        'с' | 'σ' | 'ς' => "s",
+        'т' | 'τ' => "t",
+        'у' => "u",
+        'ф' | 'φ' => "f",
+        'х' => "kh",
+        'ц' => "ts",
+        'ч' | 'χ' => "ch",
+        'ш' => "sh",
+        'щ' => "shch",
+        'ъ' | 'ь' => "",
+        'ю' => "yu",
+        'я' => "ya",
+        'θ' => "th",
+        'ξ' => "x",
+        'ψ' => "ps",
+        _ => return None,
+    };
+    Some(latin)
+}
+
+/// How artist names are reduced to the keys they are stored and matched under.
+///
+/// Pick the strategy matching how the server compares names: a server matching
+/// accented names as different artists needs a strategy keeping accents.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum KeyStrategy {
+    /// Accents and case are ignored, as by [`normalize_unicode`].
+    #[default]
+    NfdLower,
+    /// Case is ignored, accents are kept.
+    NfcCasefold,
+    /// Accents are ignored, case is kept.
+    AsciiFold,
+    /// Names must match exactly, once composed the same way (NFC).
+    Exact,
+}
+
+impl KeyStrategy {
+    /// Returns the key of `name`.
+    ///
+    /// Every strategy composes or decomposes characters the same way whatever the name's
+    /// original form, so `"Cafe\u{301}"` and `"Café"` always have the same key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musync::foundation::utils::KeyStrategy;
+    ///
+    /// assert_eq!(KeyStrategy::NfdLower.key("Björk"), "bjork");
+    /// assert_eq!(KeyStrategy::NfcCasefold.key("Björk"), "björk");
+    /// assert_eq!(KeyStrategy::AsciiFold.key("Björk"), "Bjork");
+    /// assert_eq!(KeyStrategy::Exact.key("Björk"), "Björk");
+    /// ```
+    pub fn key(&self, name: &str) -> String {
+        match self {
+            Self::NfdLower => normalize_unicode(name),
+            Self::NfcCasefold => name.nfc().collect::<String>().to_lowercase(),
+            Self::AsciiFold => name.nfd().filter(|c| !is_combining_mark(*c)).collect(),
+            Self::Exact => name.nfc().collect(),
+        }
+    }
+}
+
+/// Reduces artist names to the keys they are stored and matched under: the key of a
+/// [`KeyStrategy`], without its leading article, so `The Beatles` and `Beatles` get
+/// the same key.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ArtistKeys {
+    strategy: KeyStrategy,
+    /// The articles, keyed with `strategy`.
+    articles: Vec<String>,
+}
+
+impl ArtistKeys {
+    /// Creates keys reduced with `strategy`, leaving out any of `articles`.
+    pub fn new(strategy: KeyStrategy, articles: &[String]) -> Self {
+        Self {
+            strategy,
+            articles: articles
+                .iter()
+                .map(|article| strategy.key(article))
+                .collect(),
+        }
+    }
+
+    /// Returns the key of `name`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use musync::foundation::utils::{ArtistKeys, KeyStrategy};
+    ///
+    /// let keys = ArtistKeys::new(KeyStrategy::NfdLower, &["The".to_string()]);
+    /// assert_eq!(keys.key("The Beatles"), keys.key("Beatles"));
+    /// assert_eq!(keys.key("Them"), "them");
+    /// ```
+    pub fn key(&self, name: &str) -> String {
+        let key = self.strategy.key(name);
+        strip_leading_article(&key, &self.articles).to_string()
+    }
+}
+
+/// Removes a leading article, such as the `The` of `The Beatles`, from an artist name.
+///
+/// Articles are matched ignoring ASCII case and must be followed by whitespace, so
+/// `Them` keeps its name. A name made of an article alone is returned unchanged.
+///
+/// # Arguments
+///
+/// * `name` - A string slice that holds the artist name.
+/// * `articles` - The articles to remove, e.g. the `ignoredArticles` of a server.
+///
+/// # Examples
+///
+/// ```
+/// use musync::foundation::utils::strip_leading_article;
+///
+/// let articles = ["The".to_string(), "Los".to_string()];
+/// assert_eq!(strip_leading_article("The Beatles", &articles), "Beatles");
+/// assert_eq!(strip_leading_article("Them", &articles), "Them");
+/// assert_eq!(strip_leading_article("The", &articles), "The");
+/// ```
+pub fn strip_leading_article<'a>(name: &'a str, articles: &[String]) -> &'a str {
+    for article in articles {
+        let Some(prefix) = name.get(..article.len()) else {
+            continue;
+        };
+        let rest = &name[article.len()..];
+        if prefix.eq_ignore_ascii_case(article) && rest.starts_with(char::is_whitespace) {
+            let rest = rest.trim_start();
+            if !rest.is_empty() {
+                return rest;
+            }
+        }
+    }
+    name
+}
+
+/// Reads the release year from an album folder name, such as the `1969` of
+/// `1969 - Abbey Road` or `Abbey Road (1969)`.
+///
+/// Only years from 1900 to 2099 that aren't part of a longer number are recognized, and
+/// the first one wins.
+///
+/// # Arguments
+///
+/// * `folder_name` - A string slice that holds the album folder name.
+///
+/// # Examples
+///
+/// ```
+/// use musync::foundation::utils::parse_album_year;
+///
+/// assert_eq!(parse_album_year("Abbey Road [1969]"), Some(1969));
+/// assert_eq!(parse_album_year("2001 - Amnesiac"), Some(2001));
+/// assert_eq!(parse_album_year("Symphony No. 12345"), None);
+/// ```
+pub fn parse_album_year(folder_name: &str) -> Option<u32> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| {
+        Regex::new(r"(?:^|\D)((?:19|20)\d{2})(?:\D|$)").expect("valid album year regex")
+    });
+    re.captures(folder_name)?.get(1)?.as_str().parse().ok()
+}
+
+/// Reduces an album name to a key that ignores case, accents, punctuation and spacing.
+///
+/// The name is normalized with [`normalize_unicode`] and apostrophes are dropped, so
+/// `Pepper's` matches `Peppers`. Every other character that isn't a letter or a digit
+/// becomes a space, and runs of whitespace are collapsed. A name made only of
+/// punctuation, such as `...`, keeps its trimmed lowercase self instead so it doesn't
+/// match every other one.
+///
+/// # Arguments
+///
+/// * `name` - A string slice that holds the album name.
+///
+/// # Examples
+///
+/// ```
+/// use musync::foundation::utils::canonical_album_name;
+///
+/// assert_eq!(canonical_album_name("Album: Live!"), "album live");
+/// assert_eq!(canonical_album_name("ALBUM   live"), "album live");
+/// ```
+pub fn canonical_album_name(name: &str) -> String {
+    let canonical = normalize_unicode(name)
+        .chars()
+        .filter(|c| !matches!(c, '\'' | '\u{2019}'))
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if canonical.is_empty() {
+        name.trim().to_lowercase()
+    } else {
+        canonical
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_cleaner_strips_brackets() {
+        let cleaner = AlbumNameCleaner::default();
+        assert_eq!(
+            cleaner.clean("Abbey Road [Remastered] [2009]"),
+            "Abbey Road"
+        );
+        assert_eq!(cleaner.clean("Kid A {Deluxe}"), "Kid A {Deluxe}");
+        assert_eq!(clean_album_name(" Help! [Mono] "), "Help!");
+    }
+
+    #[test]
+    fn test_cleaner_applies_patterns_in_order() {
+        let cleaner =
+            AlbumNameCleaner::from_patterns(&[r"\[.*?\]", r"\{.*?\}", r"\s*- Single$"]).unwrap();
+        assert_eq!(
+            cleaner.clean("Paranoid Android [2017] {Remaster} - Single"),
+            "Paranoid Android"
+        );
+
+        // A later pattern only sees what the earlier ones left.
+        let cleaner = AlbumNameCleaner::from_patterns(&[r"\s*- Single$", r"\(.*?\)$"]).unwrap();
+        assert_eq!(cleaner.clean("Lucky (Live) - Single"), "Lucky");
+
+        let keep_everything = AlbumNameCleaner::new(Vec::new());
+        assert_eq!(
+            keep_everything.clean("OK Computer [OKNOTOK]"),
+            "OK Computer [OKNOTOK]"
+        );
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_an_error() {
+        assert!(AlbumNameCleaner::from_patterns(&["[unclosed"]).is_err());
+    }
+
+    #[test]
+    fn test_canonical_album_name_ignores_case_and_punctuation() {
+        assert_eq!(canonical_album_name("Album: Live!"), "album live");
+        assert_eq!(canonical_album_name("Album Live"), "album live");
+        assert_eq!(
+            canonical_album_name("  Sgt. Pepper's  Lonely Hearts "),
+            "sgt peppers lonely hearts"
+        );
+        assert_eq!(
+            canonical_album_name("Café Tacvba"),
+            canonical_album_name("CAFE\u{301} tacvba")
+        );
+        assert_ne!(
+            canonical_album_name("Live 1"),
+            canonical_album_name("Live 2")
+        );
+    }
+
+    #[test]
+    fn test_canonical_album_name_keeps_punctuation_only_names() {
+        assert_eq!(canonical_album_name(" ... "), "...");
+        assert_eq!(canonical_album_name("!!!"), "!!!");
+        assert_ne!(canonical_album_name("..."), canonical_album_name("!!!"));
+    }
+
+    #[test]
+    fn test_normalize_unicode_strips_combining_marks() {
+        assert_eq!(normalize_unicode("Café"), "cafe");
+        assert_eq!(normalize_unicode("Cafe\u{301}"), "cafe");
+        assert_eq!(normalize_unicode("CAFÉ"), "cafe");
+        assert_eq!(normalize_unicode("Björk"), "bjork");
+    }
+
+    #[test]
+    fn test_key_strategies() {
+        for (strategy, expected) in [
+            (
+                KeyStrategy::NfdLower,
+                ["cafe", "cafe", "ac/dc", "sigur ros"],
+            ),
+            (
+                KeyStrategy::NfcCasefold,
+                ["café", "café", "ac/dc", "sigur rós"],
+            ),
+            (
+                KeyStrategy::AsciiFold,
+                ["Cafe", "CAFE", "AC/DC", "Sigur Ros"],
+            ),
+            (KeyStrategy::Exact, ["Café", "CAFÉ", "AC/DC", "Sigur Rós"]),
+        ] {
+            let keys = ["Cafe\u{301}", "CAFÉ", "AC/DC", "Sigur Rós"].map(|name| strategy.key(name));
+            assert_eq!(keys, expected, "{strategy:?}");
+            // Keys are stable, so a key can be looked up again.
+            for key in &keys {
+                assert_eq!(&strategy.key(key), key, "{strategy:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_normalize_unicode_keeps_other_characters() {
+        assert_eq!(normalize_unicode("AC/DC"), "ac/dc");
+        assert_eq!(normalize_unicode("坂本龍一"), "坂本龍一");
+    }
 }
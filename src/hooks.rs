@@ -0,0 +1,78 @@
+//! The `post_sync_command` hook, a shell command run after each sync, e.g. to send a
+//! notification with ntfy, a webhook or an email.
+
+use std::io;
+use std::process::Command;
+
+/// What a sync did, passed to the hook as `MUSYNC_*` environment variables.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SyncOutcome {
+    /// Number of albums uploaded successfully (`MUSYNC_UPLOADED`).
+    pub uploaded: usize,
+    /// Number of albums that failed to upload (`MUSYNC_FAILED`).
+    pub failed: usize,
+    /// Number of local albums missing on the servers, summed over every server
+    /// (`MUSYNC_MISSING`).
+    pub missing: usize,
+}
+
+/// Builds the command running `command` with `sh -c`, with the outcome of the sync in
+/// its environment.
+pub fn build_post_sync_command(command: &str, outcome: &SyncOutcome) -> Command {
+    let mut hook = Command::new("sh");
+    hook.arg("-c")
+        .arg(command)
+        .env("MUSYNC_UPLOADED", outcome.uploaded.to_string())
+        .env("MUSYNC_FAILED", outcome.failed.to_string())
+        .env("MUSYNC_MISSING", outcome.missing.to_string());
+    hook
+}
+
+/// Runs the post-sync hook and prints its output.
+///
+/// A hook that can't be started or exits with a failure status only prints a warning:
+/// the sync it reports on already happened.
+pub fn run_post_sync_command(command: &str, outcome: &SyncOutcome) {
+    println!("\x1b[34mRunning post_sync_command...\x1b[0m");
+    let result = build_post_sync_command(command, outcome)
+        .output()
+        .and_then(|output| {
+            print!("{}", String::from_utf8_lossy(&output.stdout));
+            eprint!("{}", String::from_utf8_lossy(&output.stderr));
+            if output.status.success() {
+                Ok(())
+            } else {
+                Err(io::Error::other(format!(
+                    "exited with status {}",
+                    output.status
+                )))
+            }
+        });
+    if let Err(e) = result {
+        eprintln!("\x1b[33mpost_sync_command failed: {}\x1b[0m", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hook_receives_the_outcome_in_its_environment() {
+        let outcome = SyncOutcome {
+            uploaded: 3,
+            failed: 1,
+            missing: 7,
+        };
+
+        let output = build_post_sync_command(
+            r#"printf '%s %s %s' "$MUSYNC_UPLOADED" "$MUSYNC_FAILED" "$MUSYNC_MISSING""#,
+            &outcome,
+        )
+        .output()
+        .unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "3 1 7");
+    }
+}
@@ -1,10 +1,18 @@
 pub mod api_client;
 pub mod configuration;
+pub mod doctor;
 pub mod foundation;
+pub mod hooks;
+pub mod metrics;
+pub mod observer;
 pub mod process;
 pub mod startup;
 
-pub use api_client::{compare_with_api, upload_missing_albums};
+pub use api_client::{compare_with_api, upload_missing_albums, CompareOptions, UploadSummary};
 pub use configuration::*;
 pub use foundation::database::*;
-pub use process::process_root;
+pub use observer::{ConsoleObserver, SyncObserver};
+pub use process::{
+    collect_albums, is_audio_file, process_root, process_root_with, ArtistOutcome, ProcessError,
+    ScanOptions, ScanSummary, AUDIO_EXTENSIONS,
+};
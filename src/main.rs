@@ -1,30 +1,292 @@
-use clap::Command;
-use musync::configuration::{create_config, ConfigFolder};
-use musync::startup::run;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use musync::configuration::{create_config, parse_setting_override, ConfigFolder, OutputFormat};
+use musync::foundation::utils::parse_duration;
+use musync::startup::{
+    clean, diff, doctor, export, import, run, verify, ExitCode, RunOptions, SyncError,
+};
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() {
     let args = Command::new("musync")
         .about("🎵 Music synchronization tool utilizing the Subsonic API 🎵")
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .global(true)
+                .value_name("PATH")
+                .help("Configuration file to use instead of the default one"),
+        )
+        .arg(
+            Arg::new("db-path")
+                .long("db-path")
+                .global(true)
+                .value_name("PATH")
+                .help("Database folder to use (overrides MUSYNC_DB)"),
+        )
         .subcommand(
             Command::new("run")
-                .about("🚀 Run the synchronization process to keep your music in sync"),
+                .about("🚀 Run the synchronization process to keep your music in sync")
+                .arg(
+                    Arg::new("refresh")
+                        .long("refresh")
+                        .action(ArgAction::SetTrue)
+                        .help("Fetch the artist list from the server even if it is cached"),
+                )
+                .arg(
+                    Arg::new("since")
+                        .long("since")
+                        .value_name("DURATION")
+                        .value_parser(parse_duration)
+                        .help("Only scan folders modified within DURATION (e.g. 24h, 7d)"),
+                )
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .short('f')
+                        .action(ArgAction::SetTrue)
+                        .help("Scan the library even if nothing changed since the last scan"),
+                )
+                .arg(
+                    Arg::new("set")
+                        .long("set")
+                        .value_name("KEY=VALUE")
+                        .value_parser(parse_setting_override)
+                        .action(ArgAction::Append)
+                        .help("Override a setting of the configuration file for this run (repeatable)"),
+                )
+                .arg(
+                    Arg::new("mirror")
+                        .long("mirror")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["no-upload", "resume"])
+                        .help("DANGEROUS: delete remote albums that aren't in the local library"),
+                )
+                .arg(
+                    Arg::new("yes")
+                        .long("yes")
+                        .short('y')
+                        .action(ArgAction::SetTrue)
+                        .help("Delete remote albums without asking for confirmation"),
+                )
+                .arg(
+                    Arg::new("allow-empty")
+                        .long("allow-empty")
+                        .action(ArgAction::SetTrue)
+                        .help("Compare and upload even if no artist folder is found in local_path"),
+                )
+                .arg(
+                    Arg::new("metrics-file")
+                        .long("metrics-file")
+                        .value_name("PATH")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .conflicts_with("resume")
+                        .help("Write Prometheus metrics of the sync to PATH, for node_exporter"),
+                )
+                .arg(
+                    Arg::new("confirm-large")
+                        .long("confirm-large")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["no-upload", "resume"])
+                        .help("Upload even if more albums are missing than max_upload_batch"),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .long("limit")
+                        .value_name("N")
+                        .value_parser(clap::value_parser!(usize))
+                        .conflicts_with_all(["no-upload", "resume"])
+                        .help("Upload at most N albums to each server, leaving the others for later runs"),
+                )
+                .arg(
+                    Arg::new("profile")
+                        .long("profile")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("resume")
+                        .help("Print the time spent scanning, comparing and uploading"),
+                )
+                .arg(
+                    Arg::new("full")
+                        .long("full")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("resume")
+                        .help("Compare the albums of every mismatched artist, even if unchanged"),
+                )
+                .arg(
+                    Arg::new("no-upload")
+                        .long("no-upload")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("resume")
+                        .help("Only compare with the server, without uploading missing albums"),
+                )
+                .arg(
+                    Arg::new("verbose")
+                        .long("verbose")
+                        .short('v')
+                        .action(ArgAction::SetTrue)
+                        .help("List every updated artist in the scan summary"),
+                )
+                .arg(
+                    Arg::new("resume")
+                        .long("resume")
+                        .action(ArgAction::SetTrue)
+                        .help(
+                            "Upload the albums left by an interrupted sync without comparing again",
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("🛠️ Create or update configuration file for musync")
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .short('f')
+                        .visible_alias("yes")
+                        .action(ArgAction::SetTrue)
+                        .help("Overwrite an existing configuration without asking"),
+                )
+                .arg(
+                    Arg::new("interactive")
+                        .long("interactive")
+                        .short('i')
+                        .action(ArgAction::SetTrue)
+                        .help("Ask for the settings and write a filled-in configuration"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .value_parser(["text", "json"])
+                        .default_value("text")
+                        .help("Print the created paths as colored text or as JSON"),
+                ),
+        )
+        .subcommand(
+            Command::new("clean")
+                .about("🧹 Clear the local database to force a full rescan")
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .short('f')
+                        .visible_alias("yes")
+                        .action(ArgAction::SetTrue)
+                        .help("Clear the database without asking"),
+                ),
         )
         .subcommand(
-            Command::new("config").about("🛠️ Create or update configuration file for musync"),
+            Command::new("diff").about(
+                "🔀 Show which albums went missing or were resolved since the previous sync",
+            ),
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("🩺 Check the local database for corrupt entries and stale paths"),
+        )
+        .subcommand(Command::new("doctor").about(
+            "🔍 Check that the tools, configuration and servers a sync needs are in place",
+        ))
+        .subcommand(
+            Command::new("export")
+                .about("📦 Export the local database to a JSON file")
+                .arg(
+                    Arg::new("file")
+                        .required(true)
+                        .value_name("FILE")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("JSON file to write"),
+                ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("📥 Import a JSON export into the local database")
+                .arg(
+                    Arg::new("file")
+                        .required(true)
+                        .value_name("FILE")
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("JSON file written by 'musync export'"),
+                ),
         )
         .get_matches();
 
-    let cfg_folder = ConfigFolder::new();
+    let result = execute(&args).await;
+    if let Err(e) = &result {
+        eprintln!("\x1b[1m\x1b[31m{}\x1b[0m", e);
+        std::process::exit(ExitCode::of(&result) as i32);
+    }
+}
+
+async fn execute(args: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let mut cfg_folder = match args.get_one::<String>("config") {
+        Some(config_file) => ConfigFolder::from_config_file(config_file),
+        None => ConfigFolder::new().map_err(|e| SyncError::new(ExitCode::Config, e))?,
+    };
+    if let Some(db_path) = args.get_one::<String>("db-path") {
+        cfg_folder = cfg_folder.with_db_path(db_path);
+    }
 
     match args.subcommand() {
-        Some(("run", _)) => {
+        Some(("run", sub_args)) => {
             println!("\x1b[1m\x1b[34mStarting the synchronization process...\x1b[0m");
-            run(cfg_folder).await
+            let options = RunOptions {
+                refresh: sub_args.get_flag("refresh"),
+                since: sub_args.get_one::<Duration>("since").copied(),
+                force: sub_args.get_flag("force"),
+                resume: sub_args.get_flag("resume"),
+                no_upload: sub_args.get_flag("no-upload"),
+                full: sub_args.get_flag("full"),
+                mirror: sub_args.get_flag("mirror"),
+                yes: sub_args.get_flag("yes"),
+                allow_empty: sub_args.get_flag("allow-empty"),
+                metrics_file: sub_args.get_one::<PathBuf>("metrics-file").cloned(),
+                confirm_large: sub_args.get_flag("confirm-large"),
+                profile: sub_args.get_flag("profile"),
+                limit: sub_args.get_one::<usize>("limit").copied(),
+                verbose: sub_args.get_flag("verbose"),
+                overrides: sub_args
+                    .get_many::<(String, String)>("set")
+                    .map(|overrides| overrides.cloned().collect())
+                    .unwrap_or_default(),
+            };
+            run(cfg_folder, &options).await
+        }
+        Some(("config", sub_args)) => {
+            let format = match sub_args.get_one::<String>("format").map(String::as_str) {
+                Some("json") => OutputFormat::Json,
+                _ => OutputFormat::Text,
+            };
+            if format == OutputFormat::Text {
+                println!("\x1b[1m\x1b[34mConfiguring musync...\x1b[0m");
+            }
+            create_config(
+                cfg_folder,
+                sub_args.get_flag("force"),
+                sub_args.get_flag("interactive"),
+                format,
+            )
+        }
+        Some(("clean", sub_args)) => {
+            println!("\x1b[1m\x1b[34mCleaning the local database...\x1b[0m");
+            clean(cfg_folder, sub_args.get_flag("force"))
+        }
+        Some(("diff", _)) => diff(cfg_folder),
+        Some(("verify", _)) => {
+            println!("\x1b[1m\x1b[34mVerifying the local database...\x1b[0m");
+            verify(cfg_folder)
+        }
+        Some(("doctor", _)) => {
+            println!("\x1b[1m\x1b[34mChecking the environment...\x1b[0m");
+            doctor(cfg_folder).await
+        }
+        Some(("export", sub_args)) => {
+            println!("\x1b[1m\x1b[34mExporting the local database...\x1b[0m");
+            export(cfg_folder, sub_args.get_one::<PathBuf>("file").unwrap())
         }
-        Some(("config", _)) => {
-            println!("\x1b[1m\x1b[34mConfiguring musync...\x1b[0m");
-            create_config(cfg_folder)
+        Some(("import", sub_args)) => {
+            println!("\x1b[1m\x1b[34mImporting into the local database...\x1b[0m");
+            import(cfg_folder, sub_args.get_one::<PathBuf>("file").unwrap())
         }
         _ => {
             print_usage();
@@ -38,5 +300,11 @@ fn print_usage() {
     println!("📖 Available Commands:");
     println!("  \x1b[1m\x1b[32mmusync run\x1b[0m    - 🚀 Start synchronization");
     println!("  \x1b[1m\x1b[32mmusync config\x1b[0m - 🛠️  Create or update configuration file");
+    println!("  \x1b[1m\x1b[32mmusync clean\x1b[0m  - 🧹 Clear the local database");
+    println!("  \x1b[1m\x1b[32mmusync diff\x1b[0m   - 🔀 Show changes since the last sync");
+    println!("  \x1b[1m\x1b[32mmusync verify\x1b[0m - 🩺 Check the local database");
+    println!("  \x1b[1m\x1b[32mmusync doctor\x1b[0m - 🔍 Check the environment");
+    println!("  \x1b[1m\x1b[32mmusync export\x1b[0m - 📦 Export the local database to JSON");
+    println!("  \x1b[1m\x1b[32mmusync import\x1b[0m - 📥 Import a JSON export");
     println!("\x1b[33mUse these commands to manage your music library more effectively!\x1b[0m\n");
 }
@@ -0,0 +1,161 @@
+//! Metrics about a sync, written in the Prometheus text exposition format so that the
+//! textfile collector of node_exporter can pick them up.
+
+use std::fmt::Write as _;
+use std::path::Path;
+use std::{fs, io};
+
+/// What a sync found and did, as exported to Prometheus.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RunMetrics {
+    /// Number of artists stored for the local library.
+    pub artists_total: usize,
+    /// Number of local albums missing on each server, by server name.
+    pub albums_missing: Vec<(String, usize)>,
+    /// Number of albums that failed to upload.
+    pub uploads_failed: usize,
+    /// When the sync finished, as a Unix timestamp in seconds.
+    pub last_run_timestamp: u64,
+}
+
+impl RunMetrics {
+    /// Formats the metrics in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        write_metric(
+            &mut out,
+            "musync_artists_total",
+            "Number of artists in the local library.",
+            &[(None, self.artists_total as u64)],
+        );
+        let albums_missing: Vec<_> = self
+            .albums_missing
+            .iter()
+            .map(|(server, count)| (Some(server.as_str()), *count as u64))
+            .collect();
+        write_metric(
+            &mut out,
+            "musync_albums_missing",
+            "Number of local albums missing on the server.",
+            &albums_missing,
+        );
+        write_metric(
+            &mut out,
+            "musync_uploads_failed",
+            "Number of albums that failed to upload during the last sync.",
+            &[(None, self.uploads_failed as u64)],
+        );
+        write_metric(
+            &mut out,
+            "musync_last_run_timestamp",
+            "Unix time at which the last sync finished, in seconds.",
+            &[(None, self.last_run_timestamp)],
+        );
+        out
+    }
+}
+
+/// Writes a gauge with its `HELP` and `TYPE` lines, one sample per value. Values with a
+/// server name get a `server` label.
+fn write_metric(out: &mut String, name: &str, help: &str, values: &[(Option<&str>, u64)]) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    for (server, value) in values {
+        match server {
+            Some(server) => {
+                let _ = writeln!(
+                    out,
+                    "{}{{server=\"{}\"}} {}",
+                    name,
+                    escape_label_value(server),
+                    value
+                );
+            }
+            None => {
+                let _ = writeln!(out, "{} {}", name, value);
+            }
+        }
+    }
+}
+
+/// Escapes a label value as the exposition format requires.
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+/// Writes `metrics` to `path`.
+///
+/// The metrics are written to a temporary file next to `path`, which is then renamed,
+/// so node_exporter never reads a partially written file.
+pub fn write_metrics_file(path: &Path, metrics: &RunMetrics) -> io::Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    fs::write(&temp_path, metrics.to_prometheus())?;
+    fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_metrics_are_valid_exposition_format() {
+        let metrics = RunMetrics {
+            artists_total: 42,
+            albums_missing: vec![("home".to_string(), 3), ("my \"vps\"".to_string(), 0)],
+            uploads_failed: 1,
+            last_run_timestamp: 1_700_000_000,
+        };
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("musync.prom");
+
+        write_metrics_file(&path, &metrics).unwrap();
+
+        let sample = Regex::new(
+            r#"^([a-zA-Z_:][a-zA-Z0-9_:]*)(\{[a-zA-Z_][a-zA-Z0-9_]*="(?:[^"\\]|\\.)*"\})? (\d+)$"#,
+        )
+        .unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        let mut typed = BTreeSet::new();
+        let mut sampled = Vec::new();
+        for line in text.lines() {
+            if let Some(rest) = line.strip_prefix("# TYPE ") {
+                let (name, kind) = rest.split_once(' ').unwrap();
+                assert_eq!(kind, "gauge");
+                typed.insert(name.to_string());
+            } else if !line.starts_with("# HELP ") {
+                let captures = sample.captures(line).unwrap_or_else(|| panic!("{line}"));
+                assert!(typed.contains(&captures[1]), "{line}");
+                sampled.push(line.to_string());
+            }
+        }
+
+        assert_eq!(
+            typed.into_iter().collect::<Vec<_>>(),
+            [
+                "musync_albums_missing",
+                "musync_artists_total",
+                "musync_last_run_timestamp",
+                "musync_uploads_failed",
+            ]
+        );
+        assert_eq!(
+            sampled,
+            [
+                "musync_artists_total 42",
+                "musync_albums_missing{server=\"home\"} 3",
+                r#"musync_albums_missing{server="my \"vps\""} 0"#,
+                "musync_uploads_failed 1",
+                "musync_last_run_timestamp 1700000000",
+            ]
+        );
+        assert!(!temp_dir.path().join("musync.prom.tmp").exists());
+    }
+}
@@ -0,0 +1,402 @@
+//! Hooks for following the progress of a sync.
+//!
+//! Scanning and uploading report what they do to a [`SyncObserver`] instead of writing
+//! to the terminal, so musync can be embedded in applications with their own progress
+//! display. The CLI uses [`ConsoleObserver`], which draws `indicatif` progress bars.
+
+use crate::api_client::UploadSummary;
+use crate::configuration::ProgressStyleSettings;
+use crate::process::{ArtistOutcome, InvalidAudio};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Template of the upload progress bars when no `progress_style` is configured.
+const DEFAULT_PROGRESS_TEMPLATE: &str =
+    "{elapsed_precise} [{bar:40.cyan/blue}] {pos}/{len} {unit} {msg}";
+
+/// Receives progress events while the library is scanned and albums are uploaded.
+///
+/// Every method has an empty default implementation, so implementors only override
+/// the events they care about. Artists are scanned in parallel, which is why
+/// observers must be `Sync`.
+pub trait SyncObserver: Sync {
+    /// Called before the local library is scanned.
+    fn on_scan_started(&self) {}
+
+    /// Called once an artist has been scanned, with what happened to its stored data.
+    fn on_artist_scanned(&self, _artist: &str, _outcome: &ArtistOutcome) {}
+
+    /// Called when the album at `album_path` is left out of the scan, with
+    /// `validate_audio`, because of an invalid audio file.
+    fn on_invalid_audio(&self, _album_path: &Path, _invalid: &InvalidAudio) {}
+
+    /// Called when the albums at `path` and `other` of an artist have the same name
+    /// once cleaned, such as `Album [CD1]` and `Album [CD2]`, and are compared as one
+    /// album.
+    fn on_album_name_collision(&self, _artist: &str, _album: &str, _path: &str, _other: &str) {}
+
+    /// Called, with `require_year`, for each album of an artist whose folder name has
+    /// no year, with the stored path of the album.
+    fn on_album_without_year(&self, _artist: &str, _album_path: &str) {}
+
+    /// Called after the scan, with the number of scanned artists.
+    fn on_scan_finished(&self, _scanned: usize) {}
+
+    /// Called before uploading, with the number of albums to upload.
+    fn on_upload_started(&self, _album_count: usize) {}
+
+    /// Called before an album is uploaded.
+    fn on_album_upload_started(&self, _artist: &str, _album: &str) {}
+
+    /// Called while the current album is uploaded, with its progress from 0 to 100.
+    fn on_album_upload_progress(&self, _percent: u64) {}
+
+    /// Called while the current album is uploaded, with the file being transferred.
+    fn on_album_upload_file(&self, _file: &str) {}
+
+    /// Called once an album has been uploaded or has failed to upload.
+    fn on_upload_done(&self, _album_path: &str, _result: &io::Result<()>) {}
+
+    /// Called after all albums have been processed.
+    fn on_uploads_finished(&self, _summary: &UploadSummary) {}
+
+    /// Called with a problem that doesn't stop the sync, such as a remote album that
+    /// can't be deleted.
+    fn warn(&self, _message: &str) {}
+}
+
+/// Observer printing progress to the terminal, as the `musync` CLI does.
+///
+/// Scanning shows a spinner with one line per artist; uploading shows an overall
+/// progress bar and one bar per album. Failures are printed to stderr.
+pub struct ConsoleObserver {
+    album_style: ProgressStyle,
+    overall_style: ProgressStyle,
+    state: Mutex<ConsoleState>,
+}
+
+#[derive(Default)]
+struct ConsoleState {
+    multi_progress: MultiProgress,
+    scan: Option<ProgressBar>,
+    overall: Option<ProgressBar>,
+    album: Option<(ProgressBar, String)>,
+}
+
+impl ConsoleObserver {
+    /// Creates an observer using the default progress bar style.
+    pub fn new() -> Self {
+        Self::with_progress_style(None).expect("default progress style is valid")
+    }
+
+    /// Creates an observer whose upload progress bars use the configured style.
+    ///
+    /// An invalid template or set of progress characters is returned as an
+    /// `InvalidInput` error.
+    ///
+    /// # Arguments
+    ///
+    /// * `style` - The optional `progress_style` of the remote settings.
+    ///
+    pub fn with_progress_style(style: Option<&ProgressStyleSettings>) -> io::Result<Self> {
+        Ok(Self {
+            album_style: progress_style(style, "%")?,
+            overall_style: progress_style(style, "albums")?,
+            state: Mutex::new(ConsoleState::default()),
+        })
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, ConsoleState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for ConsoleObserver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SyncObserver for ConsoleObserver {
+    fn on_scan_started(&self) {
+        let mut state = self.state();
+        let bar = state.multi_progress.add(ProgressBar::new_spinner());
+        bar.set_style(
+            ProgressStyle::default_spinner()
+                .template("{spinner:.cyan} {elapsed_precise} Scanned {pos} artists {msg}")
+                .unwrap(),
+        );
+        bar.enable_steady_tick(Duration::from_millis(100));
+        state.scan = Some(bar);
+    }
+
+    fn on_artist_scanned(&self, artist: &str, outcome: &ArtistOutcome) {
+        let message = match outcome {
+            ArtistOutcome::Unchanged => format!("Artist: {} (unchanged)", artist),
+            ArtistOutcome::Updated { album_count } => {
+                format!("Artist: {}, Albums: {} (updated)", artist, album_count)
+            }
+            ArtistOutcome::Skipped => format!("Artist: {} (skipped)", artist),
+        };
+
+        match &self.state().scan {
+            Some(bar) => {
+                bar.suspend(|| println!("{}", message));
+                bar.inc(1);
+            }
+            None => println!("{}", message),
+        }
+    }
+
+    fn on_invalid_audio(&self, album_path: &Path, invalid: &InvalidAudio) {
+        self.warn(&format!(
+            "Skipping album {}, invalid audio file {}",
+            album_path.display(),
+            invalid
+        ));
+    }
+
+    fn on_album_name_collision(&self, artist: &str, album: &str, path: &str, other: &str) {
+        self.warn(&format!(
+            "Albums {} and {} of {} are both named '{}' and are compared as one album",
+            other, path, artist, album
+        ));
+    }
+
+    fn on_album_without_year(&self, artist: &str, album_path: &str) {
+        self.warn(&format!(
+            "Album {} of {} has no year in its folder name",
+            album_path, artist
+        ));
+    }
+
+    fn on_scan_finished(&self, scanned: usize) {
+        if let Some(bar) = self.state().scan.take() {
+            bar.finish_with_message(format!("- scan completed ({scanned} artists)"));
+        }
+    }
+
+    fn on_upload_started(&self, album_count: usize) {
+        let mut state = self.state();
+        let bar = state
+            .multi_progress
+            .add(ProgressBar::new(album_count as u64));
+        bar.set_style(self.overall_style.clone());
+        state.overall = Some(bar);
+    }
+
+    fn on_album_upload_started(&self, artist: &str, album: &str) {
+        let mut state = self.state();
+        let label = format!("{artist} - {album}");
+        if let Some(overall) = &state.overall {
+            overall.set_message(format!("Uploading: {label}"));
+        }
+
+        let bar = state.multi_progress.add(ProgressBar::new(100));
+        bar.set_style(self.album_style.clone());
+        bar.set_message(label.clone());
+        state.album = Some((bar, label));
+    }
+
+    fn on_album_upload_progress(&self, percent: u64) {
+        if let Some((bar, _)) = &self.state().album {
+            bar.set_position(percent);
+        }
+    }
+
+    fn on_album_upload_file(&self, file: &str) {
+        if let Some((bar, label)) = &self.state().album {
+            bar.set_message(format!("{label}: {file}"));
+        }
+    }
+
+    fn on_upload_done(&self, album_path: &str, result: &io::Result<()>) {
+        let mut state = self.state();
+        if result.is_ok() {
+            if let Some(overall) = &state.overall {
+                overall.inc(1);
+            }
+        }
+        // Albums uploaded together with others of their artist share a single bar.
+        let Some((bar, label)) = state.album.take() else {
+            if let Err(e) = result {
+                eprintln!("Failed to upload {album_path}: {e}");
+            }
+            return;
+        };
+
+        match result {
+            Ok(()) => {
+                bar.finish_with_message(format!("Uploaded: {label}"));
+            }
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                bar.finish_with_message(format!("Timed out: {label}"));
+                eprintln!("Failed to upload {label}: {e}");
+            }
+            Err(e) => {
+                bar.finish_with_message(format!("Failed: {label}"));
+                eprintln!("Failed to upload {label}: {e}");
+            }
+        }
+    }
+
+    fn on_uploads_finished(&self, summary: &UploadSummary) {
+        if let Some(overall) = self.state().overall.take() {
+            if summary.failed == 0 {
+                overall.finish_with_message("All uploads completed");
+            } else {
+                overall
+                    .finish_with_message(format!("Uploads completed, {} failed", summary.failed));
+            }
+        }
+    }
+
+    /// Prints the warning to stderr, above the progress bars when there are any.
+    fn warn(&self, message: &str) {
+        self.state()
+            .multi_progress
+            .suspend(|| eprintln!("\x1b[33m{}\x1b[0m", message));
+    }
+}
+
+/// Builds the progress bar style, using the configured template and characters when set.
+///
+/// `{unit}` in the template is replaced by `unit`.
+pub(crate) fn progress_style(
+    custom: Option<&ProgressStyleSettings>,
+    unit: &str,
+) -> io::Result<ProgressStyle> {
+    let (template, progress_chars) = match custom {
+        Some(custom) => (custom.template.as_str(), custom.progress_chars.as_str()),
+        None => (DEFAULT_PROGRESS_TEMPLATE, "##-"),
+    };
+
+    // indicatif panics on fewer than two progress characters, so check it up front.
+    if progress_chars.chars().count() < 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Invalid progress_chars '{}': at least 2 characters are required",
+                progress_chars
+            ),
+        ));
+    }
+
+    let style = ProgressStyle::default_bar()
+        .template(&template.replace("{unit}", unit))
+        .map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid progress template '{}': {}", template, e),
+            )
+        })?;
+
+    Ok(style.progress_chars(progress_chars))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Observer recording every event it receives, in order.
+    #[derive(Default)]
+    pub(crate) struct RecordingObserver {
+        pub(crate) events: Mutex<Vec<String>>,
+    }
+
+    impl RecordingObserver {
+        fn record(&self, event: String) {
+            self.events.lock().unwrap().push(event);
+        }
+
+        pub(crate) fn events(&self) -> Vec<String> {
+            self.events.lock().unwrap().clone()
+        }
+    }
+
+    impl SyncObserver for RecordingObserver {
+        fn on_scan_started(&self) {
+            self.record("scan_started".to_string());
+        }
+
+        fn on_artist_scanned(&self, artist: &str, outcome: &ArtistOutcome) {
+            self.record(format!("artist_scanned {artist} {outcome:?}"));
+        }
+
+        fn on_invalid_audio(&self, album_path: &Path, invalid: &InvalidAudio) {
+            self.record(format!(
+                "invalid_audio {} {}",
+                album_path.display(),
+                invalid.reason
+            ));
+        }
+
+        fn on_album_name_collision(&self, artist: &str, album: &str, path: &str, other: &str) {
+            let (first, second) = if path < other {
+                (path, other)
+            } else {
+                (other, path)
+            };
+            self.record(format!(
+                "name_collision {artist} - {album}: {first}, {second}"
+            ));
+        }
+
+        fn on_album_without_year(&self, artist: &str, album_path: &str) {
+            self.record(format!("without_year {artist} {album_path}"));
+        }
+
+        fn on_scan_finished(&self, scanned: usize) {
+            self.record(format!("scan_finished {scanned}"));
+        }
+
+        fn on_upload_started(&self, album_count: usize) {
+            self.record(format!("upload_started {album_count}"));
+        }
+
+        fn on_album_upload_started(&self, artist: &str, album: &str) {
+            self.record(format!("album_started {artist} - {album}"));
+        }
+
+        fn on_album_upload_progress(&self, percent: u64) {
+            self.record(format!("progress {percent}"));
+        }
+
+        fn on_album_upload_file(&self, file: &str) {
+            self.record(format!("file {file}"));
+        }
+
+        fn on_upload_done(&self, album_path: &str, result: &io::Result<()>) {
+            let status = if result.is_ok() { "ok" } else { "failed" };
+            self.record(format!("upload_done {album_path} {status}"));
+        }
+
+        fn on_uploads_finished(&self, summary: &UploadSummary) {
+            self.record(format!(
+                "uploads_finished {}/{}",
+                summary.succeeded,
+                summary.total()
+            ));
+        }
+
+        fn warn(&self, message: &str) {
+            self.record(format!("warn {message}"));
+        }
+    }
+
+    #[test]
+    fn test_console_observer_rejects_invalid_progress_style() {
+        let style = ProgressStyleSettings {
+            template: "{elapsed_precise} [{bar:cyan/blue}]".to_string(),
+            progress_chars: "##-".to_string(),
+        };
+
+        let error = ConsoleObserver::with_progress_style(Some(&style))
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,219 @@
+//! This module provides a cheap, library-wide fingerprint used to detect whether
+//! anything changed since the last sync without walking the whole collection, and the
+//! per-album digests the `ContentHash` change detection compares.
+
+use crate::process::ignore::read_ignore_file;
+use crate::process::process::get_last_modified_time;
+use rayon::prelude::*;
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use walkdir::WalkDir;
+
+/// FNV-1a parameters. A hand-rolled hash keeps the fingerprint stable across Rust
+/// releases, unlike `DefaultHasher`, so a stored value stays comparable after upgrades.
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Computes a fingerprint of the subdirectories of `root`, down to `depth` levels.
+///
+/// The paths and modification times of the artist (or, with the `AlbumOnly` layout,
+/// album) folders, and of the folders they are nested in with a `library_depth` above
+/// 1, are hashed in path order. Adding, removing or renaming a folder, or changing its
+/// direct entries, changes the fingerprint. Changes deeper in the tree don't update the
+/// folder's modification time and go unnoticed, so this is coarser than the per-artist
+/// check done during a scan.
+///
+/// # Arguments
+///
+/// * `root` - The path to the root directory of the music collection.
+/// * `depth` - How many levels below `root` the artist folders are, at least 1.
+///
+pub fn library_fingerprint(root: &Path, depth: usize) -> io::Result<u64> {
+    let mut folders = Vec::new();
+    for entry in WalkDir::new(root).min_depth(1).max_depth(depth.max(1)) {
+        let entry = entry?;
+        if entry.file_type().is_dir() {
+            let modified = get_last_modified_time(entry.path())?;
+            let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+            folders.push((relative.to_string_lossy().into_owned(), modified));
+        }
+    }
+    folders.sort();
+
+    let mut hash = FNV_OFFSET_BASIS;
+    for (name, modified) in &folders {
+        // The separator keeps ("ab", "c") and ("a", "bc") from hashing the same.
+        hash = fnv1a(hash, name.bytes().chain([0]).chain(modified.to_le_bytes()));
+    }
+
+    Ok(hash)
+}
+
+/// Computes a digest of the `.musyncignore` file of `root`, 0 without one, so editing
+/// the ignore rules can be told apart from the folders the fingerprint covers.
+///
+/// # Arguments
+///
+/// * `root` - The path to the root directory of the music collection.
+///
+pub fn ignore_file_digest(root: &Path) -> io::Result<u64> {
+    Ok(read_ignore_file(root)?.map_or(0, |contents| fnv1a(FNV_OFFSET_BASIS, contents.bytes())))
+}
+
+/// Combines a [`library_fingerprint`] with a description of the settings the library is
+/// scanned with, so changing them changes the fingerprint.
+///
+/// # Arguments
+///
+/// * `library` - The fingerprint of the library folders.
+/// * `settings` - The settings affecting what a scan stores, in any stable text form.
+///
+pub fn scan_fingerprint(library: u64, settings: &str) -> u64 {
+    fnv1a(library, settings.bytes())
+}
+
+/// Computes a digest of the files in the album directory at `album_path`.
+///
+/// The relative path, size and modification time (in nanoseconds) of every file below
+/// the directory are hashed in path order, so the digest is the same from one scan to
+/// the next until a file is added, removed, renamed, resized or rewritten. The files are
+/// read in parallel, which keeps large albums on network shares quick to check. Their
+/// contents aren't read.
+///
+/// # Arguments
+///
+/// * `album_path` - The path to the album directory.
+///
+pub fn album_digest(album_path: &Path) -> io::Result<u64> {
+    let paths = WalkDir::new(album_path)
+        .min_depth(1)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .map(walkdir::DirEntry::into_path)
+        .collect::<Vec<_>>();
+
+    let mut files = paths
+        .par_iter()
+        .map(|path| {
+            let metadata = path.metadata()?;
+            let modified = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_nanos());
+            let relative = path.strip_prefix(album_path).unwrap_or(path);
+            Ok((
+                relative.to_string_lossy().into_owned(),
+                metadata.len(),
+                modified,
+            ))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    files.sort();
+
+    let mut hash = FNV_OFFSET_BASIS;
+    for (name, size, modified) in &files {
+        let fields = size.to_le_bytes().into_iter().chain(modified.to_le_bytes());
+        hash = fnv1a(hash, name.bytes().chain([0]).chain(fields));
+    }
+
+    Ok(hash)
+}
+
+/// Folds `bytes` into the FNV-1a `hash`.
+fn fnv1a(mut hash: u64, bytes: impl IntoIterator<Item = u8>) -> u64 {
+    for byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_fingerprint_changes_when_an_artist_is_added() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("Beatles").join("Abbey Road")).unwrap();
+        fs::write(temp_dir.path().join("cover.jpg"), b"").unwrap();
+
+        let before = library_fingerprint(temp_dir.path(), 1).unwrap();
+        assert_eq!(library_fingerprint(temp_dir.path(), 1).unwrap(), before);
+
+        fs::create_dir_all(temp_dir.path().join("Radiohead").join("Kid A")).unwrap();
+
+        assert_ne!(library_fingerprint(temp_dir.path(), 1).unwrap(), before);
+    }
+
+    #[test]
+    fn test_fingerprint_covers_artists_nested_in_genres() {
+        let temp_dir = TempDir::new().unwrap();
+        let beatles = temp_dir.path().join("Rock").join("Beatles");
+        fs::create_dir_all(&beatles).unwrap();
+        let two_days_ago = SystemTime::now() - Duration::from_secs(2 * 24 * 60 * 60);
+        fs::File::open(&beatles)
+            .unwrap()
+            .set_modified(two_days_ago)
+            .unwrap();
+
+        let top_level = library_fingerprint(temp_dir.path(), 1).unwrap();
+        let nested = library_fingerprint(temp_dir.path(), 2).unwrap();
+        fs::create_dir(beatles.join("Abbey Road")).unwrap();
+
+        assert_eq!(library_fingerprint(temp_dir.path(), 1).unwrap(), top_level);
+        assert_ne!(library_fingerprint(temp_dir.path(), 2).unwrap(), nested);
+    }
+
+    #[test]
+    fn test_scan_fingerprint_changes_with_the_settings() {
+        let with_tags = scan_fingerprint(42, "use_tags: true");
+
+        assert_eq!(scan_fingerprint(42, "use_tags: true"), with_tags);
+        assert_ne!(scan_fingerprint(42, "use_tags: false"), with_tags);
+        assert_ne!(scan_fingerprint(43, "use_tags: true"), with_tags);
+    }
+
+    #[test]
+    fn test_ignore_file_digest_changes_with_the_rules() {
+        let temp_dir = TempDir::new().unwrap();
+        assert_eq!(ignore_file_digest(temp_dir.path()).unwrap(), 0);
+
+        fs::write(temp_dir.path().join(".musyncignore"), "Podcasts\n").unwrap();
+        let podcasts = ignore_file_digest(temp_dir.path()).unwrap();
+        assert_ne!(podcasts, 0);
+
+        fs::write(temp_dir.path().join(".musyncignore"), "Podcasts\nDemos\n").unwrap();
+        assert_ne!(ignore_file_digest(temp_dir.path()).unwrap(), podcasts);
+    }
+
+    #[test]
+    fn test_album_digest_changes_with_the_album_content_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let ok_computer = temp_dir.path().join("OK Computer");
+        let kid_a = temp_dir.path().join("Kid A");
+        fs::create_dir_all(ok_computer.join("CD1")).unwrap();
+        fs::create_dir_all(&kid_a).unwrap();
+        fs::write(ok_computer.join("CD1").join("01 Airbag.flac"), b"airbag").unwrap();
+        fs::write(ok_computer.join("02 Paranoid Android.flac"), b"android").unwrap();
+        fs::write(kid_a.join("01 Everything.flac"), b"everything").unwrap();
+
+        let ok_computer_before = album_digest(&ok_computer).unwrap();
+        let kid_a_before = album_digest(&kid_a).unwrap();
+        assert_eq!(album_digest(&ok_computer).unwrap(), ok_computer_before);
+
+        fs::write(
+            ok_computer.join("CD1").join("01 Airbag.flac"),
+            b"airbag, remastered",
+        )
+        .unwrap();
+
+        assert_ne!(album_digest(&ok_computer).unwrap(), ok_computer_before);
+        assert_eq!(album_digest(&kid_a).unwrap(), kid_a_before);
+    }
+}
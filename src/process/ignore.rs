@@ -0,0 +1,177 @@
+//! This module reads the `.musyncignore` file of the library root, which lists glob
+//! patterns of artist and album folders to leave out of the scan, like a `.gitignore`.
+//!
+//! Patterns are parsed with `globset`: `*` matches any part of a folder name, `?` a
+//! single character, `**` any number of folders and `[...]` a set of characters. A
+//! pattern without a `/` matches a folder name at any level; a pattern with a `/`
+//! matches the path of a folder relative to the root, e.g. `Artist/Live*`.
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+/// Name of the ignore file read from the root of the library.
+pub const IGNORE_FILE: &str = ".musyncignore";
+
+/// The folders excluded by an ignore file.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    root: PathBuf,
+    /// Patterns matching a folder name.
+    names: GlobSet,
+    /// Patterns matching the path of a folder relative to `root`.
+    paths: GlobSet,
+}
+
+impl IgnoreRules {
+    /// Reads the `.musyncignore` file of `root`. A missing file ignores nothing.
+    ///
+    /// An invalid pattern is returned as an `InvalidData` error.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The path to the root directory of the music collection.
+    ///
+    pub fn load(root: &Path) -> io::Result<Self> {
+        match read_ignore_file(root)? {
+            Some(contents) => Self::parse(root, &contents),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Parses the contents of an ignore file, one pattern per line.
+    ///
+    /// Empty lines and lines starting with `#` are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `root` - The folder that patterns containing a `/` are relative to.
+    /// * `contents` - The contents of the ignore file.
+    ///
+    pub fn parse(root: &Path, contents: &str) -> io::Result<Self> {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+
+        for line in contents.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            // Only folders are matched, so a trailing `/` changes nothing.
+            let pattern = line.trim_end_matches('/');
+            let glob = GlobBuilder::new(pattern.trim_start_matches('/'))
+                .literal_separator(true)
+                .build()
+                .map_err(|e| invalid_pattern(line, e))?;
+            if pattern.contains('/') {
+                paths.add(glob);
+            } else {
+                names.add(glob);
+            }
+        }
+
+        let build = |set: GlobSetBuilder| {
+            set.build().map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid patterns in {}: {}", IGNORE_FILE, e),
+                )
+            })
+        };
+        Ok(Self {
+            root: root.to_path_buf(),
+            names: build(names)?,
+            paths: build(paths)?,
+        })
+    }
+
+    /// Returns `true` if no pattern was read.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.paths.is_empty()
+    }
+
+    /// Returns `true` if the folder at `path` matches one of the patterns.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        if self.names.is_match(name.as_ref()) {
+            return true;
+        }
+
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        let relative = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        self.paths.is_match(relative)
+    }
+}
+
+/// Reads the `.musyncignore` file of `root`, `None` when there is none.
+pub fn read_ignore_file(root: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(root.join(IGNORE_FILE)) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn invalid_pattern(pattern: &str, e: globset::Error) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Invalid pattern '{}' in {}: {}", pattern, IGNORE_FILE, e),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ignore_patterns() {
+        let root = Path::new("/music");
+        let rules = IgnoreRules::parse(
+            root,
+            "# Podcasts aren't music\nPodcasts\n\n*Bootleg*\nQueen/Live ??\n/Various [A-C]*/\n**/Demos\n",
+        )
+        .unwrap();
+
+        for ignored in [
+            "/music/Podcasts",
+            "/music/Artist/Podcasts",
+            "/music/Artist/Rare Bootlegs",
+            "/music/Queen/Live 86",
+            "/music/Various Artists",
+            "/music/Demos",
+            "/music/Artist/Demos",
+        ] {
+            assert!(rules.is_ignored(Path::new(ignored)), "{ignored}");
+        }
+        for kept in [
+            "/music/Queen",
+            "/music/Queen/Live 1986",
+            "/music/Queen/Sub/Live 86",
+            "/music/Various Ducks",
+            "/music/# Podcasts aren't music",
+        ] {
+            assert!(!rules.is_ignored(Path::new(kept)), "{kept}");
+        }
+    }
+
+    #[test]
+    fn test_missing_ignore_file_ignores_nothing() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let rules = IgnoreRules::load(temp_dir.path()).unwrap();
+
+        assert!(rules.is_empty());
+        assert!(!rules.is_ignored(&temp_dir.path().join("Artist")));
+    }
+}
@@ -0,0 +1,78 @@
+//! This module checks the audio files of an album before it is stored, so albums left
+//! broken by a failed rip (empty or unreadable files) aren't uploaded.
+
+use crate::process::process::is_audio_file;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// An audio file that can't be played.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidAudio {
+    pub path: PathBuf,
+    /// Why the file is invalid.
+    pub reason: String,
+}
+
+impl fmt::Display for InvalidAudio {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.reason)
+    }
+}
+
+/// Returns the first invalid audio file of an album, in file-name order.
+///
+/// A file is invalid when it is empty, or when its headers can't be read, as for a
+/// truncated FLAC or an MP3 without a single valid frame. Files in sub-folders, such as
+/// disc folders, are checked too.
+///
+/// # Arguments
+///
+/// * `album_path` - The path to the album directory.
+/// * `extensions` - The audio file extensions.
+///
+pub fn find_invalid_audio(album_path: &Path, extensions: &[&str]) -> Option<InvalidAudio> {
+    WalkDir::new(album_path)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file() && is_audio_file(entry.path(), extensions))
+        .find_map(|entry| {
+            let reason = match entry.metadata() {
+                Ok(metadata) if metadata.len() == 0 => "empty file".to_string(),
+                Ok(_) => lofty::read_from_path(entry.path()).err()?.to_string(),
+                Err(e) => e.to_string(),
+            };
+            Some(InvalidAudio {
+                path: entry.into_path(),
+                reason,
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::tags::tests::write_tagged_flac;
+    use crate::process::AUDIO_EXTENSIONS;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_empty_and_corrupt_files_are_invalid() {
+        let temp_dir = TempDir::new().unwrap();
+        write_tagged_flac(&temp_dir.path().join("01.flac"), "Artist", "Album", 2001);
+        fs::write(temp_dir.path().join("cover.jpg"), b"").unwrap();
+        assert_eq!(find_invalid_audio(temp_dir.path(), &AUDIO_EXTENSIONS), None);
+
+        fs::write(temp_dir.path().join("02.flac"), b"").unwrap();
+        let invalid = find_invalid_audio(temp_dir.path(), &AUDIO_EXTENSIONS).unwrap();
+        assert_eq!(invalid.path, temp_dir.path().join("02.flac"));
+        assert_eq!(invalid.reason, "empty file");
+
+        fs::remove_file(temp_dir.path().join("02.flac")).unwrap();
+        fs::write(temp_dir.path().join("02.flac"), b"not a flac file").unwrap();
+        let invalid = find_invalid_audio(temp_dir.path(), &AUDIO_EXTENSIONS).unwrap();
+        assert_eq!(invalid.path, temp_dir.path().join("02.flac"));
+    }
+}
@@ -1,3 +1,19 @@
+mod fingerprint;
+mod ignore;
+mod integrity;
+#[allow(clippy::module_inception)]
 mod process;
+mod process_error;
+mod sidecar;
+mod tags;
 
-pub use process::process_root;
+pub use fingerprint::{ignore_file_digest, library_fingerprint, scan_fingerprint};
+pub use ignore::{read_ignore_file, IgnoreRules, IGNORE_FILE};
+pub use integrity::{find_invalid_audio, InvalidAudio};
+pub use process::{
+    collect_albums, collect_albums_with, is_audio_file, loose_tracks, process_root,
+    process_root_with, ArtistOutcome, ScanOptions, ScanSummary, AUDIO_EXTENSIONS,
+};
+pub use process_error::ProcessError;
+pub use sidecar::{read_sidecar, AlbumSidecar};
+pub use tags::{read_album_tags, AlbumTags};
@@ -3,155 +3,1122 @@
 //! It includes functions for traversing directory structures, identifying audio files,
 //! and updating artist information in a database.
 
-use crate::foundation::database::{get_artist_data, store_artist_data};
-use crate::foundation::utils::{clean_album_name, normalize_unicode};
+use crate::configuration::{ChangeDetection, LibraryLayout};
+use crate::foundation::database::{
+    artist_keys, export_database, get_artist_data_by_key, store_artist_data_by_key, AlbumData,
+    ArtistData, ArtistStore,
+};
+use crate::foundation::utils::{parse_album_year, AlbumNameCleaner, ArtistKeys, DiscFolderMatcher};
+use crate::observer::{ConsoleObserver, SyncObserver};
+use crate::process::fingerprint::album_digest;
+use crate::process::ignore::IgnoreRules;
+use crate::process::integrity::find_invalid_audio;
+use crate::process::sidecar::{read_sidecar, AlbumSidecar};
+use crate::process::tags::{read_album_tags, AlbumTags};
+use crate::process::ProcessError;
 use rayon::prelude::*;
-use sled::Db;
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 use std::time::UNIX_EPOCH;
 use std::{fs, io};
-use walkdir::WalkDir;
+use walkdir::{DirEntry, WalkDir};
 
-/// Supported audio file extensions.
-const AUDIO_EXTENSIONS: [&str; 4] = ["mp3", "flac", "wav", "m4a"];
+/// Audio file extensions recognized when scanning the collection.
+pub const AUDIO_EXTENSIONS: [&str; 4] = ["mp3", "flac", "wav", "m4a"];
+
+/// Artist name used when an album folder does not follow the `"Artist - Album"` convention.
+const UNKNOWN_ARTIST: &str = "Unknown Artist";
+
+/// Options controlling how the local music collection is scanned.
+#[derive(Debug, Default, Clone)]
+pub struct ScanOptions {
+    /// How artists and albums are laid out under the root directory.
+    pub layout: LibraryLayout,
+    /// How many levels below the root the artist (or, with the `AlbumOnly` layout,
+    /// album) folders are, e.g. 2 for `Genre/Artist/Album`. 0 is treated as 1.
+    pub library_depth: usize,
+    /// Read artist and album names from audio tags, falling back to folder names.
+    pub use_tags: bool,
+    /// Only process folders modified at or after this Unix timestamp, in seconds.
+    /// Older folders are skipped without being descended into.
+    pub modified_since: Option<u64>,
+    /// Rules applied to album names before they are stored.
+    pub album_names: AlbumNameCleaner,
+    /// Recognizes the disc folders of multi-disc albums. A folder whose only sub-folders
+    /// with audio are disc folders is one album; elsewhere, they are albums like others.
+    pub disc_folders: DiscFolderMatcher,
+    /// Read the MusicBrainz album ID of each album from its tags.
+    pub read_musicbrainz_ids: bool,
+    /// Leave out albums containing an empty or unreadable audio file, with a warning.
+    pub validate_audio: bool,
+    /// What tells that a stored artist folder changed and must be scanned again.
+    pub change_detection: ChangeDetection,
+    /// Scan every artist again, whatever `change_detection` says, e.g. because the
+    /// settings changing the stored albums changed.
+    pub rescan_all: bool,
+    /// Warn about albums whose folder name has no year, without leaving them out. A
+    /// year in the sidecar file or, with `use_tags`, in the tags is enough.
+    pub require_year: bool,
+    /// Name of the sidecar file, e.g. `album.nfo`, whose album title, year and
+    /// MusicBrainz ID take precedence over the folder name and tags.
+    pub sidecar_file: Option<String>,
+    /// Name of the album made of the audio files directly in an artist folder, such as
+    /// loose singles. Those files are left out when unset.
+    pub singles_album: Option<String>,
+    /// Artist and album folders left out of the scan. [`process_root`] replaces them
+    /// with the patterns of the root's `.musyncignore` file.
+    pub ignore: IgnoreRules,
+}
+
+impl ScanOptions {
+    /// Returns `true` if a folder last modified at `last_modified` is older than the
+    /// `modified_since` cutoff.
+    fn is_too_old(&self, last_modified: u64) -> bool {
+        self.modified_since
+            .is_some_and(|cutoff| last_modified < cutoff)
+    }
+
+    /// Walks the folders `library_depth` levels below `root`. Ignored folders above that
+    /// level aren't descended into.
+    fn library_folders(&self, root: &Path) -> impl Iterator<Item = walkdir::Result<DirEntry>> + '_ {
+        let depth = self.library_depth.max(1);
+        WalkDir::new(root)
+            .min_depth(depth)
+            .max_depth(depth)
+            .into_iter()
+            .filter_entry(move |entry| {
+                entry.depth() == 0
+                    || entry.depth() == depth
+                    || !self.ignore.is_ignored(entry.path())
+            })
+    }
+}
+
+/// What a scan did with the stored data of an artist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArtistOutcome {
+    /// The artist's folder hasn't changed since it was stored.
+    Unchanged,
+    /// The artist's albums were collected and stored again.
+    Updated { album_count: usize },
+    /// The artist's folder wasn't scanned, because it is ignored or older than the
+    /// `modified_since` cutoff.
+    Skipped,
+}
+
+/// How many artists a scan updated, left unchanged or skipped.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ScanSummary {
+    pub updated: usize,
+    /// Names of the updated artists, sorted.
+    pub updated_artists: Vec<String>,
+    pub unchanged: usize,
+    /// Skipped artist folders or, with the `AlbumOnly` layout, album folders.
+    pub skipped: usize,
+    /// Albums whose folder name has no year, only counted with `require_year`.
+    pub missing_year: usize,
+}
+
+impl ScanSummary {
+    /// Number of scanned artists, whether or not their stored data changed.
+    pub fn scanned(&self) -> usize {
+        self.updated + self.unchanged
+    }
+
+    /// Returns `true` if the scan found no folder at all, scanned or skipped, which
+    /// usually means the root isn't the music library.
+    pub fn is_empty(&self) -> bool {
+        self.scanned() == 0 && self.skipped == 0
+    }
+}
 
 /// Process the root directory of the music collection.
 ///
-/// This function walks through the immediate subdirectories of the root,
-/// treating each as an artist folder, and processes them in parallel.
+/// Depending on the configured layout, the subdirectories `library_depth` levels below
+/// the root (its immediate subdirectories by default) are treated either as artist
+/// folders or as album folders. A spinner tracks the number of artists processed so far.
+///
+/// Folders matching the patterns of a `.musyncignore` file in the root are skipped, and
+/// the albums stored in them before are removed. Album paths are stored relative to the
+/// root.
+///
+/// Returns how many artists were updated, left unchanged or skipped.
 ///
 /// # Arguments
 ///
 /// * `root` - The path to the root directory of the music collection.
 /// * `db` - A reference to the database where artist information is stored.
+/// * `options` - Options controlling how the collection is scanned.
 ///
-pub fn process_root(root: &Path, db: &Db) -> io::Result<()> {
-    WalkDir::new(root)
-        .min_depth(1)
-        .max_depth(1)
-        .into_iter()
+pub fn process_root(
+    root: &Path,
+    db: &dyn ArtistStore,
+    options: &ScanOptions,
+) -> Result<ScanSummary, ProcessError> {
+    process_root_with(root, db, options, &ConsoleObserver::new())
+}
+
+/// Process the root directory of the music collection, reporting progress to `observer`.
+///
+/// Behaves like [`process_root`], which uses a [`ConsoleObserver`]. Artists are scanned
+/// in parallel, so `on_artist_scanned` may be called from several threads.
+///
+/// # Arguments
+///
+/// * `root` - The path to the root directory of the music collection.
+/// * `db` - A reference to the database where artist information is stored.
+/// * `options` - Options controlling how the collection is scanned.
+/// * `observer` - Receives the progress of the scan.
+///
+pub fn process_root_with(
+    root: &Path,
+    db: &dyn ArtistStore,
+    options: &ScanOptions,
+    observer: &dyn SyncObserver,
+) -> Result<ScanSummary, ProcessError> {
+    let options = &ScanOptions {
+        ignore: IgnoreRules::load(root)?,
+        ..options.clone()
+    };
+    let progress = ScanProgress {
+        observer,
+        updated: AtomicUsize::new(0),
+        updated_artists: Mutex::new(Vec::new()),
+        unchanged: AtomicUsize::new(0),
+        skipped: AtomicUsize::new(0),
+        missing_year: AtomicUsize::new(0),
+    };
+    observer.on_scan_started();
+
+    let result = match options.layout {
+        LibraryLayout::ArtistAlbum => process_artist_folders(root, db, options, &progress),
+        LibraryLayout::AlbumOnly => process_album_folders(root, db, options, &progress),
+    }
+    .and_then(|()| {
+        remove_ignored_albums(root, db, &options.ignore).map_err(ProcessError::Database)
+    });
+
+    let mut updated_artists = progress
+        .updated_artists
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner());
+    updated_artists.sort_by_key(|artist| artist.to_lowercase());
+    let summary = ScanSummary {
+        updated: progress.updated.load(Ordering::Relaxed),
+        updated_artists,
+        unchanged: progress.unchanged.load(Ordering::Relaxed),
+        skipped: progress.skipped.load(Ordering::Relaxed),
+        missing_year: progress.missing_year.load(Ordering::Relaxed),
+    };
+    observer.on_scan_finished(summary.scanned());
+    result.map(|()| summary)
+}
+
+/// Removes the stored albums in folders matching `ignore`, and the artists left without
+/// albums, so folders ignored after they were stored aren't compared or uploaded.
+fn remove_ignored_albums(
+    root: &Path,
+    db: &dyn ArtistStore,
+    ignore: &IgnoreRules,
+) -> io::Result<()> {
+    if ignore.is_empty() {
+        return Ok(());
+    }
+    for (key, mut data) in export_database(db)? {
+        let album_count = data.albums.len();
+        data.albums
+            .retain(|album| !is_in_ignored_folder(root, &album.full_path(root), ignore));
+        if data.albums.len() == album_count {
+            continue;
+        }
+        if data.albums.is_empty() {
+            db.remove(key.as_bytes())?;
+        } else {
+            store_artist_data_by_key(
+                db,
+                &key,
+                &data.display_name,
+                data.albums.len(),
+                data.last_modified,
+                data.albums,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns `true` if the album at `album_path`, or a folder it is in below `root`,
+/// matches `ignore`.
+fn is_in_ignored_folder(root: &Path, album_path: &Path, ignore: &IgnoreRules) -> bool {
+    let relative = album_path.strip_prefix(root).unwrap_or(album_path);
+    relative
+        .ancestors()
+        .filter(|path| !path.as_os_str().is_empty())
+        .any(|path| ignore.is_ignored(&root.join(path)))
+}
+
+/// Counts the outcome of each artist across the parallel artist walk and forwards
+/// scanned artists to the observer.
+///
+/// The counts live in atomics so that every rayon worker can report a finished
+/// artist without locking. Only updated artists, usually few, are recorded by name.
+struct ScanProgress<'a> {
+    observer: &'a dyn SyncObserver,
+    updated: AtomicUsize,
+    updated_artists: Mutex<Vec<String>>,
+    unchanged: AtomicUsize,
+    skipped: AtomicUsize,
+    missing_year: AtomicUsize,
+}
+
+impl ScanProgress<'_> {
+    /// Reports the albums of an artist whose folder name, sidecar file and, with
+    /// `use_tags`, tags have no year to the observer, when `require_year` is set, and
+    /// counts them.
+    fn check_years(
+        &self,
+        root: &Path,
+        artist_name: &str,
+        albums: &[AlbumData],
+        options: &ScanOptions,
+    ) {
+        if !options.require_year {
+            return;
+        }
+        for album in albums {
+            let folder_name = Path::new(&album.path)
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default();
+            let album_path = album.full_path(root);
+            // An unreadable sidecar was already reported while scanning the album.
+            let sidecar_year = options
+                .sidecar_file
+                .as_deref()
+                .and_then(|file_name| read_sidecar(&album_path, file_name).ok().flatten())
+                .and_then(|sidecar| sidecar.year);
+            let has_year = parse_album_year(&folder_name).is_some()
+                || sidecar_year.is_some()
+                || (options.use_tags
+                    && read_album_tags(&album_path).is_some_and(|tags| tags.year.is_some()));
+            if !has_year {
+                self.observer
+                    .on_album_without_year(artist_name, &album.path);
+                self.missing_year.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn artist_done(&self, artist: &str, outcome: ArtistOutcome) {
+        let count = match outcome {
+            ArtistOutcome::Updated { .. } => {
+                self.updated_artists
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push(artist.to_string());
+                &self.updated
+            }
+            ArtistOutcome::Unchanged => &self.unchanged,
+            ArtistOutcome::Skipped => {
+                // Skipped artists weren't scanned, so the observer doesn't hear of them.
+                self.skipped.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+        count.fetch_add(1, Ordering::Relaxed);
+        self.observer.on_artist_scanned(artist, &outcome);
+    }
+}
+
+/// Process a collection laid out as `Artist/Album/tracks`.
+///
+/// This function walks through the subdirectories `library_depth` levels below the
+/// root, treating each as an artist folder, and processes them in parallel. Folders
+/// whose names have the same key, such as `The Beatles` and `Beatles` when `The` is an
+/// ignored article, are stored as one artist.
+///
+fn process_artist_folders(
+    root: &Path,
+    db: &dyn ArtistStore,
+    options: &ScanOptions,
+    progress: &ScanProgress,
+) -> Result<(), ProcessError> {
+    let keys = artist_keys(db).map_err(ProcessError::Database)?;
+    let folders = options
+        .library_folders(root)
         .par_bridge()
-        .try_for_each(|entry| match entry {
-            Ok(entry) => {
-                let path = entry.path();
-                if path.is_dir() && has_sub_folders(path)? {
-                    let artist_name =
-                        path.file_name().and_then(|n| n.to_str()).ok_or_else(|| {
-                            io::Error::new(io::ErrorKind::InvalidData, "Invalid artist name")
-                        })?;
-
-                    process_artist_folder(path, artist_name, db)
-                } else {
-                    Ok(())
-                }
+        .map(|entry| {
+            let entry = entry?;
+            let path = entry.path();
+            if !path.is_dir() {
+                return Ok(None);
+            }
+            if options.ignore.is_ignored(path) {
+                let folder_name = entry.file_name().to_string_lossy();
+                progress.artist_done(&folder_name, ArtistOutcome::Skipped);
+                return Ok(None);
             }
-            Err(e) => Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                format!("Failed to read directory entry. Details: {}", e),
-            )),
+            // Old folders aren't walked: whether they are artists is only checked when
+            // a recent folder has the same key.
+            let too_old = options.is_too_old(get_last_modified_time(path)?);
+            // Folders without albums, such as `artwork`, aren't artists.
+            if !too_old && !is_artist_folder(path, options)? {
+                return Ok(None);
+            }
+
+            let folder_name = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .ok_or_else(|| ProcessError::InvalidArtistName(path.to_path_buf()))?;
+            Ok(Some((folder_name.to_string(), path.to_path_buf(), too_old)))
         })
+        .collect::<Result<Vec<_>, ProcessError>>()?;
+
+    let mut artists: BTreeMap<String, Vec<(String, PathBuf, bool)>> = BTreeMap::new();
+    for (folder_name, path, too_old) in folders.into_iter().flatten() {
+        artists
+            .entry(keys.key(&folder_name))
+            .or_default()
+            .push((folder_name, path, too_old));
+    }
+
+    artists.into_par_iter().try_for_each(|(_, mut candidates)| {
+        candidates.sort();
+        // An artist is skipped when none of its folders changed recently.
+        if candidates.iter().all(|(_, _, too_old)| *too_old) {
+            progress.artist_done(&candidates[0].0, ArtistOutcome::Skipped);
+            return Ok(());
+        }
+        let mut folders = Vec::with_capacity(candidates.len());
+        for (folder_name, path, too_old) in candidates {
+            if !too_old || is_artist_folder(&path, options)? {
+                folders.push((folder_name, path));
+            }
+        }
+
+        let (artist_name, outcome) = match &folders[..] {
+            [(folder_name, path)] => {
+                process_artist_folder(root, path, folder_name, db, &keys, options, progress)?
+            }
+            folders => process_merged_artist_folders(root, folders, db, &keys, options, progress)?,
+        };
+        progress.artist_done(&artist_name, outcome);
+        Ok(())
+    })
+}
+
+/// Process a collection laid out as `Album/tracks`.
+///
+/// Every folder `library_depth` levels below the root (a top-level folder by default)
+/// containing audio files is treated as an album. The artist
+/// is parsed from the leading `"Artist - Album"` part of the folder name, falling back
+/// to `Unknown Artist`. When tag reading is enabled, tag values take precedence over
+/// the folder name. Albums are grouped per artist before being stored.
+///
+/// Album folders older than `modified_since` are skipped. The stored albums of an
+/// updated artist that were skipped are kept as long as their folder still exists.
+///
+fn process_album_folders(
+    root: &Path,
+    db: &dyn ArtistStore,
+    options: &ScanOptions,
+    progress: &ScanProgress,
+) -> Result<(), ProcessError> {
+    let keys = artist_keys(db).map_err(ProcessError::Database)?;
+    let mut artists: HashMap<String, ArtistAlbums> = HashMap::new();
+
+    for entry in options.library_folders(root) {
+        let entry = entry?;
+        let path = entry.path();
+        if !path.is_dir() || options.ignore.is_ignored(path) {
+            continue;
+        }
+        let last_modified = get_last_modified_time(path)?;
+        if options.is_too_old(last_modified) {
+            progress.artist_done(&entry.file_name().to_string_lossy(), ArtistOutcome::Skipped);
+            continue;
+        }
+        let (track_count, total_bytes) = album_stats(path, &AUDIO_EXTENSIONS);
+        if track_count == 0
+            || has_invalid_audio(path, options, &AUDIO_EXTENSIONS, progress.observer)
+        {
+            continue;
+        }
+
+        let folder_name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| ProcessError::InvalidAlbumName(path.to_path_buf()))?;
+        let (mut artist_name, mut album_name) = split_album_folder_name(folder_name);
+        let mut musicbrainz_id = None;
+        if let Some(tags) = album_tags(path, options) {
+            if options.use_tags {
+                artist_name = tags.artist.unwrap_or(artist_name);
+                album_name = tags.album.unwrap_or(album_name);
+            }
+            musicbrainz_id = tags.musicbrainz_album_id;
+        }
+        let sidecar = album_sidecar(path, options, progress.observer);
+        album_name = sidecar.album.unwrap_or(album_name);
+        if options.read_musicbrainz_ids {
+            musicbrainz_id = sidecar.musicbrainz_album_id.or(musicbrainz_id);
+        }
+
+        let group = artists
+            .entry(keys.key(&artist_name))
+            .or_insert_with(|| ArtistAlbums {
+                artist_name,
+                last_modified: 0,
+                albums: Vec::new(),
+            });
+        group.last_modified = group.last_modified.max(last_modified);
+        group.albums.push(AlbumData {
+            name: options.album_names.clean(&album_name),
+            path: relative_album_path(root, path),
+            track_count,
+            total_bytes,
+            musicbrainz_id,
+            content_digest: 0,
+        });
+    }
+
+    artists.into_par_iter().try_for_each(|(key, mut group)| {
+        progress.check_years(root, &group.artist_name, &group.albums, options);
+        if let Some(stored_data) =
+            get_artist_data_by_key(db, &key).map_err(ProcessError::Database)?
+        {
+            if options.modified_since.is_some() {
+                keep_skipped_albums(root, &mut group.albums, stored_data.albums.clone());
+            }
+            if group.last_modified <= stored_data.last_modified
+                && group.albums == stored_data.albums
+            {
+                progress.artist_done(&group.artist_name, ArtistOutcome::Unchanged);
+                return Ok(());
+            }
+        }
+
+        warn_about_name_collisions(&group.artist_name, &group.albums, progress.observer);
+        let album_count = group.albums.len();
+        store_artist_data_by_key(
+            db,
+            &key,
+            &group.artist_name,
+            album_count,
+            group.last_modified,
+            group.albums,
+        )
+        .map_err(ProcessError::Database)?;
+        progress.artist_done(&group.artist_name, ArtistOutcome::Updated { album_count });
+        Ok(())
+    })
+}
+
+/// Albums found for a single artist while scanning an `AlbumOnly` library.
+struct ArtistAlbums {
+    artist_name: String,
+    last_modified: u64,
+    albums: Vec<AlbumData>,
+}
+
+/// Add the stored albums that weren't scanned to `albums`, as long as their folder still
+/// exists. Used when old folders are skipped so an update doesn't drop them.
+fn keep_skipped_albums(root: &Path, albums: &mut Vec<AlbumData>, stored_albums: Vec<AlbumData>) {
+    for stored in stored_albums {
+        if !albums.iter().any(|album| album.path == stored.path) && stored.full_path(root).exists()
+        {
+            albums.push(stored);
+        }
+    }
+}
+
+/// Reports the albums of an artist that have the same name once cleaned, such as
+/// `Album [CD1]` and `Album [CD2]`, to `observer`. Both are kept and uploaded when the
+/// server lacks the name, but the server having one of them hides the other.
+fn warn_about_name_collisions(
+    artist_name: &str,
+    albums: &[AlbumData],
+    observer: &dyn SyncObserver,
+) {
+    let mut paths_by_name: HashMap<&str, &str> = HashMap::new();
+    for album in albums {
+        if let Some(other_path) = paths_by_name.insert(&album.name, &album.path) {
+            observer.on_album_name_collision(artist_name, &album.name, &album.path, other_path);
+        }
+    }
+}
+
+/// Split an album folder name following the `"Artist - Album"` convention.
+///
+/// Folder names without a separator are attributed to `Unknown Artist`.
+///
+fn split_album_folder_name(folder_name: &str) -> (String, String) {
+    match folder_name.split_once(" - ") {
+        Some((artist, album)) if !artist.trim().is_empty() && !album.trim().is_empty() => {
+            (artist.trim().to_string(), album.trim().to_string())
+        }
+        _ => (UNKNOWN_ARTIST.to_string(), folder_name.to_string()),
+    }
 }
 
 /// Process an individual artist folder.
 ///
-/// This function checks if the artist's data needs updating, collects album information,
-/// and stores the updated data in the database.
+/// This function checks if the artist's data needs updating, per the `change_detection`
+/// strategy, collects album information, and stores the updated data in the database.
+/// When tag reading is enabled, the artist name is taken from the tags of the first
+/// track in the folder.
+///
+/// Returns the artist name, as read from the tags or the folder, and what was done
+/// with its stored data.
 ///
 /// # Arguments
 ///
+/// * `root` - The path to the root directory, which album paths are stored relative to.
 /// * `path` - The path to the artist's folder.
-/// * `artist_name` - The name of the artist.
+/// * `folder_name` - The name of the artist's folder.
 /// * `db` - A reference to the database.
+/// * `keys` - The keys artists are stored under, read once per scan.
+/// * `options` - Options controlling how the collection is scanned.
+/// * `progress` - Counts the albums without a year.
 ///
-fn process_artist_folder(path: &Path, artist_name: &str, db: &Db) -> io::Result<()> {
-    let normalized_name = normalize_unicode(artist_name);
+fn process_artist_folder(
+    root: &Path,
+    path: &Path,
+    folder_name: &str,
+    db: &dyn ArtistStore,
+    keys: &ArtistKeys,
+    options: &ScanOptions,
+    progress: &ScanProgress,
+) -> Result<(String, ArtistOutcome), ProcessError> {
+    let tagged_artist = options
+        .use_tags
+        .then(|| read_album_tags(path).and_then(|tags| tags.artist))
+        .flatten();
+    let artist_name = tagged_artist.as_deref().unwrap_or(folder_name);
     let last_modified = get_last_modified_time(path)?;
 
-    if let Some(stored_data) = get_artist_data(db, &normalized_name)? {
-        if last_modified <= stored_data.last_modified {
-            println!("Artist: {} (unchanged)", artist_name);
-            return Ok(());
+    let key = keys.key(artist_name);
+    if let Some(stored_data) = get_artist_data_by_key(db, &key).map_err(ProcessError::Database)? {
+        if !artist_changed(root, &[path], &stored_data, last_modified, options) {
+            progress.check_years(root, artist_name, &stored_data.albums, options);
+            return Ok((artist_name.to_string(), ArtistOutcome::Unchanged));
         }
     }
 
-    let albums = collect_albums(path)?;
+    let mut albums = collect_albums_with(path, options, &AUDIO_EXTENSIONS, progress.observer)?;
+    for album in &mut albums {
+        album.path = relative_album_path(root, Path::new(&album.path));
+    }
+    warn_about_name_collisions(artist_name, &albums, progress.observer);
+    progress.check_years(root, artist_name, &albums, options);
     let album_count = albums.len();
 
-    store_artist_data(db, &normalized_name, album_count, last_modified, albums)?;
-    println!("Artist: {}, Albums: {} (updated)", artist_name, album_count);
-    Ok(())
+    store_artist_data_by_key(db, &key, artist_name, album_count, last_modified, albums)
+        .map_err(ProcessError::Database)?;
+    Ok((
+        artist_name.to_string(),
+        ArtistOutcome::Updated { album_count },
+    ))
 }
 
-/// Collect album information for an artist.
+/// Process the folders of an artist whose names have the same key, such as
+/// `The Beatles` and `Beatles`, storing their albums as one artist.
 ///
-/// This function scans the artist's directory for subdirectories containing audio files,
-/// which are considered albums.
+/// The folders are scanned together, and the artist is stored again when they changed,
+/// per the `change_detection` strategy, like a single folder. The artist is named after
+/// the first folder, or its tags when tag reading is enabled.
+///
+/// Returns the artist name and what was done with its stored data.
+fn process_merged_artist_folders(
+    root: &Path,
+    folders: &[(String, PathBuf)],
+    db: &dyn ArtistStore,
+    keys: &ArtistKeys,
+    options: &ScanOptions,
+    progress: &ScanProgress,
+) -> Result<(String, ArtistOutcome), ProcessError> {
+    let (folder_name, first_path) = &folders[0];
+    let artist_name = options
+        .use_tags
+        .then(|| read_album_tags(first_path).and_then(|tags| tags.artist))
+        .flatten()
+        .unwrap_or_else(|| folder_name.clone());
+
+    let mut last_modified = 0;
+    for (_, path) in folders {
+        last_modified = last_modified.max(get_last_modified_time(path)?);
+    }
+    let paths: Vec<&Path> = folders.iter().map(|(_, path)| path.as_path()).collect();
+
+    let key = keys.key(&artist_name);
+    if let Some(stored_data) = get_artist_data_by_key(db, &key).map_err(ProcessError::Database)? {
+        if !artist_changed(root, &paths, &stored_data, last_modified, options) {
+            progress.check_years(root, &artist_name, &stored_data.albums, options);
+            return Ok((artist_name, ArtistOutcome::Unchanged));
+        }
+    }
+
+    let mut albums = Vec::new();
+    for path in &paths {
+        albums.extend(collect_albums_with(
+            path,
+            options,
+            &AUDIO_EXTENSIONS,
+            progress.observer,
+        )?);
+    }
+    for album in &mut albums {
+        album.path = relative_album_path(root, Path::new(&album.path));
+    }
+
+    warn_about_name_collisions(&artist_name, &albums, progress.observer);
+    progress.check_years(root, &artist_name, &albums, options);
+    let album_count = albums.len();
+    store_artist_data_by_key(db, &key, &artist_name, album_count, last_modified, albums)
+        .map_err(ProcessError::Database)?;
+    Ok((artist_name, ArtistOutcome::Updated { album_count }))
+}
+
+/// Returns `true` if the folders at `paths` of an artist, last modified at
+/// `last_modified`, changed since `stored` was stored, per the `change_detection`
+/// strategy.
 ///
-fn collect_albums(artist_path: &Path) -> io::Result<Vec<(String, String)>> {
+/// Every artist changed with `rescan_all`. `AlbumCount` and `ContentHash` ignore
+/// modification times, for libraries whose backup tools touch them without changing
+/// anything. They compare the stored albums with the ones [`collect_albums`] would
+/// store now, see [`current_albums`].
+fn artist_changed(
+    root: &Path,
+    paths: &[&Path],
+    stored: &ArtistData,
+    last_modified: u64,
+    options: &ScanOptions,
+) -> bool {
+    if options.rescan_all {
+        return true;
+    }
+    if options.change_detection == ChangeDetection::Mtime {
+        return last_modified > stored.last_modified;
+    }
+    // An album that can't be read is rescanned, which reports the error.
+    let Ok(mut current) = current_albums(root, paths, options) else {
+        return true;
+    };
+    if options.change_detection == ChangeDetection::AlbumCount {
+        return current.len() != stored.album_count;
+    }
+    let mut stored: Vec<_> = stored
+        .albums
+        .iter()
+        .map(|album| (album.path.clone(), album.content_digest))
+        .collect();
+    current.sort();
+    stored.sort();
+    current != stored
+}
+
+/// The stored path and content digest of each album [`collect_albums`] would store for
+/// the folders at `paths`, without reading their tags or sidecars: albums left out by
+/// `validate_audio` are left out, and the `singles_album` is included. Digests are only
+/// computed with `ContentHash`, like when collecting, and are 0 otherwise.
+fn current_albums(
+    root: &Path,
+    paths: &[&Path],
+    options: &ScanOptions,
+) -> io::Result<Vec<(String, u64)>> {
+    let mut albums = Vec::new();
+    for path in paths {
+        for entry in album_folders(path, options) {
+            if !has_audio_files(entry.path(), &AUDIO_EXTENSIONS)
+                || (options.validate_audio
+                    && find_invalid_audio(entry.path(), &AUDIO_EXTENSIONS).is_some())
+            {
+                continue;
+            }
+            let digest = match options.change_detection {
+                ChangeDetection::ContentHash => album_digest(entry.path())?,
+                _ => 0,
+            };
+            albums.push((relative_album_path(root, entry.path()), digest));
+        }
+
+        if let Some(name) = options.singles_album.as_deref() {
+            let singles_path = path.join(name);
+            if !singles_path.exists() && !loose_audio_files(path, &AUDIO_EXTENSIONS).is_empty() {
+                albums.push((relative_album_path(root, &singles_path), 0));
+            }
+        }
+    }
+    Ok(albums)
+}
+
+/// The sub-folders of an artist folder that may be albums: ignored folders and a folder
+/// named like the artist are left out. There are none when the artist folder is itself
+/// a multi-disc album, see [`is_multi_disc_album`].
+fn album_folders(artist_path: &Path, options: &ScanOptions) -> Vec<DirEntry> {
+    if is_multi_disc_album(artist_path, options) {
+        return Vec::new();
+    }
     WalkDir::new(artist_path)
         .min_depth(1)
         .max_depth(1)
         .into_iter()
         .filter_map(Result::ok)
-        .filter(|entry| entry.path().is_dir())
+        .filter(|entry| {
+            entry.path().is_dir()
+                && !options.ignore.is_ignored(entry.path())
+                && entry.file_name() != artist_path.file_name().unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Returns `true` if the only sub-folders with audio files of the folder at `path` are
+/// disc folders, such as `CD1` and `CD2`: the folder is then one album, whose discs
+/// aren't albums on their own.
+fn is_multi_disc_album(path: &Path, options: &ScanOptions) -> bool {
+    let mut folders = WalkDir::new(path)
+        .min_depth(1)
+        .max_depth(1)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry.path().is_dir()
+                && !options.ignore.is_ignored(entry.path())
+                && has_audio_files(entry.path(), &AUDIO_EXTENSIONS)
+        })
+        .peekable();
+    folders.peek().is_some() && folders.all(|entry| is_disc_folder(entry.path(), options))
+}
+
+/// Collect album information for an artist.
+///
+/// This function scans the artist's directory for subdirectories containing audio files,
+/// which are considered albums. The tracks of a multi-disc album can be in disc folders
+/// (`Album/CD1`, `Album/CD2`); the album is still counted once. An artist folder whose
+/// only sub-folders are disc folders is an album without an album folder: it is left
+/// out, with a warning. Elsewhere, a folder named like a disc is an album. Album names
+/// come from the folder name, or from the ALBUM tag when tag reading is enabled and the
+/// tag is present. Each album directory is walked once to count its tracks and total
+/// size. With `validate_audio`, albums containing an empty or unreadable audio file are
+/// left out. With `singles_album`, the audio files directly in the artist folder form
+/// one more album.
+///
+/// # Arguments
+///
+/// * `artist_path` - The path to the artist's folder.
+/// * `options` - Options controlling how the collection is scanned.
+/// * `extensions` - The audio file extensions, e.g. [`AUDIO_EXTENSIONS`].
+///
+/// # Example
+///
+/// ```
+/// use musync::process::{collect_albums, ScanOptions, AUDIO_EXTENSIONS};
+/// use std::fs;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let library = tempfile::tempdir()?;
+/// let artist = library.path().join("Radiohead");
+/// fs::create_dir_all(artist.join("Kid A"))?;
+/// fs::create_dir_all(artist.join("Artwork"))?;
+/// fs::write(artist.join("Kid A").join("01 Everything in Its Right Place.flac"), b"")?;
+///
+/// let albums = collect_albums(&artist, &ScanOptions::default(), &AUDIO_EXTENSIONS)?;
+///
+/// assert_eq!(albums.len(), 1);
+/// assert_eq!(albums[0].name, "Kid A");
+/// assert_eq!(albums[0].track_count, 1);
+/// # Ok(())
+/// # }
+/// ```
+pub fn collect_albums(
+    artist_path: &Path,
+    options: &ScanOptions,
+    extensions: &[&str],
+) -> Result<Vec<AlbumData>, ProcessError> {
+    collect_albums_with(artist_path, options, extensions, &ConsoleObserver::new())
+}
+
+/// Collect album information for an artist, reporting albums left out by
+/// `validate_audio` and skipped folders to `observer`.
+///
+/// Behaves like [`collect_albums`], which uses a [`ConsoleObserver`].
+pub fn collect_albums_with(
+    artist_path: &Path,
+    options: &ScanOptions,
+    extensions: &[&str],
+    observer: &dyn SyncObserver,
+) -> Result<Vec<AlbumData>, ProcessError> {
+    if is_multi_disc_album(artist_path, options) {
+        observer.warn(&format!(
+            "Skipping {}: its only folders are discs, move them to an album folder",
+            artist_path.display()
+        ));
+    }
+    let mut albums = album_folders(artist_path, options)
+        .into_iter()
         .filter_map(|entry| {
             let album_name = entry.file_name().to_str()?;
-            if album_name != artist_path.file_name()?.to_str()? && has_audio_files(entry.path()) {
-                let cleaned_name = clean_album_name(album_name);
+            let (track_count, total_bytes) = album_stats(entry.path(), extensions);
+            if track_count > 0 && !has_invalid_audio(entry.path(), options, extensions, observer) {
+                let tags = album_tags(entry.path(), options).unwrap_or_default();
+                let sidecar = album_sidecar(entry.path(), options, observer);
+                let tagged_album = tags.album.filter(|_| options.use_tags);
+                let cleaned_name = options.album_names.clean(
+                    sidecar
+                        .album
+                        .as_deref()
+                        .or(tagged_album.as_deref())
+                        .unwrap_or(album_name),
+                );
+                let musicbrainz_id = sidecar
+                    .musicbrainz_album_id
+                    .filter(|_| options.read_musicbrainz_ids)
+                    .or(tags.musicbrainz_album_id);
+                let content_digest = match options.change_detection {
+                    ChangeDetection::ContentHash => match album_digest(entry.path()) {
+                        Ok(digest) => digest,
+                        Err(e) => return Some(Err(ProcessError::Io(e))),
+                    },
+                    _ => 0,
+                };
                 let full_path = entry.path().to_string_lossy().into_owned();
-                Some(Ok((cleaned_name, full_path)))
+                Some(Ok(AlbumData {
+                    name: cleaned_name,
+                    path: full_path,
+                    track_count,
+                    total_bytes,
+                    musicbrainz_id,
+                    content_digest,
+                }))
             } else {
                 None
             }
         })
+        .collect::<Result<Vec<_>, ProcessError>>()?;
+    albums.extend(singles_album(artist_path, options, extensions, observer));
+    Ok(albums)
+}
+
+/// The album of the audio files directly in the artist folder at `artist_path`, named
+/// `singles_album`, when it is set and there are any.
+///
+/// Its path is the one an album folder of that name would have. It doesn't exist, which
+/// tells the album apart from the others: see [`loose_tracks`]. The loose files are left
+/// out, with a warning, when the artist does have an album folder of that name.
+fn singles_album(
+    artist_path: &Path,
+    options: &ScanOptions,
+    extensions: &[&str],
+    observer: &dyn SyncObserver,
+) -> Option<AlbumData> {
+    let name = options.singles_album.as_deref()?;
+    let tracks = loose_audio_files(artist_path, extensions);
+    if tracks.is_empty() {
+        return None;
+    }
+    let path = artist_path.join(name);
+    if path.exists() {
+        observer.warn(&format!(
+            "Skipping the loose tracks of {}: it already has a '{}' folder",
+            artist_path.display(),
+            name
+        ));
+        return None;
+    }
+
+    Some(AlbumData {
+        name: options.album_names.clean(name),
+        path: path.to_string_lossy().into_owned(),
+        track_count: tracks.len(),
+        total_bytes: tracks
+            .iter()
+            .filter_map(|track| fs::metadata(track).ok())
+            .map(|metadata| metadata.len())
+            .sum(),
+        musicbrainz_id: None,
+        content_digest: 0,
+    })
+}
+
+/// Returns the audio files directly in the folder at `path`, in file-name order.
+fn loose_audio_files(path: &Path, extensions: &[&str]) -> Vec<PathBuf> {
+    WalkDir::new(path)
+        .min_depth(1)
+        .max_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file() && is_audio_file(entry.path(), extensions))
+        .map(DirEntry::into_path)
         .collect()
 }
 
-/// Check if a directory contains any audio files.
+/// Returns the tracks of the singles album at `album_path`: the audio files directly
+/// in its artist folder. `None` when `album_path` is an album folder, or when it doesn't
+/// exist and its parent has no loose tracks.
 ///
 /// # Arguments
 ///
-/// * `path` - The path to check for audio files.
+/// * `album_path` - The full path of an album, as stored.
 ///
-fn has_audio_files(path: &Path) -> bool {
-    WalkDir::new(path)
+pub fn loose_tracks(album_path: &Path) -> Option<Vec<PathBuf>> {
+    if album_path.exists() {
+        return None;
+    }
+    let tracks = loose_audio_files(album_path.parent()?, &AUDIO_EXTENSIONS);
+    (!tracks.is_empty()).then_some(tracks)
+}
+
+/// Reads the tags of the album at `path`, when `options` need any of them. The
+/// MusicBrainz ID is only kept with `read_musicbrainz_ids`.
+fn album_tags(path: &Path, options: &ScanOptions) -> Option<AlbumTags> {
+    if !options.use_tags && !options.read_musicbrainz_ids {
+        return None;
+    }
+    let mut tags = read_album_tags(path)?;
+    if !options.read_musicbrainz_ids {
+        tags.musicbrainz_album_id = None;
+    }
+    Some(tags)
+}
+
+/// Reads the sidecar file of the album at `path`, when `sidecar_file` is set. Empty
+/// when it isn't, or when the album has no readable sidecar; an unreadable one is
+/// reported to `observer`.
+fn album_sidecar(path: &Path, options: &ScanOptions, observer: &dyn SyncObserver) -> AlbumSidecar {
+    let Some(file_name) = options.sidecar_file.as_deref() else {
+        return AlbumSidecar::default();
+    };
+    read_sidecar(path, file_name)
+        .unwrap_or_else(|e| {
+            observer.warn(&format!("Ignoring {e}"));
+            None
+        })
+        .unwrap_or_default()
+}
+
+/// Returns `true`, reporting it to `observer`, if `validate_audio` is set and the album
+/// at `path` has an empty or unreadable audio file.
+fn has_invalid_audio(
+    path: &Path,
+    options: &ScanOptions,
+    extensions: &[&str],
+    observer: &dyn SyncObserver,
+) -> bool {
+    if !options.validate_audio {
+        return false;
+    }
+    match find_invalid_audio(path, extensions) {
+        Some(invalid) => {
+            observer.on_invalid_audio(path, &invalid);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Path of the album directory at `path` relative to `root`, as it is stored: with `/`
+/// separators whatever the local OS, so a database is portable between systems.
+fn relative_album_path(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace(std::path::MAIN_SEPARATOR, "/")
+}
+
+/// Returns `true` if `path` is the disc folder of a multi-disc album, per `options`.
+fn is_disc_folder(path: &Path, options: &ScanOptions) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| options.disc_folders.is_disc_folder(name))
+}
+
+/// Count the audio files of an album directory and the size of all its files, in bytes.
+///
+/// Files in sub-folders, such as the disc folders of a multi-disc album, are included.
+///
+/// # Arguments
+///
+/// * `album_path` - The path to the album directory.
+/// * `extensions` - The audio file extensions.
+///
+fn album_stats(album_path: &Path, extensions: &[&str]) -> (usize, u64) {
+    WalkDir::new(album_path)
         .into_iter()
         .filter_map(Result::ok)
-        .any(|e| is_audio_file(e.path()))
+        .filter(|entry| entry.file_type().is_file())
+        .fold((0, 0), |(tracks, bytes), entry| {
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+            let is_track = usize::from(is_audio_file(entry.path(), extensions));
+            (tracks + is_track, bytes + size)
+        })
 }
 
 /// Check if a file is an audio file based on its extension.
 ///
-fn is_audio_file(path: &Path) -> bool {
+/// The comparison ignores case, so `extensions` are expected in lowercase.
+///
+/// # Arguments
+///
+/// * `path` - The path to the file.
+/// * `extensions` - The audio file extensions, e.g. [`AUDIO_EXTENSIONS`].
+///
+pub fn is_audio_file(path: &Path, extensions: &[&str]) -> bool {
     path.extension()
         .and_then(|ext| ext.to_str())
-        .map(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        .map(|ext| extensions.contains(&ext.to_lowercase().as_str()))
         .unwrap_or(false)
 }
 
 /// Get the last modified time of a file or directory.
 ///
-fn get_last_modified_time(path: &Path) -> io::Result<u64> {
+pub(crate) fn get_last_modified_time(path: &Path) -> io::Result<u64> {
     path.metadata()?
         .modified()?
         .duration_since(UNIX_EPOCH)
         .map(|duration| duration.as_secs())
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
+        .map_err(|e| io::Error::other(e.to_string()))
 }
 
-/// Check if a directory contains any sub-folders.
-/// Ensure that only valid artist directories with sub-folders (potential albums) are processed,
-/// and artists without any albums are skipped.
-fn has_sub_folders(path: &Path) -> io::Result<bool> {
+/// Returns `true` if the folder at `path` has albums: a sub-folder with audio files or,
+/// with `singles_album`, audio files of its own.
+fn is_artist_folder(path: &Path, options: &ScanOptions) -> io::Result<bool> {
+    Ok(has_album_folders(path)?
+        || (options.singles_album.is_some()
+            && !loose_audio_files(path, &AUDIO_EXTENSIONS).is_empty()))
+}
+
+/// Check if a directory contains a sub-folder with audio files.
+/// Ensure that only valid artist directories with albums are processed, and artists whose
+/// only sub-folders hold no audio, such as `artwork`, are skipped.
+fn has_album_folders(path: &Path) -> io::Result<bool> {
     Ok(fs::read_dir(path)?
         .filter_map(Result::ok)
-        .any(|entry| entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false)))
+        .filter(|entry| entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false))
+        .any(|entry| has_audio_files(&entry.path(), &AUDIO_EXTENSIONS)))
+}
+
+/// Check if a directory or any of its sub-folders contains an audio file.
+fn has_audio_files(path: &Path, extensions: &[&str]) -> bool {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .any(|entry| entry.file_type().is_file() && is_audio_file(entry.path(), extensions))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::foundation::database::{
+        count_artists, get_artist_data, set_key_articles, MemoryStore,
+    };
+    use crate::foundation::utils::normalize_unicode;
+    use crate::observer::tests::RecordingObserver;
+    use crate::process::tags::tests::write_tagged_flac;
     use std::fs::{self, File};
+    use std::time::{Duration, SystemTime};
     use tempfile::TempDir;
 
     fn create_test_directory(structure: &[(&str, &[&str])]) -> TempDir {
@@ -200,13 +1167,769 @@ mod tests {
 
         let db = sled::Config::new().temporary(true).open().unwrap();
 
-        process_root(temp_dir.path(), &db).unwrap();
+        process_root(temp_dir.path(), &db, &ScanOptions::default()).unwrap();
+
+        let artist1_data = get_artist_data(&db, "Artist1").unwrap().unwrap();
+        assert_eq!(artist1_data.album_count, 2);
+
+        let artist2_data = get_artist_data(&db, "Artist2").unwrap().unwrap();
+        assert_eq!(artist2_data.album_count, 1);
+    }
+
+    #[test]
+    fn test_albums_cleaned_to_the_same_name_are_all_stored() {
+        let temp_dir = create_test_directory(&[("Artist", &["Album [CD1]", "Album [CD2]"])]);
+        for album in ["Album [CD1]", "Album [CD2]"] {
+            File::create(temp_dir.path().join("Artist").join(album).join("01.mp3")).unwrap();
+        }
+        let db = MemoryStore::new();
+        let observer = RecordingObserver::default();
+
+        process_root_with(temp_dir.path(), &db, &ScanOptions::default(), &observer).unwrap();
+
+        let collisions: Vec<_> = observer
+            .events()
+            .into_iter()
+            .filter(|event| event.starts_with("name_collision"))
+            .collect();
+        assert_eq!(
+            collisions,
+            ["name_collision Artist - Album: Artist/Album [CD1], Artist/Album [CD2]"]
+        );
+        let mut albums = get_artist_data(&db, "Artist").unwrap().unwrap().albums;
+        albums.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(albums.len(), 2);
+        assert!(albums.iter().all(|album| album.name == "Album"));
+        assert_eq!(
+            Path::new(&albums[0].path),
+            Path::new("Artist").join("Album [CD1]")
+        );
+        assert_eq!(
+            Path::new(&albums[1].path),
+            Path::new("Artist").join("Album [CD2]")
+        );
+    }
+
+    #[test]
+    fn test_artist_folders_differing_by_an_article_are_one_artist() {
+        let temp_dir =
+            create_test_directory(&[("The Beatles", &["Abbey Road"]), ("Beatles", &["Revolver"])]);
+        for (artist, album) in [("The Beatles", "Abbey Road"), ("Beatles", "Revolver")] {
+            File::create(temp_dir.path().join(artist).join(album).join("01.mp3")).unwrap();
+        }
+        let db = MemoryStore::new();
+        set_key_articles(&db, &["The".to_string()]).unwrap();
+
+        process_root(temp_dir.path(), &db, &ScanOptions::default()).unwrap();
+        let summary = process_root(temp_dir.path(), &db, &ScanOptions::default()).unwrap();
+
+        assert_eq!(count_artists(&db).unwrap(), 1);
+        let beatles = get_artist_data(&db, "The Beatles").unwrap().unwrap();
+        assert_eq!(beatles, get_artist_data(&db, "Beatles").unwrap().unwrap());
+        let mut albums: Vec<_> = beatles.albums.iter().map(|album| &album.name).collect();
+        albums.sort();
+        assert_eq!(albums, ["Abbey Road", "Revolver"]);
+        assert_eq!(summary.unchanged, 1);
+    }
+
+    #[test]
+    fn test_merged_artist_folders_follow_change_detection_and_since() {
+        let temp_dir =
+            create_test_directory(&[("The Beatles", &["Abbey Road"]), ("Beatles", &["Revolver"])]);
+        for (artist, album) in [("The Beatles", "Abbey Road"), ("Beatles", "Revolver")] {
+            File::create(temp_dir.path().join(artist).join(album).join("01.mp3")).unwrap();
+            set_modified_days_ago(&temp_dir.path().join(artist), 2);
+        }
+        let db = MemoryStore::new();
+        set_key_articles(&db, &["The".to_string()]).unwrap();
+        let options = ScanOptions {
+            change_detection: ChangeDetection::AlbumCount,
+            modified_since: Some(days_ago(1)),
+            ..ScanOptions::default()
+        };
+        process_root(temp_dir.path(), &db, &ScanOptions::default()).unwrap();
+
+        // Touching a folder isn't a change for AlbumCount.
+        set_modified_days_ago(&temp_dir.path().join("Beatles"), 0);
+        let summary = process_root(temp_dir.path(), &db, &options).unwrap();
+        assert_eq!(summary.unchanged, 1);
+
+        // The old folder is still scanned along the recent one of the same artist.
+        fs::create_dir(temp_dir.path().join("Beatles").join("Help")).unwrap();
+        File::create(temp_dir.path().join("Beatles").join("Help").join("01.mp3")).unwrap();
+        let summary = process_root(temp_dir.path(), &db, &options).unwrap();
+        assert_eq!(summary.updated, 1);
+        let beatles = get_artist_data(&db, "Beatles").unwrap().unwrap();
+        assert_eq!(beatles.album_count, 3);
+    }
+
+    #[test]
+    fn test_artists_nested_in_genre_folders() {
+        let temp_dir = create_test_directory(&[("Rock", &["Beatles", "Radiohead"])]);
+        let rock = temp_dir.path().join("Rock");
+        for (artist, album) in [("Beatles", "Abbey Road"), ("Radiohead", "Kid A")] {
+            fs::create_dir(rock.join(artist).join(album)).unwrap();
+            File::create(rock.join(artist).join(album).join("01.mp3")).unwrap();
+        }
+        let db = MemoryStore::new();
+        let options = ScanOptions {
+            library_depth: 2,
+            ..ScanOptions::default()
+        };
+
+        let summary = process_root(temp_dir.path(), &db, &options).unwrap();
+
+        assert_eq!(summary.updated, 2);
+        assert!(get_artist_data(&db, "rock").unwrap().is_none());
+        let beatles = get_artist_data(&db, "Beatles").unwrap().unwrap();
+        assert_eq!(beatles.albums[0].name, "Abbey Road");
+        assert_eq!(
+            Path::new(&beatles.albums[0].path),
+            Path::new("Rock").join("Beatles").join("Abbey Road")
+        );
+    }
+
+    #[test]
+    fn test_require_year_reports_albums_without_a_year() {
+        let temp_dir = create_test_directory(&[("Beatles", &["Abbey Road (1969)", "Let It Be"])]);
+        for album in ["Abbey Road (1969)", "Let It Be"] {
+            File::create(temp_dir.path().join("Beatles").join(album).join("01.mp3")).unwrap();
+        }
+        let db = MemoryStore::new();
+
+        let summary = process_root(temp_dir.path(), &db, &ScanOptions::default()).unwrap();
+        assert_eq!(summary.missing_year, 0);
+
+        let options = ScanOptions {
+            require_year: true,
+            ..ScanOptions::default()
+        };
+        let observer = RecordingObserver::default();
+        let summary = process_root_with(temp_dir.path(), &db, &options, &observer).unwrap();
+
+        // The album is reported, but still stored, whether or not the artist changed.
+        assert_eq!(summary.unchanged, 1);
+        assert_eq!(summary.missing_year, 1);
+        assert!(observer
+            .events()
+            .contains(&"without_year Beatles Beatles/Let It Be".to_string()));
+        let beatles = get_artist_data(&db, "Beatles").unwrap().unwrap();
+        assert_eq!(beatles.album_count, 2);
+    }
+
+    #[test]
+    fn test_require_year_accepts_the_year_of_the_tags() {
+        let temp_dir = TempDir::new().unwrap();
+        let album_path = temp_dir.path().join("Beatles").join("Let It Be");
+        fs::create_dir_all(&album_path).unwrap();
+        write_tagged_flac(&album_path.join("01.flac"), "Beatles", "Let It Be", 1970);
+        let db = MemoryStore::new();
+
+        let options = ScanOptions {
+            require_year: true,
+            ..ScanOptions::default()
+        };
+        let summary = process_root(temp_dir.path(), &db, &options).unwrap();
+        assert_eq!(summary.missing_year, 1);
+
+        let options = ScanOptions {
+            use_tags: true,
+            ..options
+        };
+        let summary = process_root(temp_dir.path(), &db, &options).unwrap();
+        assert_eq!(summary.missing_year, 0);
+    }
+
+    #[test]
+    fn test_album_paths_resolve_after_the_root_moves() {
+        let temp_dir = TempDir::new().unwrap();
+        let old_root = temp_dir.path().join("old");
+        fs::create_dir_all(old_root.join("Artist1").join("Album1")).unwrap();
+        File::create(old_root.join("Artist1").join("Album1").join("01.mp3")).unwrap();
+        let db = MemoryStore::new();
+
+        process_root(&old_root, &db, &ScanOptions::default()).unwrap();
+        let new_root = temp_dir.path().join("new");
+        fs::rename(&old_root, &new_root).unwrap();
+
+        let albums = get_artist_data(&db, "Artist1").unwrap().unwrap().albums;
+        assert_eq!(
+            Path::new(&albums[0].path),
+            Path::new("Artist1").join("Album1")
+        );
+        assert!(albums[0].full_path(&new_root).join("01.mp3").exists());
+    }
+
+    #[test]
+    fn test_process_root_reports_scanned_artist_count() {
+        let temp_dir = create_test_directory(&[
+            ("Artist1", &["Album1"]),
+            ("Artist2", &["Album2"]),
+            ("Artist3", &["Album3"]),
+        ]);
+        for (artist, album) in [
+            ("Artist1", "Album1"),
+            ("Artist2", "Album2"),
+            ("Artist3", "Album3"),
+        ] {
+            File::create(temp_dir.path().join(artist).join(album).join("t.mp3")).unwrap();
+        }
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+
+        let summary = process_root(temp_dir.path(), &db, &ScanOptions::default()).unwrap();
+        assert_eq!(summary.scanned(), 3);
+
+        // Unchanged artists still count towards the scan total.
+        let rescanned = process_root(temp_dir.path(), &db, &ScanOptions::default()).unwrap();
+        assert_eq!(rescanned.scanned(), 3);
+    }
+
+    #[test]
+    fn test_empty_root_has_an_empty_summary() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("artwork")).unwrap();
+        let db = MemoryStore::new();
+
+        let summary = process_root(temp_dir.path(), &db, &ScanOptions::default()).unwrap();
+
+        assert!(summary.is_empty());
+    }
+
+    #[test]
+    fn test_second_run_leaves_artist_unchanged() {
+        let temp_dir = create_test_directory(&[("Artist1", &["Album1"])]);
+        File::create(temp_dir.path().join("Artist1").join("Album1").join("t.mp3")).unwrap();
+        let db = MemoryStore::new();
+
+        let first = process_root_with(
+            temp_dir.path(),
+            &db,
+            &ScanOptions::default(),
+            &RecordingObserver::default(),
+        )
+        .unwrap();
+        let second = process_root_with(
+            temp_dir.path(),
+            &db,
+            &ScanOptions::default(),
+            &RecordingObserver::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            first,
+            ScanSummary {
+                updated: 1,
+                updated_artists: vec!["Artist1".to_string()],
+                ..ScanSummary::default()
+            }
+        );
+        assert_eq!(
+            second,
+            ScanSummary {
+                unchanged: 1,
+                ..ScanSummary::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_musyncignore_excludes_artists_and_albums() {
+        let temp_dir = create_test_directory(&[
+            ("Artist1", &["Album1", "Album1 (Live)"]),
+            ("Podcasts", &["Episode1"]),
+        ]);
+        for path in [
+            "Artist1/Album1",
+            "Artist1/Album1 (Live)",
+            "Podcasts/Episode1",
+        ] {
+            File::create(temp_dir.path().join(path).join("01.mp3")).unwrap();
+        }
+        fs::write(
+            temp_dir.path().join(".musyncignore"),
+            "# Not music\nPodcasts\nArtist1/*(Live)\n",
+        )
+        .unwrap();
+        let db = MemoryStore::new();
+
+        let summary = process_root(temp_dir.path(), &db, &ScanOptions::default()).unwrap();
+
+        assert!(get_artist_data(&db, "Podcasts").unwrap().is_none());
+        let albums = get_artist_data(&db, "Artist1").unwrap().unwrap().albums;
+        assert_eq!(albums.len(), 1);
+        assert_eq!(albums[0].name, "Album1");
+        assert_eq!(summary.skipped, 1);
+    }
+
+    #[test]
+    fn test_musyncignore_removes_albums_stored_before() {
+        let temp_dir = create_test_directory(&[
+            ("Artist1", &["Album1", "Album1 (Live)"]),
+            ("Podcasts", &["Episode1"]),
+        ]);
+        for path in [
+            "Artist1/Album1",
+            "Artist1/Album1 (Live)",
+            "Podcasts/Episode1",
+        ] {
+            File::create(temp_dir.path().join(path).join("01.mp3")).unwrap();
+        }
+        let db = MemoryStore::new();
+        process_root(temp_dir.path(), &db, &ScanOptions::default()).unwrap();
+        assert_eq!(count_artists(&db).unwrap(), 2);
+
+        fs::write(
+            temp_dir.path().join(".musyncignore"),
+            "Podcasts\nArtist1/*(Live)\n",
+        )
+        .unwrap();
+        process_root(temp_dir.path(), &db, &ScanOptions::default()).unwrap();
+
+        assert!(get_artist_data(&db, "Podcasts").unwrap().is_none());
+        let artist = get_artist_data(&db, "Artist1").unwrap().unwrap();
+        assert_eq!(artist.album_count, 1);
+        assert_eq!(artist.albums[0].name, "Album1");
+    }
+
+    #[test]
+    fn test_validate_audio_skips_albums_with_empty_files() {
+        let temp_dir = create_test_directory(&[("Artist1", &["Good", "Broken"])]);
+        let artist_path = temp_dir.path().join("Artist1");
+        write_tagged_flac(&artist_path.join("Good").join("01.flac"), "A", "Good", 2001);
+        write_tagged_flac(
+            &artist_path.join("Broken").join("01.flac"),
+            "A",
+            "Broken",
+            2001,
+        );
+        File::create(artist_path.join("Broken").join("02.flac")).unwrap();
+
+        let albums = collect_albums(&artist_path, &ScanOptions::default(), &AUDIO_EXTENSIONS);
+        assert_eq!(albums.unwrap().len(), 2);
+
+        let options = ScanOptions {
+            validate_audio: true,
+            ..ScanOptions::default()
+        };
+        let observer = RecordingObserver::default();
+        let albums =
+            collect_albums_with(&artist_path, &options, &AUDIO_EXTENSIONS, &observer).unwrap();
+        assert_eq!(albums.len(), 1);
+        assert_eq!(albums[0].name, "Good");
+        assert_eq!(
+            observer.events(),
+            [format!(
+                "invalid_audio {} empty file",
+                artist_path.join("Broken").display()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_artist_without_audio_sub_folders_is_skipped() {
+        let temp_dir = create_test_directory(&[("Artist1", &["artwork"]), ("Artist2", &["Album"])]);
+        File::create(
+            temp_dir
+                .path()
+                .join("Artist1")
+                .join("artwork")
+                .join("cover.jpg"),
+        )
+        .unwrap();
+        File::create(temp_dir.path().join("Artist2").join("Album").join("01.mp3")).unwrap();
+        let db = MemoryStore::new();
+
+        let observer = RecordingObserver::default();
+        process_root_with(temp_dir.path(), &db, &ScanOptions::default(), &observer).unwrap();
+
+        assert!(get_artist_data(&db, "Artist1").unwrap().is_none());
+        assert_eq!(
+            observer.events(),
+            [
+                "scan_started",
+                "artist_scanned Artist2 Updated { album_count: 1 }",
+                "scan_finished 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_process_root_on_memory_store() {
+        let temp_dir = create_test_directory(&[("Artist1", &["Album1", "Album2"])]);
+        for album in ["Album1", "Album2"] {
+            File::create(temp_dir.path().join("Artist1").join(album).join("t.mp3")).unwrap();
+        }
+        let store = MemoryStore::new();
+
+        let observer = RecordingObserver::default();
+        process_root_with(temp_dir.path(), &store, &ScanOptions::default(), &observer).unwrap();
+        process_root_with(temp_dir.path(), &store, &ScanOptions::default(), &observer).unwrap();
+
+        let data = get_artist_data(&store, "Artist1").unwrap().unwrap();
+        let mut albums: Vec<_> = data
+            .albums
+            .iter()
+            .map(|album| album.name.as_str())
+            .collect();
+        albums.sort();
+        assert_eq!(albums, ["Album1", "Album2"]);
+        assert!(observer
+            .events()
+            .contains(&"artist_scanned Artist1 Unchanged".to_string()));
+    }
+
+    #[test]
+    fn test_process_root_reports_progress_to_observer() {
+        let temp_dir = create_test_directory(&[("Artist1", &["Album1", "Album2"])]);
+        for album in ["Album1", "Album2"] {
+            File::create(temp_dir.path().join("Artist1").join(album).join("t.mp3")).unwrap();
+        }
+        let db = sled::Config::new().temporary(true).open().unwrap();
+
+        let observer = RecordingObserver::default();
+        process_root_with(temp_dir.path(), &db, &ScanOptions::default(), &observer).unwrap();
+        process_root_with(temp_dir.path(), &db, &ScanOptions::default(), &observer).unwrap();
+
+        assert_eq!(
+            observer.events(),
+            [
+                "scan_started",
+                "artist_scanned Artist1 Updated { album_count: 2 }",
+                "scan_finished 1",
+                "scan_started",
+                "artist_scanned Artist1 Unchanged",
+                "scan_finished 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_process_root_album_only_layout() {
+        let temp_dir = create_test_directory(&[
+            ("Artist1 - Album1", &[]),
+            ("Artist1 - Album2 [2020]", &[]),
+            ("Artist2 - Album3", &[]),
+            ("Loose Album", &[]),
+        ]);
+        File::create(temp_dir.path().join("Artist1 - Album1").join("a.mp3")).unwrap();
+        File::create(
+            temp_dir
+                .path()
+                .join("Artist1 - Album2 [2020]")
+                .join("b.flac"),
+        )
+        .unwrap();
+        File::create(temp_dir.path().join("Artist2 - Album3").join("c.wav")).unwrap();
+        File::create(temp_dir.path().join("Loose Album").join("d.m4a")).unwrap();
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let options = ScanOptions {
+            layout: LibraryLayout::AlbumOnly,
+            ..ScanOptions::default()
+        };
+
+        process_root(temp_dir.path(), &db, &options).unwrap();
 
         let artist1_data = get_artist_data(&db, "Artist1").unwrap().unwrap();
         assert_eq!(artist1_data.album_count, 2);
+        assert!(artist1_data
+            .albums
+            .iter()
+            .any(|album| album.name == "Album1"));
+        assert!(artist1_data
+            .albums
+            .iter()
+            .any(|album| album.name == "Album2"));
 
         let artist2_data = get_artist_data(&db, "Artist2").unwrap().unwrap();
         assert_eq!(artist2_data.album_count, 1);
+        assert_eq!(artist2_data.albums[0].name, "Album3");
+
+        let unknown_data = get_artist_data(&db, UNKNOWN_ARTIST).unwrap().unwrap();
+        assert_eq!(unknown_data.albums[0].name, "Loose Album");
+    }
+
+    #[test]
+    fn test_process_root_prefers_tags_over_folder_names() {
+        let temp_dir = create_test_directory(&[("Folder Artist", &["Folder Album"])]);
+        let album_path = temp_dir.path().join("Folder Artist").join("Folder Album");
+        write_tagged_flac(&album_path.join("01.flac"), "Tag Artist", "Tag Album", 2001);
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let options = ScanOptions {
+            use_tags: true,
+            ..ScanOptions::default()
+        };
+
+        process_root(temp_dir.path(), &db, &options).unwrap();
+
+        assert!(get_artist_data(&db, "Folder Artist").unwrap().is_none());
+        let artist_data = get_artist_data(&db, "Tag Artist").unwrap().unwrap();
+        assert_eq!(artist_data.album_count, 1);
+        assert_eq!(artist_data.albums[0].name, "Tag Album");
+    }
+
+    #[test]
+    fn test_process_root_falls_back_to_folder_names_without_tags() {
+        let temp_dir = create_test_directory(&[("Folder Artist", &["Folder Album"])]);
+        File::create(
+            temp_dir
+                .path()
+                .join("Folder Artist")
+                .join("Folder Album")
+                .join("01.mp3"),
+        )
+        .unwrap();
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let options = ScanOptions {
+            use_tags: true,
+            ..ScanOptions::default()
+        };
+
+        process_root(temp_dir.path(), &db, &options).unwrap();
+
+        let artist_data = get_artist_data(&db, "Folder Artist").unwrap().unwrap();
+        assert_eq!(artist_data.albums[0].name, "Folder Album");
+    }
+
+    fn set_modified_days_ago(path: &Path, days: u64) {
+        let modified = SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60);
+        File::open(path).unwrap().set_modified(modified).unwrap();
+    }
+
+    fn days_ago(days: u64) -> u64 {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        now.as_secs() - days * 24 * 60 * 60
+    }
+
+    #[test]
+    fn test_process_root_skips_artists_older_than_since() {
+        for (since_days, expect_scanned) in [(1, false), (3, true)] {
+            let temp_dir = create_test_directory(&[("Old Artist", &["Album"])]);
+            let artist_path = temp_dir.path().join("Old Artist");
+            File::create(artist_path.join("Album").join("01.mp3")).unwrap();
+            set_modified_days_ago(&artist_path, 2);
+
+            let db = sled::Config::new().temporary(true).open().unwrap();
+            let options = ScanOptions {
+                modified_since: Some(days_ago(since_days)),
+                ..ScanOptions::default()
+            };
+
+            let summary = process_root(temp_dir.path(), &db, &options).unwrap();
+
+            let stored = get_artist_data(&db, "Old Artist").unwrap();
+            assert_eq!(stored.is_some(), expect_scanned, "--since {since_days}d");
+            assert_eq!(summary.skipped, usize::from(!expect_scanned));
+        }
+    }
+
+    #[test]
+    fn test_change_detection_strategies() {
+        use ChangeDetection::*;
+        let changes = ["touch", "add track", "add album"];
+
+        for (strategy, expected) in [
+            (Mtime, [true, true, true]),
+            (AlbumCount, [false, false, true]),
+            (ContentHash, [false, true, true]),
+        ] {
+            for (change, expect_updated) in changes.into_iter().zip(expected) {
+                let temp_dir = create_test_directory(&[("Artist", &["Album1", "Artwork"])]);
+                let artist_path = temp_dir.path().join("Artist");
+                File::create(artist_path.join("Album1").join("01.mp3")).unwrap();
+                set_modified_days_ago(&artist_path, 2);
+                let db = MemoryStore::new();
+                let options = ScanOptions {
+                    change_detection: strategy,
+                    ..ScanOptions::default()
+                };
+                process_root(temp_dir.path(), &db, &options).unwrap();
+
+                match change {
+                    "add track" => {
+                        File::create(artist_path.join("Album1").join("02.mp3")).unwrap();
+                    }
+                    "add album" => {
+                        fs::create_dir(artist_path.join("Album2")).unwrap();
+                        File::create(artist_path.join("Album2").join("01.mp3")).unwrap();
+                    }
+                    _ => {}
+                }
+                set_modified_days_ago(&artist_path, 0);
+                let summary = process_root(temp_dir.path(), &db, &options).unwrap();
+
+                assert_eq!(
+                    summary.updated,
+                    usize::from(expect_updated),
+                    "{strategy:?}, {change}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_unchanged_artist_with_singles_and_invalid_albums_is_not_rescanned() {
+        for strategy in [ChangeDetection::AlbumCount, ChangeDetection::ContentHash] {
+            let temp_dir = create_test_directory(&[("Artist", &["Good", "Empty1", "Empty2"])]);
+            let artist_path = temp_dir.path().join("Artist");
+            write_tagged_flac(&artist_path.join("Good").join("01.flac"), "A", "Good", 2001);
+            File::create(artist_path.join("Empty1").join("01.mp3")).unwrap();
+            File::create(artist_path.join("Empty2").join("01.mp3")).unwrap();
+            File::create(artist_path.join("single.mp3")).unwrap();
+            let db = MemoryStore::new();
+            let options = ScanOptions {
+                change_detection: strategy,
+                validate_audio: true,
+                singles_album: Some("Singles".to_string()),
+                ..ScanOptions::default()
+            };
+            process_root(temp_dir.path(), &db, &options).unwrap();
+            let stored = get_artist_data(&db, "Artist").unwrap().unwrap();
+            assert_eq!(stored.album_count, 2, "{strategy:?}");
+
+            set_modified_days_ago(&artist_path, 0);
+            let summary = process_root(temp_dir.path(), &db, &options).unwrap();
+
+            assert_eq!(summary.updated, 0, "{strategy:?}");
+            assert_eq!(summary.unchanged, 1, "{strategy:?}");
+        }
+    }
+
+    #[test]
+    fn test_rescan_all_cleans_unchanged_artists_again() {
+        let temp_dir = create_test_directory(&[("Artist", &["Album [Live]"])]);
+        File::create(
+            temp_dir
+                .path()
+                .join("Artist")
+                .join("Album [Live]")
+                .join("01.mp3"),
+        )
+        .unwrap();
+        let db = MemoryStore::new();
+        process_root(temp_dir.path(), &db, &ScanOptions::default()).unwrap();
+
+        let keep_brackets = ScanOptions {
+            album_names: AlbumNameCleaner::from_patterns(&[r"\s*- Single$"]).unwrap(),
+            ..ScanOptions::default()
+        };
+        let summary = process_root(temp_dir.path(), &db, &keep_brackets).unwrap();
+        assert_eq!(summary.unchanged, 1);
+        let albums = get_artist_data(&db, "Artist").unwrap().unwrap().albums;
+        assert_eq!(albums[0].name, "Album");
+
+        let rescan_all = ScanOptions {
+            rescan_all: true,
+            ..keep_brackets
+        };
+        let summary = process_root(temp_dir.path(), &db, &rescan_all).unwrap();
+        assert_eq!(summary.updated, 1);
+        let albums = get_artist_data(&db, "Artist").unwrap().unwrap().albums;
+        assert_eq!(albums[0].name, "Album [Live]");
+    }
+
+    #[test]
+    fn test_album_only_since_keeps_skipped_albums() {
+        let temp_dir = create_test_directory(&[("Artist - Old", &[]), ("Artist - New", &[])]);
+        File::create(temp_dir.path().join("Artist - Old").join("a.mp3")).unwrap();
+        File::create(temp_dir.path().join("Artist - New").join("b.mp3")).unwrap();
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let options = ScanOptions {
+            layout: LibraryLayout::AlbumOnly,
+            ..ScanOptions::default()
+        };
+        process_root(temp_dir.path(), &db, &options).unwrap();
+
+        set_modified_days_ago(&temp_dir.path().join("Artist - Old"), 2);
+        File::create(temp_dir.path().join("Artist - New").join("c.mp3")).unwrap();
+        let options = ScanOptions {
+            modified_since: Some(days_ago(1)),
+            ..options
+        };
+        process_root(temp_dir.path(), &db, &options).unwrap();
+
+        let artist_data = get_artist_data(&db, "Artist").unwrap().unwrap();
+        assert_eq!(artist_data.albums.len(), 2);
+    }
+
+    #[test]
+    fn test_missing_root_is_a_read_directory_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = sled::Config::new().temporary(true).open().unwrap();
+
+        let result = process_root(
+            &temp_dir.path().join("missing"),
+            &db,
+            &ScanOptions::default(),
+        );
+
+        assert!(matches!(result, Err(ProcessError::ReadDirectory(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_folder_names_are_invalid_names() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let folder = temp_dir.path().join(OsStr::from_bytes(b"Bj\xf6rk - Post"));
+        fs::create_dir_all(folder.join("Disc")).unwrap();
+        File::create(folder.join("01.mp3")).unwrap();
+        File::create(folder.join("Disc").join("01.mp3")).unwrap();
+        let db = sled::Config::new().temporary(true).open().unwrap();
+
+        let result = process_root(temp_dir.path(), &db, &ScanOptions::default());
+        assert!(matches!(result, Err(ProcessError::InvalidArtistName(path)) if path == folder));
+
+        let options = ScanOptions {
+            layout: LibraryLayout::AlbumOnly,
+            ..ScanOptions::default()
+        };
+        let result = process_root(temp_dir.path(), &db, &options);
+        assert!(matches!(result, Err(ProcessError::InvalidAlbumName(path)) if path == folder));
+    }
+
+    #[test]
+    fn test_corrupt_artist_entry_is_a_database_error() {
+        let temp_dir = create_test_directory(&[("Artist1", &["Album1"])]);
+        File::create(temp_dir.path().join("Artist1").join("Album1").join("a.mp3")).unwrap();
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        db.insert(normalize_unicode("Artist1"), &[0xff_u8][..])
+            .unwrap();
+
+        let result = process_root(temp_dir.path(), &db, &ScanOptions::default());
+
+        assert!(matches!(result, Err(ProcessError::Database(_))));
+    }
+
+    #[test]
+    fn test_io_errors_convert_to_io_variant() {
+        let error = ProcessError::from(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(
+            matches!(error, ProcessError::Io(e) if e.kind() == io::ErrorKind::PermissionDenied)
+        );
+    }
+
+    #[test]
+    fn test_split_album_folder_name() {
+        assert_eq!(
+            split_album_folder_name("Artist - Album"),
+            ("Artist".to_string(), "Album".to_string())
+        );
+        assert_eq!(
+            split_album_folder_name("Artist - Album - Live"),
+            ("Artist".to_string(), "Album - Live".to_string())
+        );
+        assert_eq!(
+            split_album_folder_name("Album"),
+            (UNKNOWN_ARTIST.to_string(), "Album".to_string())
+        );
     }
 
     #[test]
@@ -218,33 +1941,237 @@ mod tests {
         File::create(artist_path.join("Album1").join("test.mp3")).unwrap();
         File::create(artist_path.join("Album2").join("test.flac")).unwrap();
 
-        let albums = collect_albums(&artist_path).unwrap();
+        let albums =
+            collect_albums(&artist_path, &ScanOptions::default(), &AUDIO_EXTENSIONS).unwrap();
 
         assert_eq!(albums.len(), 2);
-        assert!(albums.iter().any(|(name, _)| name == "Album1"));
-        assert!(albums.iter().any(|(name, _)| name == "Album2"));
-        assert!(!albums.iter().any(|(name, _)| name == "NotAnAlbum"));
+        assert!(albums.iter().any(|album| album.name == "Album1"));
+        assert!(albums.iter().any(|album| album.name == "Album2"));
+        assert!(!albums.iter().any(|album| album.name == "NotAnAlbum"));
+    }
+
+    #[test]
+    fn test_sidecar_overrides_the_folder_name() {
+        let temp_dir = create_test_directory(&[("Radiohead", &["ok_comp_1997_rip", "Kid A"])]);
+        let artist_path = temp_dir.path().join("Radiohead");
+        let album_path = artist_path.join("ok_comp_1997_rip");
+        File::create(album_path.join("01.flac")).unwrap();
+        File::create(artist_path.join("Kid A").join("01.flac")).unwrap();
+        fs::write(
+            album_path.join("album.nfo"),
+            "<album><title>OK Computer</title><year>1997</year>\
+             <musicbrainzalbumid>b1392450</musicbrainzalbumid></album>",
+        )
+        .unwrap();
+        let options = ScanOptions {
+            sidecar_file: Some("album.nfo".to_string()),
+            read_musicbrainz_ids: true,
+            ..ScanOptions::default()
+        };
+
+        let mut albums = collect_albums(&artist_path, &options, &AUDIO_EXTENSIONS).unwrap();
+        albums.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let names: Vec<_> = albums.iter().map(|album| album.name.as_str()).collect();
+        assert_eq!(names, ["Kid A", "OK Computer"]);
+        assert_eq!(albums[1].musicbrainz_id.as_deref(), Some("b1392450"));
+        assert_eq!(albums[0].musicbrainz_id, None);
+    }
+
+    #[test]
+    fn test_unreadable_sidecar_is_reported() {
+        let temp_dir = create_test_directory(&[("Radiohead", &["Kid A"])]);
+        let artist_path = temp_dir.path().join("Radiohead");
+        let album_path = artist_path.join("Kid A");
+        File::create(album_path.join("01.flac")).unwrap();
+        fs::write(album_path.join("album.nfo"), "<album><title>x</album>").unwrap();
+        let options = ScanOptions {
+            sidecar_file: Some("album.nfo".to_string()),
+            ..ScanOptions::default()
+        };
+        let observer = RecordingObserver::default();
+
+        let albums =
+            collect_albums_with(&artist_path, &options, &AUDIO_EXTENSIONS, &observer).unwrap();
+
+        assert_eq!(albums[0].name, "Kid A");
+        assert_eq!(
+            observer.events(),
+            [format!(
+                "warn Ignoring unreadable sidecar file {}",
+                album_path.join("album.nfo").display()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_loose_tracks_form_a_singles_album() {
+        let temp_dir = TempDir::new().unwrap();
+        let artist_path = temp_dir.path().join("Artist");
+        fs::create_dir(&artist_path).unwrap();
+        fs::write(artist_path.join("single1.mp3"), vec![0u8; 100]).unwrap();
+        fs::write(artist_path.join("single2.mp3"), vec![0u8; 50]).unwrap();
+        fs::write(artist_path.join("folder.jpg"), b"").unwrap();
+        let options = ScanOptions {
+            singles_album: Some("Singles".to_string()),
+            ..ScanOptions::default()
+        };
+
+        let db = MemoryStore::new();
+        process_root_with(
+            temp_dir.path(),
+            &db,
+            &options,
+            &RecordingObserver::default(),
+        )
+        .unwrap();
+
+        let data = get_artist_data(&db, "Artist").unwrap().unwrap();
+        assert_eq!(
+            data.albums,
+            [AlbumData {
+                name: "Singles".to_string(),
+                path: "Artist/Singles".to_string(),
+                track_count: 2,
+                total_bytes: 150,
+                musicbrainz_id: None,
+                content_digest: 0,
+            }]
+        );
+        let tracks = loose_tracks(&data.albums[0].full_path(temp_dir.path())).unwrap();
+        assert_eq!(
+            tracks,
+            [
+                artist_path.join("single1.mp3"),
+                artist_path.join("single2.mp3")
+            ]
+        );
+        assert_eq!(loose_tracks(&artist_path), None);
+
+        let albums =
+            collect_albums(&artist_path, &ScanOptions::default(), &AUDIO_EXTENSIONS).unwrap();
+        assert!(albums.is_empty());
+
+        fs::create_dir(artist_path.join("Singles")).unwrap();
+        let observer = RecordingObserver::default();
+        let albums =
+            collect_albums_with(&artist_path, &options, &AUDIO_EXTENSIONS, &observer).unwrap();
+        assert!(albums.is_empty());
+        assert_eq!(
+            observer.events(),
+            [format!(
+                "warn Skipping the loose tracks of {}: it already has a 'Singles' folder",
+                artist_path.display()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_multi_disc_album_is_counted_once() {
+        let temp_dir = create_test_directory(&[("Artist", &["Album"])]);
+        let artist_path = temp_dir.path().join("Artist");
+        for disc in ["CD1", "CD2"] {
+            let disc_path = artist_path.join("Album").join(disc);
+            fs::create_dir(&disc_path).unwrap();
+            File::create(disc_path.join("01.flac")).unwrap();
+        }
+
+        let albums =
+            collect_albums(&artist_path, &ScanOptions::default(), &AUDIO_EXTENSIONS).unwrap();
+
+        assert_eq!(albums.len(), 1);
+        assert_eq!(albums[0].name, "Album");
+        assert_eq!(albums[0].track_count, 2);
+    }
+
+    #[test]
+    fn test_disc_folder_pattern_is_configurable() {
+        let temp_dir = create_test_directory(&[("Artist", &["Album"])]);
+        let album_path = temp_dir.path().join("Artist").join("Album");
+        for disc in ["Part 1", "Part 2"] {
+            fs::create_dir(album_path.join(disc)).unwrap();
+            File::create(album_path.join(disc).join("01.flac")).unwrap();
+        }
+        let options = ScanOptions {
+            disc_folders: DiscFolderMatcher::from_pattern(r"^Part \d+$").unwrap(),
+            ..ScanOptions::default()
+        };
+
+        // The artist folder is scanned as if it were the album.
+        let discs = collect_albums(&album_path, &ScanOptions::default(), &AUDIO_EXTENSIONS);
+        let observer = RecordingObserver::default();
+        let parts =
+            collect_albums_with(&album_path, &options, &AUDIO_EXTENSIONS, &observer).unwrap();
+
+        assert_eq!(discs.unwrap().len(), 2);
+        assert!(parts.is_empty());
+        assert_eq!(
+            observer.events(),
+            [format!(
+                "warn Skipping {}: its only folders are discs, move them to an album folder",
+                album_path.display()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_album_named_like_a_disc_is_kept() {
+        let temp_dir = create_test_directory(&[("Artist", &["Album", "Disc 1"])]);
+        let artist_path = temp_dir.path().join("Artist");
+        File::create(artist_path.join("Album").join("01.flac")).unwrap();
+        File::create(artist_path.join("Disc 1").join("01.flac")).unwrap();
+
+        let albums =
+            collect_albums(&artist_path, &ScanOptions::default(), &AUDIO_EXTENSIONS).unwrap();
+
+        let mut names: Vec<_> = albums.iter().map(|album| album.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["Album", "Disc 1"]);
+    }
+
+    #[test]
+    fn test_collect_albums_computes_sizes() {
+        let temp_dir = create_test_directory(&[("Artist", &["Album"])]);
+        let album_path = temp_dir.path().join("Artist").join("Album");
+        fs::write(album_path.join("01.mp3"), vec![0u8; 1000]).unwrap();
+        fs::write(album_path.join("02.flac"), vec![0u8; 2500]).unwrap();
+        fs::write(album_path.join("cover.jpg"), vec![0u8; 300]).unwrap();
+        fs::create_dir(album_path.join("CD2")).unwrap();
+        fs::write(album_path.join("CD2").join("03.mp3"), vec![0u8; 200]).unwrap();
+
+        let albums = collect_albums(
+            &temp_dir.path().join("Artist"),
+            &ScanOptions::default(),
+            &AUDIO_EXTENSIONS,
+        )
+        .unwrap();
+
+        assert_eq!(albums.len(), 1);
+        assert_eq!(albums[0].track_count, 3);
+        assert_eq!(albums[0].total_bytes, 4000);
     }
 
     #[test]
-    fn test_has_audio_files() {
+    fn test_album_stats_counts_audio_files() {
         let temp_dir = TempDir::new().unwrap();
         let test_path = temp_dir.path().join("test");
         fs::create_dir(&test_path).unwrap();
 
-        assert!(!has_audio_files(&test_path));
+        assert_eq!(album_stats(&test_path, &AUDIO_EXTENSIONS).0, 0);
 
         File::create(test_path.join("test.mp3")).unwrap();
-        assert!(has_audio_files(&test_path));
+        assert_eq!(album_stats(&test_path, &AUDIO_EXTENSIONS).0, 1);
     }
 
     #[test]
     fn test_is_audio_file() {
-        assert!(is_audio_file(Path::new("test.mp3")));
-        assert!(is_audio_file(Path::new("test.FLAC")));
-        assert!(is_audio_file(Path::new("test.wav")));
-        assert!(is_audio_file(Path::new("test.m4a")));
-        assert!(!is_audio_file(Path::new("test.txt")));
+        assert!(is_audio_file(Path::new("test.mp3"), &AUDIO_EXTENSIONS));
+        assert!(is_audio_file(Path::new("test.FLAC"), &AUDIO_EXTENSIONS));
+        assert!(is_audio_file(Path::new("test.wav"), &AUDIO_EXTENSIONS));
+        assert!(is_audio_file(Path::new("test.m4a"), &AUDIO_EXTENSIONS));
+        assert!(!is_audio_file(Path::new("test.txt"), &AUDIO_EXTENSIONS));
+        assert!(is_audio_file(Path::new("test.OPUS"), &["opus"]));
+        assert!(!is_audio_file(Path::new("test.mp3"), &["opus"]));
     }
 
     #[test]
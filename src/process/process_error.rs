@@ -0,0 +1,61 @@
+use std::path::PathBuf;
+use std::{fmt, io};
+
+/// Errors that can occur while scanning the local music collection.
+#[derive(Debug)]
+pub enum ProcessError {
+    /// An artist folder name isn't valid UTF-8.
+    InvalidArtistName(PathBuf),
+    /// An album folder name isn't valid UTF-8.
+    InvalidAlbumName(PathBuf),
+    /// A directory of the collection couldn't be listed.
+    ReadDirectory(walkdir::Error),
+    /// Reading a file or folder of the collection failed.
+    Io(io::Error),
+    /// Reading or storing artist data in the local database failed.
+    Database(io::Error),
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProcessError::InvalidArtistName(path) => write!(
+                f,
+                "Invalid artist name (not valid UTF-8): {}",
+                path.display()
+            ),
+            ProcessError::InvalidAlbumName(path) => write!(
+                f,
+                "Invalid album name (not valid UTF-8): {}",
+                path.display()
+            ),
+            ProcessError::ReadDirectory(e) => {
+                write!(f, "Failed to read directory entry. Details: {}", e)
+            }
+            ProcessError::Io(e) => write!(f, "IO error: {}", e),
+            ProcessError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ProcessError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProcessError::ReadDirectory(e) => Some(e),
+            ProcessError::Io(e) | ProcessError::Database(e) => Some(e),
+            ProcessError::InvalidArtistName(_) | ProcessError::InvalidAlbumName(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for ProcessError {
+    fn from(error: io::Error) -> Self {
+        ProcessError::Io(error)
+    }
+}
+
+impl From<walkdir::Error> for ProcessError {
+    fn from(error: walkdir::Error) -> Self {
+        ProcessError::ReadDirectory(error)
+    }
+}
@@ -0,0 +1,182 @@
+//! This module reads the metadata sidecar file of an album, such as Kodi's `album.nfo`
+//! or an `album.json`, holding its canonical title, year and MusicBrainz ID. Sidecars
+//! take precedence over folder names and tags, which are often less reliable.
+//!
+//! Files ending in `.json` are read as a JSON object with `album` (or `title`), `year`
+//! and `musicbrainz_album_id` fields. Any other file is read as an XML document like
+//! Kodi's `album.nfo`, whose root element has `title`, `year` and `musicbrainzalbumid`
+//! children.
+
+use crate::foundation::utils::parse_album_year;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The metadata of an album read from its sidecar file. Fields the file doesn't hold
+/// are `None`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AlbumSidecar {
+    pub album: Option<String>,
+    pub year: Option<u32>,
+    /// The MusicBrainz release ID.
+    pub musicbrainz_album_id: Option<String>,
+}
+
+/// Reads the sidecar file named `file_name` in the album directory at `album_path`.
+///
+/// Returns `None` when the album has no such file, and an `InvalidData` error naming
+/// the file when it can't be parsed.
+///
+/// # Arguments
+///
+/// * `album_path` - The path to the album directory.
+/// * `file_name` - The name of the sidecar file, e.g. `album.nfo`.
+///
+pub fn read_sidecar(album_path: &Path, file_name: &str) -> io::Result<Option<AlbumSidecar>> {
+    let path = album_path.join(file_name);
+    let Ok(contents) = fs::read_to_string(&path) else {
+        return Ok(None);
+    };
+    parse_sidecar(file_name, &contents)
+        .map(Some)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unreadable sidecar file {}", path.display()),
+            )
+        })
+}
+
+/// Parses the contents of a sidecar file, as JSON when `file_name` ends in `.json` and
+/// as XML otherwise.
+fn parse_sidecar(file_name: &str, contents: &str) -> Option<AlbumSidecar> {
+    if Path::new(file_name)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+    {
+        parse_json_sidecar(contents)
+    } else {
+        parse_nfo_sidecar(contents)
+    }
+}
+
+fn parse_json_sidecar(contents: &str) -> Option<AlbumSidecar> {
+    let value: Value = serde_json::from_str(contents).ok()?;
+    let object = value.as_object()?;
+    let field = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| match object.get(*name)? {
+                Value::String(text) => Some(text.clone()),
+                Value::Number(number) => Some(number.to_string()),
+                _ => None,
+            })
+            .and_then(non_empty)
+    };
+
+    Some(AlbumSidecar {
+        album: field(&["album", "title"]),
+        year: field(&["year"]).and_then(|year| parse_album_year(&year)),
+        musicbrainz_album_id: field(&["musicbrainz_album_id", "musicbrainzalbumid"]),
+    })
+}
+
+fn parse_nfo_sidecar(contents: &str) -> Option<AlbumSidecar> {
+    let mut reader = Reader::from_str(contents);
+    reader.config_mut().trim_text(true);
+
+    let mut sidecar = AlbumSidecar::default();
+    // Only the children of the root element are read, not the titles of its tracks.
+    let mut depth = 0;
+    let mut current = None;
+    loop {
+        match reader.read_event().ok()? {
+            Event::Start(element) => {
+                depth += 1;
+                current = (depth == 2)
+                    .then(|| String::from_utf8_lossy(element.local_name().as_ref()).to_lowercase());
+            }
+            Event::End(_) => {
+                depth -= 1;
+                current = None;
+            }
+            Event::Text(text) => {
+                let Some(name) = &current else { continue };
+                let text = text.unescape().ok()?.into_owned();
+                match name.as_str() {
+                    "title" => sidecar.album = sidecar.album.or(non_empty(text)),
+                    "year" => sidecar.year = sidecar.year.or(parse_album_year(&text)),
+                    "musicbrainzalbumid" => {
+                        sidecar.musicbrainz_album_id =
+                            sidecar.musicbrainz_album_id.or(non_empty(text))
+                    }
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Some(sidecar)
+}
+
+fn non_empty(text: String) -> Option<String> {
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nfo_and_json_sidecars() {
+        let nfo = r#"<?xml version="1.0" encoding="UTF-8"?>
+<album>
+  <title>OK Computer</title>
+  <year>1997</year>
+  <musicbrainzalbumid>b1392450-e666-3926-a536-22c65f834433</musicbrainzalbumid>
+  <track><title>Airbag</title></track>
+</album>"#;
+        let expected = AlbumSidecar {
+            album: Some("OK Computer".to_string()),
+            year: Some(1997),
+            musicbrainz_album_id: Some("b1392450-e666-3926-a536-22c65f834433".to_string()),
+        };
+        assert_eq!(parse_sidecar("album.nfo", nfo), Some(expected.clone()));
+
+        let json = r#"{"title": "OK Computer", "year": 1997,
+            "musicbrainz_album_id": "b1392450-e666-3926-a536-22c65f834433"}"#;
+        assert_eq!(parse_sidecar("album.json", json), Some(expected));
+
+        assert_eq!(
+            parse_sidecar("album.json", r#"{"album": " ", "year": "1997-05-21"}"#),
+            Some(AlbumSidecar {
+                year: Some(1997),
+                ..AlbumSidecar::default()
+            })
+        );
+        assert_eq!(parse_sidecar("album.json", "not json"), None);
+        assert_eq!(parse_sidecar("album.nfo", "<album><title>x</album>"), None);
+    }
+
+    #[test]
+    fn test_read_sidecar_reports_unreadable_files() {
+        let album = tempfile::tempdir().unwrap();
+        assert_eq!(read_sidecar(album.path(), "album.json").unwrap(), None);
+
+        fs::write(album.path().join("album.json"), "not json").unwrap();
+        let error = read_sidecar(album.path(), "album.json").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "unreadable sidecar file {}",
+                album.path().join("album.json").display()
+            )
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! This module provides functionality for reading artist and album metadata from audio tags.
+//!
+//! Tag reading is used as an alternative to folder-name-based detection for libraries
+//! that don't embed metadata in their directory structure.
+
+use crate::process::process::{is_audio_file, AUDIO_EXTENSIONS};
+use lofty::prelude::*;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Artist, album and year information read from the tags of an audio file.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AlbumTags {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub year: Option<u32>,
+    /// The MusicBrainz release ID (`MUSICBRAINZ_ALBUMID`).
+    pub musicbrainz_album_id: Option<String>,
+}
+
+/// Read the ARTIST/ALBUM/DATE/MUSICBRAINZ_ALBUMID tags of the first audio file found in a
+/// directory.
+///
+/// Files are visited in file-name order so the same track is picked on every run.
+/// Returns `None` when the directory contains no readable audio file or the file
+/// carries no tags at all; individual missing tags are reported as `None` fields.
+///
+/// # Arguments
+///
+/// * `album_path` - The path to the album directory.
+///
+pub fn read_album_tags(album_path: &Path) -> Option<AlbumTags> {
+    let first_audio_file = WalkDir::new(album_path)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(Result::ok)
+        .find(|e| e.file_type().is_file() && is_audio_file(e.path(), &AUDIO_EXTENSIONS))?;
+
+    let tagged_file = lofty::read_from_path(first_audio_file.path()).ok()?;
+    let tag = tagged_file
+        .primary_tag()
+        .or_else(|| tagged_file.first_tag())?;
+
+    Some(AlbumTags {
+        artist: non_empty(tag.artist().as_deref()),
+        album: non_empty(tag.album().as_deref()),
+        year: tag.date().map(|date| u32::from(date.year)),
+        musicbrainz_album_id: non_empty(tag.get_string(ItemKey::MusicBrainzReleaseId)),
+    })
+}
+
+fn non_empty(value: Option<&str>) -> Option<String> {
+    value
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use lofty::config::WriteOptions;
+    use lofty::tag::items::Timestamp;
+    use lofty::tag::{Tag, TagType};
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// Writes a minimal, valid FLAC file (a lone STREAMINFO block) tagged with the given values.
+    pub(crate) fn write_tagged_flac(path: &Path, artist: &str, album: &str, year: u16) {
+        let mut bytes = b"fLaC".to_vec();
+        // Last-metadata-block flag set, block type 0 (STREAMINFO), 34 bytes long.
+        bytes.extend_from_slice(&[0x80, 0x00, 0x00, 0x22]);
+        // Min/max block size and min/max frame size.
+        bytes.extend_from_slice(&[0x10, 0x00, 0x10, 0x00]);
+        bytes.extend_from_slice(&[0x00; 6]);
+        // 44100 Hz, 2 channels, 16 bits per sample, 44100 samples, zeroed MD5 signature.
+        bytes.extend_from_slice(&[0x0A, 0xC4, 0x42, 0xF0, 0x00, 0x00, 0xAC, 0x44]);
+        bytes.extend_from_slice(&[0x00; 16]);
+        fs::write(path, bytes).unwrap();
+
+        let mut tag = Tag::new(TagType::VorbisComments);
+        tag.set_artist(artist.to_string());
+        tag.set_album(album.to_string());
+        tag.set_date(Timestamp {
+            year,
+            ..Timestamp::default()
+        });
+        tag.save_to_path(path, WriteOptions::default()).unwrap();
+    }
+
+    #[test]
+    fn test_read_album_tags() {
+        let temp_dir = TempDir::new().unwrap();
+        write_tagged_flac(
+            &temp_dir.path().join("01.flac"),
+            "Tagged Artist",
+            "Tagged Album",
+            1999,
+        );
+
+        let tags = read_album_tags(temp_dir.path()).unwrap();
+        assert_eq!(tags.artist.as_deref(), Some("Tagged Artist"));
+        assert_eq!(tags.album.as_deref(), Some("Tagged Album"));
+        assert_eq!(tags.year, Some(1999));
+    }
+
+    /// Adds a MUSICBRAINZ_ALBUMID tag to a file written by [`write_tagged_flac`].
+    pub(crate) fn set_musicbrainz_album_id(path: &Path, id: &str) {
+        let mut tagged_file = lofty::read_from_path(path).unwrap();
+        let tag = tagged_file.primary_tag_mut().unwrap();
+        tag.insert_text(ItemKey::MusicBrainzReleaseId, id.to_string());
+        tag.save_to_path(path, WriteOptions::default()).unwrap();
+    }
+
+    #[test]
+    fn test_read_musicbrainz_album_id() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("01.flac");
+        write_tagged_flac(&path, "Artist", "Album", 1999);
+        assert_eq!(
+            read_album_tags(temp_dir.path())
+                .unwrap()
+                .musicbrainz_album_id,
+            None
+        );
+
+        set_musicbrainz_album_id(&path, "b84ee12a-09ef-421b-82de-0441a926375b");
+
+        let tags = read_album_tags(temp_dir.path()).unwrap();
+        assert_eq!(
+            tags.musicbrainz_album_id.as_deref(),
+            Some("b84ee12a-09ef-421b-82de-0441a926375b")
+        );
+    }
+
+    #[test]
+    fn test_read_album_tags_without_audio_files() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("cover.jpg"), b"not audio").unwrap();
+
+        assert!(read_album_tags(temp_dir.path()).is_none());
+    }
+}
@@ -10,60 +10,1648 @@
 /// 4. Compares local data with the API
 /// 5. Uploads any missing albums
 ///
-use crate::{api_client, configuration, foundation::database, process};
-use configuration::ConfigFolder;
-use std::path::Path;
+use crate::foundation::utils::{confirm, InstanceLock};
+use crate::hooks::{self, SyncOutcome};
+use crate::metrics::{self, RunMetrics};
+use crate::observer::{ConsoleObserver, SyncObserver};
+use crate::{api_client, configuration, doctor, foundation::database, process};
+use api_client::{
+    MirroredAlbum, PendingQueue, PendingUploads, RemoteAlbum, Snapshot, UploadSummary,
+    MAX_SNAPSHOTS, PENDING_FILE, SNAPSHOTS_DIR,
+};
+use configuration::{ChangeDetection, ConfigFolder, MissingLocallyAction, Server};
+use process::ProcessError;
+use sled::Db;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{fmt, fs, io};
 
-pub async fn run(cfg_folder: ConfigFolder) -> Result<(), Box<dyn std::error::Error>> {
+/// File, inside the configuration folder, listing the albums missing locally. With
+/// several servers, the server name is appended (`wishlist-<server>.txt`).
+const WISHLIST_FILE: &str = "wishlist.txt";
+
+/// Number of albums listed when a server has more missing albums than
+/// `max_upload_batch`.
+const LARGE_BATCH_PREVIEW: usize = 10;
+
+/// Database metadata entry holding the library fingerprint of the last full scan.
+const LIBRARY_FINGERPRINT_KEY: &str = "library_fingerprint";
+
+/// Metadata key of the [`album_settings`] of the last full scan.
+const ALBUM_SETTINGS_KEY: &str = "album_settings";
+
+/// Options of the `run` command.
+#[derive(Debug, Default, Clone)]
+pub struct RunOptions {
+    /// Ignore the cached artist list and fetch it from the server again.
+    pub refresh: bool,
+    /// Only scan folders modified within this duration.
+    pub since: Option<Duration>,
+    /// Scan the library even when its fingerprint is unchanged since the last scan.
+    pub force: bool,
+    /// Upload the albums left in the queue of an interrupted sync, without scanning
+    /// or comparing.
+    pub resume: bool,
+    /// Only scan and compare, without uploading the missing albums.
+    pub no_upload: bool,
+    /// Compare the albums of every mismatched artist, even when its counts are unchanged
+    /// since the last comparison.
+    pub full: bool,
+    /// Settings overriding the configuration file for this run, as `(key, value)` pairs.
+    pub overrides: Vec<(String, String)>,
+    /// Delete the remote albums missing from the local library, as with `mirror_delete`.
+    pub mirror: bool,
+    /// Delete remote albums without asking for confirmation.
+    pub yes: bool,
+    /// Compare and upload even when the scan finds no artist folder in `local_path`.
+    pub allow_empty: bool,
+    /// Write the metrics of the sync to this file, in the Prometheus text format.
+    pub metrics_file: Option<PathBuf>,
+    /// Upload even when more albums are missing on a server than `max_upload_batch`.
+    pub confirm_large: bool,
+    /// Print the time spent in each phase of the sync.
+    pub profile: bool,
+    /// Upload at most this many albums to each server, leaving the others for the next
+    /// runs.
+    pub limit: Option<usize>,
+    /// List every updated artist in the scan summary, instead of the first few.
+    pub verbose: bool,
+}
+
+/// Wall-clock time spent in each phase of a sync, printed with `--profile`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SyncProfile {
+    /// Scanning the local library, zero when the scan was skipped.
+    pub scan: Duration,
+    /// Comparing with every server.
+    pub compare: Duration,
+    /// Uploading the missing albums.
+    pub upload: Duration,
+    /// Number of requests sent to the servers while comparing.
+    pub api_requests: usize,
+}
+
+impl fmt::Display for SyncProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Scan:    {:>8.2}s", self.scan.as_secs_f64())?;
+        writeln!(
+            f,
+            "Compare: {:>8.2}s ({} API requests)",
+            self.compare.as_secs_f64(),
+            self.api_requests
+        )?;
+        write!(f, "Upload:  {:>8.2}s", self.upload.as_secs_f64())
+    }
+}
+
+/// Exit codes of `musync`, telling scripts why a command failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    Success = 0,
+    /// Any failure without a code of its own.
+    Failure = 1,
+    /// The configuration is missing or invalid.
+    Config = 2,
+    /// The local library couldn't be scanned.
+    Scan = 3,
+    /// The comparison with a server failed.
+    Compare = 4,
+    /// Albums couldn't be uploaded.
+    Upload = 5,
+}
+
+impl ExitCode {
+    /// Returns the exit code of a command's result: the code of a [`SyncError`], or
+    /// [`ExitCode::Failure`] for any other error.
+    pub fn of(result: &Result<(), Box<dyn std::error::Error>>) -> Self {
+        match result {
+            Ok(()) => ExitCode::Success,
+            Err(e) => e
+                .downcast_ref::<SyncError>()
+                .map_or(ExitCode::Failure, |e| e.code),
+        }
+    }
+}
+
+/// An error of `musync run`, with the exit code of the step that failed.
+#[derive(Debug)]
+pub struct SyncError {
+    pub code: ExitCode,
+    error: Box<dyn std::error::Error>,
+}
+
+impl SyncError {
+    pub fn new(code: ExitCode, error: impl Into<Box<dyn std::error::Error>>) -> Self {
+        Self {
+            code,
+            error: error.into(),
+        }
+    }
+
+    fn config(error: impl Into<Box<dyn std::error::Error>>) -> Self {
+        Self::new(ExitCode::Config, error)
+    }
+
+    fn scan(error: impl Into<Box<dyn std::error::Error>>) -> Self {
+        Self::new(ExitCode::Scan, error)
+    }
+
+    fn compare(error: impl Into<Box<dyn std::error::Error>>) -> Self {
+        Self::new(ExitCode::Compare, error)
+    }
+
+    fn upload(error: impl Into<Box<dyn std::error::Error>>) -> Self {
+        Self::new(ExitCode::Upload, error)
+    }
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl std::error::Error for SyncError {}
+
+impl From<Box<dyn std::error::Error>> for SyncError {
+    fn from(error: Box<dyn std::error::Error>) -> Self {
+        Self::new(ExitCode::Failure, error)
+    }
+}
+
+impl From<String> for SyncError {
+    fn from(error: String) -> Self {
+        Self::new(ExitCode::Failure, error)
+    }
+}
+
+/// Runs a sync. Its errors are [`SyncError`]s, whose code `musync` exits with.
+pub async fn run(
+    cfg_folder: ConfigFolder,
+    options: &RunOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
     if !cfg_folder.config_dir.exists() || !cfg_folder.config_file.exists() {
+        return Err(SyncError::config(
+            "Configuration folder or config.yaml not found. Please run 'musync config' first.",
+        )
+        .into());
+    }
+
+    // Held until the sync finishes, so a second `musync run` can't race this one.
+    let _lock = InstanceLock::acquire(&cfg_folder.config_dir)?;
+
+    println!("\x1b[1m\x1b[34mStarting synchronization...\x1b[0m");
+    start_sync(cfg_folder, options).await?;
+    Ok(())
+}
+
+/// Scans, compares and uploads, returning the time spent in each phase.
+async fn start_sync(
+    config_folder: ConfigFolder,
+    options: &RunOptions,
+) -> Result<SyncProfile, SyncError> {
+    let config_file = &config_folder.config_file;
+    let config = configuration::get_configuration_with_overrides(config_file, &options.overrides)
+        .map_err(|_| SyncError::config("Unable to parse configuration file"))?;
+
+    let servers = config
+        .servers()
+        .map_err(|e| SyncError::config(format!("Invalid configuration: {}", e)))?;
+
+    let album_names = config
+        .album_name_cleaner()
+        .map_err(|e| SyncError::config(format!("Invalid configuration: {}", e)))?;
+    let disc_folders = config
+        .disc_folder_matcher()
+        .map_err(|e| SyncError::config(format!("Invalid configuration: {}", e)))?;
+
+    for server in &servers {
+        api_client::validate_progress_style(server.remote)
+            .map_err(|e| SyncError::config(format!("Invalid progress_style: {}", e)))?;
+    }
+
+    let pending_path = config_folder.config_dir.join(PENDING_FILE);
+    if options.resume {
+        resume_uploads(&pending_path, &servers)
+            .await
+            .map_err(SyncError::upload)?;
+        return Ok(SyncProfile::default());
+    }
+    let pending = PendingQueue::load(&pending_path)
+        .map_err(|e| format!("Failed to read the pending uploads: {}", e))?;
+    if !pending.is_empty() {
+        println!(
+            "\x1b[33mAn interrupted sync left {} album(s) to upload. Pass --resume to upload them without comparing again.\x1b[0m",
+            pending.len()
+        );
+    }
+
+    let db = open_config_database(&config_folder)?;
+    let rekeyed = database::set_key_strategy(&db, config.key_strategy)
+        .map_err(|e| format!("Failed to apply the key strategy: {}", e))?;
+    if rekeyed > 0 {
+        println!(
+            "\x1b[33mRe-keyed {} artist entries for the {:?} key strategy\x1b[0m",
+            rekeyed, config.key_strategy
+        );
+    }
+    let rekeyed = database::set_key_articles(&db, &config.ignore_articles)
+        .map_err(|e| format!("Failed to apply ignore_articles: {}", e))?;
+    if rekeyed > 0 {
+        println!(
+            "\x1b[33mRe-keyed {} artist entries for the ignored articles {:?}\x1b[0m",
+            rekeyed, config.ignore_articles
+        );
+    }
+
+    let local_path = configuration::resolve_local_path(&config.local_path)
+        .map_err(|e| SyncError::config(format!("Invalid local_path: {}", e)))?;
+
+    database::relativize_album_paths(&db, &local_path)
+        .map_err(|e| format!("Failed to update the stored album paths: {}", e))?;
+
+    let ignore_digest = process::ignore_file_digest(&local_path)
+        .map_err(|e| SyncError::scan(format!("Failed to read {}: {}", process::IGNORE_FILE, e)))?;
+    // Albums stored with other settings, such as other strip patterns or ignore rules,
+    // are stale even in folders that didn't change.
+    let album_settings = album_settings(&config, ignore_digest);
+    let rescan_all = database::get_metadata::<String>(&db, ALBUM_SETTINGS_KEY)
+        .unwrap_or(None)
+        .is_none_or(|stored| stored != album_settings);
+    let scan_options = process::ScanOptions {
+        layout: config.layout,
+        library_depth: config.library_depth,
+        use_tags: config.use_tags,
+        modified_since: options.since.map(|since| {
+            SystemTime::now()
+                .checked_sub(since)
+                .and_then(|cutoff| cutoff.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |cutoff| cutoff.as_secs())
+        }),
+        album_names: album_names.clone(),
+        disc_folders,
+        validate_audio: config.validate_audio,
+        change_detection: config.change_detection,
+        rescan_all,
+        require_year: config.require_year,
+        sidecar_file: config.sidecar_file.clone(),
+        singles_album: Some(config.singles_album.clone()).filter(|name| !name.is_empty()),
+        read_musicbrainz_ids: config.match_musicbrainz_ids,
+        ..process::ScanOptions::default()
+    };
+
+    let mut profile = SyncProfile::default();
+    let started = Instant::now();
+    let settings = scan_settings(&config, ignore_digest);
+    let summary =
+        scan_library(&local_path, &db, &scan_options, &settings, options).map_err(|e| {
+            SyncError::scan(format!(
+                "Failed to process the root directory: {}",
+                describe_scan_error(&e)
+            ))
+        })?;
+    profile.scan = started.elapsed();
+    // A `--since` scan leaves older folders with the albums of the previous settings.
+    if summary.is_some() && options.since.is_none() {
+        database::store_metadata(&db, ALBUM_SETTINGS_KEY, &album_settings)
+            .map_err(|e| format!("Failed to store the scan settings: {}", e))?;
+    }
+    // Mirroring trusts the scan to list every local album, so it needs a real one.
+    let library_scanned = summary.as_ref().is_some_and(|summary| !summary.is_empty());
+    if let Some(summary) = &summary {
+        if summary.is_empty() {
+            check_empty_library(&local_path, options.allow_empty).map_err(SyncError::scan)?;
+        }
+        let albums =
+            database::count_albums(&db).map_err(|e| format!("Failed to count albums: {}", e))?;
+        println!("{}", format_scan_summary(summary, albums, options.verbose));
+    }
+
+    let compare_options = api_client::CompareOptions {
+        refresh: options.refresh,
+        album_names,
+        fuzzy_album_match: config.fuzzy_album_match,
+        match_musicbrainz_ids: config.match_musicbrainz_ids,
+        full: options.full,
+        backend: config.comparison_backend,
+        comparison_mode: config.comparison_mode,
+        transliterate_artists: config.transliterate_artists,
+        report_sort: config.report_sort,
+    };
+
+    let started = Instant::now();
+    let reports = api_client::compare_with_servers(&db, &servers, &compare_options)
+        .await
+        .map_err(|e| SyncError::compare(format!("Error comparing with API: {}", e)))?;
+    profile.compare = started.elapsed();
+    profile.api_requests = reports.iter().map(|report| report.api_requests).sum();
+
+    let snapshot = Snapshot {
+        taken_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        reports,
+    };
+    let snapshots_dir = config_folder.config_dir.join(SNAPSHOTS_DIR);
+    // The snapshot only serves `diff`; the sync goes on without it.
+    if let Err(e) = api_client::save_snapshot(&snapshots_dir, &snapshot, MAX_SNAPSHOTS) {
+        eprintln!("\x1b[33mFailed to save the sync snapshot: {}\x1b[0m", e);
+    }
+    let reports = snapshot.reports;
+
+    let mut uploads = PendingUploads::default();
+    let mut mirrors = Vec::new();
+    let mut albums_missing = Vec::new();
+    let mut failed_artists = 0;
+
+    for (server, report) in servers.iter().zip(reports) {
+        albums_missing.push((server.name.to_string(), report.missing_in_api.len()));
+        for failed in &report.failed_artists {
+            eprintln!(
+                "\x1b[33mFailed to compare {} with '{}': {}\x1b[0m",
+                failed.artist, server.name, failed.error
+            );
+        }
+        failed_artists += report.failed_artists.len();
+        let wishlist_path = config_folder
+            .config_dir
+            .join(wishlist_file_name(server.name, servers.len()));
+        let mut missing_locally = report.missing_locally.clone();
+        api_client::sort_albums(&mut missing_locally, config.report_sort, |album| {
+            (&album.artist, &album.album, None)
+        });
+        handle_missing_locally(config.on_missing_locally, &missing_locally, &wishlist_path)
+            .map_err(|e| format!("Failed to write {}: {}", wishlist_path.display(), e))?;
+
+        if mirror_enabled(options, server.remote) && !report.missing_locally.is_empty() {
+            mirrors.push((server, report.missing_locally.clone()));
+        }
+
+        if report.missing_in_api.is_empty() {
+            println!(
+                "\x1b[32mNo missing albums to upload to '{}'. Everything is up-to-date!\x1b[0m",
+                server.name
+            );
+        } else {
+            if options.no_upload {
+                print_missing_on_server(
+                    &db,
+                    server.name,
+                    &report.missing_in_api,
+                    config.report_sort,
+                )
+                .map_err(|e| format!("Failed to read the missing albums: {}", e))?;
+            }
+            // Stored album paths are relative to the library root.
+            let album_paths = report
+                .missing_in_api
+                .iter()
+                .map(|path| local_path.join(path).to_string_lossy().into_owned())
+                .collect();
+            uploads.servers.insert(server.name.to_string(), album_paths);
+        }
+    }
+
+    if !options.no_upload {
+        limit_uploads(&mut uploads, options.limit);
+        check_upload_batch(&uploads, config.max_upload_batch, options.confirm_large)
+            .map_err(SyncError::upload)?;
+    }
+    let started = Instant::now();
+    let summary = queue_uploads(
+        &pending_path,
+        &servers,
+        uploads,
+        options.no_upload,
+        upload_queue,
+    )
+    .await
+    .map_err(SyncError::upload)?;
+    profile.upload = started.elapsed();
+
+    // Deleting comes after uploading, so a server that can't be mirrored still gets
+    // the new albums.
+    let observer = ConsoleObserver::new();
+    let mut mirror_failed = 0;
+    for (server, missing_locally) in mirrors {
+        if !library_scanned {
+            observer.warn(&format!(
+                "Not mirroring '{}': the library wasn't scanned this run or is empty. Pass --force to scan it.",
+                server.name
+            ));
+            continue;
+        }
+        let albums = api_client::albums_to_mirror(&db, &local_path, missing_locally, &observer)
+            .map_err(|e| format!("Failed to read the local albums: {}", e))?;
+        check_mirror_batch(
+            &albums,
+            server.name,
+            config.max_mirror_delete,
+            options.confirm_large,
+        )
+        .map_err(SyncError::upload)?;
+        mirror_failed += mirror_server(server, &albums, options.yes, &observer)
+            .await
+            .failed;
+    }
+
+    let outcome = SyncOutcome {
+        uploaded: summary.succeeded,
+        failed: summary.failed,
+        missing: albums_missing.iter().map(|(_, count)| count).sum(),
+    };
+    if let Some(metrics_file) = &options.metrics_file {
+        let metrics = RunMetrics {
+            artists_total: database::count_artists(&db)
+                .map_err(|e| format!("Failed to count artists: {}", e))?,
+            albums_missing,
+            uploads_failed: summary.failed,
+            last_run_timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        };
+        metrics::write_metrics_file(metrics_file, &metrics)
+            .map_err(|e| format!("Failed to write {}: {}", metrics_file.display(), e))?;
+    }
+
+    if let Some(command) = &config.post_sync_command {
+        hooks::run_post_sync_command(command, &outcome);
+    }
+
+    if options.profile {
+        println!("\x1b[1m\x1b[34mTime spent per phase:\x1b[0m\n{}", profile);
+    }
+    if summary.total() > 0 {
+        report_upload_result(&summary).map_err(SyncError::upload)?;
+    }
+    if mirror_failed > 0 {
+        return Err(SyncError::upload(format!(
+            "{} remote album(s) couldn't be deleted",
+            mirror_failed
+        )));
+    }
+    // The albums of the other artists were still synced.
+    if failed_artists > 0 {
+        return Err(SyncError::compare(format!(
+            "{} artist(s) couldn't be compared",
+            failed_artists
+        )));
+    }
+    Ok(profile)
+}
+
+/// Prints the local albums missing on a server, with their size, in the order chosen by
+/// `report_sort`.
+fn print_missing_on_server(
+    db: &Db,
+    server_name: &str,
+    paths: &[String],
+    sort: configuration::ReportSort,
+) -> io::Result<()> {
+    println!("\x1b[1m\x1b[34mAlbums missing on '{}':\x1b[0m", server_name);
+    for album in api_client::reported_albums(db, paths, sort)? {
+        println!("\x1b[33m  {}\x1b[0m", album);
+    }
+    Ok(())
+}
+
+/// Returns `true` if the remote albums missing locally must be deleted: `--mirror` or
+/// `mirror_delete` is set, and the run isn't compare-only (`--no-upload`).
+fn mirror_enabled(options: &RunOptions, remote: &configuration::RemoteSettings) -> bool {
+    (options.mirror || remote.mirror_delete) && !options.no_upload
+}
+
+/// Stops the sync when more albums would be deleted from `server` than
+/// `max_mirror_delete`, unless `confirm_large` is set, like [`check_upload_batch`].
+fn check_mirror_batch(
+    albums: &[MirroredAlbum],
+    server: &str,
+    max_mirror_delete: usize,
+    confirm_large: bool,
+) -> Result<(), String> {
+    if confirm_large || albums.len() <= max_mirror_delete {
+        return Ok(());
+    }
+    println!(
+        "\x1b[1m\x1b[33m{} album(s) would be deleted from '{}', more than max_mirror_delete ({}):\x1b[0m",
+        albums.len(),
+        server,
+        max_mirror_delete
+    );
+    for album in albums.iter().take(LARGE_BATCH_PREVIEW) {
+        println!(
+            "\x1b[33m  {} - {}\x1b[0m",
+            album.album.artist, album.album.album
+        );
+    }
+    if albums.len() > LARGE_BATCH_PREVIEW {
+        println!(
+            "\x1b[33m  ... and {} more\x1b[0m",
+            albums.len() - LARGE_BATCH_PREVIEW
+        );
+    }
+    Err(format!(
+        "Refusing to delete {} album(s) from '{}'. Check the configuration, or pass --confirm-large to delete them.",
+        albums.len(),
+        server
+    ))
+}
+
+/// Deletes the albums of `server` that the local library doesn't have, after asking for
+/// confirmation unless `yes` is set.
+///
+/// Without a terminal to ask on, nothing is deleted unless `yes` is set; the sync goes
+/// on with a warning.
+async fn mirror_server(
+    server: &Server<'_>,
+    albums: &[MirroredAlbum],
+    yes: bool,
+    observer: &dyn SyncObserver,
+) -> api_client::MirrorSummary {
+    if albums.is_empty() {
+        return api_client::MirrorSummary::default();
+    }
+    if !yes && !io::stdin().is_terminal() {
+        observer.warn(&format!(
+            "Not deleting the {} album(s) missing locally from '{}': stdin is not a terminal. Pass --yes to delete them.",
+            albums.len(),
+            server.name
+        ));
+        return api_client::MirrorSummary::default();
+    }
+    for album in albums {
+        println!(
+            "\x1b[31m  {} - {}\x1b[0m",
+            album.album.artist, album.album.album
+        );
+    }
+    let question = format!(
+        "Delete these {} album(s) from '{}'? They can't be recovered.",
+        albums.len(),
+        server.name
+    );
+    if !yes {
+        match confirm(&question) {
+            Ok(true) => {}
+            Ok(false) => {
+                println!("\x1b[33mNo remote album deleted.\x1b[0m");
+                return api_client::MirrorSummary::default();
+            }
+            Err(e) => {
+                observer.warn(&format!(
+                    "No remote album deleted from '{}': {}",
+                    server.name, e
+                ));
+                return api_client::MirrorSummary::default();
+            }
+        }
+    }
+
+    let summary = api_client::delete_remote_albums(albums, server.remote, observer).await;
+    println!(
+        "\x1b[32mDeleted {} album(s) from '{}'.\x1b[0m",
+        summary.deleted, server.name
+    );
+    summary
+}
+
+/// Keeps the first `limit` albums to upload to each server, in path order so every run
+/// picks the same ones, and says how many are left for the next runs.
+fn limit_uploads(uploads: &mut PendingUploads, limit: Option<usize>) {
+    let Some(limit) = limit else {
+        return;
+    };
+    for (server, albums) in &mut uploads.servers {
+        if albums.len() <= limit {
+            continue;
+        }
+        albums.sort();
+        println!(
+            "\x1b[33mUploading {} of the {} album(s) missing on '{}' (--limit), the others are left for the next runs\x1b[0m",
+            limit,
+            albums.len(),
+            server
+        );
+        albums.truncate(limit);
+    }
+}
+
+/// Stops the sync when more albums are missing on a server than `max_upload_batch`,
+/// unless `confirm_large` is set. A misconfiguration, such as a wrong `remote_path` or
+/// music folder, can make the whole library look missing, so the count and the first
+/// albums are printed for the user to check before passing `--confirm-large`.
+fn check_upload_batch(
+    uploads: &PendingUploads,
+    max_upload_batch: Option<usize>,
+    confirm_large: bool,
+) -> Result<(), String> {
+    let Some(max_upload_batch) = max_upload_batch else {
+        return Ok(());
+    };
+    if confirm_large {
+        return Ok(());
+    }
+
+    for (server, albums) in &uploads.servers {
+        if albums.len() <= max_upload_batch {
+            continue;
+        }
+        println!(
+            "\x1b[1m\x1b[33m{} album(s) are missing on '{}', more than max_upload_batch ({}):\x1b[0m",
+            albums.len(),
+            server,
+            max_upload_batch
+        );
+        for album in albums.iter().take(LARGE_BATCH_PREVIEW) {
+            println!("\x1b[33m  {}\x1b[0m", album);
+        }
+        if albums.len() > LARGE_BATCH_PREVIEW {
+            println!(
+                "\x1b[33m  ... and {} more\x1b[0m",
+                albums.len() - LARGE_BATCH_PREVIEW
+            );
+        }
+        return Err(format!(
+            "Refusing to upload {} album(s) to '{}'. Check the configuration, or pass --confirm-large to upload them.",
+            albums.len(),
+            server
+        ));
+    }
+    Ok(())
+}
+
+/// Saves the albums to upload as the pending queue and passes it to `upload`.
+///
+/// With `no_upload`, the missing albums are only counted: neither the queue nor
+/// `upload` is touched, so a previous interrupted sync can still be resumed, and an
+/// empty summary is returned.
+async fn queue_uploads(
+    pending_path: &Path,
+    servers: &[Server<'_>],
+    uploads: PendingUploads,
+    no_upload: bool,
+    upload: impl AsyncFnOnce(
+        &PendingQueue,
+        &[Server],
+    ) -> Result<UploadSummary, Box<dyn std::error::Error>>,
+) -> Result<UploadSummary, Box<dyn std::error::Error>> {
+    if no_upload {
+        for (server, albums) in &uploads.servers {
+            println!(
+                "\x1b[1m{} album(s) missing on '{}', not uploaded (--no-upload)\x1b[0m",
+                albums.len(),
+                server
+            );
+        }
+        return Ok(UploadSummary::default());
+    }
+
+    let queue = PendingQueue::create(pending_path, uploads)
+        .map_err(|e| format!("Failed to write the pending uploads: {}", e))?;
+    upload(&queue, servers).await
+}
+
+/// Uploads the queue left by an interrupted sync, skipping the scan and comparison.
+async fn resume_uploads(
+    pending_path: &Path,
+    servers: &[Server<'_>],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let queue = PendingQueue::load(pending_path)
+        .map_err(|e| format!("Failed to read the pending uploads: {}", e))?;
+    if queue.is_empty() {
+        println!("\x1b[32mNo interrupted sync to resume.\x1b[0m");
+        return Ok(());
+    }
+
+    println!(
+        "\x1b[1m\x1b[34mResuming {} pending upload(s)...\x1b[0m",
+        queue.len()
+    );
+    for name in queue.servers() {
+        if !servers.iter().any(|server| server.name == name) {
+            eprintln!("\x1b[33mSkipping pending uploads to '{name}': server is no longer configured\x1b[0m");
+        }
+    }
+    let summary = upload_queue(&queue, servers).await?;
+    if summary.total() > 0 {
+        report_upload_result(&summary)?;
+    }
+    Ok(())
+}
+
+/// Uploads the queued albums of each server. Uploaded albums are removed from the queue
+/// as they finish.
+///
+/// Ctrl-C stops the uploads, killing the transfer in progress. The albums not uploaded
+/// yet stay in the queue, for `--resume`.
+///
+/// Returns the combined summary of every server.
+async fn upload_queue(
+    queue: &PendingQueue,
+    servers: &[Server<'_>],
+) -> Result<UploadSummary, Box<dyn std::error::Error>> {
+    tokio::select! {
+        summary = upload_queued_servers(queue, servers) => summary,
+        _ = tokio::signal::ctrl_c() => Err(format!(
+            "Upload interrupted, {} album(s) left to upload. Run 'musync run --resume' to upload them.",
+            queue.len()
+        )
+        .into()),
+    }
+}
+
+async fn upload_queued_servers(
+    queue: &PendingQueue,
+    servers: &[Server<'_>],
+) -> Result<UploadSummary, Box<dyn std::error::Error>> {
+    let mut summary = UploadSummary::default();
+
+    for server in servers {
+        if queue.albums(server.name).is_empty() {
+            continue;
+        }
+        println!(
+            "\x1b[1m\x1b[34mUploading missing albums to '{}'...\x1b[0m",
+            server.name
+        );
+        let server_summary = api_client::upload_queued_albums(queue, server.name, server.remote)
+            .await
+            .map_err(|e| format!("Failed to upload albums: {}", e))?;
+        summary.merge(&server_summary);
+    }
+
+    Ok(summary)
+}
+
+/// Scans the library, unless its fingerprint matches the one stored by the last full
+/// scan and `force` isn't set.
+///
+/// The fingerprint covers `settings`, the scan and key settings from [`scan_settings`],
+/// so changing them, or re-keying the database, scans again. The fingerprint is only
+/// stored after a full scan, since a `--since` scan skips older folders and may miss
+/// changes made to them. The scan is never skipped with the `ContentHash` change
+/// detection, which looks inside the album folders the fingerprint doesn't cover, nor
+/// when every artist must be scanned again.
+///
+/// Returns the scan summary, or `None` when the scan was skipped.
+fn scan_library(
+    local_path: &Path,
+    db: &Db,
+    scan_options: &process::ScanOptions,
+    settings: &str,
+    options: &RunOptions,
+) -> Result<Option<process::ScanSummary>, ProcessError> {
+    let library = process::library_fingerprint(local_path, scan_options.library_depth)?;
+    let fingerprint = process::scan_fingerprint(library, settings);
+    // An unreadable stored fingerprint is treated as a changed library.
+    let stored = database::get_metadata::<u64>(db, LIBRARY_FINGERPRINT_KEY).unwrap_or(None);
+    let may_skip = !options.force
+        && !scan_options.rescan_all
+        && scan_options.change_detection != ChangeDetection::ContentHash;
+
+    if may_skip && stored == Some(fingerprint) {
+        println!("\x1b[32mLibrary unchanged since the last scan, skipping the scan.\x1b[0m");
+        return Ok(None);
+    }
+
+    let summary = process::process_root(local_path, db, scan_options)?;
+
+    // An empty library is scanned again next time, so it keeps being reported.
+    if options.since.is_none() && !summary.is_empty() {
+        database::store_metadata(db, LIBRARY_FINGERPRINT_KEY, &fingerprint)
+            .map_err(ProcessError::Database)?;
+    }
+    Ok(Some(summary))
+}
+
+/// Describes the settings changing what a scan stores, or the keys it is stored under,
+/// for the library fingerprint of [`scan_library`].
+fn scan_settings(config: &configuration::Settings, ignore_digest: u64) -> String {
+    format!(
+        "{:?}",
+        (
+            album_settings(config, ignore_digest),
+            (config.change_detection, config.require_year),
+            (config.key_strategy, &config.ignore_articles),
+        )
+    )
+}
+
+/// Describes the settings changing the albums stored for an unchanged artist folder:
+/// every artist is scanned again when they change. `ignore_digest` stands for the
+/// `.musyncignore` rules, as returned by [`process::ignore_file_digest`].
+fn album_settings(config: &configuration::Settings, ignore_digest: u64) -> String {
+    format!(
+        "{:?}",
+        (
+            ignore_digest,
+            (config.layout, config.library_depth, config.use_tags),
+            (
+                &config.album_name_strip_patterns,
+                &config.disc_folder_pattern
+            ),
+            config.validate_audio,
+            (&config.sidecar_file, &config.singles_album),
+            config.match_musicbrainz_ids,
+        )
+    )
+}
+
+/// Describes what a scan did: how many artists it updated, left unchanged or skipped,
+/// and how many albums the database now tracks.
+///
+/// The updated artists are listed after the counts: the first few on one line, or all of
+/// them, one per line, when `verbose` is set.
+fn format_scan_summary(summary: &process::ScanSummary, albums: usize, verbose: bool) -> String {
+    let mut text = format!(
+        "\x1b[1mScan summary: {} artist(s) updated, {} unchanged, {} skipped, {} album(s) tracked\x1b[0m",
+        summary.updated, summary.unchanged, summary.skipped, albums
+    );
+    let artists = &summary.updated_artists;
+    if verbose {
+        for artist in artists {
+            text.push_str(&format!("\n  {}", artist));
+        }
+    } else if !artists.is_empty() {
+        let shown = artists.len().min(LARGE_BATCH_PREVIEW);
+        text.push_str(&format!("\nUpdated: {}", artists[..shown].join(", ")));
+        if artists.len() > shown {
+            text.push_str(&format!(
+                " ... and {} more (-v to list them all)",
+                artists.len() - shown
+            ));
+        }
+    }
+    text
+}
+
+/// Stops the sync when the scan found no artist folder, which almost always means
+/// `local_path` points at the wrong folder: every album of the server would then be
+/// missing locally. With `allow_empty`, only a warning is printed.
+fn check_empty_library(local_path: &Path, allow_empty: bool) -> Result<(), String> {
+    if allow_empty {
         eprintln!(
-            "\x1b[1m\x1b[31mConfiguration folder or config.yaml not found. Please run 'musync config' first.\x1b[0m"
+            "\x1b[1m\x1b[33mWarning: no artist folders found in {}.\x1b[0m",
+            local_path.display()
         );
         return Ok(());
     }
+    Err(format!(
+        "No artist folders found in {}. Check local_path in the configuration, or pass --allow-empty if the library really is empty.",
+        local_path.display()
+    ))
+}
+
+/// Describes a scan failure along with what the user can do about it.
+fn describe_scan_error(error: &ProcessError) -> String {
+    match error {
+        ProcessError::InvalidArtistName(_) | ProcessError::InvalidAlbumName(_) => {
+            format!("{}. Rename the folder so its name is valid UTF-8.", error)
+        }
+        ProcessError::ReadDirectory(e)
+            if e.io_error().map(io::Error::kind) == Some(io::ErrorKind::PermissionDenied) =>
+        {
+            format!("{}. Check that local_path is readable.", error)
+        }
+        ProcessError::Io(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            format!("{}. Check that local_path is readable.", error)
+        }
+        ProcessError::Database(_) => format!(
+            "{}. Run 'musync verify' to check the database, or 'musync clean' to rebuild it.",
+            error
+        ),
+        _ => error.to_string(),
+    }
+}
 
-    println!("\x1b[1m\x1b[34mStarting synchronization...\x1b[0m");
-    start_sync(cfg_folder).await
+/// Name of the wishlist file of a server. With several servers, each gets its own file.
+fn wishlist_file_name(server_name: &str, server_count: usize) -> String {
+    if server_count > 1 {
+        format!("wishlist-{}.txt", server_name)
+    } else {
+        WISHLIST_FILE.to_string()
+    }
 }
 
-async fn start_sync(config_folder: ConfigFolder) -> Result<(), Box<dyn std::error::Error>> {
-    let config_file = config_folder.config_file.to_str().unwrap();
-    let config = configuration::get_configuration(config_file)
-        .map_err(|_| "Unable to parse configuration file")?;
+/// Applies the configured `on_missing_locally` action to the albums the server has but
+/// the local library doesn't.
+fn handle_missing_locally(
+    action: MissingLocallyAction,
+    albums: &[RemoteAlbum],
+    wishlist_path: &Path,
+) -> io::Result<()> {
+    match action {
+        MissingLocallyAction::Ignore => {}
+        MissingLocallyAction::Report => {
+            if albums.is_empty() {
+                println!("\x1b[32mNo albums missing locally.\x1b[0m");
+            } else {
+                println!("\x1b[1m\x1b[34mAlbums missing locally:\x1b[0m");
+                for album in albums {
+                    println!(
+                        "\x1b[33m  {} - {} (artist id: {})\x1b[0m",
+                        album.artist, album.album, album.artist_id
+                    );
+                }
+            }
+        }
+        MissingLocallyAction::WriteList => {
+            let contents: String = albums.iter().map(wishlist_line).collect();
+            fs::write(wishlist_path, contents)?;
+            println!(
+                "\x1b[32mWrote {} album(s) missing locally to {}\x1b[0m",
+                albums.len(),
+                wishlist_path.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Formats an album as a tab-separated `artist, album, artist id` wishlist line.
+fn wishlist_line(album: &RemoteAlbum) -> String {
+    format!("{}\t{}\t{}\n", album.artist, album.album, album.artist_id)
+}
+
+/// Opens the local database located in the configuration folder.
+fn open_config_database(cfg_folder: &ConfigFolder) -> Result<Db, Box<dyn std::error::Error>> {
+    open_config_database_with(cfg_folder, &database::DatabaseOptions::default())
+}
+
+/// Opens the existing local database, for commands that only read it. Fails while a
+/// sync holds the database.
+fn open_config_database_read_only(
+    cfg_folder: &ConfigFolder,
+) -> Result<Db, Box<dyn std::error::Error>> {
+    let options = database::DatabaseOptions {
+        read_only: true,
+        ..database::DatabaseOptions::default()
+    };
+    open_config_database_with(cfg_folder, &options)
+}
 
-    let db_path_as_str = config_folder
+fn open_config_database_with(
+    cfg_folder: &ConfigFolder,
+    options: &database::DatabaseOptions,
+) -> Result<Db, Box<dyn std::error::Error>> {
+    let db_path_as_str = cfg_folder
         .musync_db
         .to_str()
         .ok_or_else(|| "Failed to convert the database path to a string".to_string())?;
 
-    let db = database::open_database(db_path_as_str)?;
+    Ok(database::open_database_with(db_path_as_str, options)?)
+}
 
-    if let Err(e) = process::process_root(Path::new(&config.local_path), &db) {
-        eprintln!(
-            "\x1b[1m\x1b[31mFailed to process the root directory: {}\x1b[0m",
-            e
+/// Reports the outcome of the upload step, turning any failed album into an error so
+/// the process exits with a non-zero status.
+fn report_upload_result(summary: &UploadSummary) -> Result<(), Box<dyn std::error::Error>> {
+    if summary.failed == 0 {
+        println!(
+            "\x1b[32mSuccessfully uploaded missing albums. {}\x1b[0m",
+            summary
         );
-        return Ok(()); // Return Ok to prevent propagating the error further
+        return Ok(());
     }
 
-    let missing_albums = api_client::compare_with_api(&db, &config.api_settings)
-        .await
-        .unwrap_or_else(|e| {
-            eprintln!("\x1b[31mError comparing with API: {}\x1b[0m", e);
-            Vec::new()
-        });
+    println!(
+        "\x1b[33mUploaded {} of {} missing albums. {}\x1b[0m",
+        summary.succeeded,
+        summary.total(),
+        summary
+    );
+    Err(format!("{} album(s) failed to upload", summary.failed).into())
+}
 
-    if missing_albums.is_empty() {
-        println!("\x1b[32mNo missing albums to upload. Everything is up-to-date!\x1b[0m");
-    } else {
-        println!("\x1b[1m\x1b[34mUploading missing albums to server...\x1b[0m");
-        if let Err(e) = api_client::upload_missing_albums(&missing_albums, &config.remote_settings)
+/// Removes every cached artist entry from the local database, forcing a full rescan
+/// on the next run. Asks for confirmation unless `force` is set.
+pub fn clean(cfg_folder: ConfigFolder, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if !cfg_folder.musync_db.exists() {
+        println!("\x1b[33mNo database found. Nothing to clean.\x1b[0m");
+        return Ok(());
+    }
+
+    if !force && !confirm("This will remove all cached artist data. Continue?")? {
+        println!("\x1b[33mOperation cancelled.\x1b[0m");
+        return Ok(());
+    }
+
+    let db = open_config_database(&cfg_folder)?;
+    let removed = database::clear_database(&db)?;
+
+    println!(
+        "\x1b[32mRemoved {} artist entries from the database.\x1b[0m",
+        removed
+    );
+    Ok(())
+}
+
+/// Writes every artist entry of the local database to `output` as JSON.
+pub fn export(cfg_folder: ConfigFolder, output: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if !cfg_folder.musync_db.exists() {
+        return Err("No database found. Run 'musync run' first.".into());
+    }
+
+    let db = open_config_database_read_only(&cfg_folder)?;
+    let entries = database::export_database(&db)?;
+
+    let mut writer = io::BufWriter::new(fs::File::create(output)?);
+    serde_json::to_writer_pretty(&mut writer, &entries)?;
+    // Flushed explicitly, as dropping the writer would ignore a failed final write.
+    writer.flush()?;
+
+    println!(
+        "\x1b[32mExported {} artist entries to {}\x1b[0m",
+        entries.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// Reads artist entries exported with [`export`] from `input` and stores them in the
+/// local database, replacing entries for the same artists.
+pub fn import(cfg_folder: ConfigFolder, input: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let file = io::BufReader::new(fs::File::open(input)?);
+    let entries: Vec<(String, database::ArtistData)> = serde_json::from_reader(file)
+        .map_err(|e| format!("Invalid export file {}: {}", input.display(), e))?;
+
+    let db = open_config_database(&cfg_folder)?;
+    let imported = database::import_database(&db, entries)?;
+
+    println!(
+        "\x1b[32mImported {} artist entries from {}\x1b[0m",
+        imported,
+        input.display()
+    );
+    Ok(())
+}
+
+/// Shows which albums went missing on a server and which were resolved between the last
+/// two syncs.
+pub fn diff(cfg_folder: ConfigFolder) -> Result<(), Box<dyn std::error::Error>> {
+    let snapshots_dir = cfg_folder.config_dir.join(SNAPSHOTS_DIR);
+    let [older, newer] =
+        <[Snapshot; 2]>::try_from(api_client::latest_snapshots(&snapshots_dir, 2)?)
+            .map_err(|_| "At least two syncs are needed to show a diff. Run 'musync run' first.")?;
+
+    let diff = api_client::diff_reports(&older.reports, &newer.reports);
+    if diff.is_empty() {
+        println!("\x1b[32mNothing changed since the previous sync.\x1b[0m");
+        return Ok(());
+    }
+
+    for album in &diff.newly_missing {
+        println!(
+            "\x1b[33m+ {} (missing on '{}')\x1b[0m",
+            album.path, album.server
+        );
+    }
+    for album in &diff.resolved {
+        println!(
+            "\x1b[32m- {} (resolved on '{}')\x1b[0m",
+            album.path, album.server
+        );
+    }
+    println!(
+        "{} newly missing, {} resolved",
+        diff.newly_missing.len(),
+        diff.resolved.len()
+    );
+    Ok(())
+}
+
+/// Checks that a sync can run: the programs it needs are installed, the configuration
+/// parses, the local path and ssh keys exist and every server answers. Each check is
+/// printed with a hint to fix it when it fails.
+///
+/// Returns an error when a check fails so the process exits with a non-zero status.
+pub async fn doctor(cfg_folder: ConfigFolder) -> Result<(), Box<dyn std::error::Error>> {
+    let path = std::env::var_os("PATH");
+    let (config_check, config) = doctor::check_config(&cfg_folder.config_file);
+    let servers = config
+        .as_ref()
+        .map(|config| config.servers().unwrap_or_default())
+        .unwrap_or_default();
+
+    let mut programs = vec!["scp", "ssh"];
+    for server in &servers {
+        for program in doctor::required_programs(server.remote) {
+            if !programs.contains(&program) {
+                programs.push(program);
+            }
+        }
+    }
+    let mut checks: Vec<_> = programs
+        .into_iter()
+        .map(|program| doctor::check_program(program, path.as_deref()))
+        .collect();
+    checks.push(config_check);
+    if let Some(config) = &config {
+        checks.push(doctor::check_local_path(config));
+    }
+    for server in &servers {
+        checks.extend(doctor::check_ssh_key(server));
+        if config.as_ref().map(|config| config.comparison_backend)
+            != Some(configuration::ComparisonBackend::SshListing)
         {
-            eprintln!("\x1b[31mFailed to upload albums: {}\x1b[0m", e);
-        } else {
-            println!("\x1b[32mSuccessfully uploaded missing albums.\x1b[0m");
+            checks.push(doctor::check_server(server).await);
         }
     }
 
+    for check in &checks {
+        println!("{}", check);
+    }
+    let failed = checks.iter().filter(|check| !check.passed()).count();
+    if failed > 0 {
+        return Err(format!("{} check(s) failed", failed).into());
+    }
+    println!("\x1b[32mEverything looks good.\x1b[0m");
     Ok(())
 }
+
+/// Checks every entry of the local database without modifying it, reporting entries
+/// that can't be deserialized and album paths that no longer exist on disk.
+///
+/// Returns an error when issues are found so the process exits with a non-zero status.
+pub fn verify(cfg_folder: ConfigFolder) -> Result<(), Box<dyn std::error::Error>> {
+    if !cfg_folder.musync_db.exists() {
+        return Err("No database found. Run 'musync run' first.".into());
+    }
+
+    let config = configuration::get_configuration(&cfg_folder.config_file)
+        .map_err(|_| "Unable to parse configuration file")?;
+    let local_path = configuration::resolve_local_path(&config.local_path)
+        .map_err(|e| format!("Invalid local_path: {}", e))?;
+
+    let db = open_config_database_read_only(&cfg_folder)?;
+    let mut issues = database::verify_database(&db, &local_path);
+    // Singles albums have no folder of their own, only loose tracks.
+    issues.retain(|issue| match issue {
+        database::VerifyIssue::MissingPath { path, .. } => {
+            process::loose_tracks(&local_path.join(path)).is_none()
+        }
+        _ => true,
+    });
+
+    if issues.is_empty() {
+        println!("\x1b[32mDatabase is healthy. No issues found.\x1b[0m");
+        return Ok(());
+    }
+
+    for issue in &issues {
+        println!("\x1b[33m{}\x1b[0m", issue);
+    }
+    Err(format!("{} database issue(s) found", issues.len()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observer::tests::RecordingObserver;
+    use tempfile::TempDir;
+
+    fn write_config_folder(temp_dir: &TempDir, local_path: &Path) -> ConfigFolder {
+        write_config_folder_for(temp_dir, local_path, "http://127.0.0.1:9")
+    }
+
+    fn write_config_folder_for(
+        temp_dir: &TempDir,
+        local_path: &Path,
+        api_base_url: &str,
+    ) -> ConfigFolder {
+        let config_dir = temp_dir.path().join(".musync");
+        let cfg_folder = ConfigFolder {
+            config_file: config_dir.join("config.yaml"),
+            musync_db: config_dir.join("musync_db"),
+            config_dir,
+        };
+        fs::create_dir_all(&cfg_folder.musync_db).unwrap();
+        fs::write(
+            &cfg_folder.config_file,
+            format!(
+                r#"
+local_path: "{}"
+remote_settings:
+  remote_user: "user"
+  remote_host: "localhost"
+  remote_path: "/music"
+  ssh_key_path: "/dev/null"
+api_settings:
+  api_base_url: "{}"
+  api_username: "user"
+  api_password: "password"
+"#,
+                local_path.display(),
+                api_base_url
+            ),
+        )
+        .unwrap();
+        cfg_folder
+    }
+
+    #[test]
+    fn test_report_upload_result() {
+        let mut summary = UploadSummary {
+            succeeded: 3,
+            ..UploadSummary::default()
+        };
+        assert!(report_upload_result(&summary).is_ok());
+
+        summary.failed = 2;
+        let error = report_upload_result(&summary).unwrap_err();
+        assert_eq!(error.to_string(), "2 album(s) failed to upload");
+    }
+
+    #[test]
+    fn test_handle_missing_locally_writes_wishlist() {
+        let temp_dir = TempDir::new().unwrap();
+        let wishlist_path = temp_dir.path().join(WISHLIST_FILE);
+        let albums = vec![
+            RemoteAlbum {
+                artist: "Beatles".to_string(),
+                album: "Abbey Road".to_string(),
+                artist_id: "ar-1".to_string(),
+                local_artist: "Beatles".to_string(),
+            },
+            RemoteAlbum {
+                artist: "Radiohead".to_string(),
+                album: "Kid A".to_string(),
+                artist_id: "ar-2".to_string(),
+                local_artist: "Radiohead".to_string(),
+            },
+        ];
+
+        handle_missing_locally(MissingLocallyAction::WriteList, &albums, &wishlist_path).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&wishlist_path).unwrap(),
+            "Beatles\tAbbey Road\tar-1\nRadiohead\tKid A\tar-2\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_no_upload_never_uploads() {
+        let temp_dir = TempDir::new().unwrap();
+        let pending_path = temp_dir.path().join(PENDING_FILE);
+        let uploads = PendingUploads {
+            servers: [(
+                "default".to_string(),
+                vec!["/library/Artist/Album".to_string()],
+            )]
+            .into(),
+        };
+        let mut uploaded = false;
+
+        queue_uploads(&pending_path, &[], uploads.clone(), true, async |_, _| {
+            uploaded = true;
+            Ok(UploadSummary::default())
+        })
+        .await
+        .unwrap();
+        assert!(!uploaded);
+        assert!(!pending_path.exists());
+
+        queue_uploads(&pending_path, &[], uploads, false, async |queue, _| {
+            uploaded = true;
+            assert_eq!(queue.len(), 1);
+            Ok(UploadSummary::default())
+        })
+        .await
+        .unwrap();
+        assert!(uploaded);
+    }
+
+    #[tokio::test]
+    async fn test_limit_uploads_the_first_albums() {
+        let temp_dir = TempDir::new().unwrap();
+        let pending_path = temp_dir.path().join(PENDING_FILE);
+        let mut uploads = PendingUploads {
+            servers: [(
+                "default".to_string(),
+                ["E", "B", "D", "A", "C"]
+                    .map(|album| format!("/library/Artist/{album}"))
+                    .to_vec(),
+            )]
+            .into(),
+        };
+
+        limit_uploads(&mut uploads, Some(2));
+        let mut uploaded = Vec::new();
+        queue_uploads(&pending_path, &[], uploads, false, async |queue, _| {
+            uploaded = queue.albums("default");
+            Ok(UploadSummary::default())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(uploaded, ["/library/Artist/A", "/library/Artist/B"]);
+    }
+
+    #[test]
+    fn test_scan_is_skipped_only_with_unchanged_library_and_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        let album = temp_dir.path().join("Air").join("Moon Safari");
+        fs::create_dir_all(&album).unwrap();
+        fs::write(album.join("01.flac"), "").unwrap();
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let scan = |settings: &str, change_detection| {
+            let scan_options = process::ScanOptions {
+                change_detection,
+                ..process::ScanOptions::default()
+            };
+            scan_library(
+                temp_dir.path(),
+                &db,
+                &scan_options,
+                settings,
+                &RunOptions::default(),
+            )
+            .unwrap()
+            .is_some()
+        };
+
+        assert!(scan("use_tags: false", ChangeDetection::Mtime));
+        assert!(!scan("use_tags: false", ChangeDetection::Mtime));
+        assert!(scan("use_tags: true", ChangeDetection::Mtime));
+        assert!(!scan("use_tags: true", ChangeDetection::Mtime));
+        assert!(scan("use_tags: true", ChangeDetection::ContentHash));
+    }
+
+    #[test]
+    fn test_scan_summary_counts_the_artists_of_the_library() {
+        let temp_dir = TempDir::new().unwrap();
+        let add_album = |path: &str| {
+            let album = temp_dir.path().join(path);
+            fs::create_dir_all(&album).unwrap();
+            fs::write(album.join("01.flac"), "").unwrap();
+        };
+        add_album("Air/Moon Safari");
+        add_album("Podcasts/Episode 1");
+        fs::write(temp_dir.path().join(".musyncignore"), "Podcasts\n").unwrap();
+        let db = database::MemoryStore::new();
+        let scan = || {
+            process::process_root_with(
+                temp_dir.path(),
+                &db,
+                &process::ScanOptions::default(),
+                &RecordingObserver::default(),
+            )
+            .unwrap()
+        };
+        scan();
+
+        add_album("Radiohead/OK Computer");
+        add_album("Radiohead/Kid A");
+        add_album("Muse/Absolution");
+        let summary = scan();
+        let albums = database::count_albums(&db).unwrap();
+
+        assert_eq!(summary.updated, 2);
+        assert_eq!(summary.updated_artists, ["Muse", "Radiohead"]);
+        assert_eq!(summary.unchanged, 1);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(albums, 4);
+        assert_eq!(
+            format_scan_summary(&summary, albums, false),
+            "\x1b[1mScan summary: 2 artist(s) updated, 1 unchanged, 1 skipped, 4 album(s) tracked\x1b[0m\n\
+             Updated: Muse, Radiohead"
+        );
+        assert!(format_scan_summary(&summary, albums, true).ends_with("\n  Muse\n  Radiohead"));
+    }
+
+    #[test]
+    fn test_large_upload_batch_needs_confirmation() {
+        let albums = |count: usize| PendingUploads {
+            servers: [(
+                "default".to_string(),
+                (0..count)
+                    .map(|i| format!("/library/Artist/Album {i}"))
+                    .collect(),
+            )]
+            .into(),
+        };
+
+        let error = check_upload_batch(&albums(21), Some(20), false).unwrap_err();
+        assert!(error.starts_with("Refusing to upload 21 album(s) to 'default'"));
+
+        assert!(check_upload_batch(&albums(21), Some(20), true).is_ok());
+        assert!(check_upload_batch(&albums(20), Some(20), false).is_ok());
+        assert!(check_upload_batch(&albums(500), None, false).is_ok());
+    }
+
+    #[test]
+    fn test_mirror_needs_confirmation_for_large_batches() {
+        let album = |artist: &str, album: &str| MirroredAlbum {
+            album: RemoteAlbum {
+                artist: artist.to_string(),
+                album: album.to_string(),
+                artist_id: "1".to_string(),
+                local_artist: artist.to_string(),
+            },
+            artist_folder: artist.to_string(),
+        };
+        let albums = [album("Air", "Talkie Walkie"), album("Muse", "Absolution")];
+
+        let error = check_mirror_batch(&albums, "default", 1, false).unwrap_err();
+        assert!(error.starts_with("Refusing to delete 2 album(s) from 'default'"));
+        assert!(check_mirror_batch(&albums, "default", 1, true).is_ok());
+        assert!(check_mirror_batch(&albums, "default", 2, false).is_ok());
+    }
+
+    #[test]
+    fn test_mirror_deletion_is_opt_in() {
+        let remote = configuration::RemoteSettings::default();
+        let mirror_delete = configuration::RemoteSettings {
+            mirror_delete: true,
+            ..configuration::RemoteSettings::default()
+        };
+        let mirror = RunOptions {
+            mirror: true,
+            ..RunOptions::default()
+        };
+        let dry_run = RunOptions {
+            mirror: true,
+            no_upload: true,
+            ..RunOptions::default()
+        };
+
+        assert!(!mirror_enabled(&RunOptions::default(), &remote));
+        assert!(mirror_enabled(&mirror, &remote));
+        assert!(mirror_enabled(&RunOptions::default(), &mirror_delete));
+        assert!(!mirror_enabled(&dry_run, &mirror_delete));
+    }
+
+    #[test]
+    fn test_wishlist_file_name() {
+        assert_eq!(wishlist_file_name("default", 1), "wishlist.txt");
+        assert_eq!(wishlist_file_name("vps", 2), "wishlist-vps.txt");
+    }
+
+    #[test]
+    fn test_handle_missing_locally_ignore_writes_nothing() {
+        let temp_dir = TempDir::new().unwrap();
+        let wishlist_path = temp_dir.path().join(WISHLIST_FILE);
+
+        handle_missing_locally(MissingLocallyAction::Ignore, &[], &wishlist_path).unwrap();
+
+        assert!(!wishlist_path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_run_fails_when_local_path_is_missing() {
+        let temp_dir = TempDir::new().unwrap();
+        let cfg_folder = write_config_folder(&temp_dir, &temp_dir.path().join("missing"));
+
+        let result = run(cfg_folder, &RunOptions::default()).await;
+
+        assert_eq!(ExitCode::of(&result), ExitCode::Config);
+        let error = result.unwrap_err().to_string();
+        assert!(error.starts_with("Invalid local_path"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_fails_when_scan_fails() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let library = temp_dir.path().join("library");
+        // An artist folder whose name isn't UTF-8 can't be stored.
+        let album = library
+            .join(std::ffi::OsStr::from_bytes(b"Artist-\xff"))
+            .join("Album");
+        fs::create_dir_all(&album).unwrap();
+        fs::write(album.join("01.mp3"), b"").unwrap();
+        let cfg_folder = write_config_folder(&temp_dir, &library);
+
+        let result = run(cfg_folder, &RunOptions::default()).await;
+
+        assert_eq!(ExitCode::of(&result), ExitCode::Scan);
+        let error = result.unwrap_err().to_string();
+        assert!(error.starts_with("Failed to process the root directory"));
+    }
+
+    #[tokio::test]
+    async fn test_run_fails_with_config_code_without_configuration() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_dir = temp_dir.path().join(".musync");
+        let cfg_folder = ConfigFolder {
+            config_file: config_dir.join("config.yaml"),
+            musync_db: config_dir.join("musync_db"),
+            config_dir,
+        };
+
+        let result = run(cfg_folder, &RunOptions::default()).await;
+
+        assert_eq!(ExitCode::of(&result), ExitCode::Config);
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(ExitCode::of(&Ok(())) as i32, 0);
+        assert_eq!(ExitCode::of(&Err("disk full".into())) as i32, 1);
+        for (error, code) in [
+            (SyncError::config("bad config"), 2),
+            (SyncError::scan("unreadable"), 3),
+            (SyncError::compare("server down"), 4),
+            (SyncError::upload("2 album(s) failed to upload"), 5),
+        ] {
+            let message = error.to_string();
+            let result: Result<(), Box<dyn std::error::Error>> = Err(error.into());
+            assert_eq!(ExitCode::of(&result) as i32, code, "{message}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_fails_when_comparison_fails() {
+        let temp_dir = TempDir::new().unwrap();
+        let library = temp_dir.path().join("library");
+        fs::create_dir_all(library.join("Artist").join("Album")).unwrap();
+        fs::write(library.join("Artist").join("Album").join("01.mp3"), b"").unwrap();
+        let cfg_folder = write_config_folder(&temp_dir, &library);
+
+        let result = run(cfg_folder, &RunOptions::default()).await;
+
+        assert_eq!(ExitCode::of(&result), ExitCode::Compare);
+        let error = result.unwrap_err().to_string();
+        assert!(error.starts_with("Error comparing with API"));
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_when_library_is_empty() {
+        let temp_dir = TempDir::new().unwrap();
+        let library = temp_dir.path().join("library");
+        fs::create_dir(&library).unwrap();
+        let cfg_folder = write_config_folder(&temp_dir, &library);
+
+        let result = run(cfg_folder, &RunOptions::default()).await;
+        assert_eq!(ExitCode::of(&result), ExitCode::Scan);
+        let error = result.unwrap_err().to_string();
+        assert!(error.starts_with("No artist folders found"));
+    }
+
+    #[tokio::test]
+    async fn test_allow_empty_compares_an_empty_library() {
+        let temp_dir = TempDir::new().unwrap();
+        let library = temp_dir.path().join("library");
+        fs::create_dir(&library).unwrap();
+        let cfg_folder = write_config_folder(&temp_dir, &library);
+
+        let allow_empty = RunOptions {
+            allow_empty: true,
+            ..RunOptions::default()
+        };
+        let result = run(cfg_folder, &allow_empty).await;
+        let error = result.unwrap_err().to_string();
+        assert!(error.starts_with("Error comparing with API"));
+    }
+
+    #[tokio::test]
+    async fn test_sync_profile_counts_api_requests() {
+        use serde_json::json;
+        use wiremock::matchers::path;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let subsonic_ok = |body: serde_json::Value| {
+            let mut response = json!({ "status": "ok", "version": "1.16.1" });
+            response
+                .as_object_mut()
+                .unwrap()
+                .extend(body.as_object().unwrap().clone());
+            ResponseTemplate::new(200).set_body_json(json!({ "subsonic-response": response }))
+        };
+        Mock::given(path("/getArtists"))
+            .respond_with(subsonic_ok(
+                json!({ "artists": { "index": [{ "name": "A", "artist": [
+                { "id": "1", "name": "Artist", "albumCount": 2 },
+            ] }] } }),
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(path("/getArtist"))
+            .respond_with(subsonic_ok(json!({ "artist": { "id": "1", "album": [
+                { "name": "Album" },
+                { "name": "Live" },
+            ] } })))
+            .mount(&server)
+            .await;
+
+        let temp_dir = TempDir::new().unwrap();
+        let library = temp_dir.path().join("library");
+        fs::create_dir_all(library.join("Artist").join("Album")).unwrap();
+        fs::write(library.join("Artist").join("Album").join("01.mp3"), b"").unwrap();
+        let cfg_folder = write_config_folder_for(&temp_dir, &library, &server.uri());
+
+        let options = RunOptions {
+            profile: true,
+            ..RunOptions::default()
+        };
+        let profile = start_sync(cfg_folder, &options).await.unwrap();
+
+        // The version ping, the artist list and the artist whose albums differ.
+        assert_eq!(profile.api_requests, 3);
+        assert!(profile.scan > Duration::ZERO);
+        assert!(profile.compare > Duration::ZERO);
+    }
+}
@@ -0,0 +1,94 @@
+//! Runs a whole sync against a temporary library and a mock Subsonic server.
+
+use musync::api_client::{latest_snapshots, RemoteAlbum, PENDING_FILE, SNAPSHOTS_DIR};
+use musync::configuration::ConfigFolder;
+use musync::startup::{run, RunOptions};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+use wiremock::matchers::path;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn subsonic_ok(body: Value) -> ResponseTemplate {
+    let mut response = json!({ "status": "ok", "version": "1.16.1" });
+    response
+        .as_object_mut()
+        .unwrap()
+        .extend(body.as_object().unwrap().clone());
+    ResponseTemplate::new(200).set_body_json(json!({ "subsonic-response": response }))
+}
+
+fn write_album(library: &Path, artist: &str, album: &str) {
+    let album_dir = library.join(artist).join(album);
+    fs::create_dir_all(&album_dir).unwrap();
+    fs::write(album_dir.join("01.mp3"), b"").unwrap();
+}
+
+#[tokio::test]
+async fn test_run_syncs_a_library_with_a_mock_server() {
+    let server = MockServer::start().await;
+    Mock::given(path("/ping"))
+        .respond_with(subsonic_ok(json!({})))
+        .mount(&server)
+        .await;
+    Mock::given(path("/getArtists"))
+        .respond_with(subsonic_ok(
+            json!({ "artists": { "index": [{ "name": "R", "artist": [
+            { "id": "7", "name": "Radiohead", "albumCount": 2 },
+        ] }] } }),
+        ))
+        .mount(&server)
+        .await;
+    Mock::given(path("/getArtist"))
+        .respond_with(subsonic_ok(json!({ "artist": { "id": "7", "album": [
+            { "name": "OK Computer" },
+            { "name": "Kid A" },
+        ] } })))
+        .mount(&server)
+        .await;
+
+    let temp_dir = TempDir::new().unwrap();
+    let library = temp_dir.path().join("library");
+    write_album(&library, "Radiohead", "OK Computer");
+    let config_dir = temp_dir.path().join("config");
+    fs::create_dir_all(&config_dir).unwrap();
+    let config_file = config_dir.join("config.yaml");
+    fs::write(
+        &config_file,
+        format!(
+            r#"
+local_path: "{}"
+remote_settings:
+  remote_user: "user"
+  remote_host: "localhost"
+  remote_path: "/music"
+api_settings:
+  api_base_url: "{}"
+  api_username: "user"
+  api_password: "password"
+"#,
+            library.display(),
+            server.uri()
+        ),
+    )
+    .unwrap();
+    let cfg_folder = ConfigFolder::with_paths(&config_file, temp_dir.path().join("db"));
+
+    run(cfg_folder, &RunOptions::default()).await.unwrap();
+
+    let snapshots = latest_snapshots(&config_dir.join(SNAPSHOTS_DIR), 1).unwrap();
+    let report = &snapshots[0].reports[0];
+    assert!(report.missing_in_api.is_empty());
+    assert_eq!(
+        report.missing_locally,
+        [RemoteAlbum {
+            artist: "Radiohead".to_string(),
+            album: "Kid A".to_string(),
+            artist_id: "7".to_string(),
+            local_artist: "Radiohead".to_string(),
+        }]
+    );
+    assert!(!config_dir.join(PENDING_FILE).exists());
+    assert!(temp_dir.path().join("db").exists());
+}